@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! JSON views of the maintainer daemon's state, served by its http API.
+//!
+//! These borrow from a [`SolidoState`], so serving a request does not need to
+//! copy the state, it only serializes the parts that are useful to consumers.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lido::state::{Lido, Validator};
+use lido::token::{Lamports, StLamports};
+use lido::util::serialize_b58;
+use serde::Serialize;
+use solana_program::clock::{Epoch, Slot};
+use solana_program::pubkey::Pubkey;
+
+use lido::stake_account::StakeAccount;
+
+use crate::maintenance::{MaintenanceOutput, SolidoState};
+
+/// A maintenance step that the daemon performed.
+#[derive(Clone, Serialize)]
+pub struct MaintenanceRecord {
+    /// Slot of the state that the maintenance was based on.
+    pub slot: Slot,
+
+    /// Epoch of the state that the maintenance was based on.
+    pub epoch: Epoch,
+
+    /// Unix timestamp (in seconds) at which we observed that state.
+    pub observed_at: u64,
+
+    pub output: MaintenanceOutput,
+}
+
+impl MaintenanceRecord {
+    pub fn new(state: &SolidoState, output: MaintenanceOutput) -> MaintenanceRecord {
+        MaintenanceRecord {
+            slot: state.clock.slot,
+            epoch: state.clock.epoch,
+            observed_at: unix_seconds(state.produced_at),
+            output,
+        }
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Serialize)]
+pub struct StakeAccountView {
+    #[serde(serialize_with = "serialize_b58")]
+    pub address: Pubkey,
+    pub seed: u64,
+    pub activation_epoch: Epoch,
+    pub inactive_lamports: Lamports,
+    pub activating_lamports: Lamports,
+    pub active_lamports: Lamports,
+    pub deactivating_lamports: Lamports,
+}
+
+impl StakeAccountView {
+    fn new(address: Pubkey, account: &StakeAccount) -> StakeAccountView {
+        StakeAccountView {
+            address,
+            seed: account.seed,
+            activation_epoch: account.activation_epoch,
+            inactive_lamports: account.balance.inactive,
+            activating_lamports: account.balance.activating,
+            active_lamports: account.balance.active,
+            deactivating_lamports: account.balance.deactivating,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ValidatorView<'a> {
+    #[serde(serialize_with = "serialize_b58")]
+    pub vote_account: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub identity_account: Pubkey,
+
+    pub name: &'a str,
+    pub keybase_username: Option<&'a str>,
+
+    /// The validator entry as stored in the validator list.
+    #[serde(flatten)]
+    pub entry: &'a Validator,
+
+    pub vote_account_balance_lamports: Lamports,
+    pub identity_account_balance_lamports: Lamports,
+    pub last_voted_slot: Slot,
+    pub last_voted_timestamp: i64,
+    pub vote_credits: u64,
+
+    pub stake_accounts: Vec<StakeAccountView>,
+    pub unstake_accounts: Vec<StakeAccountView>,
+}
+
+impl<'a> ValidatorView<'a> {
+    /// Return the view of the validator at the given index in `state.validators`.
+    pub fn new(state: &'a SolidoState, index: usize) -> ValidatorView<'a> {
+        let validator = &state.validators.entries[index];
+        let vote_account = &state.validator_vote_accounts[index];
+        let info = &state.validator_infos[index];
+        let to_views = |accounts: &[(Pubkey, StakeAccount)]| {
+            accounts
+                .iter()
+                .map(|(address, account)| StakeAccountView::new(*address, account))
+                .collect()
+        };
+        ValidatorView {
+            vote_account: validator.pubkey,
+            identity_account: vote_account.node_pubkey,
+            name: &info.name,
+            keybase_username: info.keybase_username.as_deref(),
+            entry: &validator.entry,
+            vote_account_balance_lamports: state.validator_vote_account_balances[index],
+            identity_account_balance_lamports: state.validator_identity_account_balances[index],
+            last_voted_slot: vote_account.last_timestamp.slot,
+            last_voted_timestamp: vote_account.last_timestamp.timestamp,
+            vote_credits: vote_account.credits(),
+            stake_accounts: to_views(&state.validator_stake_accounts[index]),
+            unstake_accounts: to_views(&state.validator_unstake_accounts[index]),
+        }
+    }
+
+    /// Return the view of the validator with the given vote account, if there is one.
+    pub fn find(state: &'a SolidoState, vote_account: &Pubkey) -> Option<ValidatorView<'a>> {
+        state
+            .validators
+            .entries
+            .iter()
+            .position(|validator| &validator.pubkey == vote_account)
+            .map(|index| ValidatorView::new(state, index))
+    }
+}
+
+#[derive(Serialize)]
+pub struct MaintainerView {
+    #[serde(serialize_with = "serialize_b58")]
+    pub address: Pubkey,
+    pub balance_lamports: Lamports,
+
+    /// Whether this maintainer is on duty at the slot of the snapshot.
+    pub on_duty: bool,
+
+    /// The slot at which the maintainer's next duty slice starts.
+    pub next_duty_slot: Option<Slot>,
+}
+
+#[derive(Serialize)]
+pub struct StateView<'a> {
+    /// Unix timestamp (in seconds) at which we observed this state.
+    pub observed_at: u64,
+    pub slot: Slot,
+    pub epoch: Epoch,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub solido_program_id: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub solido_address: Pubkey,
+
+    /// The Solido instance, including the exchange rate and fee configuration.
+    pub solido: &'a Lido,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub reserve_address: Pubkey,
+
+    /// Balance of the reserve that can be staked, excluding the rent-exempt amount.
+    pub reserve_balance_lamports: Lamports,
+
+    pub st_sol_supply: StLamports,
+
+    pub maintainers: Vec<MaintainerView>,
+    pub validators: Vec<ValidatorView<'a>>,
+}
+
+impl<'a> StateView<'a> {
+    pub fn new(state: &'a SolidoState) -> StateView<'a> {
+        let on_duty = state.get_current_maintainer_duty();
+        let maintainers = state
+            .solido
+            .maintainers
+            .entries
+            .iter()
+            .zip(&state.maintainer_balances)
+            .map(|(maintainer, balance)| MaintainerView {
+                address: maintainer.pubkey,
+                balance_lamports: *balance,
+                on_duty: on_duty == Some(maintainer.pubkey),
+                next_duty_slot: state.get_next_maintainer_duty_slot(&maintainer.pubkey),
+            })
+            .collect();
+        StateView {
+            observed_at: unix_seconds(state.produced_at),
+            slot: state.clock.slot,
+            epoch: state.clock.epoch,
+            solido_program_id: state.solido_program_id,
+            solido_address: state.solido_address,
+            solido: &state.solido,
+            reserve_address: state.reserve_address,
+            reserve_balance_lamports: state.get_effective_reserve(),
+            st_sol_supply: StLamports(state.st_sol_mint.supply),
+            maintainers,
+            validators: (0..state.validators.len())
+                .map(|index| ValidatorView::new(state, index))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! A per-epoch budget for the fees that the maintainer daemon pays.
+//!
+//! A bug, or an RPC node that flaps, could make the maintainer send failing
+//! transactions over and over again, and burn its SOL on fees. To bound the
+//! damage, the daemon tracks the fees it paid in the current epoch, and how
+//! often it performed every kind of operation, and it refuses to send
+//! transactions that would exceed either limit. The fees we paid in the current
+//! epoch are also recorded in the maintenance journal, if there is one, so the
+//! budget is not reset when the daemon restarts.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use lido::token::Lamports;
+use solana_sdk::clock::Epoch;
+
+use crate::journal::FeePaid;
+use crate::maintenance::MaintenanceOperation;
+use crate::prometheus::{write_metric, Metric, MetricFamily};
+
+#[derive(Clone, Debug)]
+pub struct FeeBudget {
+    /// The most we spend on transaction fees in a single epoch.
+    max_fees_per_epoch: Lamports,
+
+    /// The most operations of a single kind that we perform in a single epoch.
+    max_operations_per_epoch: u64,
+
+    /// The epoch that the counters below are for.
+    epoch: Epoch,
+
+    /// Fees paid in `epoch`.
+    fees_spent_in_epoch: Lamports,
+
+    /// Number of operations performed in `epoch`, per kind of operation.
+    operations_in_epoch: BTreeMap<String, u64>,
+
+    /// Fees paid since launch.
+    fees_spent_total: Lamports,
+}
+
+impl FeeBudget {
+    pub fn new(max_fees_per_epoch: Lamports, max_operations_per_epoch: u64) -> FeeBudget {
+        FeeBudget {
+            max_fees_per_epoch,
+            max_operations_per_epoch,
+            epoch: 0,
+            fees_spent_in_epoch: Lamports(0),
+            operations_in_epoch: BTreeMap::new(),
+            fees_spent_total: Lamports(0),
+        }
+    }
+
+    /// Start a new budget if the epoch changed.
+    fn observe_epoch(&mut self, epoch: Epoch) {
+        if epoch > self.epoch {
+            self.epoch = epoch;
+            self.fees_spent_in_epoch = Lamports(0);
+            self.operations_in_epoch.clear();
+        }
+    }
+
+    /// Return how much of the fee budget is left for the current epoch.
+    pub fn remaining_fees(&self) -> Lamports {
+        Lamports(
+            self.max_fees_per_epoch
+                .0
+                .saturating_sub(self.fees_spent_in_epoch.0),
+        )
+    }
+
+    /// Return how many operations of the given kind we can still perform in
+    /// the current epoch.
+    pub fn remaining_operations(&self, kind: &str) -> u64 {
+        let performed = self.operations_in_epoch.get(kind).cloned().unwrap_or(0);
+        self.max_operations_per_epoch.saturating_sub(performed)
+    }
+
+    /// Return a description of the exceeded limit if sending a transaction in
+    /// `epoch` that pays `fee` and performs `operations` would exceed the budget.
+    pub fn check(
+        &mut self,
+        epoch: Epoch,
+        fee: Lamports,
+        operations: &[MaintenanceOperation],
+    ) -> Result<(), String> {
+        self.observe_epoch(epoch);
+
+        if fee > self.remaining_fees() {
+            return Err(format!(
+                "Refusing to send a transaction with a fee of {}, only {} of the \
+                fee budget of {} for epoch {} is left.",
+                fee,
+                self.remaining_fees(),
+                self.max_fees_per_epoch,
+                epoch,
+            ));
+        }
+
+        let mut counts = BTreeMap::new();
+        for operation in operations {
+            *counts.entry(operation.kind()).or_insert(0_u64) += 1;
+        }
+        for (kind, count) in counts {
+            if count > self.remaining_operations(kind) {
+                return Err(format!(
+                    "Refusing to perform {} more times, we already performed it {} \
+                    times in epoch {}, the limit is {}.",
+                    kind,
+                    self.max_operations_per_epoch - self.remaining_operations(kind),
+                    epoch,
+                    self.max_operations_per_epoch,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record that we paid `fee` for a transaction that performs `operations`.
+    ///
+    /// Call this after [`FeeBudget::check`], which selects the epoch.
+    pub fn record(&mut self, fee: Lamports, operations: &[MaintenanceOperation]) {
+        self.fees_spent_total = (self.fees_spent_total + fee)
+            .expect("Does not overflow, we cannot spend more than u64::MAX Lamports.");
+        self.record_in_epoch(fee, operations.iter().map(|op| op.kind()));
+    }
+
+    fn record_in_epoch<'a, I: IntoIterator<Item = &'a str>>(&mut self, fee: Lamports, kinds: I) {
+        self.fees_spent_in_epoch = (self.fees_spent_in_epoch + fee)
+            .expect("Does not overflow, we cannot spend more than u64::MAX Lamports.");
+        for kind in kinds {
+            *self
+                .operations_in_epoch
+                .entry(kind.to_string())
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Restore the spending in the current epoch from the maintenance journal.
+    ///
+    /// These fees were paid before launch, so they do not count towards the
+    /// total since launch.
+    pub fn restore(&mut self, fees_paid: &[FeePaid]) {
+        for fee_paid in fees_paid {
+            self.observe_epoch(fee_paid.epoch);
+            if fee_paid.epoch == self.epoch {
+                self.record_in_epoch(
+                    fee_paid.fee,
+                    fee_paid.operations.iter().map(|kind| &kind[..]),
+                );
+            }
+        }
+    }
+
+    /// Serialize the budget metrics in Prometheus text format.
+    pub fn write_prometheus<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_maintainer_fees_spent_lamports",
+                help:
+                    "Amount of Lamports that the maintainer paid in transaction fees, since launch.",
+                type_: "counter",
+                metrics: vec![Metric::new(self.fees_spent_total.0)],
+            },
+        )?;
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_maintainer_fee_budget_remaining_lamports",
+                help: "Amount of Lamports that the maintainer can still spend on fees in the current epoch.",
+                type_: "gauge",
+                metrics: vec![Metric::new(self.remaining_fees().0)],
+            },
+        )?;
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_maintainer_operation_budget_remaining",
+                help: "Number of operations that the maintainer can still perform in the current epoch.",
+                type_: "gauge",
+                metrics: MaintenanceOperation::KINDS
+                    .iter()
+                    .map(|kind| {
+                        Metric::new(self.remaining_operations(kind))
+                            .with_label("operation", kind.to_string())
+                    })
+                    .collect(),
+            },
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fee_budget_refuses_to_exceed_limits_and_resets_every_epoch() {
+        let mut budget = FeeBudget::new(Lamports(10_000), 2);
+        let fee = Lamports(5_000);
+        let operations = [MaintenanceOperation::UpdateExchangeRate];
+
+        budget.check(1, fee, &operations).ok().unwrap();
+        budget.record(fee, &operations);
+        assert_eq!(budget.remaining_fees(), Lamports(5_000));
+        assert_eq!(budget.remaining_operations("UpdateExchangeRate"), 1);
+
+        // Two of the same operation in one transaction exceed the operation limit.
+        assert!(budget
+            .check(1, fee, &[operations[0].clone(), operations[0].clone()])
+            .is_err());
+
+        budget.check(1, fee, &operations).ok().unwrap();
+        budget.record(fee, &operations);
+
+        // Now the fee budget is exhausted.
+        assert_eq!(budget.remaining_fees(), Lamports(0));
+        assert!(budget.check(1, fee, &[]).is_err());
+
+        // In the next epoch, we have a new budget, but the total stays.
+        budget.check(2, fee, &operations).ok().unwrap();
+        assert_eq!(budget.remaining_fees(), Lamports(10_000));
+        assert_eq!(budget.remaining_operations("UpdateExchangeRate"), 2);
+        assert_eq!(budget.fees_spent_total, Lamports(10_000));
+    }
+
+    #[test]
+    fn fee_budget_restores_spending_in_the_most_recent_epoch() {
+        let fee_paid = |epoch, fee| FeePaid {
+            epoch,
+            fee: Lamports(fee),
+            operations: vec!["UpdateExchangeRate".to_string()],
+        };
+        let mut budget = FeeBudget::new(Lamports(10_000), 2);
+        budget.restore(&[fee_paid(1, 5_000), fee_paid(2, 3_000), fee_paid(2, 4_000)]);
+
+        budget.check(2, Lamports(0), &[]).ok().unwrap();
+        assert_eq!(budget.remaining_fees(), Lamports(3_000));
+        assert_eq!(budget.remaining_operations("UpdateExchangeRate"), 0);
+        assert_eq!(budget.fees_spent_total, Lamports(0));
+    }
+}
@@ -9,10 +9,13 @@ use std::{
 use clap::Clap;
 use serde::Deserialize;
 use serde_json::Value;
+use solana_sdk::clock::Epoch;
 use solana_sdk::pubkey::{ParsePubkeyError, Pubkey};
 
 use lido::token::Lamports;
+use lido::token::Rational;
 use lido::token::StLamports;
+use lido::MINIMUM_STAKE_ACCOUNT_BALANCE;
 
 pub fn get_option_from_config<T: FromStr>(
     name: &'static str,
@@ -20,17 +23,32 @@ pub fn get_option_from_config<T: FromStr>(
 ) -> Option<T> {
     let config_file = config_file?;
     let value = config_file.values.get(name)?;
-    if let Value::String(str_value) = value {
-        match T::from_str(str_value) {
-            Err(_) => {
-                eprintln!("Could not convert {} from string.", str_value);
-                std::process::exit(1);
+    let str_value = match value {
+        Value::String(str_value) => str_value.clone(),
+        // An array of strings is equivalent to a comma-separated string, for
+        // options that hold a list, such as `UrlVec`.
+        Value::Array(elements) => {
+            let mut str_elements = Vec::with_capacity(elements.len());
+            for element in elements {
+                match element {
+                    Value::String(str_element) => str_elements.push(str_element.as_str()),
+                    _ => {
+                        eprintln!("Expected only strings in the array for {}.", name);
+                        std::process::exit(1);
+                    }
+                }
             }
-            Ok(t) => Some(t),
+            str_elements.join(",")
         }
-    } else {
         // TODO: Support numbers
-        None
+        _ => return None,
+    };
+    match T::from_str(&str_value) {
+        Err(_) => {
+            eprintln!("Could not convert {} from string.", str_value);
+            std::process::exit(1);
+        }
+        Ok(t) => Some(t),
     }
 }
 
@@ -161,6 +179,24 @@ macro_rules! cli_opt_struct {
     }
 }
 
+/// Type to represent a list of URLs, separated by ','.
+#[derive(Debug, Clone, Default)]
+pub struct UrlVec(pub Vec<String>);
+
+impl FromStr for UrlVec {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let urls = s
+            .split(',')
+            .map(|url| url.trim())
+            .filter(|url| !url.is_empty())
+            .map(|url| url.to_string())
+            .collect();
+        Ok(UrlVec(urls))
+    }
+}
+
 /// Type to represent a vector of `Pubkey`.
 // TODO(#218) Accept an array in the json config file.
 #[derive(Debug, Clone)]
@@ -237,9 +273,11 @@ cli_opt_struct! {
         #[clap(long)]
         keypair_path: PathBuf => get_default_keypair_path(),
 
-        /// URL of cluster to connect to (e.g., https://api.devnet.solana.com for solana devnet)
-        #[clap(long)]
-        cluster: String => "http://127.0.0.1:8899".to_owned(),
+        /// URL of cluster to connect to (e.g., https://api.devnet.solana.com for solana devnet).
+        /// Multiple RPC endpoints can be given as a comma-separated list, in
+        /// order of preference, to fail over when one is unhealthy.
+        #[clap(long, value_name = "urls")]
+        cluster: UrlVec => UrlVec(vec!["http://127.0.0.1:8899".to_owned()]),
 
         /// Whether to output text or json.
         #[clap(long = "output", possible_values = &["text", "json"])]
@@ -340,6 +378,77 @@ cli_opt_struct! {
          /// Amount to withdraw in stSOL, using . as decimal separator.
          #[clap(long, value_name = "st_sol")]
          amount_st_sol: StLamports,
+
+         /// Show the plan and ask for confirmation before a withdrawal that
+         /// needs more than one transaction.
+         #[clap(long, value_name = "bool")]
+         confirm: bool => false,
+
+         /// File that records the progress of the withdrawal. If the file
+         /// exists, resume the withdrawal that it records, instead of planning
+         /// a new one. The file is removed when the withdrawal completes.
+         /// Defaults to a file in ~/.config/solido named after the Solido
+         /// instance and the signer, which owns the stSOL account.
+         #[clap(long, value_name = "path")]
+         progress_path: String => String::new(),
+    }
+}
+
+cli_opt_struct! {
+    StakeAccountsOpts {
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    DeactivateStakeOpts {
+        /// Stake account to deactivate. Its stake authority must be the signer.
+        #[clap(long, value_name = "address")]
+        stake_account: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    WithdrawStakeOpts {
+        /// Stake account to withdraw the inactive SOL from. Its withdraw
+        /// authority must be the signer.
+        #[clap(long, value_name = "address")]
+        stake_account: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    MergeStakeOpts {
+        /// Stake account to merge into the destination. It ceases to exist
+        /// after the merge.
+        #[clap(long, value_name = "address")]
+        source_stake_account: Pubkey,
+
+        /// Stake account that receives the stake of the source account.
+        #[clap(long, value_name = "address")]
+        destination_stake_account: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    HistoryOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Only show transactions in this slot or later.
+        #[clap(long, value_name = "slot")]
+        from_slot: u64 => 0,
+
+        /// Only show transactions in this epoch, or "all" to not restrict by epoch.
+        #[clap(long, value_name = "epoch")]
+        epoch: EpochFilter => EpochFilter::All,
     }
 }
 
@@ -394,6 +503,124 @@ cli_opt_struct! {
     }
 }
 
+cli_opt_struct! {
+    RemoveValidatorOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Vote account of the validator to remove.
+        #[clap(long, value_name = "address")]
+        validator_vote_account: Pubkey,
+
+        /// Multisig instance.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long, value_name = "address")]
+        multisig_program_id: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    ClaimValidatorFeeOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Vote account of the validator whose fee to claim.
+        #[clap(long, value_name = "address")]
+        validator_vote_account: Pubkey,
+
+        /// Multisig instance.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long, value_name = "address")]
+        multisig_program_id: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    MergeValidatorStakeOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Vote account of the validator whose stake accounts to merge.
+        #[clap(long, value_name = "address")]
+        validator_vote_account: Pubkey,
+
+        /// Multisig instance.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long, value_name = "address")]
+        multisig_program_id: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    ChangeRewardDistributionOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        // See also the docs section of `create-solido` in main.rs for a description
+        // of the fee shares.
+        /// Treasury fee share of the rewards.
+        #[clap(long, value_name = "int")]
+        treasury_fee_share: u32,
+
+        /// Validation fee share of the rewards.
+        #[clap(long, value_name = "int")]
+        validation_fee_share: u32,
+
+        /// Developer fee share of the rewards.
+        #[clap(long, value_name = "int")]
+        developer_fee_share: u32,
+
+        /// Share of the rewards that goes to stSOL appreciation (the non-fee part).
+        #[clap(long, value_name = "int")]
+        st_sol_appreciation_share: u32,
+
+        /// stSOL SPL token account that receives the treasury fees.
+        #[clap(long, value_name = "address")]
+        treasury_account: Pubkey,
+
+        /// stSOL SPL token account that receives the developer fees.
+        #[clap(long, value_name = "address")]
+        developer_account: Pubkey,
+
+        /// Multisig instance.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long, value_name = "address")]
+        multisig_program_id: Pubkey,
+    }
+}
+
 cli_opt_struct! {
     AddRemoveMaintainerOpts {
         /// Address of the Solido program.
@@ -417,6 +644,25 @@ cli_opt_struct! {
     }
 }
 
+cli_opt_struct! {
+    MigrateStateOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Multisig instance.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long)]
+        multisig_program_id: Pubkey,
+    }
+}
+
 cli_opt_struct! {
      ShowSolidoOpts {
         /// The solido instance to show.
@@ -461,6 +707,27 @@ cli_opt_struct! {
         /// `StakeTime::OnlyNearEpochEnd`.
         #[clap(long, value_name = "anytime/only-near-epoch-end")]
         stake_time: StakeTime => StakeTime::OnlyNearEpochEnd,
+
+        /// Only simulate maintenance transactions instead of sending them, and
+        /// act as the maintainer on duty, so no maintainer key is needed.
+        #[clap(long, value_name = "bool")]
+        dry_run: bool => false,
+    }
+}
+
+cli_opt_struct! {
+    PlanRebalanceOpts {
+        /// Address of the Solido program.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Account that stores the data for this Solido instance.
+        #[clap(long, value_name = "address")]
+        solido_address: Pubkey,
+
+        /// Fee per signature in Lamports, used to estimate the transaction fees.
+        #[clap(long, value_name = "lamports")]
+        lamports_per_signature: u64 => 5000,
     }
 }
 
@@ -548,6 +815,43 @@ cli_opt_struct! {
         /// Address of the Multisig program.
         #[clap(long)]
         multisig_program_id: Pubkey,
+
+        /// If the transaction upgrades a program, compare the program in the
+        /// buffer against this local build, for example target/deploy/lido.so.
+        #[clap(long, value_name = "path")]
+        verify_against: PathBuf => PathBuf::default(),
+    }
+}
+
+cli_opt_struct! {
+    ListTransactionsOpts {
+        /// The multisig whose transactions to list.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// Address of the Solido program, to decode Solido instructions.
+        #[clap(long, value_name = "address")]
+        solido_program_id: Pubkey,
+
+        /// Address of the Multisig program.
+        #[clap(long)]
+        multisig_program_id: Pubkey,
+
+        /// Also list transactions that were already executed.
+        #[clap(long, value_name = "bool")]
+        include_executed: bool => false,
+    }
+}
+
+cli_opt_struct! {
+    VerifyProgramOpts {
+        /// Address of the deployed program to verify.
+        #[clap(long, value_name = "address")]
+        program_id: Pubkey,
+
+        /// The local build to compare against, for example target/deploy/lido.so.
+        #[clap(long, value_name = "path")]
+        verify_against: PathBuf,
     }
 }
 
@@ -602,6 +906,63 @@ cli_opt_struct! {
         /// Address of the Solido program.
         #[clap(long)]
         solido_program_id: Pubkey,
+
+        /// Compare the buffers of upgrade transactions against this local
+        /// build, for example target/deploy/lido.so.
+        #[clap(long, value_name = "path")]
+        verify_against: PathBuf => PathBuf::default(),
+    }
+}
+
+cli_opt_struct! {
+    ExportApprovalOpts {
+        /// The multisig account whose owners should vote for this proposal.
+        #[clap(long, value_name = "address")]
+        multisig_address: Pubkey,
+
+        /// The transaction to approve.
+        #[clap(long, value_name = "address")]
+        transaction_address: Pubkey,
+
+        /// The owner who will sign the approval offline. This owner also pays
+        /// the transaction fee.
+        #[clap(long, value_name = "address")]
+        owner: Pubkey,
+
+        /// Durable nonce account to use instead of a recent blockhash. Without
+        /// a nonce, the approval has to be submitted within about a minute.
+        /// The owner must be the nonce authority.
+        #[clap(long, value_name = "address")]
+        nonce_account: Pubkey => Pubkey::default(),
+
+        /// File to write the approval request to.
+        #[clap(long, value_name = "path")]
+        approval_path: PathBuf,
+
+        /// Address of the Multisig program.
+        #[clap(long)]
+        multisig_program_id: Pubkey,
+
+        /// Address of the Solido program, to decode Solido instructions.
+        #[clap(long)]
+        solido_program_id: Pubkey,
+    }
+}
+
+cli_opt_struct! {
+    SignOfflineOpts {
+        /// Approval request created with 'export-approval'. The signature is
+        /// added to this file.
+        #[clap(long, value_name = "path")]
+        approval_path: PathBuf,
+    }
+}
+
+cli_opt_struct! {
+    SubmitApprovalOpts {
+        /// Approval request that was signed with 'sign-offline'.
+        #[clap(long, value_name = "path")]
+        approval_path: PathBuf,
     }
 }
 
@@ -645,6 +1006,179 @@ impl FromStr for StakeTime {
     }
 }
 
+/// A fraction between 0 and 1, written as "numerator/denominator".
+#[derive(Copy, Clone, Debug)]
+pub struct Fraction(pub Rational);
+
+impl FromStr for Fraction {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Fraction, &'static str> {
+        let err = "Invalid fraction, expected 'numerator/denominator'.";
+        let (numerator, denominator) = s.split_once('/').ok_or(err)?;
+        let fraction = Rational {
+            numerator: numerator.trim().parse().map_err(|_| err)?,
+            denominator: denominator.trim().parse().map_err(|_| err)?,
+        };
+        if fraction.denominator == 0 || fraction.numerator > fraction.denominator {
+            return Err("Invalid fraction, it must be between 0 and 1.");
+        }
+        Ok(Fraction(fraction))
+    }
+}
+
+/// How the maintainer balances stake across validators.
+///
+/// These are set in the "rebalancing" section of the config file, where every
+/// key is optional, for example:
+///
+/// ```json
+/// "rebalancing": {
+///     "unstake_threshold": "1/10",
+///     "stake_after_epoch_fraction": "19/20",
+///     "max_unstake_per_epoch_sol": "1000.0",
+///     "reserve_buffer_sol": "10.0",
+///     "min_stake_deposit_sol": "1.0"
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RebalancingConfig {
+    /// Unstake when a validator is this fraction of its target below or above it.
+    pub unstake_threshold: Rational,
+
+    /// With `StakeTime::OnlyNearEpochEnd`, the fraction of the epoch that
+    /// must have passed before we stake or unstake.
+    pub stake_after_epoch_fraction: Rational,
+
+    /// The most we unstake in a single epoch, unlimited if not set.
+    pub max_unstake_per_epoch: Option<Lamports>,
+
+    /// Amount to keep in the reserve rather than staking it.
+    pub reserve_buffer: Lamports,
+
+    /// The least we stake in a single stake deposit.
+    pub min_stake_deposit: Lamports,
+}
+
+impl Default for RebalancingConfig {
+    fn default() -> RebalancingConfig {
+        RebalancingConfig {
+            unstake_threshold: Rational {
+                numerator: 1,
+                denominator: 10,
+            },
+            stake_after_epoch_fraction: Rational {
+                numerator: 19,
+                denominator: 20,
+            },
+            max_unstake_per_epoch: None,
+            reserve_buffer: Lamports(0),
+            min_stake_deposit: MINIMUM_STAKE_ACCOUNT_BALANCE,
+        }
+    }
+}
+
+impl RebalancingConfig {
+    const KEYS: [&'static str; 5] = [
+        "unstake_threshold",
+        "stake_after_epoch_fraction",
+        "max_unstake_per_epoch_sol",
+        "reserve_buffer_sol",
+        "min_stake_deposit_sol",
+    ];
+
+    /// Read the "rebalancing" section of the config file, if there is one.
+    ///
+    /// Exits if the section is invalid.
+    pub fn from_config_file_or_exit(config_file: Option<&ConfigFile>) -> RebalancingConfig {
+        let mut result = RebalancingConfig::default();
+        let section = match config_file.and_then(|f| f.values.get("rebalancing")) {
+            Some(section) => ConfigFile {
+                values: section.clone(),
+            },
+            None => return result,
+        };
+        match section.values.as_object() {
+            Some(values) => {
+                if let Some(key) = values.keys().find(|k| !Self::KEYS.contains(&k.as_str())) {
+                    eprintln!("Unknown key \"{}\" in the rebalancing config.", key);
+                    std::process::exit(1);
+                }
+            }
+            None => {
+                eprintln!("Expected the rebalancing config to be an object.");
+                std::process::exit(1);
+            }
+        }
+
+        let section = Some(&section);
+        if let Some(Fraction(threshold)) = get_option_from_config(Self::KEYS[0], section) {
+            result.unstake_threshold = threshold;
+        }
+        if let Some(Fraction(fraction)) = get_option_from_config(Self::KEYS[1], section) {
+            result.stake_after_epoch_fraction = fraction;
+        }
+        if let Some(amount) = get_option_from_config(Self::KEYS[2], section) {
+            result.max_unstake_per_epoch = Some(amount);
+        }
+        if let Some(amount) = get_option_from_config(Self::KEYS[3], section) {
+            result.reserve_buffer = amount;
+        }
+        if let Some(amount) = get_option_from_config(Self::KEYS[4], section) {
+            result.min_stake_deposit = amount;
+        }
+        result.validate_or_exit();
+        result
+    }
+
+    /// Reject settings that would make the maintainer send transactions that
+    /// the program rejects. Exits if validation fails.
+    pub fn validate_or_exit(&self) {
+        if self.min_stake_deposit < MINIMUM_STAKE_ACCOUNT_BALANCE {
+            eprintln!(
+                "The minimum stake deposit must be at least {}.",
+                MINIMUM_STAKE_ACCOUNT_BALANCE
+            );
+            std::process::exit(1);
+        }
+        match self.max_unstake_per_epoch {
+            Some(amount) if amount < MINIMUM_STAKE_ACCOUNT_BALANCE => {
+                eprintln!(
+                    "The maximum unstake per epoch must be at least {}.",
+                    MINIMUM_STAKE_ACCOUNT_BALANCE
+                );
+                std::process::exit(1);
+            }
+            _ => {}
+        }
+        if self.unstake_threshold.numerator == 0 {
+            eprintln!("The unstake threshold must be more than 0.");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Restrict a command to a single epoch, or not.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EpochFilter {
+    All,
+    Epoch(Epoch),
+}
+
+impl FromStr for EpochFilter {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<EpochFilter, &'static str> {
+        match s {
+            "all" => Ok(EpochFilter::All),
+            _ => match Epoch::from_str(s) {
+                Ok(epoch) => Ok(EpochFilter::Epoch(epoch)),
+                Err(_) => Err("Invalid epoch, expected a number or 'all'."),
+            },
+        }
+    }
+}
+
 cli_opt_struct! {
     RunMaintainerOpts {
         /// Address of the Solido program.
@@ -655,7 +1189,7 @@ cli_opt_struct! {
         #[clap(long)]
         solido_address: Pubkey,
 
-        /// Listen address and port for the http server that serves a /metrics endpoint. Defaults to 0.0.0.0:8923.
+        /// Listen address and port for the http server that serves /metrics and the /api endpoints. Defaults to 0.0.0.0:8923.
         #[clap(long)]
         listen: String => "0.0.0.0:8923".to_owned(),
 
@@ -666,12 +1200,82 @@ cli_opt_struct! {
         #[clap(long)]
         max_poll_interval_seconds: u64 => 30,
 
+        /// Websocket RPC url to subscribe to changes of the Solido accounts.
+        /// When set, the daemon reads the on-chain state again when a relevant
+        /// account changes or a new epoch starts, and the max poll interval only
+        /// bounds the time between reads, so it can be raised. When not set, the
+        /// daemon polls.
+        #[clap(long)]
+        websocket_url: String => String::new(),
+
+        /// File to record maintenance transactions in before sending them. When
+        /// sending fails in a way where the transaction may still execute, the
+        /// daemon waits for its outcome before performing more maintenance,
+        /// also after a restart. When not set, there is no journal.
+        #[clap(long, value_name = "path")]
+        journal_path: String => String::new(),
+
         /// Try to do stake and unstake operations any time if set to
         /// `StakeTime::Anytime`. If set to `StakeTime::OnlyNearEpochEnd`, will
         /// try to stake/unstake only at the end of the epoch. Defaults to
         /// `StakeTime::OnlyNearEpochEnd`.
         #[clap(long, value_name = "anytime/only-near-epoch-end")]
         stake_time: StakeTime => StakeTime::OnlyNearEpochEnd,
+
+        /// Only simulate maintenance transactions instead of sending them, and
+        /// act as the maintainer on duty, so no maintainer key is needed.
+        #[clap(long, value_name = "bool")]
+        dry_run: bool => false,
+
+        /// /healthz and /readyz fail when the last successful read of the
+        /// on-chain state is older than this. Defaults to 300s.
+        #[clap(long)]
+        max_snapshot_age_seconds: u64 => 300,
+
+        /// /readyz fails when the observed slot did not advance for this long.
+        /// Defaults to 120s.
+        #[clap(long)]
+        max_slot_stall_seconds: u64 => 120,
+
+        /// /healthz and /readyz fail after this many consecutive failed
+        /// maintenance iterations. Defaults to 10.
+        #[clap(long)]
+        max_consecutive_errors: u64 => 10,
+
+        /// Comma-separated list of URLs to POST a JSON alert to when something
+        /// needs attention. Alerting is disabled if none are set.
+        #[clap(long, value_name = "urls")]
+        alert_webhook_urls: UrlVec => UrlVec::default(),
+
+        /// Alert when this many maintenance iterations in a row failed.
+        /// Defaults to 3.
+        #[clap(long)]
+        alert_consecutive_errors: u64 => 3,
+
+        /// Alert when a maintainer's balance drops below this amount of SOL.
+        /// Defaults to 1 SOL.
+        #[clap(long, value_name = "sol")]
+        alert_maintainer_balance_sol: Lamports => Lamports(1_000_000_000),
+
+        /// Alert when an active validator did not vote for this many slots.
+        /// Defaults to 1000 slots.
+        #[clap(long)]
+        alert_vote_lag_slots: u64 => 1_000,
+
+        /// Alert when the exchange rate was not updated this many slots into
+        /// the epoch. Defaults to 1000 slots.
+        #[clap(long)]
+        alert_exchange_rate_lag_slots: u64 => 1_000,
+
+        /// Refuse to send maintenance transactions when the fees paid in the
+        /// current epoch would exceed this amount of SOL. Defaults to 0.1 SOL.
+        #[clap(long, value_name = "sol")]
+        max_fees_per_epoch_sol: Lamports => Lamports(100_000_000),
+
+        /// Refuse to perform a kind of maintenance operation more often than
+        /// this in a single epoch. Defaults to 1000.
+        #[clap(long)]
+        max_operations_per_epoch: u64 => 1_000,
     }
 }
 
@@ -708,3 +1312,45 @@ cli_opt_struct! {
         amount: u64
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rebalancing_config_overrides_only_the_given_keys() {
+        let config_file = ConfigFile {
+            values: serde_json::json!({
+                "rebalancing": {
+                    "unstake_threshold": "1/5",
+                    "reserve_buffer_sol": "2.5",
+                }
+            }),
+        };
+        let config = RebalancingConfig::from_config_file_or_exit(Some(&config_file));
+        let default = RebalancingConfig::default();
+        assert_eq!(
+            config.unstake_threshold,
+            Rational {
+                numerator: 1,
+                denominator: 5
+            }
+        );
+        assert_eq!(config.reserve_buffer, Lamports(2_500_000_000));
+        assert_eq!(
+            config.stake_after_epoch_fraction,
+            default.stake_after_epoch_fraction
+        );
+        assert_eq!(config.max_unstake_per_epoch, None);
+        assert_eq!(config.min_stake_deposit, default.min_stake_deposit);
+    }
+
+    #[test]
+    fn fraction_must_be_between_zero_and_one() {
+        assert!(Fraction::from_str("19/20").is_ok());
+        assert!(Fraction::from_str("0/1").is_ok());
+        assert!(Fraction::from_str("3/2").is_err());
+        assert!(Fraction::from_str("1/0").is_err());
+        assert!(Fraction::from_str("0.5").is_err());
+    }
+}
@@ -8,64 +8,132 @@
 //! executes maintenance tasks if needed. It also publishes a snapshot of its
 //! most recently seen Solido state in an `Arc` so the http threads can serve it
 //! without blocking the main loop.
+//!
+//! The http server serves Prometheus metrics at `/metrics`, and a JSON API:
+//!
+//!  * `/api/state`: the most recent Solido state snapshot.
+//!  * `/api/validators/<vote account>`: the state of a single validator.
+//!  * `/api/maintenance`: the most recent maintenance that we performed.
+//!
+//! Finally, it serves `/healthz` and `/readyz` for liveness and readiness probes.
+//! The outcome of their checks is also available as the `solido_daemon_check_ok`
+//! metric.
+//!
+//! Optionally, the main loop POSTs alerts to webhooks when it detects conditions
+//! that need attention, such as a low maintainer balance.
 
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
 use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
+use lido::token::Lamports;
+use lido::util::serialize_b58;
 use rand::{rngs::ThreadRng, Rng};
-use solana_sdk::clock::{Clock, Slot};
+use serde::Serialize;
+use solana_sdk::clock::{Clock, Epoch, Slot};
+use solana_sdk::pubkey::Pubkey;
 use tiny_http::{Header, Request, Response, Server};
 
-use crate::config::RunMaintainerOpts;
+use crate::api::{MaintenanceRecord, StateView, ValidatorView};
+use crate::budget::FeeBudget;
+use crate::config::{RebalancingConfig, RunMaintainerOpts};
+use crate::endpoint::{self, EndpointMetrics};
 use crate::error::{AsPrettyError, Error};
-use crate::maintenance::{try_perform_maintenance, MaintenanceOutput, SolidoState};
+use crate::journal::{JournalCounters, MaintenanceJournal, Outcome};
+use crate::maintenance::{
+    try_perform_maintenance, MaintenanceOperation, MaintenanceOutput, SolidoState,
+};
 use crate::prometheus::{write_metric, Metric, MetricFamily};
 use crate::snapshot::SnapshotError;
+use crate::subscription::{AccountWatcher, WakeReason};
 use crate::SnapshotClientConfig;
 
+/// Number of maintenance records to keep for the `/api/maintenance` endpoint.
+const MAX_RECENT_MAINTENANCE: usize = 100;
+
 /// Metrics counters that track how many maintenance operations we performed.
 #[derive(Clone)]
-struct MaintenanceMetrics {
+pub struct MaintenanceMetrics {
     /// Number of times that we checked if there was maintenance to perform.
     polls: u64,
 
     /// Number of times that we tried to perform maintenance, but encountered an error.
     errors: u64,
 
+    /// Whether we are running in dry-run mode. In that case the counters below
+    /// count what we would have performed, and we export them under different
+    /// names, so they cannot be confused with real maintenance.
+    dry_run: bool,
+
+    /// Number of transactions that failed in simulation, in dry-run mode.
+    simulation_failures: u64,
+
+    /// Number of maintenance transactions we executed. One transaction can
+    /// contain multiple operations.
+    transactions: u64,
+
     /// Number of times we performed `StakeDeposit`.
-    transactions_stake_deposit: u64,
+    operations_stake_deposit: u64,
 
     /// Number of times we performed `UpdateExchangeRate`.
-    transactions_update_exchange_rate: u64,
+    operations_update_exchange_rate: u64,
 
     /// Number of times we performed `WithdrawInactiveStake`.
-    transactions_withdraw_inactive_stake: u64,
+    operations_withdraw_inactive_stake: u64,
 
     /// Number of times we performed `CollectValidatorFee`
-    transactions_collect_validator_fee: u64,
+    operations_collect_validator_fee: u64,
 
     /// Number of times we performed a `MergeStake`.
-    transactions_merge_stake: u64,
+    operations_merge_stake: u64,
 
     /// Number of times we performed `ClaimValidatorFee`.
-    transactions_claim_validator_fee: u64,
-    // TODO(#96#issuecomment-859388866): Track how much the daemon spends on transaction fees,
-    // so we know how much SOL it costs to operate.
-    // spent_lamports_total: u64
+    operations_claim_validator_fee: u64,
     /// Number of times we performed `UnstakeFromInactiveValidator`.
-    transactions_unstake_from_inactive_validator: u64,
+    operations_unstake_from_inactive_validator: u64,
 
     /// Number of times we performed `RemoveValidator`.
-    transactions_remove_validator: u64,
+    operations_remove_validator: u64,
 
     /// Number of times we performed `Unstake` on an active validator for balancing purposes.
-    transactions_unstake_from_active_validator: u64,
+    operations_unstake_from_active_validator: u64,
+
+    /// Counters of transactions with an ambiguous outcome, if the journal is enabled.
+    journal: Option<JournalCounters>,
+
+    /// Fees spent and what is left of the budget, if we send transactions.
+    fee_budget: Option<FeeBudget>,
 }
 
 impl MaintenanceMetrics {
+    pub fn new(dry_run: bool) -> MaintenanceMetrics {
+        MaintenanceMetrics {
+            polls: 0,
+            errors: 0,
+            dry_run,
+            simulation_failures: 0,
+            transactions: 0,
+            operations_stake_deposit: 0,
+            operations_update_exchange_rate: 0,
+            operations_withdraw_inactive_stake: 0,
+            operations_collect_validator_fee: 0,
+            operations_merge_stake: 0,
+            operations_claim_validator_fee: 0,
+            operations_unstake_from_inactive_validator: 0,
+            operations_remove_validator: 0,
+            operations_unstake_from_active_validator: 0,
+            journal: None,
+            fee_budget: None,
+        }
+    }
+
     /// Serialize metrics in Prometheus text format.
     pub fn write_prometheus<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
         write_metric(
@@ -84,62 +152,133 @@ impl MaintenanceMetrics {
             type_: "counter",
             metrics: vec![Metric::new(self.errors)]
         })?;
+        let (transactions_name, operations_name) = if self.dry_run {
+            write_metric(out, &MetricFamily {
+                name: "solido_maintenance_dry_run_simulation_failures_total",
+                help: "Number of maintenance transactions that failed in simulation, since launch.",
+                type_: "counter",
+                metrics: vec![Metric::new(self.simulation_failures)],
+            })?;
+            (
+                "solido_maintenance_dry_run_transactions_total",
+                "solido_maintenance_dry_run_operations_total",
+            )
+        } else {
+            (
+                "solido_maintenance_transactions_total",
+                "solido_maintenance_operations_total",
+            )
+        };
+        write_metric(
+            out,
+            &MetricFamily {
+                name: transactions_name,
+                help: "Number of maintenance transactions executed (or that would have been, in a dry run), since launch.",
+                type_: "counter",
+                metrics: vec![Metric::new(self.transactions)],
+            },
+        )?;
         write_metric(
             out,
             &MetricFamily {
-                name: "solido_maintenance_transactions_total",
-                help: "Number of maintenance transactions executed, since launch.",
+                name: operations_name,
+                help: "Number of maintenance operations executed (or that would have been, in a dry run), since launch.",
                 type_: "counter",
                 metrics: vec![
-                    Metric::new(self.transactions_stake_deposit)
+                    Metric::new(self.operations_stake_deposit)
                         .with_label("operation", "StakeDeposit".to_string()),
-                    Metric::new(self.transactions_update_exchange_rate)
+                    Metric::new(self.operations_update_exchange_rate)
                         .with_label("operation", "UpdateExchangeRate".to_string()),
-                    Metric::new(self.transactions_withdraw_inactive_stake)
+                    Metric::new(self.operations_withdraw_inactive_stake)
                         .with_label("operation", "WithdrawInactiveStake".to_string()),
-                    Metric::new(self.transactions_collect_validator_fee)
+                    Metric::new(self.operations_collect_validator_fee)
                         .with_label("operation", "CollectValidatorFee".to_string()),
-                    Metric::new(self.transactions_merge_stake)
+                    Metric::new(self.operations_merge_stake)
                         .with_label("operation", "MergeStake".to_string()),
-                    Metric::new(self.transactions_claim_validator_fee)
+                    Metric::new(self.operations_claim_validator_fee)
                         .with_label("operation", "ClaimValidatorFee".to_string()),
-                    Metric::new(self.transactions_unstake_from_inactive_validator)
+                    Metric::new(self.operations_unstake_from_inactive_validator)
                         .with_label("operation", "UnstakeFromInactiveValidator".to_string()),
-                    Metric::new(self.transactions_remove_validator)
+                    Metric::new(self.operations_remove_validator)
                         .with_label("operation", "RemoveValidator".to_string()),
-                    Metric::new(self.transactions_unstake_from_active_validator)
+                    Metric::new(self.operations_unstake_from_active_validator)
                         .with_label("operation", "UnstakeFromActiveValidator".to_string()),
                 ],
             },
         )?;
+        if let Some(journal) = self.journal.as_ref() {
+            write_metric(out, &MetricFamily {
+                name: "solido_maintenance_ambiguous_transactions_total",
+                help: "Number of maintenance transactions where sending failed, but the transaction might have executed, since launch.",
+                type_: "counter",
+                metrics: vec![Metric::new(journal.ambiguous)],
+            })?;
+            write_metric(out, &MetricFamily {
+                name: "solido_maintenance_ambiguous_transactions_resolved_total",
+                help: "Number of maintenance transactions with an ambiguous outcome, for which we later confirmed the outcome, since launch.",
+                type_: "counter",
+                metrics: vec![
+                    Metric::new(journal.resolved_executed)
+                        .with_label("outcome", Outcome::Executed.to_string()),
+                    Metric::new(journal.resolved_failed)
+                        .with_label("outcome", Outcome::Failed.to_string()),
+                    Metric::new(journal.resolved_expired)
+                        .with_label("outcome", Outcome::Expired.to_string()),
+                ],
+            })?;
+        }
+        if let Some(fee_budget) = self.fee_budget.as_ref() {
+            fee_budget.write_prometheus(out)?;
+        }
         Ok(())
     }
 
-    /// Increment the counter for a maintenance operation.
+    /// Increment the counters for the transactions and operations performed.
+    ///
+    /// In a dry run, transactions that failed in simulation would not have
+    /// performed anything, so we only count them as failures. Transactions that
+    /// we failed to send are counted as errors by the caller.
     pub fn observe_maintenance(&mut self, maintenance_output: &MaintenanceOutput) {
-        match *maintenance_output {
-            MaintenanceOutput::StakeDeposit { .. } => {
-                self.transactions_stake_deposit += 1;
+        for transaction in &maintenance_output.transactions {
+            if transaction.simulation_failed() {
+                self.simulation_failures += 1;
+                continue;
             }
-            MaintenanceOutput::UpdateExchangeRate => {
-                self.transactions_update_exchange_rate += 1;
+            if transaction.send_failed() {
+                continue;
             }
-            MaintenanceOutput::WithdrawInactiveStake { .. } => {
-                self.transactions_withdraw_inactive_stake += 1;
+            self.transactions += 1;
+            for operation in &transaction.operations {
+                self.observe_operation(operation);
             }
-            MaintenanceOutput::CollectValidatorFee { .. } => {
-                self.transactions_collect_validator_fee += 1
+        }
+    }
+
+    /// Increment the counter for a maintenance operation.
+    fn observe_operation(&mut self, operation: &MaintenanceOperation) {
+        match *operation {
+            MaintenanceOperation::StakeDeposit { .. } => {
+                self.operations_stake_deposit += 1;
             }
-            MaintenanceOutput::MergeStake { .. } => self.transactions_merge_stake += 1,
-            MaintenanceOutput::ClaimValidatorFee { .. } => {
-                self.transactions_claim_validator_fee += 1
+            MaintenanceOperation::UpdateExchangeRate => {
+                self.operations_update_exchange_rate += 1;
             }
-            MaintenanceOutput::UnstakeFromInactiveValidator { .. } => {
-                self.transactions_unstake_from_inactive_validator += 1
+            MaintenanceOperation::WithdrawInactiveStake { .. } => {
+                self.operations_withdraw_inactive_stake += 1;
             }
-            MaintenanceOutput::RemoveValidator { .. } => self.transactions_remove_validator += 1,
-            MaintenanceOutput::UnstakeFromActiveValidator { .. } => {
-                self.transactions_unstake_from_active_validator += 1
+            MaintenanceOperation::CollectValidatorFee { .. } => {
+                self.operations_collect_validator_fee += 1
+            }
+            MaintenanceOperation::MergeStake { .. } => self.operations_merge_stake += 1,
+            MaintenanceOperation::ClaimValidatorFee { .. } => {
+                self.operations_claim_validator_fee += 1
+            }
+            MaintenanceOperation::UnstakeFromInactiveValidator { .. } => {
+                self.operations_unstake_from_inactive_validator += 1
+            }
+            MaintenanceOperation::RemoveValidator { .. } => self.operations_remove_validator += 1,
+            MaintenanceOperation::UnstakeFromActiveValidator { .. } => {
+                self.operations_unstake_from_active_validator += 1
             }
         }
     }
@@ -153,6 +292,479 @@ struct Snapshot {
     /// The current state of on-chain accounts, and the time at which we obtained
     /// that data.
     solido: Option<SolidoState>,
+
+    /// The most recent maintenance that we performed, oldest first.
+    recent_maintenance: VecDeque<MaintenanceRecord>,
+
+    /// The instant after we successfully queried the on-chain state for the last time.
+    last_read_success: Instant,
+
+    /// The instant at which we first observed the most recent slot, if any.
+    slot_advanced_at: Option<Instant>,
+
+    /// Number of maintenance iterations in a row that failed.
+    consecutive_errors: u64,
+
+    /// Metrics about the requests made to every RPC endpoint.
+    rpc_endpoints: Vec<EndpointMetrics>,
+}
+
+/// Thresholds for the `/healthz` and `/readyz` endpoints.
+#[derive(Clone)]
+struct HealthThresholds {
+    max_snapshot_age: Duration,
+    max_slot_stall: Duration,
+    max_consecutive_errors: u64,
+}
+
+impl HealthThresholds {
+    fn new(opts: &RunMaintainerOpts) -> HealthThresholds {
+        HealthThresholds {
+            max_snapshot_age: Duration::from_secs(*opts.max_snapshot_age_seconds()),
+            max_slot_stall: Duration::from_secs(*opts.max_slot_stall_seconds()),
+            max_consecutive_errors: *opts.max_consecutive_errors(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HealthCheck {
+    name: &'static str,
+    ok: bool,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct HealthReport {
+    ok: bool,
+    checks: Vec<HealthCheck>,
+}
+
+/// Check whether the daemon is healthy, and if `readiness` is set, also whether
+/// it is ready to perform maintenance.
+///
+/// Health only covers whether our main loop makes progress, restarting the
+/// daemon might help if it does not. Readiness also covers conditions outside
+/// of the daemon, where a restart does not help.
+fn check_health(
+    snapshot: &Snapshot,
+    thresholds: &HealthThresholds,
+    now: Instant,
+    readiness: bool,
+) -> HealthReport {
+    let mut checks = Vec::new();
+
+    let snapshot_age = now.saturating_duration_since(snapshot.last_read_success);
+    checks.push(HealthCheck {
+        name: "snapshot_age",
+        ok: snapshot_age <= thresholds.max_snapshot_age,
+        message: format!(
+            "Last successful read of the on-chain state was {:.1}s ago.",
+            snapshot_age.as_secs_f32()
+        ),
+    });
+
+    checks.push(HealthCheck {
+        name: "consecutive_errors",
+        ok: snapshot.consecutive_errors < thresholds.max_consecutive_errors,
+        message: format!(
+            "{} maintenance iterations in a row failed.",
+            snapshot.consecutive_errors
+        ),
+    });
+
+    if readiness {
+        checks.push(match snapshot.slot_advanced_at {
+            Some(t) => {
+                let stall = now.saturating_duration_since(t);
+                HealthCheck {
+                    name: "slot_advancing",
+                    ok: stall <= thresholds.max_slot_stall,
+                    message: format!("Slot last advanced {:.1}s ago.", stall.as_secs_f32()),
+                }
+            }
+            None => HealthCheck {
+                name: "slot_advancing",
+                ok: false,
+                message: "No slot observed yet.".to_string(),
+            },
+        });
+
+        checks.push(match snapshot.solido.as_ref() {
+            Some(solido) => match solido.get_maintainer_balance() {
+                Some(balance) => HealthCheck {
+                    name: "maintainer_balance",
+                    ok: balance >= SolidoState::MINIMUM_MAINTAINER_BALANCE,
+                    message: format!(
+                        "Maintainer balance is {}, the minimum is {}.",
+                        balance,
+                        SolidoState::MINIMUM_MAINTAINER_BALANCE
+                    ),
+                },
+                None => HealthCheck {
+                    name: "maintainer_balance",
+                    ok: true,
+                    message: "Not a maintainer, no balance needed.".to_string(),
+                },
+            },
+            None => HealthCheck {
+                name: "maintainer_balance",
+                ok: false,
+                message: "The most recent read of the on-chain state failed.".to_string(),
+            },
+        });
+    }
+
+    HealthReport {
+        ok: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+/// Thresholds for raising alerts, see [`detect_alerts`].
+struct AlertConfig {
+    consecutive_errors: u64,
+    maintainer_balance: Lamports,
+    vote_lag_slots: Slot,
+    exchange_rate_lag_slots: Slot,
+}
+
+impl AlertConfig {
+    fn new(opts: &RunMaintainerOpts) -> AlertConfig {
+        AlertConfig {
+            consecutive_errors: *opts.alert_consecutive_errors(),
+            maintainer_balance: *opts.alert_maintainer_balance_sol(),
+            vote_lag_slots: *opts.alert_vote_lag_slots(),
+            exchange_rate_lag_slots: *opts.alert_exchange_rate_lag_slots(),
+        }
+    }
+}
+
+/// A condition that needs the attention of an operator.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type")]
+enum AlertEvent {
+    MaintenanceError,
+
+    MaintainerBalanceLow {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+        balance_lamports: Lamports,
+    },
+
+    /// The maintainer that we act as can no longer pay for maintenance.
+    MaintainerBalanceInsufficient {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+        balance_lamports: Lamports,
+        minimum_lamports: Lamports,
+    },
+
+    ValidatorNotVoting {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+        last_voted_slot: Slot,
+    },
+
+    ExchangeRateNotUpdated {
+        epoch: Epoch,
+        slots_into_epoch: Slot,
+    },
+
+    ValidatorRemovable {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+}
+
+impl AlertEvent {
+    /// Return the condition that this event is an instance of.
+    ///
+    /// While a condition holds, the details of the event may change (e.g. the
+    /// balance keeps dropping), but we only alert once per condition.
+    fn condition(&self) -> (&'static str, Option<Pubkey>) {
+        match self {
+            AlertEvent::MaintenanceError => ("MaintenanceError", None),
+            AlertEvent::MaintainerBalanceLow { maintainer, .. } => {
+                ("MaintainerBalanceLow", Some(*maintainer))
+            }
+            AlertEvent::MaintainerBalanceInsufficient { maintainer, .. } => {
+                ("MaintainerBalanceInsufficient", Some(*maintainer))
+            }
+            AlertEvent::ValidatorNotVoting {
+                validator_vote_account,
+                ..
+            } => ("ValidatorNotVoting", Some(*validator_vote_account)),
+            AlertEvent::ExchangeRateNotUpdated { .. } => ("ExchangeRateNotUpdated", None),
+            AlertEvent::ValidatorRemovable {
+                validator_vote_account,
+            } => ("ValidatorRemovable", Some(*validator_vote_account)),
+        }
+    }
+}
+
+impl fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertEvent::MaintenanceError => write!(
+                f,
+                "Maintenance failed, see the maintainer logs for details."
+            ),
+            AlertEvent::MaintainerBalanceLow {
+                maintainer,
+                balance_lamports,
+            } => write!(
+                f,
+                "Balance of maintainer {} is low: {}.",
+                maintainer, balance_lamports
+            ),
+            AlertEvent::MaintainerBalanceInsufficient {
+                maintainer,
+                balance_lamports,
+                minimum_lamports,
+            } => write!(
+                f,
+                "Balance of maintainer {} is {}, we need at least {} to perform maintenance.",
+                maintainer, balance_lamports, minimum_lamports
+            ),
+            AlertEvent::ValidatorNotVoting {
+                validator_vote_account,
+                last_voted_slot,
+            } => write!(
+                f,
+                "Validator {} has not voted since slot {}.",
+                validator_vote_account, last_voted_slot
+            ),
+            AlertEvent::ExchangeRateNotUpdated {
+                epoch,
+                slots_into_epoch,
+            } => write!(
+                f,
+                "The exchange rate was not updated for epoch {}, {} slots into the epoch.",
+                epoch, slots_into_epoch
+            ),
+            AlertEvent::ValidatorRemovable {
+                validator_vote_account,
+            } => write!(f, "Validator {} can be removed.", validator_vote_account),
+        }
+    }
+}
+
+/// Return all alert conditions that currently hold.
+///
+/// The conditions that we derive from the on-chain state are those of the most
+/// recent state that we managed to read, see [`detect_state_alerts`].
+fn detect_alerts(
+    state_events: &[AlertEvent],
+    consecutive_errors: u64,
+    config: &AlertConfig,
+) -> Vec<AlertEvent> {
+    let mut events = Vec::new();
+
+    // A single failed iteration is usually a transient problem that resolves
+    // in the next iteration, only alert when it persists.
+    if consecutive_errors > 0 && consecutive_errors >= config.consecutive_errors {
+        events.push(AlertEvent::MaintenanceError);
+    }
+
+    events.extend(state_events.iter().cloned());
+    events
+}
+
+/// Return the alert conditions that hold in the given state.
+fn detect_state_alerts(state: &SolidoState, config: &AlertConfig) -> Vec<AlertEvent> {
+    let mut events = Vec::new();
+
+    for (maintainer, balance) in state
+        .solido
+        .maintainers
+        .entries
+        .iter()
+        .zip(&state.maintainer_balances)
+    {
+        if *balance < config.maintainer_balance {
+            events.push(AlertEvent::MaintainerBalanceLow {
+                maintainer: maintainer.pubkey,
+                balance_lamports: *balance,
+            });
+        }
+    }
+
+    // This is the same check as the one that makes us refuse to perform
+    // maintenance, and that fails readiness.
+    if let Some(balance) = state.get_maintainer_balance() {
+        if balance < SolidoState::MINIMUM_MAINTAINER_BALANCE {
+            events.push(AlertEvent::MaintainerBalanceInsufficient {
+                maintainer: state.maintainer_address,
+                balance_lamports: balance,
+                minimum_lamports: SolidoState::MINIMUM_MAINTAINER_BALANCE,
+            });
+        }
+    }
+
+    for (validator, vote_account) in state
+        .validators
+        .entries
+        .iter()
+        .zip(&state.validator_vote_accounts)
+    {
+        let last_voted_slot = vote_account.last_timestamp.slot;
+        if validator.entry.active
+            && state.clock.slot.saturating_sub(last_voted_slot) > config.vote_lag_slots
+        {
+            events.push(AlertEvent::ValidatorNotVoting {
+                validator_vote_account: validator.pubkey,
+                last_voted_slot,
+            });
+        }
+        if validator.entry.check_can_be_removed().is_ok() {
+            events.push(AlertEvent::ValidatorRemovable {
+                validator_vote_account: validator.pubkey,
+            });
+        }
+    }
+
+    let epoch_start = state
+        .epoch_schedule
+        .get_first_slot_in_epoch(state.clock.epoch);
+    let slots_into_epoch = state.clock.slot.saturating_sub(epoch_start);
+    if state.solido.exchange_rate.computed_in_epoch < state.clock.epoch
+        && slots_into_epoch > config.exchange_rate_lag_slots
+    {
+        events.push(AlertEvent::ExchangeRateNotUpdated {
+            epoch: state.clock.epoch,
+            slots_into_epoch,
+        });
+    }
+
+    events
+}
+
+/// Tracks which alert conditions hold, so we alert only when a condition starts to hold.
+#[derive(Default)]
+struct AlertTracker {
+    active: HashSet<(&'static str, Option<Pubkey>)>,
+}
+
+impl AlertTracker {
+    /// Record the conditions that currently hold, return the events for the
+    /// conditions that did not hold before.
+    fn update(&mut self, events: Vec<AlertEvent>) -> Vec<AlertEvent> {
+        let previous = std::mem::take(&mut self.active);
+        let mut new_events = Vec::new();
+        for event in events {
+            let condition = event.condition();
+            if !previous.contains(&condition) {
+                new_events.push(event);
+            }
+            self.active.insert(condition);
+        }
+        new_events
+    }
+}
+
+/// The body that we POST to alert webhooks.
+#[derive(Serialize)]
+struct AlertPayload<'a> {
+    /// Human-readable description of the event. The name of this field is what
+    /// Slack-compatible webhooks expect.
+    text: String,
+
+    #[serde(serialize_with = "serialize_b58")]
+    solido_address: Pubkey,
+
+    /// The slot of the most recent state, if we have one.
+    slot: Option<Slot>,
+
+    event: &'a AlertEvent,
+}
+
+/// POST the body to the webhook, fail if the webhook does not accept it.
+fn post_alert(client: &reqwest::blocking::Client, url: &str, body: Vec<u8>) -> reqwest::Result<()> {
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sends alerts to webhooks from a background thread, so a slow webhook does
+/// not block the main loop.
+struct AlertSink {
+    config: AlertConfig,
+    tracker: AlertTracker,
+    solido_address: Pubkey,
+    sender: mpsc::Sender<Vec<u8>>,
+
+    /// The conditions that held in the most recent state that we read, and its slot.
+    ///
+    /// When we fail to read the state, we can't tell whether these conditions
+    /// still hold, so we assume that they do, rather than resolving them and
+    /// alerting again once we can read the state again.
+    state_events: Vec<AlertEvent>,
+    slot: Option<Slot>,
+}
+
+impl AlertSink {
+    /// Start the alert sink, if any webhooks are configured.
+    fn start(opts: &RunMaintainerOpts) -> Option<AlertSink> {
+        let urls = opts.alert_webhook_urls().0.clone();
+        if urls.is_empty() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        std::thread::Builder::new()
+            .name("alert_sink".to_string())
+            .spawn(move || {
+                let client = reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .expect("Failed to create http client for alerts.");
+                for body in receiver {
+                    for url in &urls {
+                        if let Err(err) = post_alert(&client, url, body.clone()) {
+                            println!("Failed to send alert to {}: {}", url, err);
+                        }
+                    }
+                }
+            })
+            .expect("Failed to spawn alert sink thread.");
+
+        Some(AlertSink {
+            config: AlertConfig::new(opts),
+            tracker: AlertTracker::default(),
+            solido_address: *opts.solido_address(),
+            sender,
+            state_events: Vec::new(),
+            slot: None,
+        })
+    }
+
+    /// Send alerts for the conditions that started to hold since the previous call.
+    ///
+    /// `state` is `None` if we failed to read the on-chain state.
+    fn observe(&mut self, state: Option<&SolidoState>, consecutive_errors: u64) {
+        if let Some(state) = state {
+            self.state_events = detect_state_alerts(state, &self.config);
+            self.slot = Some(state.clock.slot);
+        }
+        let events = detect_alerts(&self.state_events, consecutive_errors, &self.config);
+        for event in self.tracker.update(events) {
+            println!("Alert: {}", event);
+            let payload = AlertPayload {
+                text: event.to_string(),
+                solido_address: self.solido_address,
+                slot: self.slot,
+                event: &event,
+            };
+            let body = serde_json::to_vec(&payload).expect("Serializing an alert does not fail.");
+            // The thread only stops when we drop the sender, so sending does not fail.
+            let _ = self.sender.send(body);
+        }
+    }
 }
 
 enum MaintenanceResult {
@@ -173,23 +785,38 @@ enum MaintenanceResult {
 fn run_maintenance_iteration(
     config: &mut SnapshotClientConfig,
     opts: &RunMaintainerOpts,
+    rebalancing: &RebalancingConfig,
+    mut journal: Option<&mut MaintenanceJournal>,
+    mut fee_budget: Option<&mut FeeBudget>,
 ) -> MaintenanceResult {
     let result = config.with_snapshot(|mut config| {
-        let state = SolidoState::new(
+        let mut state = SolidoState::new(
             &mut config,
             opts.solido_program_id(),
             opts.solido_address(),
             *opts.stake_time(),
+            rebalancing,
         )?;
 
+        if *opts.dry_run() {
+            state.act_as_maintainer_on_duty();
+        }
+
         // If it's not our maintainer duty at this time, then don't try to
         // perform maintenance; a different maintainer should be doing it
         // right now.
-        if state.get_current_maintainer_duty() != Some(config.signer.pubkey()) {
+        if !state.is_on_duty(&state.maintainer_address) {
             return Ok(MaintenanceResult::OkIdle(state));
         }
 
-        match try_perform_maintenance(&mut config, &state) {
+        match try_perform_maintenance(
+            &mut config,
+            &state,
+            *opts.dry_run(),
+            state.is_standing_in(&state.maintainer_address),
+            journal.as_deref_mut(),
+            fee_budget.as_deref_mut(),
+        ) {
             Ok(None) => Ok(MaintenanceResult::OkIdle(state)),
             Ok(Some(output)) => Ok(MaintenanceResult::OkMaintenance(state, output)),
             Err(SnapshotError::MissingAccount) => Err(SnapshotError::MissingAccount),
@@ -213,6 +840,9 @@ fn run_maintenance_iteration(
 struct BlockTimeEstimator {
     /// Observed values of the slot in the clock sysvar, and the instant at which we observed them.
     observations: Vec<(Instant, Slot)>,
+
+    /// The instant at which we first observed the most recent slot.
+    slot_advanced_at: Option<Instant>,
 }
 
 impl BlockTimeEstimator {
@@ -221,16 +851,26 @@ impl BlockTimeEstimator {
     pub fn new() -> Self {
         Self {
             observations: Vec::with_capacity(Self::NUM_OBSERVATIONS),
+            slot_advanced_at: None,
         }
     }
 
     pub fn observe_clock(&mut self, at: Instant, clock: &Clock) {
+        match self.get_most_recent_slot() {
+            Some(slot) if slot >= clock.slot => {}
+            _ => self.slot_advanced_at = Some(at),
+        }
         if self.observations.len() == Self::NUM_OBSERVATIONS {
             self.observations.remove(0);
         }
         self.observations.push((at, clock.slot));
     }
 
+    /// Return the instant at which we first observed the most recent slot.
+    pub fn get_slot_advanced_at(&self) -> Option<Instant> {
+        self.slot_advanced_at
+    }
+
     pub fn get_most_recent_slot(&self) -> Option<Slot> {
         Some(self.observations.last()?.1)
     }
@@ -267,11 +907,27 @@ impl BlockTimeEstimator {
 /// to swap the Arc.
 type SnapshotMutex = Mutex<Option<Arc<Snapshot>>>;
 
-struct Daemon<'a, 'b> {
+/// When to run the next iteration of the main loop.
+#[derive(Debug, Eq, PartialEq)]
+pub enum NextIteration {
+    /// After an error, back off before we try again.
+    AfterError,
+
+    /// Sleep for the poll interval, or until our maintainer duty starts.
+    AfterPollInterval,
+
+    /// Right away, there may be more maintenance to perform.
+    Immediately,
+}
+
+pub struct Daemon<'a, 'b> {
     config: &'a mut SnapshotClientConfig<'b>,
 
     opts: &'a RunMaintainerOpts,
 
+    /// How to balance stake across validators.
+    rebalancing: &'a RebalancingConfig,
+
     /// Random number generator used for exponential backoff with jitter on errors.
     rng: ThreadRng,
 
@@ -286,32 +942,106 @@ struct Daemon<'a, 'b> {
 
     /// Mutex where we publish the latest snapshot for use by the webserver.
     snapshot_mutex: Arc<SnapshotMutex>,
+
+    /// The most recent maintenance that we performed, oldest first.
+    recent_maintenance: VecDeque<MaintenanceRecord>,
+
+    /// Number of maintenance iterations in a row that failed.
+    consecutive_errors: u64,
+
+    /// Where to send alerts to, if alerting is enabled.
+    alert_sink: Option<AlertSink>,
+
+    /// Subscription to changes of the Solido accounts, if a websocket url was set.
+    account_watcher: Option<AccountWatcher>,
+
+    /// Journal of the maintenance transactions we sent, if a journal path was set.
+    journal: Option<MaintenanceJournal>,
+
+    /// Limits on the fees we pay. In a dry run we pay no fees, so there is none.
+    fee_budget: Option<FeeBudget>,
+}
+
+/// Open the maintenance journal, if a path is configured. Exits on failure.
+///
+/// In a dry run we don't send transactions, so there is no need for a journal.
+fn open_journal(opts: &RunMaintainerOpts) -> Option<MaintenanceJournal> {
+    if opts.journal_path().is_empty() || *opts.dry_run() {
+        return None;
+    }
+    match MaintenanceJournal::open(Path::new(opts.journal_path())) {
+        Ok(journal) => {
+            println!(
+                "Opened maintenance journal {} with {} pending transactions.",
+                opts.journal_path(),
+                journal.pending().len(),
+            );
+            Some(journal)
+        }
+        Err(err) => {
+            err.print_pretty();
+            eprintln!(
+                "Failed to open the maintenance journal at {}.",
+                opts.journal_path()
+            );
+            std::process::exit(1);
+        }
+    }
 }
 
 impl<'a, 'b> Daemon<'a, 'b> {
-    pub fn new(config: &'a mut SnapshotClientConfig<'b>, opts: &'a RunMaintainerOpts) -> Self {
-        let metrics = MaintenanceMetrics {
-            polls: 0,
-            errors: 0,
-            transactions_stake_deposit: 0,
-            transactions_update_exchange_rate: 0,
-            transactions_withdraw_inactive_stake: 0,
-            transactions_collect_validator_fee: 0,
-            transactions_merge_stake: 0,
-            transactions_claim_validator_fee: 0,
-            transactions_unstake_from_inactive_validator: 0,
-            transactions_remove_validator: 0,
-            transactions_unstake_from_active_validator: 0,
+    pub fn new(
+        config: &'a mut SnapshotClientConfig<'b>,
+        opts: &'a RunMaintainerOpts,
+        rebalancing: &'a RebalancingConfig,
+    ) -> Self {
+        let metrics = MaintenanceMetrics::new(*opts.dry_run());
+        let journal = open_journal(opts);
+        let fee_budget = if *opts.dry_run() {
+            None
+        } else {
+            let mut fee_budget = FeeBudget::new(
+                *opts.max_fees_per_epoch_sol(),
+                *opts.max_operations_per_epoch(),
+            );
+            // Continue with the budget of the current epoch where we left off.
+            if let Some(journal) = journal.as_ref() {
+                fee_budget.restore(journal.fees_paid());
+            }
+            Some(fee_budget)
         };
         Daemon {
             config,
             opts,
+            rebalancing,
             rng: rand::thread_rng(),
             last_read_success: Instant::now(),
             block_time_estimator: BlockTimeEstimator::new(),
             metrics,
             snapshot_mutex: Arc::new(Mutex::new(None)),
+            recent_maintenance: VecDeque::with_capacity(MAX_RECENT_MAINTENANCE),
+            consecutive_errors: 0,
+            alert_sink: AlertSink::start(opts),
+            account_watcher: None,
+            journal,
+            fee_budget,
+        }
+    }
+
+    /// Raise alerts for conditions that started to hold, if alerting is enabled.
+    fn observe_alerts(&mut self, state: Option<&SolidoState>) {
+        if let Some(alert_sink) = self.alert_sink.as_mut() {
+            alert_sink.observe(state, self.consecutive_errors);
+        }
+    }
+
+    /// Remember the maintenance we performed, so we can serve it in the http API.
+    fn record_maintenance(&mut self, state: &SolidoState, output: MaintenanceOutput) {
+        if self.recent_maintenance.len() == MAX_RECENT_MAINTENANCE {
+            self.recent_maintenance.pop_front();
         }
+        self.recent_maintenance
+            .push_back(MaintenanceRecord::new(state, output));
     }
 
     /// Publish a new snapshot that from now on will be served by the http server.
@@ -325,9 +1055,17 @@ impl<'a, 'b> Daemon<'a, 'b> {
             self.block_time_estimator.observe_clock(now, &solido.clock);
         }
 
+        self.metrics.journal = self.journal.as_ref().map(|journal| journal.counters);
+        self.metrics.fee_budget = self.fee_budget.clone();
+
         let snapshot = Snapshot {
             metrics: self.metrics.clone(),
             solido,
+            recent_maintenance: self.recent_maintenance.clone(),
+            last_read_success: self.last_read_success,
+            slot_advanced_at: self.block_time_estimator.get_slot_advanced_at(),
+            consecutive_errors: self.consecutive_errors,
+            rpc_endpoints: self.config.client.get_endpoint_metrics(),
         };
         self.snapshot_mutex
             .lock()
@@ -367,10 +1105,21 @@ impl<'a, 'b> Daemon<'a, 'b> {
             .as_ref()
             .and_then(|snapshot| snapshot.solido.as_ref())
             .map(|solido| {
-                (
-                    solido.get_current_maintainer_duty() == Some(maintainer),
+                // If maintenance is overdue, we may have to stand in for the
+                // maintainer on duty before our own duty starts.
+                let next_fallback_slot = if solido.has_overdue_maintenance() {
+                    solido.get_next_fallback_duty_slot(&maintainer)
+                } else {
+                    None
+                };
+                let next_duty_slot = match (
                     solido.get_next_maintainer_duty_slot(&maintainer),
-                )
+                    next_fallback_slot,
+                ) {
+                    (Some(duty), Some(fallback)) => Some(duty.min(fallback)),
+                    (duty, fallback) => duty.or(fallback),
+                };
+                (solido.is_on_duty(&maintainer), next_duty_slot)
             });
 
         let next_duty_slot = is_on_duty_and_next_duty_slot.and_then(|(_, slot)| slot);
@@ -405,50 +1154,298 @@ impl<'a, 'b> Daemon<'a, 'b> {
             fmt_option_duration(Some(sleep_time)),
         );
 
-        std::thread::sleep(sleep_time);
+        if self.opts.websocket_url().is_empty() {
+            std::thread::sleep(sleep_time);
+        } else {
+            self.wait_for_changes(sleep_time, next_duty_slot);
+        }
     }
 
-    /// Run maintenance in a loop.
-    fn run(mut self) -> ! {
-        loop {
-            self.metrics.polls += 1;
-            match run_maintenance_iteration(self.config, self.opts) {
-                MaintenanceResult::ErrSnapshot(err) => {
-                    println!("Error while obtaining on-chain state.");
-                    err.print_pretty();
-                    self.metrics.errors += 1;
-                    self.publish_snapshot(None);
-                    self.sleep_after_error();
-                }
-                MaintenanceResult::ErrMaintenance(state, err) => {
-                    println!("Error while performing maintenance.");
+    /// Wait until a watched account changes, until the next epoch or our next
+    /// duty slice starts, but at most `timeout`.
+    ///
+    /// If we cannot subscribe to changes, this sleeps for `timeout`, like when
+    /// polling.
+    fn wait_for_changes(&mut self, timeout: Duration, next_duty_slot: Option<Slot>) {
+        let watch = self
+            .snapshot_mutex
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|snapshot| snapshot.solido.as_ref())
+            .map(|solido| {
+                let next_epoch_slot = solido
+                    .epoch_schedule
+                    .get_first_slot_in_epoch(solido.clock.epoch + 1);
+                (solido.get_watched_accounts(), next_epoch_slot)
+            });
+        let (addresses, next_epoch_slot) = match watch {
+            Some(watch) => watch,
+            None => return std::thread::sleep(timeout),
+        };
+
+        // Validators and stake accounts come and go, so subscribe again when
+        // the set of accounts to watch changes.
+        let is_watching = match self.account_watcher.as_ref() {
+            Some(watcher) => watcher.addresses() == &addresses[..],
+            None => false,
+        };
+        if !is_watching {
+            self.account_watcher = None;
+            match AccountWatcher::connect(self.opts.websocket_url(), addresses) {
+                Ok(watcher) => self.account_watcher = Some(watcher),
+                Err(err) => {
+                    println!("Failed to subscribe to account changes, sleeping instead.");
                     err.print_pretty();
-                    self.metrics.errors += 1;
-                    self.publish_snapshot(Some(state));
-                    // After a failed maintenance transaction, we sleep the regular
-                    // poll interval. This ensures that if there is a bug that causes
-                    // maintenance transactions to always fail (like [1]), we don't
-                    // go in a busy loop submitting failing transactions.
-                    // 1: https://github.com/ChorusOne/solido/issues/422
-                    self.sleep_until_next_iteration();
+                    return std::thread::sleep(timeout);
                 }
-                MaintenanceResult::OkIdle(state) => {
-                    self.publish_snapshot(Some(state));
-                    self.sleep_until_next_iteration();
-                }
-                MaintenanceResult::OkMaintenance(state, output) => {
-                    println!("{}", output);
-                    self.metrics.observe_maintenance(&output);
-                    self.publish_snapshot(Some(state));
-                    // Note, we do not sleep here. If we performed maintenance, we
-                    // might not be done yet, so we should immediately check again.
+            }
+        }
+
+        let wake_at_slot = next_duty_slot.map_or(next_epoch_slot, |slot| slot.min(next_epoch_slot));
+        let watcher = self.account_watcher.as_ref().unwrap();
+        match watcher.wait(timeout, wake_at_slot) {
+            WakeReason::AccountChanged(address) => println!("Account {} changed.", address),
+            WakeReason::ReachedSlot(slot) => println!("Reached slot {}.", slot),
+            WakeReason::Timeout => {}
+            WakeReason::Disconnected(err) => {
+                println!("Lost websocket connection: {}", err);
+                // We reconnect in the next iteration.
+                self.account_watcher = None;
+            }
+        }
+    }
+
+    /// Run a single iteration of the main loop, and return when to run the next one.
+    pub fn run_iteration(&mut self) -> NextIteration {
+        // Changes that happened before we read the state will be part of it.
+        if let Some(watcher) = self.account_watcher.as_ref() {
+            watcher.drain();
+        }
+        self.metrics.polls += 1;
+        match run_maintenance_iteration(
+            self.config,
+            self.opts,
+            self.rebalancing,
+            self.journal.as_mut(),
+            self.fee_budget.as_mut(),
+        ) {
+            MaintenanceResult::ErrSnapshot(err) => {
+                println!("Error while obtaining on-chain state.");
+                err.print_pretty();
+                self.metrics.errors += 1;
+                self.consecutive_errors += 1;
+                self.observe_alerts(None);
+                self.publish_snapshot(None);
+                NextIteration::AfterError
+            }
+            MaintenanceResult::ErrMaintenance(state, err) => {
+                println!("Error while performing maintenance.");
+                err.print_pretty();
+                self.metrics.errors += 1;
+                self.consecutive_errors += 1;
+                self.observe_alerts(Some(&state));
+                self.publish_snapshot(Some(state));
+                // After a failed maintenance transaction, we sleep the regular
+                // poll interval. This ensures that if there is a bug that causes
+                // maintenance transactions to always fail (like [1]), we don't
+                // go in a busy loop submitting failing transactions.
+                // 1: https://github.com/ChorusOne/solido/issues/422
+                NextIteration::AfterPollInterval
+            }
+            MaintenanceResult::OkIdle(state) => {
+                self.consecutive_errors = 0;
+                self.observe_alerts(Some(&state));
+                self.publish_snapshot(Some(state));
+                NextIteration::AfterPollInterval
+            }
+            MaintenanceResult::OkMaintenance(state, output) if output.send_failed() => {
+                println!("Error while performing maintenance.");
+                println!("{}", output);
+                self.metrics.errors += 1;
+                self.consecutive_errors += 1;
+                self.metrics.observe_maintenance(&output);
+                self.record_maintenance(&state, output);
+                self.observe_alerts(Some(&state));
+                self.publish_snapshot(Some(state));
+                // Like above, sleep the regular poll interval after a failure.
+                NextIteration::AfterPollInterval
+            }
+            MaintenanceResult::OkMaintenance(state, output) => {
+                println!("{}", output);
+                self.consecutive_errors = 0;
+                self.metrics.observe_maintenance(&output);
+                self.record_maintenance(&state, output);
+                self.observe_alerts(Some(&state));
+                self.publish_snapshot(Some(state));
+                // If we performed maintenance, we might not be done yet, so we
+                // should immediately check again. In a dry run nothing changed
+                // on-chain though, so checking again would only simulate the
+                // same transactions again.
+                if *self.opts.dry_run() {
+                    NextIteration::AfterPollInterval
+                } else {
+                    NextIteration::Immediately
                 }
-            };
+            }
+        }
+    }
+
+    /// Run maintenance in a loop.
+    fn run(mut self) -> ! {
+        loop {
+            match self.run_iteration() {
+                NextIteration::AfterError => self.sleep_after_error(),
+                NextIteration::AfterPollInterval => self.sleep_until_next_iteration(),
+                NextIteration::Immediately => {}
+            }
+        }
+    }
+
+    /// Return the metrics about what the daemon has done so far.
+    #[cfg(test)]
+    pub fn metrics(&self) -> &MaintenanceMetrics {
+        &self.metrics
+    }
+}
+
+/// A response to an http request, before we hand it to the http server.
+struct HttpResponse {
+    status_code: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn text(status_code: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            content_type: "text/plain; charset=UTF-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn json<T: Serialize>(value: &T) -> HttpResponse {
+        match serde_json::to_vec(value) {
+            Ok(body) => HttpResponse {
+                status_code: 200,
+                content_type: "application/json",
+                body,
+            },
+            Err(..) => HttpResponse::text(500, "error"),
+        }
+    }
+
+    fn not_found() -> HttpResponse {
+        HttpResponse::text(404, "Not Found")
+    }
+
+    fn no_state() -> HttpResponse {
+        HttpResponse::text(
+            503,
+            "Service Unavailable\n\nFailed to obtain the on-chain state, try again shortly.",
+        )
+    }
+}
+
+/// Write the outcome of every health and readiness check as a metric.
+fn write_health_prometheus<W: io::Write>(report: &HealthReport, out: &mut W) -> io::Result<()> {
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_daemon_check_ok",
+            help: "Whether a check of /healthz or /readyz passes (1) or fails (0).",
+            type_: "gauge",
+            metrics: report
+                .checks
+                .iter()
+                .map(|check| {
+                    Metric::new(check.ok as u64).with_label("check", check.name.to_string())
+                })
+                .collect(),
+        },
+    )
+}
+
+fn serve_metrics(snapshot: &Snapshot, thresholds: &HealthThresholds, now: Instant) -> HttpResponse {
+    let mut out: Vec<u8> = Vec::new();
+    let mut is_ok = snapshot.metrics.write_prometheus(&mut out).is_ok();
+    is_ok = is_ok && endpoint::write_prometheus(&snapshot.rpc_endpoints, &mut out).is_ok();
+    let report = check_health(snapshot, thresholds, now, true);
+    is_ok = is_ok && write_health_prometheus(&report, &mut out).is_ok();
+
+    if let Some(ref solido) = snapshot.solido {
+        is_ok = is_ok && solido.write_prometheus(&mut out).is_ok();
+    }
+
+    if is_ok {
+        HttpResponse {
+            status_code: 200,
+            // text/plain with version=0.0.4 is what Prometheus expects as the content type,
+            // see also https://prometheus.io/docs/instrumenting/exposition_formats/.
+            // We add the charset so you can view the metrics in a browser too when it
+            // contains non-ascii bytes.
+            content_type: "text/plain; version=0.0.4; charset=UTF-8",
+            body: out,
         }
+    } else {
+        HttpResponse::text(500, "error")
+    }
+}
+
+fn serve_health(
+    snapshot: &Snapshot,
+    thresholds: &HealthThresholds,
+    now: Instant,
+    readiness: bool,
+) -> HttpResponse {
+    let report = check_health(snapshot, thresholds, now, readiness);
+    let mut response = HttpResponse::json(&report);
+    if !report.ok && response.status_code == 200 {
+        response.status_code = 503;
     }
+    response
 }
 
-fn serve_request(request: Request, snapshot_mutex: &SnapshotMutex) -> Result<(), std::io::Error> {
+/// Produce the response for the given request path, ignoring any query string.
+fn route(
+    url: &str,
+    snapshot: &Snapshot,
+    thresholds: &HealthThresholds,
+    now: Instant,
+) -> HttpResponse {
+    let path = url.split('?').next().unwrap_or(url);
+    match path {
+        "/metrics" => serve_metrics(snapshot, thresholds, now),
+        "/healthz" => serve_health(snapshot, thresholds, now, false),
+        "/readyz" => serve_health(snapshot, thresholds, now, true),
+        "/api/state" => match snapshot.solido {
+            Some(ref solido) => HttpResponse::json(&StateView::new(solido)),
+            None => HttpResponse::no_state(),
+        },
+        "/api/maintenance" => HttpResponse::json(&snapshot.recent_maintenance),
+        _ => match path.strip_prefix("/api/validators/") {
+            Some(vote_account) => {
+                let solido = match snapshot.solido {
+                    Some(ref solido) => solido,
+                    None => return HttpResponse::no_state(),
+                };
+                Pubkey::from_str(vote_account)
+                    .ok()
+                    .and_then(|vote_account| ValidatorView::find(solido, &vote_account))
+                    .map(|validator| HttpResponse::json(&validator))
+                    .unwrap_or_else(HttpResponse::not_found)
+            }
+            None => HttpResponse::not_found(),
+        },
+    }
+}
+
+fn serve_request(
+    request: Request,
+    snapshot_mutex: &SnapshotMutex,
+    thresholds: &HealthThresholds,
+) -> Result<(), std::io::Error> {
     // Take the current snapshot. This only holds the lock briefly, and does
     // not prevent other threads from updating the snapshot while this request
     // handler is running.
@@ -469,29 +1466,14 @@ fn serve_request(request: Request, snapshot_mutex: &SnapshotMutex) -> Result<(),
         }
     };
 
-    // We don't even look at the request, for now we always serve the metrics.
-
-    let mut out: Vec<u8> = Vec::new();
-    let mut is_ok = snapshot.metrics.write_prometheus(&mut out).is_ok();
-
-    if let Some(ref solido) = snapshot.solido {
-        is_ok = is_ok && solido.write_prometheus(&mut out).is_ok();
-    }
-
-    if is_ok {
-        // text/plain with version=0.0.4 is what Prometheus expects as the content type,
-        // see also https://prometheus.io/docs/instrumenting/exposition_formats/.
-        // We add the charset so you can view the metrics in a browser too when it
-        // contains non-ascii bytes.
-        let content_type = Header::from_bytes(
-            &b"Content-Type"[..],
-            &b"text/plain; version=0.0.4; charset=UTF-8"[..],
-        )
+    let response = route(request.url(), &snapshot, thresholds, Instant::now());
+    let content_type = Header::from_bytes(&b"Content-Type"[..], response.content_type.as_bytes())
         .expect("Static header value, does not fail at runtime.");
-        request.respond(Response::from_data(out).with_header(content_type))
-    } else {
-        request.respond(Response::from_string("error").with_status_code(500))
-    }
+    request.respond(
+        Response::from_data(response.body)
+            .with_status_code(response.status_code)
+            .with_header(content_type),
+    )
 }
 
 /// Spawn threads that run the http server.
@@ -514,20 +1496,21 @@ fn start_http_server(
     println!("Http server listening on {}", opts.listen());
 
     // Spawn a number of http handler threads, so we can handle requests in
-    // parallel. This server is only used to serve metrics, it can be super basic,
+    // parallel. This server is only used to serve metrics and the state, it can be super basic,
     // but some degree of parallelism is nice in case a client is slow to send
     // its request or something like that.
     (0..num_cpus::get())
         .map(|i| {
             let server_clone = server.clone();
             let snapshot_mutex_clone = snapshot_mutex.clone();
+            let thresholds = HealthThresholds::new(opts);
             std::thread::Builder::new()
                 .name(format!("http_handler_{}", i))
                 .spawn(move || {
                     for request in server_clone.incoming_requests() {
                         // Ignore any errors; if we fail to respond, then there's little
                         // we can do about it here ... the client should just retry.
-                        let _ = serve_request(request, &*snapshot_mutex_clone);
+                        let _ = serve_request(request, &*snapshot_mutex_clone, &thresholds);
                     }
                 })
                 .expect("Failed to spawn http handler thread.")
@@ -536,8 +1519,12 @@ fn start_http_server(
 }
 
 /// Run the maintenance daemon.
-pub fn main(config: &mut SnapshotClientConfig, opts: &RunMaintainerOpts) {
-    let daemon = Daemon::new(config, opts);
+pub fn main(
+    config: &mut SnapshotClientConfig,
+    opts: &RunMaintainerOpts,
+    rebalancing: &RebalancingConfig,
+) {
+    let daemon = Daemon::new(config, opts, rebalancing);
     let _http_threads = start_http_server(opts, daemon.snapshot_mutex.clone());
     daemon.run();
 }
@@ -545,6 +1532,7 @@ pub fn main(config: &mut SnapshotClientConfig, opts: &RunMaintainerOpts) {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_utils::new_empty_solido;
 
     #[test]
     fn block_time_estimator_computes_block_time_from_two_or_more_observations() {
@@ -617,4 +1605,282 @@ mod test {
             Some(Duration::from_secs(0))
         );
     }
+
+    fn new_empty_snapshot(now: Instant) -> Snapshot {
+        Snapshot {
+            metrics: MaintenanceMetrics::new(false),
+            solido: None,
+            recent_maintenance: VecDeque::new(),
+            last_read_success: now,
+            slot_advanced_at: Some(now),
+            consecutive_errors: 0,
+            rpc_endpoints: Vec::new(),
+        }
+    }
+
+    fn new_thresholds() -> HealthThresholds {
+        HealthThresholds {
+            max_snapshot_age: Duration::from_secs(300),
+            max_slot_stall: Duration::from_secs(120),
+            max_consecutive_errors: 10,
+        }
+    }
+
+    #[test]
+    fn route_serves_known_paths_and_rejects_others() {
+        let now = Instant::now();
+        let snapshot = new_empty_snapshot(now);
+        let thresholds = new_thresholds();
+        let route = |url: &str| route(url, &snapshot, &thresholds, now);
+
+        let response = route("/metrics");
+        assert_eq!(response.status_code, 200);
+        assert!(String::from_utf8(response.body)
+            .unwrap()
+            .contains("solido_maintenance_polls_total 0"));
+        // The outcome of the readiness checks is a metric too.
+        let response = route("/metrics");
+        let metrics = String::from_utf8(response.body).unwrap();
+        assert!(metrics.contains("solido_daemon_check_ok{check=\"snapshot_age\"} 1"));
+        assert!(metrics.contains("solido_daemon_check_ok{check=\"maintainer_balance\"} 0"));
+
+        // The query string is not part of the path.
+        let response = route("/api/maintenance?limit=5");
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.content_type, "application/json");
+        assert_eq!(response.body, b"[]");
+
+        // Without a state, we cannot serve the state.
+        assert_eq!(route("/api/state").status_code, 503);
+        let validator_path = format!("/api/validators/{}", Pubkey::new_unique());
+        assert_eq!(route(&validator_path).status_code, 503);
+
+        assert_eq!(route("/").status_code, 404);
+        assert_eq!(route("/api/unknown").status_code, 404);
+    }
+
+    #[test]
+    fn health_fails_on_stale_snapshot_and_consecutive_errors() {
+        let t0 = Instant::now();
+        let thresholds = new_thresholds();
+        let mut snapshot = new_empty_snapshot(t0);
+
+        assert!(check_health(&snapshot, &thresholds, t0, false).ok);
+        assert!(
+            !check_health(&snapshot, &thresholds, t0 + Duration::from_secs(301), false).ok,
+            "The snapshot is too old."
+        );
+
+        snapshot.consecutive_errors = 9;
+        assert!(check_health(&snapshot, &thresholds, t0, false).ok);
+        snapshot.consecutive_errors = 10;
+        assert!(!check_health(&snapshot, &thresholds, t0, false).ok);
+    }
+
+    #[test]
+    fn readiness_fails_on_stalled_slot_and_missing_state() {
+        let t0 = Instant::now();
+        let thresholds = new_thresholds();
+        let mut snapshot = new_empty_snapshot(t0);
+
+        // We did read the state recently, but the slot has been the same for
+        // 200 seconds, and the most recent read did not produce a state.
+        let t1 = t0 + Duration::from_secs(200);
+        snapshot.last_read_success = t1;
+        assert!(check_health(&snapshot, &thresholds, t1, false).ok);
+        let report = check_health(&snapshot, &thresholds, t1, true);
+        let failed: Vec<&str> = report
+            .checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name)
+            .collect();
+        assert_eq!(failed, vec!["slot_advancing", "maintainer_balance"]);
+        assert_eq!(
+            serve_health(&snapshot, &thresholds, t1, true).status_code,
+            503
+        );
+        assert_eq!(
+            serve_health(&snapshot, &thresholds, t1, false).status_code,
+            200
+        );
+    }
+
+    #[test]
+    fn block_time_estimator_tracks_when_the_slot_last_advanced() {
+        let t0 = Instant::now();
+        let mut estimator = BlockTimeEstimator::new();
+        assert_eq!(estimator.get_slot_advanced_at(), None);
+
+        let mut clock = Clock::default();
+        clock.slot = 100;
+        estimator.observe_clock(t0, &clock);
+        assert_eq!(estimator.get_slot_advanced_at(), Some(t0));
+
+        // Observing the same slot again does not count as advancing.
+        let t1 = t0 + Duration::from_secs(10);
+        estimator.observe_clock(t1, &clock);
+        assert_eq!(estimator.get_slot_advanced_at(), Some(t0));
+
+        let t2 = t0 + Duration::from_secs(20);
+        clock.slot = 101;
+        estimator.observe_clock(t2, &clock);
+        assert_eq!(estimator.get_slot_advanced_at(), Some(t2));
+    }
+
+    #[test]
+    fn alert_tracker_alerts_once_per_condition() {
+        let mut tracker = AlertTracker::default();
+        let maintainer = Pubkey::new_unique();
+        let balance_low = |lamports| AlertEvent::MaintainerBalanceLow {
+            maintainer,
+            balance_lamports: Lamports(lamports),
+        };
+
+        assert_eq!(
+            tracker.update(vec![AlertEvent::MaintenanceError, balance_low(10)]),
+            vec![AlertEvent::MaintenanceError, balance_low(10)]
+        );
+
+        // The conditions still hold, even though the balance changed, so no
+        // new alerts.
+        assert_eq!(
+            tracker.update(vec![AlertEvent::MaintenanceError, balance_low(5)]),
+            vec![]
+        );
+
+        // The error resolved, and then it occurs again, so we alert again.
+        assert_eq!(tracker.update(vec![balance_low(5)]), vec![]);
+        assert_eq!(
+            tracker.update(vec![AlertEvent::MaintenanceError, balance_low(5)]),
+            vec![AlertEvent::MaintenanceError]
+        );
+    }
+
+    fn new_alert_config() -> AlertConfig {
+        AlertConfig {
+            consecutive_errors: 3,
+            maintainer_balance: Lamports(0),
+            vote_lag_slots: 1_000,
+            exchange_rate_lag_slots: 1_000,
+        }
+    }
+
+    /// Return a state where the maintainer that we act as has the given balance.
+    fn new_state_with_maintainer_balance(balance: Lamports) -> SolidoState {
+        let mut state = new_empty_solido();
+        state.solido.maintainers.maximum_entries = 1;
+        state
+            .solido
+            .maintainers
+            .add(state.maintainer_address, ())
+            .unwrap();
+        state.maintainer_balances = vec![balance];
+        state
+    }
+
+    #[test]
+    fn detect_alerts_reports_errors_after_threshold() {
+        let config = new_alert_config();
+        assert_eq!(detect_alerts(&[], 0, &config), vec![]);
+        assert_eq!(detect_alerts(&[], 2, &config), vec![]);
+        assert_eq!(
+            detect_alerts(&[], 3, &config),
+            vec![AlertEvent::MaintenanceError]
+        );
+    }
+
+    #[test]
+    fn alert_sink_keeps_state_conditions_when_reading_state_fails() {
+        let (sender, receiver) = mpsc::channel();
+        let mut sink = AlertSink {
+            config: new_alert_config(),
+            tracker: AlertTracker::default(),
+            solido_address: Pubkey::new_unique(),
+            sender,
+            state_events: Vec::new(),
+            slot: None,
+        };
+        let received_types = || -> Vec<String> {
+            receiver
+                .try_iter()
+                .map(|body| {
+                    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    json["event"]["type"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+        let state = new_state_with_maintainer_balance(Lamports(1));
+
+        sink.observe(Some(&state), 0);
+        assert_eq!(received_types(), vec!["MaintainerBalanceInsufficient"]);
+
+        // Reading the state failed twice, that is not enough to alert about
+        // errors, and the balance condition still holds.
+        sink.observe(None, 1);
+        sink.observe(None, 2);
+        assert_eq!(received_types(), Vec::<String>::new());
+
+        // When we can read the state again, the balance is still low, but we
+        // already alerted about that.
+        sink.observe(Some(&state), 0);
+        assert_eq!(received_types(), Vec::<String>::new());
+
+        sink.observe(None, 3);
+        assert_eq!(received_types(), vec!["MaintenanceError"]);
+    }
+
+    #[test]
+    fn detect_state_alerts_reports_insufficient_maintainer_balance() {
+        let config = new_alert_config();
+        let state = new_state_with_maintainer_balance(SolidoState::MINIMUM_MAINTAINER_BALANCE);
+        assert_eq!(detect_state_alerts(&state, &config), vec![]);
+
+        let state = new_state_with_maintainer_balance(Lamports(1));
+        assert_eq!(
+            detect_state_alerts(&state, &config),
+            vec![AlertEvent::MaintainerBalanceInsufficient {
+                maintainer: state.maintainer_address,
+                balance_lamports: Lamports(1),
+                minimum_lamports: SolidoState::MINIMUM_MAINTAINER_BALANCE,
+            }]
+        );
+    }
+
+    #[test]
+    fn post_alert_posts_json_to_webhook() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+
+        let event = AlertEvent::ValidatorRemovable {
+            validator_vote_account: Pubkey::new_unique(),
+        };
+        let payload = AlertPayload {
+            text: event.to_string(),
+            solido_address: Pubkey::new_unique(),
+            slot: Some(42),
+            event: &event,
+        };
+        let body = serde_json::to_vec(&payload).unwrap();
+
+        let client_body = body.clone();
+        let client_thread = std::thread::spawn(move || {
+            let client = reqwest::blocking::Client::new();
+            post_alert(&client, &url, client_body).is_ok()
+        });
+
+        let mut request = server.recv().unwrap();
+        assert_eq!(request.method(), &tiny_http::Method::Post);
+        assert_eq!(request.url(), "/hook");
+        let mut received = Vec::new();
+        request.as_reader().read_to_end(&mut received).unwrap();
+        request.respond(Response::empty(200)).unwrap();
+
+        assert!(client_thread.join().unwrap());
+        assert_eq!(received, body);
+
+        let json: serde_json::Value = serde_json::from_slice(&received).unwrap();
+        assert_eq!(json["event"]["type"], "ValidatorRemovable");
+        assert_eq!(json["slot"], 42);
+    }
 }
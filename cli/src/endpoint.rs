@@ -0,0 +1,342 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! A set of RPC endpoints, that we fail over between when one is unhealthy.
+//!
+//! The endpoints are in order of preference: we use the first endpoint that is
+//! healthy. An endpoint becomes unhealthy for a while when a request to it fails
+//! in a way that is the endpoint's fault (as opposed to e.g. a transaction that
+//! fails in preflight), or when its state lags behind that of other endpoints.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::time::{Duration, Instant};
+
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::clock::Slot;
+
+use crate::prometheus::{write_metric, Metric, MetricFamily, MetricValue};
+use crate::snapshot::is_too_many_inputs_error;
+
+/// How long we avoid an endpoint after it failed.
+const UNHEALTHY_DURATION: Duration = Duration::from_secs(30);
+
+/// Counters and gauges about the requests to an RPC endpoint, since launch.
+#[derive(Clone, Debug)]
+pub struct EndpointMetrics {
+    /// Label that identifies the endpoint, without any credentials, path or
+    /// query string, because those can contain API keys.
+    pub label: String,
+
+    /// Number of requests that we made.
+    pub requests: u64,
+
+    /// Number of requests that failed due to the endpoint.
+    pub errors: u64,
+
+    /// Total time spent on requests, in nanoseconds.
+    pub request_duration_nanos: u64,
+
+    /// The highest slot that we observed from this endpoint.
+    pub highest_slot: Slot,
+
+    /// Whether this is the endpoint that we currently use.
+    pub is_active: bool,
+}
+
+/// Return whether the request failed due to the endpoint, rather than due to
+/// what we asked it.
+pub fn is_endpoint_failure(error: &ClientError) -> bool {
+    // We learn the limit of `GetMultipleAccounts` from this error, it is
+    // expected and not a reason to avoid the endpoint.
+    if is_too_many_inputs_error(error) {
+        return false;
+    }
+    matches!(
+        error.kind(),
+        ClientErrorKind::Io(..)
+            | ClientErrorKind::Reqwest(..)
+            | ClientErrorKind::SerdeJson(..)
+            | ClientErrorKind::RpcError(RpcError::RpcRequestError(..))
+            | ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::NodeUnhealthy { .. },
+                ..
+            })
+    )
+}
+
+/// Return the scheme and host of the url, without credentials, path or query string.
+fn get_endpoint_label(url: &str) -> String {
+    let (scheme, rest) = match url.find("://") {
+        Some(i) => (&url[..i + 3], &url[i + 3..]),
+        None => ("", url),
+    };
+    let authority_end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    // Drop the `user:password@` part, the password can contain an `@` too.
+    let host = match authority.rfind('@') {
+        Some(i) => &authority[i + 1..],
+        None => authority,
+    };
+    format!("{}{}", scheme, host)
+}
+
+pub struct Endpoint {
+    pub rpc_client: RpcClient,
+    metrics: RefCell<EndpointMetrics>,
+
+    /// If the endpoint failed recently, the instant until which we avoid it.
+    unhealthy_until: Cell<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn new(url: String, rpc_client: RpcClient) -> Endpoint {
+        Endpoint {
+            rpc_client,
+            metrics: RefCell::new(EndpointMetrics {
+                label: get_endpoint_label(&url),
+                requests: 0,
+                errors: 0,
+                request_duration_nanos: 0,
+                highest_slot: 0,
+                is_active: false,
+            }),
+            unhealthy_until: Cell::new(None),
+        }
+    }
+
+    /// Make a request with the endpoint's client, and record its latency and outcome.
+    pub fn call<T, F>(&self, f: F) -> ClientResult<T>
+    where
+        F: FnOnce(&RpcClient) -> ClientResult<T>,
+    {
+        let start = Instant::now();
+        let result = f(&self.rpc_client);
+        let mut metrics = self.metrics.borrow_mut();
+        metrics.requests += 1;
+        metrics.request_duration_nanos += start.elapsed().as_nanos() as u64;
+        if let Err(ref err) = result {
+            if is_endpoint_failure(err) {
+                metrics.errors += 1;
+                self.mark_unhealthy();
+            }
+        }
+        result
+    }
+
+    /// Avoid this endpoint for a while.
+    pub fn mark_unhealthy(&self) {
+        self.unhealthy_until
+            .set(Some(Instant::now() + UNHEALTHY_DURATION));
+    }
+
+    /// Record that we observed the endpoint's state at the given slot.
+    pub fn observe_slot(&self, slot: Slot) {
+        let mut metrics = self.metrics.borrow_mut();
+        metrics.highest_slot = metrics.highest_slot.max(slot);
+    }
+
+    pub fn label(&self) -> String {
+        self.metrics.borrow().label.clone()
+    }
+}
+
+/// Return the index of the first endpoint that is healthy at `now`.
+///
+/// If none of them are, return the one that becomes healthy soonest.
+fn select_endpoint(unhealthy_until: &[Option<Instant>], now: Instant) -> usize {
+    unhealthy_until
+        .iter()
+        .position(|until| until.map_or(true, |until| until <= now))
+        .unwrap_or_else(|| {
+            (0..unhealthy_until.len())
+                .min_by_key(|&i| unhealthy_until[i])
+                .expect("There is at least one endpoint.")
+        })
+}
+
+/// RPC endpoints in order of preference.
+pub struct Endpoints {
+    endpoints: Vec<Endpoint>,
+
+    /// Index of the endpoint that we currently use.
+    active: usize,
+}
+
+impl Endpoints {
+    /// Create the set from pairs of a url and a client for that url.
+    pub fn new(endpoints: Vec<(String, RpcClient)>) -> Endpoints {
+        assert!(!endpoints.is_empty(), "Need at least one RPC endpoint.");
+        Endpoints {
+            endpoints: endpoints
+                .into_iter()
+                .map(|(url, rpc_client)| Endpoint::new(url, rpc_client))
+                .collect(),
+            active: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Return the endpoint that we currently use.
+    pub fn active(&self) -> &Endpoint {
+        &self.endpoints[self.active]
+    }
+
+    /// Return the endpoint that we currently use, mutably.
+    pub fn active_mut(&mut self) -> &mut Endpoint {
+        &mut self.endpoints[self.active]
+    }
+
+    /// Switch to the most preferred endpoint that is healthy, and return it.
+    pub fn select(&mut self) -> &Endpoint {
+        let unhealthy_until: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.unhealthy_until.get())
+            .collect();
+        let index = select_endpoint(&unhealthy_until, Instant::now());
+        if index != self.active {
+            // Print to stderr, in json mode stdout must contain only the output.
+            eprintln!(
+                "Switching RPC endpoint from {} to {}.",
+                self.endpoints[self.active].label(),
+                self.endpoints[index].label(),
+            );
+            self.active = index;
+        }
+        &self.endpoints[self.active]
+    }
+
+    /// Ask every endpoint for its current slot, to learn how far the network is.
+    ///
+    /// Endpoints that fail to respond are marked unhealthy.
+    pub fn probe_slots(&self) {
+        for endpoint in &self.endpoints {
+            if let Ok(slot) = endpoint.call(|rpc_client| rpc_client.get_slot()) {
+                endpoint.observe_slot(slot);
+            }
+        }
+    }
+
+    /// Return the highest slot that we observed from any endpoint.
+    pub fn get_highest_slot(&self) -> Slot {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.metrics.borrow().highest_slot)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_metrics(&self) -> Vec<EndpointMetrics> {
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, endpoint)| EndpointMetrics {
+                is_active: i == self.active,
+                ..endpoint.metrics.borrow().clone()
+            })
+            .collect()
+    }
+}
+
+/// Serialize the endpoint metrics in Prometheus text format.
+pub fn write_prometheus<W: io::Write>(
+    endpoints: &[EndpointMetrics],
+    out: &mut W,
+) -> io::Result<()> {
+    let per_endpoint = |f: &dyn Fn(&EndpointMetrics) -> Metric<'static>| -> Vec<Metric> {
+        endpoints
+            .iter()
+            .map(|endpoint| f(endpoint).with_label("endpoint", endpoint.label.clone()))
+            .collect()
+    };
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_rpc_requests_total",
+            help: "Number of requests made to the RPC endpoint, since launch.",
+            type_: "counter",
+            metrics: per_endpoint(&|endpoint| Metric::new(endpoint.requests)),
+        },
+    )?;
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_rpc_errors_total",
+            help: "Number of requests that failed due to the RPC endpoint, since launch.",
+            type_: "counter",
+            metrics: per_endpoint(&|endpoint| Metric::new(endpoint.errors)),
+        },
+    )?;
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_rpc_request_duration_seconds_total",
+            help: "Total time spent on requests to the RPC endpoint, since launch.",
+            type_: "counter",
+            metrics: per_endpoint(&|endpoint| {
+                Metric::new(MetricValue::Nano(endpoint.request_duration_nanos))
+            }),
+        },
+    )?;
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_rpc_highest_slot",
+            help: "Highest slot observed from the RPC endpoint.",
+            type_: "gauge",
+            metrics: per_endpoint(&|endpoint| Metric::new(endpoint.highest_slot)),
+        },
+    )?;
+    write_metric(
+        out,
+        &MetricFamily {
+            name: "solido_rpc_active",
+            help: "Whether the RPC endpoint is the one currently in use (1) or not (0).",
+            type_: "gauge",
+            metrics: per_endpoint(&|endpoint| Metric::new(endpoint.is_active as u64)),
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_endpoint_label_strips_path_and_query() {
+        assert_eq!(
+            get_endpoint_label("https://rpc.example.com/secret-key"),
+            "https://rpc.example.com"
+        );
+        assert_eq!(
+            get_endpoint_label("http://127.0.0.1:8899?key=secret"),
+            "http://127.0.0.1:8899"
+        );
+        assert_eq!(get_endpoint_label("127.0.0.1:8899"), "127.0.0.1:8899");
+        assert_eq!(
+            get_endpoint_label("https://user:p@ss@rpc.example.com:8899/path"),
+            "https://rpc.example.com:8899"
+        );
+    }
+
+    #[test]
+    fn select_endpoint_prefers_first_healthy_endpoint() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(10);
+        let even_later = now + Duration::from_secs(20);
+
+        assert_eq!(select_endpoint(&[None, None], now), 0);
+        assert_eq!(select_endpoint(&[Some(later), None], now), 1);
+        // Once the backoff has passed, we go back to the preferred endpoint.
+        assert_eq!(select_endpoint(&[Some(now), None], now), 0);
+        // If all are unhealthy, pick the one that recovers first.
+        assert_eq!(select_endpoint(&[Some(even_later), Some(later)], now), 1);
+    }
+}
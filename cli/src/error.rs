@@ -15,6 +15,7 @@ use solana_sdk::signer::SignerError;
 use solana_sdk::transaction::TransactionError;
 
 use lido::error::LidoError;
+use lido::token::StLamports;
 
 /// Return whether the transaction may have executed despite the client error.
 ///
@@ -46,6 +47,20 @@ pub fn might_have_executed(error: &ClientError) -> bool {
     }
 }
 
+/// Return whether sending the transaction failed in preflight.
+///
+/// In that case the RPC node did not forward the transaction, so unlike for
+/// other errors, we know that it did not execute.
+pub fn failed_in_preflight(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(..),
+            ..
+        })
+    )
+}
+
 /// Print the message in bold using ANSI escape sequences.
 fn print_key(message: &'static str) {
     // 1m enters bold, 0m is a reset.
@@ -86,6 +101,40 @@ impl AsPrettyError for MaintenanceError {
     }
 }
 
+/// A withdrawal that needed multiple transactions failed after some of them succeeded.
+pub struct PartialWithdrawalError {
+    /// The error of the transaction that failed.
+    pub cause: Error,
+    /// The number of withdrawal transactions that did execute.
+    pub num_completed: usize,
+    /// The stake accounts created by the transactions that did execute.
+    pub new_stake_accounts: Vec<Pubkey>,
+    /// The amount of stSOL that was not withdrawn.
+    pub remaining_st_sol: StLamports,
+    /// The file that records the progress.
+    pub progress_path: String,
+}
+
+impl AsPrettyError for PartialWithdrawalError {
+    fn print_pretty(&self) {
+        self.cause.print_pretty();
+        println!();
+        print_red("The withdrawal was only partially completed.\n\n");
+        println!(
+            "{} withdrawal transactions executed successfully, and created these stake accounts:",
+            self.num_completed
+        );
+        for stake_account in &self.new_stake_accounts {
+            println!("  {}", stake_account);
+        }
+        println!(
+            "\n{} was not withdrawn. To resume the withdrawal, run the same command \
+            again. The progress is recorded in {}",
+            self.remaining_st_sol, self.progress_path,
+        );
+    }
+}
+
 /// Something went wrong either while reading CLI arguments, or while using them.
 ///
 /// This can be a user error (e.g. an invalid Ledger path), or it can be something
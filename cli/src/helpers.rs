@@ -1,13 +1,15 @@
 // SPDX-FileCopyrightText: 2021 Chorus One AG
 // SPDX-License-Identifier: GPL-3.0
 
-use std::{fmt, path::PathBuf};
+use std::{collections::HashMap, fmt, path::PathBuf};
 
 use serde::Serialize;
 use solana_program::{pubkey::Pubkey, system_instruction};
 use solana_sdk::{
     account::ReadableAccount,
-    signature::{Keypair, Signer},
+    borsh::try_from_slice_unchecked,
+    native_token::LAMPORTS_PER_SOL,
+    signature::{Keypair, Signature, Signer},
 };
 
 use lido::{
@@ -15,22 +17,29 @@ use lido::{
     find_authority_program_address,
     metrics::LamportsHistogram,
     processor::StakeType,
-    state::{Lido, RewardDistribution},
-    token::{Lamports, StLamports},
+    state::{ExchangeRate, Lido, LidoV0, RewardDistribution, Validators},
+    token::{Lamports, Rational, StLamports},
     util::serialize_b58,
-    MINT_AUTHORITY, RESERVE_ACCOUNT, REWARDS_WITHDRAW_AUTHORITY, STAKE_AUTHORITY,
+    MINIMUM_STAKE_ACCOUNT_BALANCE, MINT_AUTHORITY, RESERVE_ACCOUNT, REWARDS_WITHDRAW_AUTHORITY,
+    STAKE_AUTHORITY,
 };
 
 use crate::{
     config::{
-        AddRemoveMaintainerOpts, AddValidatorOpts, CreateSolidoOpts, DeactivateValidatorOpts,
-        DepositOpts, ShowSolidoAuthoritiesOpts, ShowSolidoOpts, WithdrawOpts,
+        AddRemoveMaintainerOpts, AddValidatorOpts, ChangeRewardDistributionOpts,
+        ClaimValidatorFeeOpts, CreateSolidoOpts, DeactivateValidatorOpts, DepositOpts,
+        MergeValidatorStakeOpts, MigrateStateOpts, RemoveValidatorOpts, ShowSolidoAuthoritiesOpts,
+        ShowSolidoOpts, WithdrawOpts,
     },
-    error::CliError,
+    error::{CliError, PartialWithdrawalError},
     get_signer_from_path,
+    journal::{get_outcome, Outcome},
+    withdraw_progress::{SubmittedWithdrawal, WithdrawProgress},
 };
 use crate::{
-    multisig::{get_multisig_program_address, propose_instruction, ProposeInstructionOutput},
+    multisig::{
+        ask_user_y_n, get_multisig_program_address, propose_instruction, ProposeInstructionOutput,
+    },
     snapshot::Result,
     spl_token_utils::{push_create_spl_token_account, push_create_spl_token_mint},
     validator_info_utils::ValidatorInfo,
@@ -43,6 +52,10 @@ pub struct CreateSolidoOutput {
     #[serde(serialize_with = "serialize_b58")]
     pub solido_address: Pubkey,
 
+    /// Account that stores the validators of this Solido instance.
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_list_address: Pubkey,
+
     /// Manages the deposited sol.
     #[serde(serialize_with = "serialize_b58")]
     pub reserve_account: Pubkey,
@@ -72,6 +85,11 @@ impl fmt::Display for CreateSolidoOutput {
             "  Solido address:                {}",
             self.solido_address
         )?;
+        writeln!(
+            f,
+            "  Validator list address:        {}",
+            self.validator_list_address
+        )?;
         writeln!(
             f,
             "  Reserve account:               {}",
@@ -130,11 +148,16 @@ pub fn command_create_solido(
     let (manager, _nonce) =
         get_multisig_program_address(opts.multisig_program_id(), opts.multisig_address());
 
-    let lido_size = Lido::calculate_size(*opts.max_validators(), *opts.max_maintainers());
+    let lido_size = Lido::calculate_size(*opts.max_maintainers());
     let lido_account_balance = config
         .client
         .get_minimum_balance_for_rent_exemption(lido_size)?;
 
+    let validator_list_size = Validators::required_bytes(*opts.max_validators() as usize);
+    let validator_list_account_balance = config
+        .client
+        .get_minimum_balance_for_rent_exemption(validator_list_size)?;
+
     let mut instructions = Vec::new();
 
     // We need to fund Lido's reserve account so it is rent-exempt, otherwise it
@@ -195,6 +218,17 @@ pub fn command_create_solido(
         opts.solido_program_id(),
     ));
 
+    // Create the account that holds the validator list, it lives separately
+    // from the Solido instance so it can grow to many validators.
+    let validator_list_keypair = Keypair::new();
+    instructions.push(system_instruction::create_account(
+        &config.signer.pubkey(),
+        &validator_list_keypair.pubkey(),
+        validator_list_account_balance.0,
+        validator_list_size as u64,
+        opts.solido_program_id(),
+    ));
+
     instructions.push(lido::instruction::initialize(
         opts.solido_program_id(),
         RewardDistribution {
@@ -207,6 +241,7 @@ pub fn command_create_solido(
         *opts.max_maintainers(),
         &lido::instruction::InitializeAccountsMeta {
             lido: lido_signer.pubkey(),
+            validator_list: validator_list_keypair.pubkey(),
             st_sol_mint: st_sol_mint_pubkey,
             manager,
             treasury_account: treasury_keypair.pubkey(),
@@ -215,11 +250,15 @@ pub fn command_create_solido(
         },
     ));
 
-    config.sign_and_send_transaction(&instructions[..], &[config.signer, &*lido_signer])?;
+    config.sign_and_send_transaction(
+        &instructions[..],
+        &[config.signer, &*lido_signer, &validator_list_keypair],
+    )?;
     eprintln!("Did send Lido init.");
 
     let result = CreateSolidoOutput {
         solido_address: lido_signer.pubkey(),
+        validator_list_address: validator_list_keypair.pubkey(),
         reserve_account,
         mint_authority,
         st_sol_mint_address: st_sol_mint_pubkey,
@@ -237,10 +276,13 @@ pub fn command_add_validator(
     let (multisig_address, _) =
         get_multisig_program_address(opts.multisig_program_id(), opts.multisig_address());
 
+    let solido = config.client.get_solido(opts.solido_address())?;
+
     let instruction = lido::instruction::add_validator(
         opts.solido_program_id(),
         &lido::instruction::AddValidatorMeta {
             lido: *opts.solido_address(),
+            validator_list: solido.validator_list,
             manager: multisig_address,
             validator_vote_account: *opts.validator_vote_account(),
             validator_fee_st_sol_account: *opts.validator_fee_account(),
@@ -262,10 +304,13 @@ pub fn command_deactivate_validator(
     let (multisig_address, _) =
         get_multisig_program_address(opts.multisig_program_id(), opts.multisig_address());
 
+    let solido = config.client.get_solido(opts.solido_address())?;
+
     let instruction = lido::instruction::deactivate_validator(
         opts.solido_program_id(),
         &lido::instruction::DeactivateValidatorMeta {
             lido: *opts.solido_address(),
+            validator_list: solido.validator_list,
             manager: multisig_address,
             validator_vote_account_to_deactivate: *opts.validator_vote_account(),
         },
@@ -278,6 +323,179 @@ pub fn command_deactivate_validator(
     )
 }
 
+/// CLI entry point to remove a validator.
+pub fn command_remove_validator(
+    config: &mut SnapshotConfig,
+    opts: &RemoveValidatorOpts,
+) -> Result<ProposeInstructionOutput> {
+    let solido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&solido)?;
+
+    // The program only removes validators that are inactive and fully
+    // unstaked, check that up front, so we don't propose a transaction that
+    // can never execute.
+    let validator = validators
+        .get(opts.validator_vote_account())
+        .map_err(|err| CliError::with_cause("Validator not found.", format!("{:?}", err)))?;
+    validator.entry.check_can_be_removed().map_err(|err| {
+        CliError::with_cause("Validator cannot be removed yet.", format!("{:?}", err))
+    })?;
+
+    let instruction = lido::instruction::remove_validator(
+        opts.solido_program_id(),
+        &lido::instruction::RemoveValidatorMeta {
+            lido: *opts.solido_address(),
+            validator_list: solido.validator_list,
+            validator_vote_account_to_remove: *opts.validator_vote_account(),
+        },
+    );
+    propose_instruction(
+        config,
+        opts.multisig_program_id(),
+        *opts.multisig_address(),
+        instruction,
+    )
+}
+
+/// CLI entry point to claim the fee credit of a validator.
+pub fn command_claim_validator_fee(
+    config: &mut SnapshotConfig,
+    opts: &ClaimValidatorFeeOpts,
+) -> Result<ProposeInstructionOutput> {
+    let solido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&solido)?;
+
+    let validator = validators
+        .get(opts.validator_vote_account())
+        .map_err(|err| CliError::with_cause("Validator not found.", format!("{:?}", err)))?;
+    let mint_authority =
+        solido.get_mint_authority(opts.solido_program_id(), opts.solido_address())?;
+
+    let instruction = lido::instruction::claim_validator_fee(
+        opts.solido_program_id(),
+        &lido::instruction::ClaimValidatorFeeMeta {
+            lido: *opts.solido_address(),
+            validator_list: solido.validator_list,
+            st_sol_mint: solido.st_sol_mint,
+            mint_authority,
+            validator_fee_st_sol_account: validator.entry.fee_address,
+        },
+    );
+    propose_instruction(
+        config,
+        opts.multisig_program_id(),
+        *opts.multisig_address(),
+        instruction,
+    )
+}
+
+/// CLI entry point to merge the two oldest stake accounts of a validator.
+pub fn command_merge_validator_stake(
+    config: &mut SnapshotConfig,
+    opts: &MergeValidatorStakeOpts,
+) -> Result<ProposeInstructionOutput> {
+    let solido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&solido)?;
+
+    let validator = validators
+        .get(opts.validator_vote_account())
+        .map_err(|err| CliError::with_cause("Validator not found.", format!("{:?}", err)))?;
+    let seeds = &validator.entry.stake_seeds;
+    if seeds.end - seeds.begin < 2 {
+        return Err(CliError::with_cause(
+            "Validator has nothing to merge.",
+            format!(
+                "Validator {} has {} stake accounts, merging needs at least two.",
+                validator.pubkey,
+                seeds.end - seeds.begin
+            ),
+        )
+        .into());
+    }
+
+    // Merge the first stake account into the second, like the maintainer does.
+    let (from_stake, _) = validator.find_stake_account_address(
+        opts.solido_program_id(),
+        opts.solido_address(),
+        seeds.begin,
+        StakeType::Stake,
+    );
+    let (to_stake, _) = validator.find_stake_account_address(
+        opts.solido_program_id(),
+        opts.solido_address(),
+        seeds.begin + 1,
+        StakeType::Stake,
+    );
+    let stake_authority =
+        solido.get_stake_authority(opts.solido_program_id(), opts.solido_address())?;
+
+    let instruction = lido::instruction::merge_stake(
+        opts.solido_program_id(),
+        &lido::instruction::MergeStakeMeta {
+            lido: *opts.solido_address(),
+            validator_list: solido.validator_list,
+            validator_vote_account: validator.pubkey,
+            from_stake,
+            to_stake,
+            stake_authority,
+        },
+    );
+    propose_instruction(
+        config,
+        opts.multisig_program_id(),
+        *opts.multisig_address(),
+        instruction,
+    )
+}
+
+/// CLI entry point to change the reward distribution and fee recipients.
+pub fn command_change_reward_distribution(
+    config: &mut SnapshotConfig,
+    opts: &ChangeRewardDistributionOpts,
+) -> Result<ProposeInstructionOutput> {
+    let (multisig_address, _) =
+        get_multisig_program_address(opts.multisig_program_id(), opts.multisig_address());
+
+    // The program rejects fee recipients that are not stSOL accounts, check
+    // that up front, so we don't propose a transaction that can never execute.
+    let solido = config.client.get_solido(opts.solido_address())?;
+    for fee_account in &[opts.treasury_account(), opts.developer_account()] {
+        let token_account: spl_token::state::Account = config.client.get_unpack(fee_account)?;
+        if token_account.mint != solido.st_sol_mint {
+            let error: crate::error::Error = CliError::with_cause(
+                "Fee recipient is not an stSOL account.",
+                format!(
+                    "Account {} has mint {}, but the stSOL mint is {}.",
+                    fee_account, token_account.mint, solido.st_sol_mint
+                ),
+            );
+            return Err(error.into());
+        }
+    }
+
+    let instruction = lido::instruction::change_reward_distribution(
+        opts.solido_program_id(),
+        RewardDistribution {
+            treasury_fee: *opts.treasury_fee_share(),
+            validation_fee: *opts.validation_fee_share(),
+            developer_fee: *opts.developer_fee_share(),
+            st_sol_appreciation: *opts.st_sol_appreciation_share(),
+        },
+        &lido::instruction::ChangeRewardDistributionMeta {
+            lido: *opts.solido_address(),
+            manager: multisig_address,
+            treasury_account: *opts.treasury_account(),
+            developer_account: *opts.developer_account(),
+        },
+    );
+    propose_instruction(
+        config,
+        opts.multisig_program_id(),
+        *opts.multisig_address(),
+        instruction,
+    )
+}
+
 /// CLI entry point to to add a maintainer to Solido.
 pub fn command_add_maintainer(
     config: &mut SnapshotConfig,
@@ -301,6 +519,86 @@ pub fn command_add_maintainer(
     )
 }
 
+#[derive(Serialize)]
+pub struct MigrateStateOutput {
+    /// Account created to hold the validators after the migration.
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_list_address: Pubkey,
+
+    /// Multisig transaction that executes the migration.
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_address: Pubkey,
+}
+
+impl fmt::Display for MigrateStateOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Validator list address: {}", self.validator_list_address)?;
+        writeln!(f, "Transaction address:    {}", self.transaction_address)?;
+        Ok(())
+    }
+}
+
+/// Command to migrate a version 0 Solido instance to the current state version.
+///
+/// This creates the account that will hold the validator list, and proposes
+/// the `MigrateStateToV1` instruction to the multisig.
+pub fn command_migrate_state(
+    config: &mut SnapshotConfig,
+    opts: &MigrateStateOpts,
+) -> Result<MigrateStateOutput> {
+    let solido_account = config.client.get_account(opts.solido_address())?;
+    if solido_account.data.first() != Some(&0) {
+        return Err(CliError::with_cause(
+            "The Solido instance does not need to be migrated.",
+            format!("State version is {:?}.", solido_account.data.first()),
+        )
+        .into());
+    }
+    let solido_v0 = try_from_slice_unchecked::<LidoV0>(&solido_account.data).map_err(|err| {
+        CliError::with_cause("Failed to deserialize version 0 Solido instance.", err)
+    })?;
+
+    let validator_list_size =
+        Validators::required_bytes(solido_v0.validators.maximum_entries as usize);
+    let validator_list_account_balance = config
+        .client
+        .get_minimum_balance_for_rent_exemption(validator_list_size)?;
+    let validator_list_keypair = Keypair::new();
+    config.sign_and_send_transaction(
+        &[system_instruction::create_account(
+            &config.signer.pubkey(),
+            &validator_list_keypair.pubkey(),
+            validator_list_account_balance.0,
+            validator_list_size as u64,
+            opts.solido_program_id(),
+        )],
+        &[config.signer, &validator_list_keypair],
+    )?;
+    eprintln!("Did create validator list account.");
+
+    let (multisig_address, _) =
+        get_multisig_program_address(opts.multisig_program_id(), opts.multisig_address());
+    let instruction = lido::instruction::migrate_state_to_v1(
+        opts.solido_program_id(),
+        &lido::instruction::MigrateStateToV1Meta {
+            lido: *opts.solido_address(),
+            manager: multisig_address,
+            validator_list: validator_list_keypair.pubkey(),
+        },
+    );
+    let proposal = propose_instruction(
+        config,
+        opts.multisig_program_id(),
+        *opts.multisig_address(),
+        instruction,
+    )?;
+
+    Ok(MigrateStateOutput {
+        validator_list_address: validator_list_keypair.pubkey(),
+        transaction_address: proposal.transaction_address,
+    })
+}
+
 /// Command to add a validator to Solido.
 pub fn command_remove_maintainer(
     config: &mut SnapshotConfig,
@@ -328,6 +626,8 @@ pub fn command_remove_maintainer(
 pub struct ShowSolidoOutput {
     pub solido: Lido,
 
+    pub validators: Validators,
+
     #[serde(serialize_with = "serialize_b58")]
     pub solido_program_id: Pubkey,
 
@@ -346,10 +646,10 @@ pub struct ShowSolidoOutput {
     #[serde(serialize_with = "serialize_b58")]
     pub rewards_withdraw_authority: Pubkey,
 
-    /// Identity account address for all validators in the same order as `solido.validators`.
+    /// Identity account address for all validators in the same order as `validators`.
     pub validator_identities: Vec<Pubkey>,
 
-    /// Contains validator info in the same order as `solido.validators`.
+    /// Contains validator info in the same order as `validators`.
     pub validator_infos: Vec<ValidatorInfo>,
 }
 
@@ -485,12 +785,16 @@ impl fmt::Display for ShowSolidoOutput {
 
         writeln!(
             f,
-            "\nValidators: {} in use out of {} that the instance can support",
-            self.solido.validators.len(),
-            self.solido.validators.maximum_entries
+            "\nValidator list:              {}",
+            self.solido.validator_list
+        )?;
+        writeln!(
+            f,
+            "Validators: {} in use out of {} that the instance can support",
+            self.validators.len(),
+            self.validators.maximum_entries
         )?;
         for ((pe, identity), info) in self
-            .solido
             .validators
             .entries
             .iter()
@@ -581,6 +885,7 @@ pub fn command_show_solido(
     opts: &ShowSolidoOpts,
 ) -> Result<ShowSolidoOutput> {
     let lido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&lido)?;
     let reserve_account =
         lido.get_reserve_account(opts.solido_program_id(), opts.solido_address())?;
     let stake_authority =
@@ -592,7 +897,7 @@ pub fn command_show_solido(
 
     let mut validator_identities = Vec::new();
     let mut validator_infos = Vec::new();
-    for validator in lido.validators.entries.iter() {
+    for validator in validators.entries.iter() {
         let vote_state = config.client.get_vote_account(&validator.pubkey)?;
         validator_identities.push(vote_state.node_pubkey);
         let info = config.client.get_validator_info(&vote_state.node_pubkey)?;
@@ -603,6 +908,7 @@ pub fn command_show_solido(
         solido_program_id: *opts.solido_program_id(),
         solido_address: *opts.solido_address(),
         solido: lido,
+        validators,
         validator_identities,
         validator_infos,
         reserve_account,
@@ -804,88 +1110,633 @@ pub fn command_deposit(
     Ok(result)
 }
 
+/// A single `Withdraw` instruction in a [`WithdrawPlan`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PlannedWithdrawal {
+    /// The validator to withdraw from.
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_vote_account: Pubkey,
+
+    /// The stake account that will be split to create the new stake account.
+    #[serde(serialize_with = "serialize_b58")]
+    pub source_stake_account: Pubkey,
+
+    /// Amount of stSOL to burn in this withdrawal.
+    pub amount_st_sol: StLamports,
+
+    /// Amount of SOL that the new stake account receives at the current exchange rate.
+    pub amount_sol: Lamports,
+}
+
+/// The sequence of withdrawals needed to withdraw a given amount of stSOL.
+///
+/// A single withdrawal can take at most 10% of the source stake account plus
+/// 10 SOL, and it must withdraw from the validator with the most stake, so a
+/// large withdrawal needs to be broken up into multiple transactions.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct WithdrawPlan {
+    pub withdrawals: Vec<PlannedWithdrawal>,
+}
+
+impl WithdrawPlan {
+    /// Return the number of distinct validators that we withdraw from.
+    pub fn num_validators(&self) -> usize {
+        let mut vote_accounts: Vec<&Pubkey> = self
+            .withdrawals
+            .iter()
+            .map(|w| &w.validator_vote_account)
+            .collect();
+        vote_accounts.sort();
+        vote_accounts.dedup();
+        vote_accounts.len()
+    }
+}
+
+impl fmt::Display for WithdrawPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "This withdrawal needs {} transactions, and creates {} new stake accounts, \
+            split off from stake accounts of {} validators:",
+            self.withdrawals.len(),
+            self.withdrawals.len(),
+            self.num_validators(),
+        )?;
+        for withdrawal in &self.withdrawals {
+            writeln!(
+                f,
+                "  - Burn {} for {}, from validator {}, stake account {}",
+                withdrawal.amount_st_sol,
+                withdrawal.amount_sol,
+                withdrawal.validator_vote_account,
+                withdrawal.source_stake_account,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Simulate withdrawing `amount` in as few transactions as the on-chain program allows.
+///
+/// `source_balances` must hold, for every validator that has stake accounts,
+/// the balance of the stake account that `Withdraw` splits from, which is the
+/// one at the begin seed. After every simulated withdrawal, we pick the next
+/// validator again with `get_validator_to_withdraw`, like the program does.
+pub fn plan_withdrawals(
+    solido_program_id: &Pubkey,
+    solido_address: &Pubkey,
+    exchange_rate: &ExchangeRate,
+    validators: &Validators,
+    source_balances: &HashMap<Pubkey, Lamports>,
+    amount: StLamports,
+) -> std::result::Result<WithdrawPlan, crate::error::Error> {
+    let mut validators = validators.clone();
+    let mut source_balances = source_balances.clone();
+    let mut remaining = amount;
+    let mut withdrawals = Vec::new();
+
+    while remaining > StLamports(0) {
+        let validator = get_validator_to_withdraw(&validators).map_err(|err| {
+            CliError::with_cause(
+                "The instance has no active validators to withdraw from.",
+                err,
+            )
+        })?;
+        let vote_account = validator.pubkey;
+        let source_balance = source_balances
+            .get(&vote_account)
+            .cloned()
+            .unwrap_or(Lamports(0));
+
+        // These are the same limits that `process_withdraw` enforces.
+        let max_by_fraction = (source_balance
+            * Rational {
+                numerator: 1,
+                denominator: 10,
+            })
+        .and_then(|x| x + Lamports(10 * LAMPORTS_PER_SOL))
+        .expect("Adding 10 SOL to 10% of a u64 does not overflow.");
+        let max_by_minimum = Lamports(
+            source_balance
+                .0
+                .saturating_sub(MINIMUM_STAKE_ACCOUNT_BALANCE.0),
+        );
+        let max_sol = max_by_fraction
+            .min(max_by_minimum)
+            .min(validator.entry.stake_accounts_balance);
+
+        // Converting back rounds down, so burning this much stSOL never yields
+        // more than `max_sol`.
+        let max_st_sol = exchange_rate.exchange_sol(max_sol).map_err(|err| {
+            CliError::with_cause("Failed to convert SOL to stSOL.", format!("{:?}", err))
+        })?;
+        let amount_st_sol = remaining.min(max_st_sol);
+        if amount_st_sol == StLamports(0) {
+            return Err(CliError::with_cause(
+                "Cannot withdraw this much stSOL, the validator with the most stake \
+                cannot cover the remainder.",
+                format!(
+                    "After {} withdrawals, {} remains, but validator {} has only {} in \
+                    its source stake account.",
+                    withdrawals.len(),
+                    remaining,
+                    vote_account,
+                    source_balance
+                ),
+            ));
+        }
+        let amount_sol = exchange_rate
+            .exchange_st_sol(amount_st_sol)
+            .map_err(|err| {
+                CliError::with_cause("Failed to convert stSOL to SOL.", format!("{:?}", err))
+            })?;
+
+        let (source_stake_account, _) = validator.find_stake_account_address(
+            solido_program_id,
+            solido_address,
+            validator.entry.stake_seeds.begin,
+            StakeType::Stake,
+        );
+
+        // Apply the withdrawal to our copy of the state, so the next iteration
+        // sees what the program would see.
+        let validator = validators
+            .get_mut(&vote_account)
+            .expect("We just found this validator.");
+        validator.entry.stake_accounts_balance = (validator.entry.stake_accounts_balance
+            - amount_sol)
+            .expect("We limited the amount to the validator's balance.");
+        source_balances.insert(
+            vote_account,
+            (source_balance - amount_sol).expect("We limited the amount to the source balance."),
+        );
+        remaining = (remaining - amount_st_sol).expect("We limited the amount to the remainder.");
+
+        withdrawals.push(PlannedWithdrawal {
+            validator_vote_account: vote_account,
+            source_stake_account,
+            amount_st_sol,
+            amount_sol,
+        });
+    }
+
+    Ok(WithdrawPlan { withdrawals })
+}
+
+/// Read the state that `plan_withdrawals` needs, and plan to withdraw `amount`.
+fn get_withdraw_plan(
+    config: &mut SnapshotConfig,
+    opts: &WithdrawOpts,
+    amount: StLamports,
+) -> Result<WithdrawPlan> {
+    let solido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&solido)?;
+
+    let mut source_balances = HashMap::new();
+    for validator in validators.entries.iter() {
+        if validator.entry.stake_seeds.begin == validator.entry.stake_seeds.end {
+            continue;
+        }
+        let (stake_address, _) = validator.find_stake_account_address(
+            opts.solido_program_id(),
+            opts.solido_address(),
+            validator.entry.stake_seeds.begin,
+            StakeType::Stake,
+        );
+        let stake_account = config.client.get_account(&stake_address)?;
+        source_balances.insert(validator.pubkey, Lamports(stake_account.lamports()));
+    }
+
+    Ok(plan_withdrawals(
+        opts.solido_program_id(),
+        opts.solido_address(),
+        &solido.exchange_rate,
+        &validators,
+        &source_balances,
+        amount,
+    )?)
+}
+
+/// A stake account created by `solido withdraw`.
+#[derive(Serialize)]
+pub struct WithdrawnStakeAccount {
+    #[serde(serialize_with = "serialize_b58")]
+    pub stake_account: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_vote_account: Pubkey,
+
+    /// Amount of stSOL that was burned for this stake account.
+    pub amount_st_sol: StLamports,
+
+    /// Balance of the stake account.
+    pub amount_sol: Lamports,
+}
+
 #[derive(Serialize)]
 pub struct WithdrawOutput {
     #[serde(serialize_with = "serialize_b58")]
     pub from_token_address: Pubkey,
 
-    /// Amount of SOL that was withdrawn.
+    /// Amount of SOL that was withdrawn, summed over all new stake accounts.
     pub withdrawn_sol: Lamports,
 
-    /// Newly created stake account, where the source stake account will be
-    /// split to.
+    /// The first of `new_stake_accounts`.
+    ///
+    /// A withdrawal used to create a single stake account, this field keeps
+    /// the output compatible with that.
     #[serde(serialize_with = "serialize_b58")]
     pub new_stake_account: Pubkey,
+
+    /// Newly created stake accounts, split off from Solido's stake accounts.
+    /// There is one per withdrawal transaction.
+    pub new_stake_accounts: Vec<WithdrawnStakeAccount>,
 }
 
 impl fmt::Display for WithdrawOutput {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "Withdrawn from:          {}", self.from_token_address)?;
         writeln!(f, "Total SOL withdrawn:     {}", self.withdrawn_sol)?;
-        writeln!(f, "New stake account:       {}", self.new_stake_account)?;
+        writeln!(f, "New stake accounts:")?;
+        for account in &self.new_stake_accounts {
+            writeln!(
+                f,
+                "  - {}: {}, from validator {}",
+                account.stake_account, account.amount_sol, account.validator_vote_account,
+            )?;
+        }
         Ok(())
     }
 }
 
+/// Look up the outcome of the withdrawal transactions recorded in the progress
+/// file, and return the stake account for every step that executed.
+///
+/// Returns an error if the outcome of a transaction is not yet known, because
+/// then sending the withdrawal again could withdraw twice.
+fn get_completed_withdrawals(
+    config: &mut SnapshotClientConfig,
+    progress: &WithdrawProgress,
+) -> std::result::Result<Vec<Option<Pubkey>>, crate::error::Error> {
+    let num_steps = progress.plan.withdrawals.len();
+    let mut completed = vec![None; num_steps];
+    let submitted: Vec<(usize, SubmittedWithdrawal)> = (0..num_steps)
+        .filter_map(|step| progress.submitted(step).map(|tx| (step, tx.clone())))
+        .collect();
+    if submitted.is_empty() {
+        return Ok(completed);
+    }
+
+    let outcomes = config.with_snapshot(|config| {
+        // Read the blockhashes before the statuses, like `journal::resolve_pending`.
+        let recent_blockhashes = config.client.get_recent_blockhashes()?;
+        let signatures: Vec<Signature> = submitted.iter().map(|(_, tx)| tx.signature).collect();
+        let statuses = config.client.get_signature_statuses(&signatures)?;
+        Ok(submitted
+            .iter()
+            .zip(statuses)
+            .map(|((_, tx), status)| {
+                let blockhash_is_recent = recent_blockhashes
+                    .iter()
+                    .any(|entry| entry.blockhash == tx.recent_blockhash);
+                get_outcome(status.as_ref(), blockhash_is_recent)
+            })
+            .collect::<Vec<_>>())
+    })?;
+
+    for ((step, tx), outcome) in submitted.iter().zip(outcomes) {
+        match outcome {
+            Some(Outcome::Executed) => completed[*step] = Some(tx.stake_account),
+            Some(outcome) => eprintln!(
+                "Withdrawal {} of {}, transaction {}, {}, it will be sent again.",
+                step + 1,
+                num_steps,
+                tx.signature,
+                outcome,
+            ),
+            None => {
+                return Err(CliError::with_cause(
+                    "The outcome of an earlier withdrawal transaction is not yet known, \
+                    please try again in a minute.",
+                    format!("Transaction {} of withdrawal {}.", tx.signature, step + 1),
+                ))
+            }
+        }
+    }
+    Ok(completed)
+}
+
+/// Return the default path of the file that records the progress of a withdrawal.
+///
+/// The stSOL account that we withdraw from is determined by the Solido instance
+/// and its owner, so there is one path per stSOL account.
+fn get_default_withdraw_progress_path(solido_address: &Pubkey, owner: &Pubkey) -> PathBuf {
+    let home = std::env::var("HOME").expect("Expected $HOME to be set.");
+    let mut path = PathBuf::from(home);
+    path.push(format!(
+        ".config/solido/withdraw-{}-{}.jsonl",
+        solido_address, owner
+    ));
+    path
+}
+
 pub fn command_withdraw(
     config: &mut SnapshotClientConfig,
     opts: &WithdrawOpts,
 ) -> std::result::Result<WithdrawOutput, crate::error::Error> {
-    let (st_sol_address, new_stake_account) = config.with_snapshot(|config| {
-        let solido = config.client.get_solido(opts.solido_address())?;
-
-        let st_sol_address = spl_associated_token_account::get_associated_token_address(
-            &config.signer.pubkey(),
-            &solido.st_sol_mint,
+    let progress_path = if opts.progress_path().is_empty() {
+        get_default_withdraw_progress_path(opts.solido_address(), &config.signer.pubkey())
+    } else {
+        PathBuf::from(opts.progress_path())
+    };
+    let progress = WithdrawProgress::open(&progress_path)?;
+    if let Some(progress) = progress.as_ref() {
+        if progress.solido_address != *opts.solido_address()
+            || progress.amount_st_sol != *opts.amount_st_sol()
+        {
+            return Err(CliError::with_cause(
+                "The progress file records a different withdrawal.",
+                format!(
+                    "{} withdraws {} from Solido instance {}. Resume that \
+                    withdrawal first, or remove the file to abandon it.",
+                    progress_path.display(),
+                    progress.amount_st_sol,
+                    progress.solido_address
+                ),
+            ));
+        }
+        eprintln!(
+            "Resuming the withdrawal recorded in {}.",
+            progress_path.display()
         );
+    }
 
-        let stake_authority =
-            solido.get_stake_authority(opts.solido_program_id(), opts.solido_address())?;
-
-        // Get heaviest validator.
-        let heaviest_validator = get_validator_to_withdraw(&solido.validators).map_err(|err| {
-            CliError::with_cause(
-                "The instance has no active validators to withdraw from.",
-                err,
-            )
+    // Read what all withdrawals have in common, and unless we resume, plan
+    // the withdrawals. We plan only once, and then execute the plan.
+    let (st_sol_address, validator_list, st_sol_mint, stake_authority, new_plan) = config
+        .with_snapshot(|config| {
+            let solido = config.client.get_solido(opts.solido_address())?;
+            let st_sol_address = spl_associated_token_account::get_associated_token_address(
+                &config.signer.pubkey(),
+                &solido.st_sol_mint,
+            );
+            let stake_authority =
+                solido.get_stake_authority(opts.solido_program_id(), opts.solido_address())?;
+            let new_plan = match progress {
+                Some(..) => None,
+                None => Some(get_withdraw_plan(config, opts, *opts.amount_st_sol())?),
+            };
+            Ok((
+                st_sol_address,
+                solido.validator_list,
+                solido.st_sol_mint,
+                stake_authority,
+                new_plan,
+            ))
         })?;
 
-        let (stake_address, _bump_seed) = heaviest_validator.find_stake_account_address(
-            opts.solido_program_id(),
-            opts.solido_address(),
-            heaviest_validator.entry.stake_seeds.begin,
-            StakeType::Stake,
-        );
-
-        let destination_stake_account = Keypair::new();
+    let mut progress = match new_plan {
+        Some(plan) => {
+            if plan.withdrawals.len() > 1 && *opts.confirm() {
+                // Stdout is for the output, which may be json.
+                eprintln!("{}", plan);
+                if !ask_user_y_n("Execute these withdrawals?") {
+                    return Err(CliError::new("Withdrawal cancelled by the user."));
+                }
+            }
+            if let Some(dir) = progress_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            WithdrawProgress::create(
+                &progress_path,
+                *opts.solido_address(),
+                *opts.amount_st_sol(),
+                plan,
+            )?
+        }
+        None => progress.expect("We only skip planning when we resume."),
+    };
+    let plan = progress.plan.clone();
+    let mut completed = get_completed_withdrawals(config, &progress)?;
 
-        let instr = lido::instruction::withdraw(
-            opts.solido_program_id(),
-            &lido::instruction::WithdrawAccountsMeta {
-                lido: *opts.solido_address(),
-                st_sol_mint: solido.st_sol_mint,
-                st_sol_account_owner: config.signer.pubkey(),
-                st_sol_account: st_sol_address,
-                validator_vote_account: heaviest_validator.pubkey,
-                source_stake_account: stake_address,
-                destination_stake_account: destination_stake_account.pubkey(),
-                stake_authority,
-            },
-            *opts.amount_st_sol(),
-        );
-        config.sign_and_send_transaction(&[instr], &[config.signer, &destination_stake_account])?;
+    for (step, withdrawal) in plan.withdrawals.iter().enumerate() {
+        if completed[step].is_some() {
+            continue;
+        }
+        let result = config.with_snapshot(|config| {
+            let destination_stake_account = Keypair::new();
+            let instr = lido::instruction::withdraw(
+                opts.solido_program_id(),
+                &lido::instruction::WithdrawAccountsMeta {
+                    lido: *opts.solido_address(),
+                    validator_list,
+                    st_sol_mint,
+                    st_sol_account_owner: config.signer.pubkey(),
+                    st_sol_account: st_sol_address,
+                    validator_vote_account: withdrawal.validator_vote_account,
+                    source_stake_account: withdrawal.source_stake_account,
+                    destination_stake_account: destination_stake_account.pubkey(),
+                    stake_authority,
+                },
+                withdrawal.amount_st_sol,
+            );
+            let transaction =
+                config.sign_transaction(&[instr], &[config.signer, &destination_stake_account])?;
+            // Record the signature before sending, so that when we resume, we
+            // can find out whether the transaction executed.
+            progress.record_submitted(step, &transaction, destination_stake_account.pubkey())?;
+            config.send_transaction(&transaction)?;
+            Ok(destination_stake_account.pubkey())
+        });
+
+        match result {
+            Ok(stake_account) => completed[step] = Some(stake_account),
+            // If we never sent a transaction, there is nothing to resume.
+            Err(err) if (0..plan.withdrawals.len()).all(|i| progress.submitted(i).is_none()) => {
+                drop(progress);
+                std::fs::remove_file(&progress_path)?;
+                return Err(err);
+            }
+            Err(err) => {
+                let remaining_st_sol = plan
+                    .withdrawals
+                    .iter()
+                    .zip(completed.iter())
+                    .filter(|(_, stake_account)| stake_account.is_none())
+                    .map(|(withdrawal, _)| withdrawal.amount_st_sol)
+                    .sum::<lido::token::Result<StLamports>>()
+                    .expect("The remainder is at most the requested amount.");
+                let new_stake_accounts: Vec<Pubkey> = completed.iter().flatten().cloned().collect();
+                return Err(Box::new(PartialWithdrawalError {
+                    cause: err,
+                    num_completed: new_stake_accounts.len(),
+                    new_stake_accounts,
+                    remaining_st_sol,
+                    progress_path: progress_path.display().to_string(),
+                }));
+            }
+        }
+    }
 
-        Ok((st_sol_address, destination_stake_account))
+    let mut new_stake_accounts: Vec<WithdrawnStakeAccount> = plan
+        .withdrawals
+        .iter()
+        .zip(completed)
+        .map(|(withdrawal, stake_account)| WithdrawnStakeAccount {
+            stake_account: stake_account.expect("All withdrawals completed."),
+            validator_vote_account: withdrawal.validator_vote_account,
+            amount_st_sol: withdrawal.amount_st_sol,
+            amount_sol: withdrawal.amount_sol,
+        })
+        .collect();
+
+    // The planned amount is an estimate, read the actual stake account balances.
+    config.with_snapshot(|config| {
+        for account in new_stake_accounts.iter_mut() {
+            let stake_account = config.client.get_account(&account.stake_account)?;
+            account.amount_sol = Lamports(stake_account.lamports());
+        }
+        Ok(())
     })?;
+    let withdrawn_sol: Option<Lamports> = new_stake_accounts
+        .iter()
+        .map(|account| account.amount_sol)
+        .sum::<lido::token::Result<Lamports>>()
+        .ok();
+
+    // The withdrawal is complete, running the command again should start a new one.
+    drop(progress);
+    std::fs::remove_file(&progress_path)?;
 
-    let stake_sol = config.with_snapshot(|config| {
-        let stake_account = config.client.get_account(&new_stake_account.pubkey())?;
-        Ok(Lamports(stake_account.lamports()))
-    })?;
     let result = WithdrawOutput {
         from_token_address: st_sol_address,
-        withdrawn_sol: stake_sol,
-        new_stake_account: new_stake_account.pubkey(),
+        withdrawn_sol: withdrawn_sol.expect("The total withdrawn SOL does not overflow a u64."),
+        new_stake_account: new_stake_accounts[0].stake_account,
+        new_stake_accounts,
     };
     Ok(result)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use lido::state::Validator;
+
+    /// Return a validator list with `n` validators that each have one stake
+    /// account with `balance`, and the source balances for them.
+    fn new_validators(n: usize, balance: Lamports) -> (Validators, HashMap<Pubkey, Lamports>) {
+        let mut validators = Validators::new(n as u32);
+        let mut source_balances = HashMap::new();
+        for _ in 0..n {
+            let vote_account = Pubkey::new_unique();
+            let mut validator = Validator::new(Pubkey::new_unique());
+            validator.stake_seeds.end = 1;
+            validator.stake_accounts_balance = balance;
+            validators.add(vote_account, validator).unwrap();
+            source_balances.insert(vote_account, balance);
+        }
+        (validators, source_balances)
+    }
+
+    fn plan(
+        validators: &Validators,
+        source_balances: &HashMap<Pubkey, Lamports>,
+        amount: StLamports,
+    ) -> Option<WithdrawPlan> {
+        plan_withdrawals(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &ExchangeRate {
+                computed_in_epoch: 0,
+                st_sol_supply: StLamports(1_000_000 * LAMPORTS_PER_SOL),
+                sol_balance: Lamports(1_000_000 * LAMPORTS_PER_SOL),
+            },
+            validators,
+            source_balances,
+            amount,
+        )
+        .ok()
+    }
+
+    #[test]
+    fn test_plan_withdrawals_small_amount_needs_single_withdrawal() {
+        let (validators, source_balances) = new_validators(2, Lamports(100 * LAMPORTS_PER_SOL));
+        let plan = plan(
+            &validators,
+            &source_balances,
+            StLamports(5 * LAMPORTS_PER_SOL),
+        )
+        .unwrap();
+        assert_eq!(plan.withdrawals.len(), 1);
+        assert_eq!(
+            plan.withdrawals[0].amount_st_sol,
+            StLamports(5 * LAMPORTS_PER_SOL)
+        );
+        assert_eq!(
+            plan.withdrawals[0].amount_sol,
+            Lamports(5 * LAMPORTS_PER_SOL)
+        );
+    }
+
+    #[test]
+    fn test_plan_withdrawals_large_amount_is_split_across_validators() {
+        let balance = Lamports(1_000 * LAMPORTS_PER_SOL);
+        let (validators, source_balances) = new_validators(2, balance);
+        let amount = StLamports(500 * LAMPORTS_PER_SOL);
+        let plan = plan(&validators, &source_balances, amount).unwrap();
+
+        // Every withdrawal takes at most 10% of the source balance + 10 SOL,
+        // and the first one takes exactly that.
+        assert!(plan.withdrawals.len() > 1);
+        assert_eq!(
+            plan.withdrawals[0].amount_sol,
+            Lamports(110 * LAMPORTS_PER_SOL)
+        );
+        let mut balances = source_balances;
+        for withdrawal in &plan.withdrawals {
+            let balance = balances
+                .get_mut(&withdrawal.validator_vote_account)
+                .unwrap();
+            assert!(withdrawal.amount_sol.0 <= balance.0 / 10 + 10 * LAMPORTS_PER_SOL);
+            *balance = (*balance - withdrawal.amount_sol).unwrap();
+        }
+
+        // After a withdrawal, the other validator has the most stake, so we
+        // alternate between the two.
+        assert_eq!(plan.num_validators(), 2);
+        for pair in plan.withdrawals.windows(2) {
+            assert_ne!(
+                pair[0].validator_vote_account,
+                pair[1].validator_vote_account
+            );
+        }
+
+        let total = plan
+            .withdrawals
+            .iter()
+            .map(|w| w.amount_st_sol)
+            .sum::<lido::token::Result<StLamports>>()
+            .unwrap();
+        assert_eq!(total, amount);
+    }
+
+    #[test]
+    fn test_plan_withdrawals_returns_error_if_amount_exceeds_stake() {
+        let (validators, source_balances) = new_validators(2, Lamports(5 * LAMPORTS_PER_SOL));
+        // We can withdraw at most 4 SOL per validator, because of the minimum
+        // stake account balance.
+        assert!(plan(
+            &validators,
+            &source_balances,
+            StLamports(8 * LAMPORTS_PER_SOL)
+        )
+        .is_some());
+        assert!(plan(
+            &validators,
+            &source_balances,
+            StLamports(9 * LAMPORTS_PER_SOL)
+        )
+        .is_none());
+    }
+}
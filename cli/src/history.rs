@@ -0,0 +1,929 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Reconstruct the history of a Solido instance from its past transactions.
+//!
+//! We page through the signatures of transactions that reference the Solido
+//! instance, fetch every transaction, and decode the Solido instructions in it
+//! into a typed timeline.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use borsh::BorshDeserialize;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_program::{clock::Slot, instruction::AccountMeta, message::Message, pubkey::Pubkey};
+use solana_sdk::clock::UnixTimestamp;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{
+    EncodedConfirmedTransaction, UiInstruction, UiTransactionEncoding,
+};
+
+use lido::{
+    instruction::{
+        AddMaintainerMeta, AddValidatorMeta, ChangeRewardDistributionMeta, ClaimValidatorFeeMeta,
+        CollectValidatorFeeMeta, DeactivateValidatorMeta, DepositAccountsMeta,
+        InitializeAccountsMeta, LidoInstruction, MergeStakeMeta, MigrateStateToV1Meta,
+        RemoveMaintainerMeta, RemoveValidatorMeta, StakeDepositAccountsMeta, UnstakeAccountsMeta,
+        UpdateExchangeRateAccountsMeta, WithdrawAccountsMeta, WithdrawInactiveStakeMeta,
+    },
+    token::{Lamports, StLamports},
+    util::serialize_b58,
+};
+
+use crate::config::{EpochFilter, HistoryOpts};
+use crate::error::{CliError, Error};
+
+/// The maximum number of signatures that `GetSignaturesForAddress` returns per call.
+const SIGNATURES_PAGE_SIZE: usize = 1000;
+
+/// Source of transactions that reference an address, usually an RPC node.
+pub trait TransactionSource {
+    /// Return signatures of transactions that reference `address`, newest first.
+    ///
+    /// Returns at most `limit` signatures, all older than `before`, if provided.
+    fn get_signatures(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, Error>;
+
+    /// Return the transaction with the given signature.
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction, Error>;
+}
+
+impl TransactionSource for RpcClient {
+    fn get_signatures(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, Error> {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: Some(limit),
+            commitment: None,
+        };
+        Ok(self.get_signatures_for_address_with_config(address, config)?)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction, Error> {
+        Ok(RpcClient::get_transaction(
+            self,
+            signature,
+            UiTransactionEncoding::Base64,
+        )?)
+    }
+}
+
+/// Responses of `GetSignaturesForAddress` and `GetTransaction`, recorded earlier.
+///
+/// This is used to test the history against a fixture, without a running RPC node.
+#[derive(Deserialize, Serialize)]
+pub struct RecordedTransactions {
+    /// Signatures of all transactions, newest first, like the RPC returns them.
+    pub signatures: Vec<RpcConfirmedTransactionStatusWithSignature>,
+
+    /// Transactions by signature.
+    pub transactions: HashMap<String, EncodedConfirmedTransaction>,
+}
+
+impl TransactionSource for RecordedTransactions {
+    fn get_signatures(
+        &self,
+        _address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, Error> {
+        let start = match before {
+            None => 0,
+            Some(before) => match self
+                .signatures
+                .iter()
+                .position(|s| s.signature == before.to_string())
+            {
+                Some(i) => i + 1,
+                None => return Ok(Vec::new()),
+            },
+        };
+        Ok(self
+            .signatures
+            .iter()
+            .skip(start)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction, Error> {
+        match self.transactions.get(&signature.to_string()) {
+            // `EncodedConfirmedTransaction` is not `Clone`, so round-trip it
+            // through json instead.
+            Some(tx) => Ok(serde_json::from_value(serde_json::to_value(tx)?)?),
+            None => Err(CliError::with_cause(
+                "The transaction is not in the recording.",
+                signature,
+            )),
+        }
+    }
+}
+
+/// A Solido instruction, with its accounts resolved to names.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum HistoryEvent {
+    Initialize {
+        #[serde(serialize_with = "serialize_b58")]
+        manager: Pubkey,
+    },
+    Deposit {
+        #[serde(serialize_with = "serialize_b58")]
+        user: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        recipient: Pubkey,
+        amount: Lamports,
+    },
+    Withdraw {
+        #[serde(serialize_with = "serialize_b58")]
+        user: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        destination_stake_account: Pubkey,
+        amount: StLamports,
+    },
+    StakeDeposit {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+        amount: Lamports,
+    },
+    Unstake {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        unstake_account: Pubkey,
+        amount: Lamports,
+    },
+    MergeStake {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        from_stake: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        to_stake: Pubkey,
+    },
+    UpdateExchangeRate,
+    WithdrawInactiveStake {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    CollectValidatorFee {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    ClaimValidatorFee {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_fee_st_sol_account: Pubkey,
+    },
+    ChangeRewardDistribution {
+        #[serde(serialize_with = "serialize_b58")]
+        manager: Pubkey,
+    },
+    AddValidator {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    DeactivateValidator {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    RemoveValidator {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    AddMaintainer {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+    },
+    RemoveMaintainer {
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+    },
+    MigrateStateToV1 {
+        #[serde(serialize_with = "serialize_b58")]
+        validator_list: Pubkey,
+    },
+}
+
+impl fmt::Display for HistoryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryEvent::Initialize { manager } => {
+                write!(f, "Initialize, manager {}", manager)
+            }
+            HistoryEvent::Deposit {
+                user,
+                recipient,
+                amount,
+            } => write!(f, "Deposit {} by {} to {}", amount, user, recipient),
+            HistoryEvent::Withdraw {
+                user,
+                validator_vote_account,
+                destination_stake_account,
+                amount,
+            } => write!(
+                f,
+                "Withdraw {} by {} from validator {} into {}",
+                amount, user, validator_vote_account, destination_stake_account
+            ),
+            HistoryEvent::StakeDeposit {
+                maintainer,
+                validator_vote_account,
+                stake_account,
+                amount,
+            } => write!(
+                f,
+                "Stake {} with validator {} in {}, by {}",
+                amount, validator_vote_account, stake_account, maintainer
+            ),
+            HistoryEvent::Unstake {
+                maintainer,
+                validator_vote_account,
+                unstake_account,
+                amount,
+            } => write!(
+                f,
+                "Unstake {} from validator {} into {}, by {}",
+                amount, validator_vote_account, unstake_account, maintainer
+            ),
+            HistoryEvent::MergeStake {
+                validator_vote_account,
+                from_stake,
+                to_stake,
+            } => write!(
+                f,
+                "Merge stake of validator {} from {} into {}",
+                validator_vote_account, from_stake, to_stake
+            ),
+            HistoryEvent::UpdateExchangeRate => write!(f, "Update exchange rate"),
+            HistoryEvent::WithdrawInactiveStake {
+                validator_vote_account,
+            } => write!(
+                f,
+                "Withdraw inactive stake of validator {}",
+                validator_vote_account
+            ),
+            HistoryEvent::CollectValidatorFee {
+                validator_vote_account,
+            } => write!(f, "Collect fees of validator {}", validator_vote_account),
+            HistoryEvent::ClaimValidatorFee {
+                validator_fee_st_sol_account,
+            } => write!(f, "Claim validator fee to {}", validator_fee_st_sol_account),
+            HistoryEvent::ChangeRewardDistribution { manager } => {
+                write!(f, "Change reward distribution, by {}", manager)
+            }
+            HistoryEvent::AddValidator {
+                validator_vote_account,
+            } => write!(f, "Add validator {}", validator_vote_account),
+            HistoryEvent::DeactivateValidator {
+                validator_vote_account,
+            } => write!(f, "Deactivate validator {}", validator_vote_account),
+            HistoryEvent::RemoveValidator {
+                validator_vote_account,
+            } => write!(f, "Remove validator {}", validator_vote_account),
+            HistoryEvent::AddMaintainer { maintainer } => {
+                write!(f, "Add maintainer {}", maintainer)
+            }
+            HistoryEvent::RemoveMaintainer { maintainer } => {
+                write!(f, "Remove maintainer {}", maintainer)
+            }
+            HistoryEvent::MigrateStateToV1 { validator_list } => {
+                write!(
+                    f,
+                    "Migrate state to version 1, validator list {}",
+                    validator_list
+                )
+            }
+        }
+    }
+}
+
+/// A Solido instruction in a transaction, and where the transaction happened.
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub signature: String,
+    pub slot: Slot,
+    pub block_time: Option<UnixTimestamp>,
+
+    /// Whether the transaction executed successfully. Failed transactions
+    /// are included, but they had no effect.
+    pub succeeded: bool,
+
+    pub event: HistoryEvent,
+}
+
+#[derive(Serialize)]
+pub struct HistoryOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub solido_address: Pubkey,
+
+    /// First slot that we looked at.
+    pub from_slot: Slot,
+
+    /// Last slot that we looked at, or none if we looked up to the latest transaction.
+    pub to_slot: Option<Slot>,
+
+    /// The Solido instructions, oldest first.
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl fmt::Display for HistoryOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "History of Solido instance {} from slot {}",
+            self.solido_address, self.from_slot
+        )?;
+        match self.to_slot {
+            Some(to_slot) => writeln!(f, " to slot {}:", to_slot)?,
+            None => writeln!(f, ":")?,
+        }
+        if self.entries.is_empty() {
+            writeln!(f, "No Solido instructions found.")?;
+        }
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "\nSlot {}, transaction {}{}",
+                entry.slot,
+                entry.signature,
+                if entry.succeeded { "" } else { " (failed)" },
+            )?;
+            writeln!(f, "  {}", entry.event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reconstruct the `AccountMeta`s of an instruction in a compiled message.
+fn get_instruction_accounts(message: &Message, account_indices: &[u8]) -> Vec<AccountMeta> {
+    account_indices
+        .iter()
+        .map(|&i| {
+            let i = i as usize;
+            AccountMeta {
+                pubkey: message.account_keys[i],
+                is_signer: message.is_signer(i),
+                is_writable: message.is_writable(i),
+            }
+        })
+        .collect()
+}
+
+/// Decode a Solido instruction into a history event.
+///
+/// Returns `None` for instructions that belong to a different Solido instance,
+/// and an error for instructions that are not valid Solido instructions.
+pub fn decode_instruction(
+    solido_address: &Pubkey,
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<Option<HistoryEvent>, Error> {
+    let instruction = LidoInstruction::try_from_slice(data)?;
+    let (lido, event) = match instruction {
+        LidoInstruction::Initialize { .. } => {
+            let accounts = InitializeAccountsMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::Initialize {
+                manager: accounts.manager,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::Deposit { amount } => {
+            let accounts = DepositAccountsMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::Deposit {
+                user: accounts.user,
+                recipient: accounts.recipient,
+                amount,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::Withdraw { amount } => {
+            let accounts = WithdrawAccountsMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::Withdraw {
+                user: accounts.st_sol_account_owner,
+                validator_vote_account: accounts.validator_vote_account,
+                destination_stake_account: accounts.destination_stake_account,
+                amount,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::StakeDeposit { amount } => {
+            let accounts = StakeDepositAccountsMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::StakeDeposit {
+                maintainer: accounts.maintainer,
+                validator_vote_account: accounts.validator_vote_account,
+                stake_account: accounts.stake_account_end,
+                amount,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::Unstake { amount } => {
+            let accounts = UnstakeAccountsMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::Unstake {
+                maintainer: accounts.maintainer,
+                validator_vote_account: accounts.validator_vote_account,
+                unstake_account: accounts.destination_unstake_account,
+                amount,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::UpdateExchangeRate => {
+            let accounts = UpdateExchangeRateAccountsMeta::try_from_slice(accounts)?;
+            (accounts.lido, HistoryEvent::UpdateExchangeRate)
+        }
+        LidoInstruction::WithdrawInactiveStake => {
+            let accounts = WithdrawInactiveStakeMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::WithdrawInactiveStake {
+                validator_vote_account: accounts.validator_vote_account,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::CollectValidatorFee => {
+            let accounts = CollectValidatorFeeMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::CollectValidatorFee {
+                validator_vote_account: accounts.validator_vote_account,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::ClaimValidatorFee => {
+            let accounts = ClaimValidatorFeeMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::ClaimValidatorFee {
+                validator_fee_st_sol_account: accounts.validator_fee_st_sol_account,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::ChangeRewardDistribution { .. } => {
+            let accounts = ChangeRewardDistributionMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::ChangeRewardDistribution {
+                manager: accounts.manager,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::AddValidator => {
+            let accounts = AddValidatorMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::AddValidator {
+                validator_vote_account: accounts.validator_vote_account,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::DeactivateValidator => {
+            let accounts = DeactivateValidatorMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::DeactivateValidator {
+                validator_vote_account: accounts.validator_vote_account_to_deactivate,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::RemoveValidator => {
+            let accounts = RemoveValidatorMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::RemoveValidator {
+                validator_vote_account: accounts.validator_vote_account_to_remove,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::AddMaintainer => {
+            let accounts = AddMaintainerMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::AddMaintainer {
+                maintainer: accounts.maintainer,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::RemoveMaintainer => {
+            let accounts = RemoveMaintainerMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::RemoveMaintainer {
+                maintainer: accounts.maintainer,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::MergeStake => {
+            let accounts = MergeStakeMeta::try_from_slice(accounts)?;
+            let event = HistoryEvent::MergeStake {
+                validator_vote_account: accounts.validator_vote_account,
+                from_stake: accounts.from_stake,
+                to_stake: accounts.to_stake,
+            };
+            (accounts.lido, event)
+        }
+        LidoInstruction::MigrateStateToV1 => {
+            let accounts = MigrateStateToV1Meta::try_from_slice(accounts)?;
+            let event = HistoryEvent::MigrateStateToV1 {
+                validator_list: accounts.validator_list,
+            };
+            (accounts.lido, event)
+        }
+    };
+
+    if &lido == solido_address {
+        Ok(Some(event))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Return the signatures of transactions in the slot range `from_slot..=to_slot`, newest first.
+fn get_signatures_in_range<S: TransactionSource>(
+    source: &S,
+    address: &Pubkey,
+    from_slot: Slot,
+    to_slot: Option<Slot>,
+    page_size: usize,
+) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, Error> {
+    // The RPC returns signatures newest first, so we can stop paging once we
+    // are past `from_slot`.
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page = source.get_signatures(address, before, page_size)?;
+        for status in &page {
+            if status.slot < from_slot {
+                return Ok(signatures);
+            }
+            if to_slot.map(|to_slot| status.slot <= to_slot) != Some(false) {
+                signatures.push(status.clone());
+            }
+        }
+        match page.last() {
+            Some(status) if page.len() == page_size => {
+                let signature = Signature::from_str(&status.signature)
+                    .map_err(|err| CliError::with_cause("Invalid signature from RPC.", err))?;
+                before = Some(signature);
+            }
+            _ => return Ok(signatures),
+        }
+    }
+}
+
+/// Return the instructions of a transaction in the order they executed, as
+/// program id index, account indices, and data.
+///
+/// Instructions that a program invoked, such as the instructions that the
+/// Multisig program executes on behalf of the manager, follow the top-level
+/// instruction that invoked them.
+fn get_executed_instructions(
+    transaction: &EncodedConfirmedTransaction,
+    message: &Message,
+) -> Vec<(u8, Vec<u8>, Vec<u8>)> {
+    let inner_instructions = transaction
+        .transaction
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.inner_instructions.as_ref());
+    let mut result = Vec::new();
+    for (i, instruction) in message.instructions.iter().enumerate() {
+        result.push((
+            instruction.program_id_index,
+            instruction.accounts.clone(),
+            instruction.data.clone(),
+        ));
+        let inner = inner_instructions
+            .iter()
+            .flat_map(|all| all.iter())
+            .filter(|inner| inner.index as usize == i)
+            .flat_map(|inner| inner.instructions.iter());
+        for instruction in inner {
+            // With the binary encoding that we request, the RPC does not
+            // parse instructions, so they are all compiled instructions.
+            if let UiInstruction::Compiled(compiled) = instruction {
+                if let Ok(data) = bs58::decode(&compiled.data).into_vec() {
+                    result.push((compiled.program_id_index, compiled.accounts.clone(), data));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Return the Solido instructions in transactions in the slot range `from_slot..=to_slot`, oldest first.
+///
+/// This includes the instructions that other programs invoke, such as the
+/// instructions that the Multisig program executes on behalf of the manager.
+pub fn get_history<S: TransactionSource>(
+    source: &S,
+    solido_program_id: &Pubkey,
+    solido_address: &Pubkey,
+    from_slot: Slot,
+    to_slot: Option<Slot>,
+) -> Result<Vec<HistoryEntry>, Error> {
+    let signatures = get_signatures_in_range(
+        source,
+        solido_address,
+        from_slot,
+        to_slot,
+        SIGNATURES_PAGE_SIZE,
+    )?;
+
+    let mut entries = Vec::new();
+    for status in signatures.iter().rev() {
+        let signature = Signature::from_str(&status.signature)
+            .map_err(|err| CliError::with_cause("Invalid signature from RPC.", err))?;
+        let encoded = source.get_transaction(&signature)?;
+        let transaction = match encoded.transaction.transaction.decode() {
+            Some(tx) => tx,
+            None => {
+                return Err(CliError::with_cause(
+                    "Failed to decode transaction.",
+                    signature,
+                ))
+            }
+        };
+        let message = &transaction.message;
+        for (program_id_index, account_indices, data) in
+            get_executed_instructions(&encoded, message)
+        {
+            if message.account_keys.get(program_id_index as usize) != Some(solido_program_id) {
+                continue;
+            }
+            let accounts = get_instruction_accounts(message, &account_indices);
+            // Transactions can fail because of malformed instructions, so we
+            // skip instructions that we cannot decode, rather than failing.
+            if let Ok(Some(event)) = decode_instruction(solido_address, &data, &accounts) {
+                entries.push(HistoryEntry {
+                    signature: status.signature.clone(),
+                    slot: status.slot,
+                    block_time: encoded.block_time,
+                    succeeded: status.err.is_none(),
+                    event,
+                });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// CLI entry point to show the history of a Solido instance.
+pub fn command_history(rpc_client: &RpcClient, opts: &HistoryOpts) -> Result<HistoryOutput, Error> {
+    let (from_slot, to_slot) = match opts.epoch() {
+        EpochFilter::All => (*opts.from_slot(), None),
+        EpochFilter::Epoch(epoch) => {
+            let epoch_schedule = rpc_client.get_epoch_schedule()?;
+            let first_slot = epoch_schedule.get_first_slot_in_epoch(*epoch);
+            let last_slot = epoch_schedule.get_last_slot_in_epoch(*epoch);
+            (first_slot.max(*opts.from_slot()), Some(last_slot))
+        }
+    };
+
+    let entries = get_history(
+        rpc_client,
+        opts.solido_program_id(),
+        opts.solido_address(),
+        from_slot,
+        to_slot,
+    )?;
+
+    Ok(HistoryOutput {
+        solido_address: *opts.solido_address(),
+        from_slot,
+        to_slot,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use solana_program::instruction::Instruction;
+    use solana_sdk::transaction::Transaction;
+    use solana_transaction_status::{
+        EncodedTransaction, EncodedTransactionWithStatusMeta, UiCompiledInstruction,
+        UiInnerInstructions, UiTransactionStatusMeta,
+    };
+
+    /// Return the recorded transactions from `test_data/history.json`.
+    ///
+    /// The recording contains, oldest first:
+    ///
+    /// * Slot 100: a deposit of 5 SOL.
+    /// * Slot 105: a deposit of 7 SOL into a different Solido instance.
+    /// * Slot 110: a system transfer, and a `StakeDeposit` of 4 SOL.
+    /// * Slot 432000: `UpdateExchangeRate`, the first slot of epoch 1.
+    /// * Slot 432010: a withdrawal of 1 stSOL that failed.
+    /// * Slot 432020: a deposit of 2 SOL.
+    fn load_fixture() -> RecordedTransactions {
+        serde_json::from_str(include_str!("../test_data/history.json")).unwrap()
+    }
+
+    fn key(n: u8) -> Pubkey {
+        Pubkey::new(&[n; 32])
+    }
+
+    fn get_events(from_slot: Slot, to_slot: Option<Slot>) -> Vec<(Slot, bool, HistoryEvent)> {
+        let solido_program_id = key(1);
+        let solido_address = key(2);
+        get_history(
+            &load_fixture(),
+            &solido_program_id,
+            &solido_address,
+            from_slot,
+            to_slot,
+        )
+        .ok()
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.slot, entry.succeeded, entry.event))
+        .collect()
+    }
+
+    #[test]
+    fn get_history_decodes_solido_instructions_oldest_first() {
+        let events = get_events(0, None);
+        let slots: Vec<Slot> = events.iter().map(|(slot, _, _)| *slot).collect();
+        // The deposit into the other instance in slot 105 is not included.
+        assert_eq!(slots, vec![100, 110, 432_000, 432_010, 432_020]);
+
+        match &events[0].2 {
+            HistoryEvent::Deposit {
+                recipient, amount, ..
+            } => {
+                assert_eq!(*recipient, key(10));
+                assert_eq!(*amount, Lamports(5_000_000_000));
+            }
+            event => panic!("Expected a deposit, got {:?}.", event),
+        }
+        match &events[1].2 {
+            HistoryEvent::StakeDeposit {
+                validator_vote_account,
+                stake_account,
+                amount,
+                ..
+            } => {
+                assert_eq!(*validator_vote_account, key(9));
+                assert_eq!(*stake_account, key(12));
+                assert_eq!(*amount, Lamports(4_000_000_000));
+            }
+            event => panic!("Expected a stake deposit, got {:?}.", event),
+        }
+        assert_eq!(events[2].2, HistoryEvent::UpdateExchangeRate);
+        match &events[3] {
+            (_, false, HistoryEvent::Withdraw { amount, .. }) => {
+                assert_eq!(*amount, StLamports(1_000_000_000));
+            }
+            event => panic!("Expected a failed withdrawal, got {:?}.", event),
+        }
+        assert!(events[4].1);
+    }
+
+    #[test]
+    fn get_history_respects_slot_range() {
+        let slots = |events: Vec<(Slot, bool, HistoryEvent)>| -> Vec<Slot> {
+            events.iter().map(|(slot, _, _)| *slot).collect()
+        };
+        assert_eq!(slots(get_events(110, Some(110))), vec![110]);
+        assert_eq!(
+            slots(get_events(432_000, Some(863_999))),
+            vec![432_000, 432_010, 432_020]
+        );
+        assert_eq!(slots(get_events(0, Some(431_999))), vec![100, 110]);
+        assert_eq!(slots(get_events(500_000, None)), Vec::<Slot>::new());
+    }
+
+    /// Return a transaction in which the Multisig program executes `instruction`.
+    fn new_multisig_execution(
+        instruction: &Instruction,
+        slot: Slot,
+    ) -> EncodedConfirmedTransaction {
+        let mut accounts = instruction.accounts.clone();
+        accounts.push(AccountMeta::new_readonly(instruction.program_id, false));
+        let execute = Instruction {
+            program_id: key(20),
+            accounts,
+            data: Vec::new(),
+        };
+        let message = Message::new(&[execute], Some(&key(21)));
+        let index_of = |address: &Pubkey| {
+            message
+                .account_keys
+                .iter()
+                .position(|k| k == address)
+                .unwrap() as u8
+        };
+        let inner = UiCompiledInstruction {
+            program_id_index: index_of(&instruction.program_id),
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|account| index_of(&account.pubkey))
+                .collect(),
+            data: bs58::encode(&instruction.data).into_string(),
+        };
+        let meta = UiTransactionStatusMeta {
+            err: None,
+            status: Ok(()),
+            fee: 5000,
+            pre_balances: Vec::new(),
+            post_balances: Vec::new(),
+            inner_instructions: Some(vec![UiInnerInstructions {
+                index: 0,
+                instructions: vec![UiInstruction::Compiled(inner)],
+            }]),
+            log_messages: None,
+            pre_token_balances: None,
+            post_token_balances: None,
+            rewards: None,
+        };
+        EncodedConfirmedTransaction {
+            slot,
+            transaction: EncodedTransactionWithStatusMeta {
+                transaction: EncodedTransaction::encode(
+                    Transaction::new_unsigned(message),
+                    UiTransactionEncoding::Base64,
+                ),
+                meta: Some(meta),
+            },
+            block_time: None,
+        }
+    }
+
+    #[test]
+    fn get_history_decodes_instructions_executed_by_the_multisig() {
+        let solido_program_id = key(1);
+        let solido_address = key(2);
+        let instruction = lido::instruction::add_maintainer(
+            &solido_program_id,
+            &AddMaintainerMeta {
+                lido: solido_address,
+                manager: key(6),
+                maintainer: key(7),
+            },
+        );
+        let signature = Signature::new(&[1; 64]);
+        let recording = RecordedTransactions {
+            signatures: vec![RpcConfirmedTransactionStatusWithSignature {
+                signature: signature.to_string(),
+                slot: 200,
+                err: None,
+                memo: None,
+                block_time: None,
+                confirmation_status: None,
+            }],
+            transactions: vec![(
+                signature.to_string(),
+                new_multisig_execution(&instruction, 200),
+            )]
+            .into_iter()
+            .collect(),
+        };
+
+        let entries = get_history(&recording, &solido_program_id, &solido_address, 0, None)
+            .ok()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].event,
+            HistoryEvent::AddMaintainer { maintainer: key(7) }
+        );
+    }
+
+    #[test]
+    fn decode_instruction_rejects_trailing_data() {
+        let solido_address = key(2);
+        let instruction = lido::instruction::add_maintainer(
+            &key(1),
+            &AddMaintainerMeta {
+                lido: solido_address,
+                manager: key(6),
+                maintainer: key(7),
+            },
+        );
+        assert!(
+            decode_instruction(&solido_address, &instruction.data, &instruction.accounts).is_ok()
+        );
+
+        let mut data = instruction.data.clone();
+        data.push(0);
+        assert!(decode_instruction(&solido_address, &data, &instruction.accounts).is_err());
+    }
+
+    #[test]
+    fn get_signatures_in_range_pages_through_signatures() {
+        let recording = load_fixture();
+        for page_size in 1..8 {
+            let signatures = get_signatures_in_range(&recording, &key(2), 101, None, page_size)
+                .ok()
+                .unwrap();
+            let slots: Vec<Slot> = signatures.iter().map(|s| s.slot).collect();
+            assert_eq!(slots, vec![432_020, 432_010, 432_000, 110, 105]);
+        }
+    }
+}
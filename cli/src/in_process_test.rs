@@ -0,0 +1,480 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! End-to-end tests that run CLI commands and the maintainer against an
+//! in-process cluster, see [`MockRpc`].
+//!
+//! Tests that stake need the BPF build of the programs; run them with
+//! `BPF_OUT_DIR=target/deploy cargo test --features test-bpf`.
+
+use clap::Clap;
+use solana_account_decoder::validator_info;
+use solana_config_program::ConfigKeys;
+use solana_sdk::account::Account;
+use solana_sdk::borsh::try_from_slice_unchecked;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_vote_program::vote_instruction;
+use solana_vote_program::vote_state::{VoteInit, VoteState};
+
+use lido::state::{Lido, Validators};
+use lido::token::Lamports;
+
+use crate::config::{
+    AddRemoveMaintainerOpts, AddValidatorOpts, CreateMultisigOpts, CreateSolidoOpts, DepositOpts,
+    OutputMode, PerformMaintenanceOpts, RebalancingConfig, RunMaintainerOpts,
+};
+use crate::daemon::{Daemon, NextIteration};
+use crate::helpers::{
+    command_add_maintainer, command_add_validator, command_create_solido, command_deposit,
+    CreateSolidoOutput,
+};
+use crate::maintenance::{run_perform_maintenance, MaintenanceOperation};
+use crate::mock_rpc::MockRpc;
+use crate::multisig::{create_multisig, execute_transaction, ProposeInstructionOutput};
+use crate::snapshot::SnapshotClient;
+use crate::spl_token_utils::push_create_spl_token_account;
+use crate::{Config, SnapshotClientConfig};
+
+fn new_config<'a>(mock: &MockRpc, signer: &'a Keypair) -> SnapshotClientConfig<'a> {
+    Config {
+        client: SnapshotClient::new(Box::new(mock.clone())),
+        signer,
+        output_mode: OutputMode::Json,
+    }
+}
+
+/// A Solido instance, managed by a multisig where the signer is the only owner.
+///
+/// The signer is also the only maintainer.
+struct Instance {
+    multisig_address: Pubkey,
+    solido: CreateSolidoOutput,
+}
+
+impl Instance {
+    fn new(config: &mut SnapshotClientConfig, mock: &MockRpc) -> Instance {
+        let solido_program_id = mock.solido_program_id.to_string();
+        let multisig_program_id = mock.multisig_program_id.to_string();
+        let signer_address = config.signer.pubkey().to_string();
+
+        // With a threshold of 1, proposals are approved as soon as they are created.
+        let mut opts = CreateMultisigOpts::parse_from(&[
+            "create-multisig",
+            "--threshold",
+            "1",
+            "--owners",
+            &signer_address,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let multisig = config
+            .with_snapshot(|config| create_multisig(config, &opts))
+            .ok()
+            .unwrap();
+        let multisig_address = multisig.multisig_address.to_string();
+
+        let mut opts = CreateSolidoOpts::parse_from(&[
+            "create-solido",
+            "--solido-program-id",
+            &solido_program_id,
+            "--max-validators",
+            "10",
+            "--max-maintainers",
+            "10",
+            "--treasury-fee-share",
+            "5",
+            "--validation-fee-share",
+            "3",
+            "--developer-fee-share",
+            "2",
+            "--st-sol-appreciation-share",
+            "90",
+            "--treasury-account-owner",
+            &signer_address,
+            "--developer-account-owner",
+            &signer_address,
+            "--multisig-address",
+            &multisig_address,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let solido = config
+            .with_snapshot(|config| command_create_solido(config, &opts))
+            .ok()
+            .unwrap();
+
+        let instance = Instance {
+            multisig_address: multisig.multisig_address,
+            solido,
+        };
+
+        let mut opts = AddRemoveMaintainerOpts::parse_from(&[
+            "add-maintainer",
+            "--solido-program-id",
+            &solido_program_id,
+            "--solido-address",
+            &instance.solido.solido_address.to_string(),
+            "--maintainer-address",
+            &signer_address,
+            "--multisig-address",
+            &multisig_address,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let proposal = config
+            .with_snapshot(|config| command_add_maintainer(config, &opts))
+            .ok()
+            .unwrap();
+        instance.execute(config, mock, &proposal);
+
+        instance
+    }
+
+    /// Execute a proposal of the multisig.
+    fn execute(
+        &self,
+        config: &mut SnapshotClientConfig,
+        mock: &MockRpc,
+        proposal: &ProposeInstructionOutput,
+    ) {
+        config
+            .with_snapshot(|config| {
+                execute_transaction(
+                    config,
+                    &proposal.transaction_address,
+                    &mock.multisig_program_id,
+                    &self.multisig_address,
+                )
+            })
+            .ok()
+            .unwrap();
+    }
+
+    /// Create a vote account that Solido accepts, and add it as a validator.
+    fn add_validator(&self, config: &mut SnapshotClientConfig, mock: &MockRpc) -> Pubkey {
+        let node = Keypair::new();
+        let vote_account = Keypair::new();
+        let solido = self.get_solido(mock);
+        let rewards_withdraw_authority = solido
+            .get_rewards_withdraw_authority(&mock.solido_program_id, &self.solido.solido_address)
+            .unwrap();
+
+        let fee_account = config
+            .with_snapshot(|config| {
+                let vote_rent = config
+                    .client
+                    .get_minimum_balance_for_rent_exemption(VoteState::size_of())?;
+                let mut instructions = vec![system_instruction::transfer(
+                    &config.signer.pubkey(),
+                    &node.pubkey(),
+                    1_000_000_000,
+                )];
+                instructions.extend(vote_instruction::create_account(
+                    &config.signer.pubkey(),
+                    &vote_account.pubkey(),
+                    &VoteInit {
+                        node_pubkey: node.pubkey(),
+                        authorized_voter: node.pubkey(),
+                        authorized_withdrawer: rewards_withdraw_authority,
+                        commission: 100,
+                    },
+                    vote_rent.0,
+                ));
+                let fee_account = push_create_spl_token_account(
+                    config,
+                    &mut instructions,
+                    &self.solido.st_sol_mint_address,
+                    &node.pubkey(),
+                )?;
+                config.sign_and_send_transaction(
+                    &instructions,
+                    &[config.signer, &node, &vote_account, &fee_account],
+                )?;
+                Ok(fee_account.pubkey())
+            })
+            .ok()
+            .unwrap();
+
+        let mut opts = AddValidatorOpts::parse_from(&[
+            "add-validator",
+            "--solido-program-id",
+            &mock.solido_program_id.to_string(),
+            "--solido-address",
+            &self.solido.solido_address.to_string(),
+            "--validator-vote-account",
+            &vote_account.pubkey().to_string(),
+            "--validator-fee-account",
+            &fee_account.to_string(),
+            "--multisig-address",
+            &self.multisig_address.to_string(),
+            "--multisig-program-id",
+            &mock.multisig_program_id.to_string(),
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let proposal = config
+            .with_snapshot(|config| command_add_validator(config, &opts))
+            .ok()
+            .unwrap();
+        self.execute(config, mock, &proposal);
+
+        // The maintainer needs the validator info to exist. Publishing it
+        // would need the Solana CLI's `ValidatorInfo` type, so we store the
+        // config account directly instead.
+        let key_list = ConfigKeys {
+            keys: vec![(validator_info::id(), false), (node.pubkey(), true)],
+        };
+        let mut data = bincode::serialize(&key_list).unwrap();
+        data.extend(bincode::serialize(r#"{"name":"Validator"}"#).unwrap());
+        mock.set_account(
+            &Pubkey::new_unique(),
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: solana_config_program::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        vote_account.pubkey()
+    }
+
+    fn deposit(&self, config: &mut SnapshotClientConfig, mock: &MockRpc, amount_sol: &str) {
+        let mut opts = DepositOpts::parse_from(&[
+            "deposit",
+            "--solido-program-id",
+            &mock.solido_program_id.to_string(),
+            "--solido-address",
+            &self.solido.solido_address.to_string(),
+            "--amount-sol",
+            amount_sol,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        command_deposit(config, &opts).ok().unwrap();
+    }
+
+    /// Perform maintenance until there is nothing left to do, return the operations performed.
+    fn perform_all_maintenance(
+        &self,
+        config: &mut SnapshotClientConfig,
+        mock: &MockRpc,
+    ) -> Vec<MaintenanceOperation> {
+        let mut opts = PerformMaintenanceOpts::parse_from(&[
+            "perform-maintenance",
+            "--solido-program-id",
+            &mock.solido_program_id.to_string(),
+            "--solido-address",
+            &self.solido.solido_address.to_string(),
+            "--stake-time",
+            "anytime",
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let mut operations = Vec::new();
+        while let Some(output) = config
+            .with_snapshot(|config| {
+                run_perform_maintenance(config, &opts, &RebalancingConfig::default())
+            })
+            .ok()
+            .unwrap()
+        {
+            assert!(!output.send_failed());
+            for transaction in output.transactions {
+                operations.extend(transaction.operations);
+            }
+        }
+        operations
+    }
+
+    fn get_solido(&self, mock: &MockRpc) -> Lido {
+        let account = mock.get_account(&self.solido.solido_address).unwrap();
+        try_from_slice_unchecked(&account.data).unwrap()
+    }
+
+    fn new_run_maintainer_opts(&self, mock: &MockRpc, extra_args: &[&str]) -> RunMaintainerOpts {
+        let solido_program_id = mock.solido_program_id.to_string();
+        let solido_address = self.solido.solido_address.to_string();
+        let mut args = vec![
+            "run-maintainer",
+            "--solido-program-id",
+            &solido_program_id,
+            "--solido-address",
+            &solido_address,
+        ];
+        args.extend_from_slice(extra_args);
+        let mut opts = RunMaintainerOpts::parse_from(&args);
+        opts.merge_with_config_and_environment(None);
+        opts
+    }
+}
+
+#[test]
+fn manage_solido_through_multisig_on_in_process_cluster() {
+    let (mock, payer) = MockRpc::start();
+    let mut config = new_config(&mock, &payer);
+    let instance = Instance::new(&mut config, &mock);
+
+    instance.deposit(&mut config, &mock, "10.0");
+    let reserve = mock.get_account(&instance.solido.reserve_account).unwrap();
+    let min_balance = Rent::default().minimum_balance(0);
+    assert_eq!(
+        Lamports(reserve.lamports - min_balance),
+        Lamports(10_000_000_000)
+    );
+
+    // In the next epoch, the maintainer updates the exchange rate. Without
+    // validators, there is nothing to stake.
+    mock.warp_to_epoch(1);
+    assert_eq!(
+        instance.perform_all_maintenance(&mut config, &mock),
+        vec![MaintenanceOperation::UpdateExchangeRate]
+    );
+
+    let solido = instance.get_solido(&mock);
+    assert_eq!(solido.maintainers.entries.len(), 1);
+    assert_eq!(solido.exchange_rate.computed_in_epoch, 1);
+
+    let vote_account = instance.add_validator(&mut config, &mock);
+    let validators = mock.get_account(&solido.validator_list).unwrap();
+    let validators: Validators = try_from_slice_unchecked(&validators.data).unwrap();
+    assert_eq!(validators.entries.len(), 1);
+    assert_eq!(validators.entries[0].pubkey, vote_account);
+}
+
+// Staking creates stake accounts through cross-program invocations, which only
+// works when we load the BPF build of the program.
+#[cfg(feature = "test-bpf")]
+#[test]
+fn withdraw_from_staked_validator_on_in_process_cluster() {
+    let (mock, payer) = MockRpc::start();
+    let mut config = new_config(&mock, &payer);
+    let instance = Instance::new(&mut config, &mock);
+    let vote_account = instance.add_validator(&mut config, &mock);
+    instance.deposit(&mut config, &mock, "10.0");
+
+    mock.warp_to_epoch(1);
+    let operations = instance.perform_all_maintenance(&mut config, &mock);
+    assert_eq!(operations[0], MaintenanceOperation::UpdateExchangeRate);
+    assert!(operations.iter().any(|operation| matches!(
+        operation,
+        MaintenanceOperation::StakeDeposit { validator_vote_account, .. }
+        if *validator_vote_account == vote_account
+    )));
+
+    let progress_path =
+        std::env::temp_dir().join(format!("solido-withdraw-{}.jsonl", Pubkey::new_unique()));
+    let mut opts = crate::config::WithdrawOpts::parse_from(&[
+        "withdraw",
+        "--solido-program-id",
+        &mock.solido_program_id.to_string(),
+        "--solido-address",
+        &instance.solido.solido_address.to_string(),
+        "--amount-st-sol",
+        "1.0",
+        "--progress-path",
+        progress_path.to_str().unwrap(),
+    ]);
+    opts.merge_with_config_and_environment(None);
+    let output = crate::helpers::command_withdraw(&mut config, &opts)
+        .ok()
+        .unwrap();
+    // The withdrawal completed, so there is nothing left to resume.
+    assert!(!progress_path.exists());
+
+    // Nobody earned rewards yet, so 1 stSOL is still worth 1 SOL.
+    assert_eq!(output.withdrawn_sol, Lamports(1_000_000_000));
+    assert_eq!(output.new_stake_accounts.len(), 1);
+    let withdrawn = &output.new_stake_accounts[0];
+    assert_eq!(withdrawn.validator_vote_account, vote_account);
+    assert_eq!(
+        withdrawn.amount_st_sol,
+        lido::token::StLamports(1_000_000_000)
+    );
+
+    // The new stake account belongs to us.
+    let stake_account = mock.get_account(&withdrawn.stake_account).unwrap();
+    let stake_state: solana_stake_program::stake_state::StakeState =
+        bincode::deserialize(&stake_account.data).unwrap();
+    let meta = stake_state.meta().unwrap();
+    assert_eq!(meta.authorized.staker, payer.pubkey());
+    assert_eq!(meta.authorized.withdrawer, payer.pubkey());
+
+    // And we paid for it with stSOL.
+    let st_sol_account = mock.get_account(&output.from_token_address).unwrap();
+    let st_sol_account =
+        <spl_token::state::Account as solana_sdk::program_pack::Pack>::unpack(&st_sol_account.data)
+            .unwrap();
+    assert_eq!(st_sol_account.amount, 9_000_000_000);
+}
+
+#[test]
+fn daemon_performs_maintenance_on_in_process_cluster() {
+    let (mock, payer) = MockRpc::start();
+    let mut config = new_config(&mock, &payer);
+    let instance = Instance::new(&mut config, &mock);
+    instance.deposit(&mut config, &mock, "10.0");
+    mock.warp_to_epoch(1);
+
+    let opts = instance.new_run_maintainer_opts(&mock, &[]);
+    let rebalancing = RebalancingConfig::default();
+    let mut daemon = Daemon::new(&mut config, &opts, &rebalancing);
+
+    // After performing maintenance, the daemon checks right away whether there
+    // is more to do. There is not, so it waits for the next poll.
+    assert_eq!(daemon.run_iteration(), NextIteration::Immediately);
+    assert_eq!(daemon.run_iteration(), NextIteration::AfterPollInterval);
+
+    let mut metrics = Vec::new();
+    daemon.metrics().write_prometheus(&mut metrics).unwrap();
+    let metrics = String::from_utf8(metrics).unwrap();
+    assert!(metrics.contains("solido_maintenance_polls_total 2"));
+    assert!(metrics.contains("solido_maintenance_errors_total 0"));
+    assert!(
+        metrics.contains("solido_maintenance_operations_total{operation=\"UpdateExchangeRate\"} 1")
+    );
+    // The maintainer is the only signer, and the fee is charged per signature.
+    assert!(metrics.contains(&format!(
+        "solido_maintainer_fees_spent_lamports {}",
+        MockRpc::LAMPORTS_PER_SIGNATURE
+    )));
+
+    assert_eq!(
+        instance.get_solido(&mock).exchange_rate.computed_in_epoch,
+        1
+    );
+}
+
+#[test]
+fn daemon_simulates_maintenance_in_dry_run_on_in_process_cluster() {
+    let (mock, payer) = MockRpc::start();
+    let mut config = new_config(&mock, &payer);
+    let instance = Instance::new(&mut config, &mock);
+    instance.deposit(&mut config, &mock, "10.0");
+    mock.warp_to_epoch(1);
+
+    let opts = instance.new_run_maintainer_opts(&mock, &["--dry-run", "true"]);
+    let rebalancing = RebalancingConfig::default();
+    let mut daemon = Daemon::new(&mut config, &opts, &rebalancing);
+
+    // In a dry run, nothing changes on-chain, so the daemon does not check
+    // again right away.
+    assert_eq!(daemon.run_iteration(), NextIteration::AfterPollInterval);
+
+    let mut metrics = Vec::new();
+    daemon.metrics().write_prometheus(&mut metrics).unwrap();
+    let metrics = String::from_utf8(metrics).unwrap();
+    assert!(metrics.contains("solido_maintenance_dry_run_simulation_failures_total 0"));
+    assert!(metrics.contains(
+        "solido_maintenance_dry_run_operations_total{operation=\"UpdateExchangeRate\"} 1"
+    ));
+
+    assert_eq!(
+        instance.get_solido(&mock).exchange_rate.computed_in_epoch,
+        0
+    );
+}
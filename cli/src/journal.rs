@@ -0,0 +1,537 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! A file-based journal of the maintenance transactions that the daemon sent.
+//!
+//! Sending a transaction can fail, while the transaction still executes (see
+//! [`crate::error::might_have_executed`]). If the daemon would simply retry
+//! after such an error, or after a restart in the middle of sending, it could
+//! submit the same maintenance twice. To prevent that, we record every
+//! transaction in the journal before we send it, and we only remove it from the
+//! journal once we know its outcome. As long as the journal holds transactions
+//! with an unknown outcome, the daemon does not perform new maintenance.
+//!
+//! The journal also records the fees that we paid, so the daemon's fee budget
+//! (see [`crate::budget`]) survives a restart.
+//!
+//! The journal file holds one json record per line, and we only append to it.
+//! When we open the journal, we compact it, so it holds only the transactions
+//! whose outcome is still unknown, and the fees paid in the most recent epoch.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::{Epoch, Slot};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::TransactionStatus;
+
+use lido::token::Lamports;
+use lido::util::{deserialize_b58, serialize_b58};
+
+use crate::error::{CliError, Error};
+use crate::maintenance::MaintenanceOperation;
+use crate::snapshot::Result;
+use crate::SnapshotConfig;
+
+/// How a transaction in the journal ended.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The transaction executed successfully.
+    Executed,
+
+    /// The transaction failed, either on-chain, or in preflight.
+    Failed,
+
+    /// The transaction did not execute, and its blockhash expired, so it never will.
+    Expired,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Executed => write!(f, "executed"),
+            Outcome::Failed => write!(f, "failed"),
+            Outcome::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+/// A line in the journal file.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Record {
+    Submitted {
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        signature: Signature,
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        recent_blockhash: Hash,
+        slot: Slot,
+        operations: serde_json::Value,
+    },
+    Resolved {
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        signature: Signature,
+        outcome: Outcome,
+    },
+    FeePaid {
+        epoch: Epoch,
+        fee: u64,
+        operations: Vec<String>,
+    },
+}
+
+/// A transaction that we sent, but for which we do not know the outcome yet.
+#[derive(Clone, Debug)]
+pub struct PendingTransaction {
+    pub signature: Signature,
+    pub recent_blockhash: Hash,
+
+    /// Slot of the state that the transaction was based on.
+    pub slot: Slot,
+
+    /// The operations that we expect the transaction to perform.
+    pub operations: serde_json::Value,
+
+    /// Whether the transaction was already pending when we opened the journal,
+    /// rather than sent since launch.
+    pub from_previous_run: bool,
+}
+
+impl PendingTransaction {
+    fn to_record(&self) -> Record {
+        Record::Submitted {
+            signature: self.signature,
+            recent_blockhash: self.recent_blockhash,
+            slot: self.slot,
+            operations: self.operations.clone(),
+        }
+    }
+}
+
+/// A fee that we paid for a transaction that performs the given kinds of operations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeePaid {
+    pub epoch: Epoch,
+    pub fee: Lamports,
+
+    /// The kinds of the operations, see [`MaintenanceOperation::kind`].
+    pub operations: Vec<String>,
+}
+
+impl FeePaid {
+    fn to_record(&self) -> Record {
+        Record::FeePaid {
+            epoch: self.epoch,
+            fee: self.fee.0,
+            operations: self.operations.clone(),
+        }
+    }
+}
+
+/// Counters of transactions with an ambiguous outcome, since launch.
+///
+/// Transactions that were still pending from before a restart are not counted,
+/// their ambiguous send was counted by the previous run.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct JournalCounters {
+    /// Number of transactions for which sending failed in a way that does not
+    /// tell whether the transaction executed.
+    pub ambiguous: u64,
+
+    /// Of the ambiguous transactions, the number that turned out to have executed.
+    pub resolved_executed: u64,
+
+    /// Of the ambiguous transactions, the number that turned out to have failed.
+    pub resolved_failed: u64,
+
+    /// Of the ambiguous transactions, the number that turned out to have expired.
+    pub resolved_expired: u64,
+}
+
+pub struct MaintenanceJournal {
+    /// The journal file, opened for appending.
+    file: File,
+
+    /// Transactions that we sent, for which we do not know the outcome yet, oldest first.
+    pending: Vec<PendingTransaction>,
+
+    /// Fees that we paid in the most recent epoch that we paid fees in.
+    fees_paid: Vec<FeePaid>,
+
+    pub counters: JournalCounters,
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record).expect("Serializing a record does not fail.");
+    line.push(b'\n');
+    out.write_all(&line)
+}
+
+/// Replay the records in a journal file, and return the transactions that are
+/// still pending, and the fees paid in the most recent epoch.
+///
+/// If we crashed while appending a record, the last line can be incomplete.
+/// That record was not durably written, so we ignore it.
+fn read_records<R: BufRead>(
+    reader: R,
+) -> std::result::Result<(Vec<PendingTransaction>, Vec<FeePaid>), Error> {
+    let lines = reader.lines().collect::<io::Result<Vec<String>>>()?;
+    let mut pending: Vec<PendingTransaction> = Vec::new();
+    let mut fees_paid: Vec<FeePaid> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let record: Record = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(..) if i + 1 == lines.len() => {
+                println!("Ignoring incomplete last record in the maintenance journal.");
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        match record {
+            Record::Submitted {
+                signature,
+                recent_blockhash,
+                slot,
+                operations,
+            } => pending.push(PendingTransaction {
+                signature,
+                recent_blockhash,
+                slot,
+                operations,
+                from_previous_run: true,
+            }),
+            Record::Resolved { signature, .. } => {
+                pending.retain(|tx| tx.signature != signature);
+            }
+            Record::FeePaid {
+                epoch,
+                fee,
+                operations,
+            } => {
+                // Only the most recent epoch counts towards the budget.
+                fees_paid.retain(|fee_paid| fee_paid.epoch >= epoch);
+                fees_paid.push(FeePaid {
+                    epoch,
+                    fee: Lamports(fee),
+                    operations,
+                });
+            }
+        }
+    }
+    Ok((pending, fees_paid))
+}
+
+impl MaintenanceJournal {
+    /// Open the journal at the given path, or create it if it does not exist.
+    pub fn open(path: &Path) -> std::result::Result<MaintenanceJournal, Error> {
+        let (pending, fees_paid) = match File::open(path) {
+            Ok(file) => read_records(BufReader::new(file))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Vec::new(), Vec::new()),
+            Err(err) => {
+                return Err(CliError::with_cause(
+                    "Failed to open the maintenance journal.",
+                    err,
+                ))
+            }
+        };
+
+        // Write the compacted journal to a new file, and then replace the old
+        // journal with it, so the journal is complete even if we crash here.
+        let compacted_path = path.with_extension("compacted");
+        let mut compacted = File::create(&compacted_path)?;
+        for transaction in &pending {
+            write_record(&mut compacted, &transaction.to_record())?;
+        }
+        for fee_paid in &fees_paid {
+            write_record(&mut compacted, &fee_paid.to_record())?;
+        }
+        compacted.sync_all()?;
+        std::fs::rename(&compacted_path, path)?;
+
+        Ok(MaintenanceJournal {
+            file: OpenOptions::new().append(true).open(path)?,
+            pending,
+            fees_paid,
+            counters: JournalCounters::default(),
+        })
+    }
+
+    /// Return the transactions that we sent, but whose outcome we do not know yet.
+    pub fn pending(&self) -> &[PendingTransaction] {
+        &self.pending
+    }
+
+    /// Return the fees that we paid in the most recent epoch that we paid fees in.
+    pub fn fees_paid(&self) -> &[FeePaid] {
+        &self.fees_paid
+    }
+
+    /// Append the record, and only return after it is on disk.
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        write_record(&mut self.file, record)?;
+        self.file.sync_data()
+    }
+
+    /// Record that we are about to send the signed transaction.
+    pub fn record_submitted(
+        &mut self,
+        transaction: &Transaction,
+        slot: Slot,
+        operations: &[MaintenanceOperation],
+    ) -> io::Result<()> {
+        let pending = PendingTransaction {
+            signature: transaction.signatures[0],
+            recent_blockhash: transaction.message.recent_blockhash,
+            slot,
+            operations: serde_json::to_value(operations)
+                .expect("Serializing operations does not fail."),
+            from_previous_run: false,
+        };
+        self.append(&pending.to_record())?;
+        self.pending.push(pending);
+        Ok(())
+    }
+
+    /// Record that we paid `fee` in `epoch` for a transaction that performs `operations`.
+    pub fn record_fee_paid(
+        &mut self,
+        epoch: Epoch,
+        fee: Lamports,
+        operations: &[MaintenanceOperation],
+    ) -> io::Result<()> {
+        let fee_paid = FeePaid {
+            epoch,
+            fee,
+            operations: operations.iter().map(|op| op.kind().to_string()).collect(),
+        };
+        self.append(&fee_paid.to_record())?;
+        self.fees_paid.retain(|other| other.epoch >= epoch);
+        self.fees_paid.push(fee_paid);
+        Ok(())
+    }
+
+    /// Record that we know the outcome of the transaction.
+    pub fn record_resolved(&mut self, signature: &Signature, outcome: Outcome) -> io::Result<()> {
+        self.append(&Record::Resolved {
+            signature: *signature,
+            outcome,
+        })?;
+        self.pending.retain(|tx| tx.signature != *signature);
+        Ok(())
+    }
+}
+
+/// Return the outcome of a pending transaction, if we can know it yet.
+///
+/// `blockhash_is_recent` indicates whether the transaction's blockhash is still
+/// recent enough for the transaction to be included in a block.
+pub fn get_outcome(
+    status: Option<&TransactionStatus>,
+    blockhash_is_recent: bool,
+) -> Option<Outcome> {
+    match status {
+        // Until the transaction is confirmed, it might still be rolled back.
+        Some(status) if !status.satisfies_commitment(CommitmentConfig::confirmed()) => None,
+        Some(status) if status.err.is_some() => Some(Outcome::Failed),
+        Some(..) => Some(Outcome::Executed),
+        None if blockhash_is_recent => None,
+        None => Some(Outcome::Expired),
+    }
+}
+
+/// Look up the outcome of the pending transactions in the journal, and record
+/// it for the transactions where it is known.
+pub fn resolve_pending(
+    config: &mut SnapshotConfig,
+    journal: &mut MaintenanceJournal,
+) -> Result<()> {
+    if journal.pending.is_empty() {
+        return Ok(());
+    }
+
+    // Read the blockhashes from the snapshot, which is older than the statuses
+    // that we read next. If a blockhash was no longer recent in the snapshot,
+    // a transaction that has no status by now will never execute.
+    let recent_blockhashes = config.client.get_recent_blockhashes()?;
+    let signatures: Vec<Signature> = journal.pending.iter().map(|tx| tx.signature).collect();
+    let statuses = config.client.get_signature_statuses(&signatures)?;
+
+    for (transaction, status) in journal.pending.clone().iter().zip(statuses) {
+        let blockhash_is_recent = recent_blockhashes
+            .iter()
+            .any(|entry| entry.blockhash == transaction.recent_blockhash);
+        match get_outcome(status.as_ref(), blockhash_is_recent) {
+            Some(outcome) => {
+                println!(
+                    "Journal: transaction {} from slot {} {}.",
+                    transaction.signature, transaction.slot, outcome
+                );
+                journal.record_resolved(&transaction.signature, outcome)?;
+                if !transaction.from_previous_run {
+                    match outcome {
+                        Outcome::Executed => journal.counters.resolved_executed += 1,
+                        Outcome::Failed => journal.counters.resolved_failed += 1,
+                        Outcome::Expired => journal.counters.resolved_expired += 1,
+                    }
+                }
+            }
+            None => println!(
+                "Journal: outcome of transaction {} from slot {} is not yet known.",
+                transaction.signature, transaction.slot
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_transaction_status::TransactionConfirmationStatus;
+    use std::path::PathBuf;
+
+    fn new_journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "solido-journal-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn new_signed_transaction() -> Transaction {
+        let payer = Keypair::new();
+        let message = Message::new(&[], Some(&payer.pubkey()));
+        Transaction::new(&[&payer], message, Hash::new_unique())
+    }
+
+    #[test]
+    fn journal_survives_reopening_and_drops_resolved_transactions() {
+        let path = new_journal_path("reopen");
+        let tx_a = new_signed_transaction();
+        let tx_b = new_signed_transaction();
+        let operations = [MaintenanceOperation::UpdateExchangeRate];
+
+        let mut journal = MaintenanceJournal::open(&path).ok().unwrap();
+        journal.record_submitted(&tx_a, 10, &operations).unwrap();
+        journal.record_submitted(&tx_b, 11, &operations).unwrap();
+        assert!(!journal.pending()[1].from_previous_run);
+        journal
+            .record_resolved(&tx_a.signatures[0], Outcome::Executed)
+            .unwrap();
+        drop(journal);
+
+        let journal = MaintenanceJournal::open(&path).ok().unwrap();
+        assert_eq!(journal.pending().len(), 1);
+        assert_eq!(journal.pending()[0].signature, tx_b.signatures[0]);
+        assert_eq!(
+            journal.pending()[0].recent_blockhash,
+            tx_b.message.recent_blockhash
+        );
+        assert_eq!(journal.pending()[0].slot, 11);
+        assert!(journal.pending()[0].from_previous_run);
+        drop(journal);
+
+        // The journal was compacted on open, so only the pending transaction is left.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn journal_keeps_fees_paid_in_the_most_recent_epoch() {
+        let path = new_journal_path("fees");
+        let operations = [MaintenanceOperation::UpdateExchangeRate];
+
+        let mut journal = MaintenanceJournal::open(&path).ok().unwrap();
+        journal
+            .record_fee_paid(1, Lamports(5_000), &operations)
+            .unwrap();
+        journal
+            .record_fee_paid(2, Lamports(5_000), &operations)
+            .unwrap();
+        journal.record_fee_paid(2, Lamports(5_000), &[]).unwrap();
+        drop(journal);
+
+        let journal = MaintenanceJournal::open(&path).ok().unwrap();
+        let expected = FeePaid {
+            epoch: 2,
+            fee: Lamports(5_000),
+            operations: vec!["UpdateExchangeRate".to_string()],
+        };
+        assert_eq!(
+            journal.fees_paid(),
+            &[
+                expected.clone(),
+                FeePaid {
+                    operations: vec![],
+                    ..expected
+                }
+            ]
+        );
+        drop(journal);
+
+        // The fees from epoch 1 were compacted away.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn journal_ignores_incomplete_last_record() {
+        let path = new_journal_path("incomplete");
+        let tx = new_signed_transaction();
+
+        let mut journal = MaintenanceJournal::open(&path).ok().unwrap();
+        journal
+            .record_submitted(&tx, 10, &[MaintenanceOperation::UpdateExchangeRate])
+            .unwrap();
+        drop(journal);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"event":"resolved","signature":"#)
+            .unwrap();
+        drop(file);
+
+        let journal = MaintenanceJournal::open(&path).ok().unwrap();
+        assert_eq!(journal.pending().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn get_outcome_waits_for_confirmation_or_expiry() {
+        let status = |confirmation_status, err| TransactionStatus {
+            slot: 10,
+            confirmations: Some(1),
+            status: Ok(()),
+            err,
+            confirmation_status: Some(confirmation_status),
+        };
+        let processed = status(TransactionConfirmationStatus::Processed, None);
+        let confirmed = status(TransactionConfirmationStatus::Confirmed, None);
+        let failed = status(
+            TransactionConfirmationStatus::Confirmed,
+            Some(solana_sdk::transaction::TransactionError::AccountInUse),
+        );
+
+        assert_eq!(get_outcome(Some(&processed), true), None);
+        assert_eq!(get_outcome(Some(&processed), false), None);
+        assert_eq!(
+            get_outcome(Some(&confirmed), false),
+            Some(Outcome::Executed)
+        );
+        assert_eq!(get_outcome(Some(&failed), true), Some(Outcome::Failed));
+        assert_eq!(get_outcome(None, true), None);
+        assert_eq!(get_outcome(None, false), Some(Outcome::Expired));
+    }
+}
@@ -14,7 +14,9 @@ use solana_remote_wallet::remote_keypair::generate_remote_keypair;
 use solana_remote_wallet::remote_wallet::maybe_wallet_manager;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::derivation_path::DerivationPath;
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{read_keypair, read_keypair_file, Signature};
 use solana_sdk::signer::Signer;
 use solana_sdk::signers::Signers;
@@ -23,22 +25,46 @@ use solana_sdk::transaction::Transaction;
 use crate::config::*;
 use crate::error::{Abort, CliError, Error};
 use crate::helpers::{
-    command_add_maintainer, command_add_validator, command_create_solido,
-    command_deactivate_validator, command_deposit, command_remove_maintainer, command_show_solido,
+    command_add_maintainer, command_add_validator, command_change_reward_distribution,
+    command_claim_validator_fee, command_create_solido, command_deactivate_validator,
+    command_deposit, command_merge_validator_stake, command_migrate_state,
+    command_remove_maintainer, command_remove_validator, command_show_solido,
 };
 use crate::multisig::MultisigOpts;
-use crate::snapshot::{Snapshot, SnapshotClient};
+use crate::replay::{RecordingSource, ReplaySource};
+use crate::snapshot::{RpcSource, Snapshot, SnapshotClient, SnapshotSource};
+use crate::stake_accounts::{
+    command_deactivate_stake, command_merge_stake, command_stake_accounts, command_withdraw_stake,
+};
 
+mod api;
+mod budget;
 mod config;
 mod daemon;
+mod endpoint;
 mod error;
 mod helpers;
+mod history;
+#[cfg(test)]
+mod in_process_test;
+mod journal;
 mod maintenance;
+#[cfg(test)]
+mod mock_rpc;
 mod multisig;
+mod offline_approval;
 mod prometheus;
+mod rebalance;
+mod replay;
 mod snapshot;
 mod spl_token_utils;
+mod stake_accounts;
+mod subscription;
+#[cfg(test)]
+mod test_utils;
 mod validator_info_utils;
+mod verify_program;
+mod withdraw_progress;
 
 /// Solido -- Interact with Lido for Solana.
 // While it is nice to have Clap handle all inputs, we also want to read
@@ -85,15 +111,33 @@ struct Opts {
     keypair_path: Option<PathBuf>,
 
     /// URL of cluster to connect to (e.g., https://api.devnet.solana.com for solana devnet) [default: http://127.0.0.1:8899]
+    ///
+    /// Multiple RPC endpoints can be given as a comma-separated list (or as an
+    /// array in the config file), in order of preference. Then we fail over to
+    /// the next endpoint when one is unhealthy, and we refuse to use a state
+    /// that is behind the most recent state that any endpoint returned.
     // Overwritten by `GeneralOpts` if None.
-    #[clap(long)]
-    cluster: Option<String>,
+    #[clap(long, value_name = "urls")]
+    cluster: Option<UrlVec>,
 
     /// Whether to output text or json. [default: "text"]
     // Overwritten by `GeneralOpts` if None.
     #[clap(long = "output", possible_values = &["text", "json"])]
     output_mode: Option<OutputMode>,
 
+    /// Record every snapshot that we read to this file.
+    ///
+    /// The recording can later be served with --replay-snapshots, to reproduce
+    /// a situation without a running RPC node.
+    #[clap(long, value_name = "path")]
+    record_snapshots: Option<PathBuf>,
+
+    /// Serve snapshots from a file recorded earlier with --record-snapshots.
+    ///
+    /// Transactions are not sent anywhere, sending them always succeeds.
+    #[clap(long, value_name = "path", conflicts_with = "record-snapshots")]
+    replay_snapshots: Option<PathBuf>,
+
     #[clap(subcommand)]
     subcommand: SubCommand,
 
@@ -165,12 +209,43 @@ REWARDS
     /// Deactivates a validator and initiates the removal process.
     DeactivateValidator(DeactivateValidatorOpts),
 
+    /// Remove an inactive validator that has no stake accounts left.
+    ///
+    /// This creates a multisig transaction that the multisig owners need to
+    /// approve. The validator must be deactivated and fully unstaked first.
+    RemoveValidator(RemoveValidatorOpts),
+
+    /// Mint the fee credit of a validator to its stSOL fee account.
+    ///
+    /// This creates a multisig transaction that the multisig owners need to
+    /// approve.
+    ClaimValidatorFee(ClaimValidatorFeeOpts),
+
+    /// Merge the two oldest stake accounts of a validator.
+    ///
+    /// This creates a multisig transaction that the multisig owners need to
+    /// approve.
+    MergeValidatorStake(MergeValidatorStakeOpts),
+
+    /// Change the reward distribution and the fee recipients.
+    ///
+    /// This creates a multisig transaction that the multisig owners need to
+    /// approve. See 'create-solido --help' for a description of the fee shares.
+    ChangeRewardDistribution(ChangeRewardDistributionOpts),
+
     /// Adds a maintainer to the Solido instance.
     AddMaintainer(AddRemoveMaintainerOpts),
 
     /// Removes a maintainer from the Solido instance.
     RemoveMaintainer(AddRemoveMaintainerOpts),
 
+    /// Migrate a version 0 Solido instance to the current state version.
+    ///
+    /// This creates the account that holds the validator list, and creates a
+    /// multisig transaction that moves the validators into it. The multisig
+    /// owners need to approve and execute it.
+    MigrateState(MigrateStateOpts),
+
     /// Deposit some SOL, receive stSOL in return.
     ///
     /// The recipient will be set to the associated token account for the signer.
@@ -182,21 +257,59 @@ REWARDS
     /// The amount of SOL is calculated and stored in the returned stake.
     Withdraw(WithdrawOpts),
 
+    /// List the signer's stake accounts that delegate to Solido validators.
+    ///
+    /// These are the stake accounts that 'withdraw' creates, and whose
+    /// withdraw authority is the signer.
+    StakeAccounts(StakeAccountsOpts),
+
+    /// Deactivate a stake account of the signer.
+    ///
+    /// The stake becomes inactive at the end of the epoch, after which it can
+    /// be withdrawn with 'withdraw-stake'.
+    DeactivateStake(DeactivateStakeOpts),
+
+    /// Withdraw the inactive SOL in a stake account to the signer.
+    WithdrawStake(WithdrawStakeOpts),
+
+    /// Merge two stake accounts of the signer.
+    MergeStake(MergeStakeOpts),
+
     /// Show an instance of Solido in detail
     ShowSolido(ShowSolidoOpts),
 
+    /// Show the Solido instructions that were executed, oldest first.
+    ///
+    /// This pages through the transactions that reference the Solido instance,
+    /// so it can take a while for long ranges. Use --from-slot or --epoch to
+    /// limit the range.
+    History(HistoryOpts),
+
     /// Show Solido authorities, even if the instance is not initialized.
     ///
     /// This is useful for testing, and when setting up a token mint ahead of
     /// time, to be used later when initializing the Solido instance.
     ShowAuthorities(ShowSolidoAuthoritiesOpts),
 
+    /// Compare a deployed program against a local build.
+    ///
+    /// This hashes the program in the program data account, without the loader
+    /// header and zero padding, and compares it byte for byte against the local
+    /// file. Exits with a nonzero status when the programs differ.
+    VerifyProgram(VerifyProgramOpts),
+
     /// Execute one iteration of periodic maintenance logic.
     ///
     /// This is mainly useful for testing. To perform maintenance continuously,
     /// use 'run-maintainer' instead.
     PerformMaintenance(PerformMaintenanceOpts),
 
+    /// Show the stake deposits and unstakes needed to balance the stake pool.
+    ///
+    /// This simulates what the maintainer would do in the coming epochs if
+    /// nothing else changes, it does not send any transactions.
+    PlanRebalance(PlanRebalanceOpts),
+
     /// Start the maintainer daemon.
     RunMaintainer(RunMaintainerOpts),
 
@@ -245,18 +358,37 @@ impl<'a> SnapshotClientConfig<'a> {
     }
 }
 
+/// Build a transaction paid for by `payer`, and sign it with the given blockhash.
+///
+/// Unlike [`SnapshotConfig::sign_transaction`], this does not need a connection
+/// to the cluster, so it can sign on an offline machine.
+pub fn sign_transaction_with_blockhash<T: Signers>(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    signers: &T,
+    blockhash: Hash,
+) -> Result<Transaction, Error> {
+    let mut tx = Transaction::new_with_payer(instructions, Some(payer));
+    tx.try_sign(signers, blockhash).map_err(|err| {
+        let boxed_error: Error = Box::new(err);
+        boxed_error
+    })?;
+    Ok(tx)
+}
+
 impl<'a> SnapshotConfig<'a> {
     pub fn sign_transaction<T: Signers>(
         &mut self,
         instructions: &[Instruction],
         signers: &T,
     ) -> snapshot::Result<Transaction> {
-        let mut tx = Transaction::new_with_payer(instructions, Some(&self.signer.pubkey()));
         let recent_blockhash = self.client.get_recent_blockhash()?;
-        tx.try_sign(signers, recent_blockhash).map_err(|err| {
-            let boxed_error: Error = Box::new(err);
-            boxed_error
-        })?;
+        let tx = sign_transaction_with_blockhash(
+            &self.signer.pubkey(),
+            instructions,
+            signers,
+            recent_blockhash,
+        )?;
         Ok(tx)
     }
 
@@ -266,17 +398,28 @@ impl<'a> SnapshotConfig<'a> {
         signers: &T,
     ) -> snapshot::Result<Signature> {
         let transaction = self.sign_transaction(instructions, signers)?;
+        Ok(self.send_transaction(&transaction)?)
+    }
+
+    /// Send a signed transaction, and wait for it to be confirmed.
+    ///
+    /// Unlike [`SnapshotConfig::sign_and_send_transaction`], this returns the
+    /// client error, so callers can inspect it.
+    pub fn send_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> solana_client::client_error::Result<Signature> {
         let signature_result = match self.output_mode {
             OutputMode::Text => {
                 // In text mode, we can display a spinner.
                 self.client
-                    .send_and_confirm_transaction_with_spinner(&transaction)
+                    .send_and_confirm_transaction_with_spinner(transaction)
             }
             OutputMode::Json => {
                 // In json mode, printing a spinner to stdout would break the
                 // json that we also print to stdout, so opt for the silent
                 // version.
-                self.client.send_and_confirm_transaction(&transaction)
+                self.client.send_and_confirm_transaction(transaction)
             }
         };
 
@@ -294,7 +437,7 @@ impl<'a> SnapshotConfig<'a> {
             _ => {}
         }
 
-        Ok(signature_result?)
+        signature_result
     }
 }
 
@@ -334,9 +477,31 @@ fn main() {
     // of that we base transactions on old states, which increases the likelihood
     // of them failing when executed. So we go back to "confirmed" after all. See
     // also https://github.com/ChorusOne/solido/pull/437.
-    let rpc_client =
-        RpcClient::new_with_commitment(opts.cluster.unwrap(), CommitmentConfig::confirmed());
-    let snapshot_client = SnapshotClient::new(rpc_client);
+    let cluster = opts.cluster.unwrap();
+    if cluster.0.is_empty() {
+        eprintln!("Expected at least one url in --cluster.");
+        std::process::exit(1);
+    }
+    let endpoints = cluster
+        .0
+        .into_iter()
+        .map(|url| {
+            let rpc_client =
+                RpcClient::new_with_commitment(url.clone(), CommitmentConfig::confirmed());
+            (url, rpc_client)
+        })
+        .collect();
+    let source: Box<dyn SnapshotSource> = match (opts.record_snapshots, opts.replay_snapshots) {
+        (_, Some(path)) => Box::new(
+            ReplaySource::load(&path).ok_or_abort_with("Failed to load recorded snapshots."),
+        ),
+        (Some(path), None) => Box::new(RecordingSource::new(
+            Box::new(RpcSource::new(endpoints)),
+            path,
+        )),
+        (None, None) => Box::new(RpcSource::new(endpoints)),
+    };
+    let snapshot_client = SnapshotClient::new(source);
 
     let output_mode = opts.output_mode.unwrap();
     let mut config = Config {
@@ -355,8 +520,10 @@ fn main() {
         SubCommand::Multisig(cmd_opts) => multisig::main(&mut config, cmd_opts),
         SubCommand::PerformMaintenance(cmd_opts) => {
             // This command only performs one iteration, `RunMaintainer` runs continuously.
-            let result = config
-                .with_snapshot(|config| maintenance::run_perform_maintenance(config, &cmd_opts));
+            let rebalancing = RebalancingConfig::from_config_file_or_exit(config_file.as_ref());
+            let result = config.with_snapshot(|config| {
+                maintenance::run_perform_maintenance(config, &cmd_opts, &rebalancing)
+            });
             let output = result.ok_or_abort_with("Failed to perform maintenance.");
             match (output_mode, output) {
                 (OutputMode::Text, None) => {
@@ -364,11 +531,25 @@ fn main() {
                 }
                 (OutputMode::Json, None) => println!("null"),
 
-                (mode, Some(output)) => print_output(mode, &output),
+                (mode, Some(output)) => {
+                    print_output(mode, &output);
+                    if output.send_failed() {
+                        std::process::exit(1);
+                    }
+                }
             }
         }
+        SubCommand::PlanRebalance(cmd_opts) => {
+            let rebalancing = RebalancingConfig::from_config_file_or_exit(config_file.as_ref());
+            let result = config.with_snapshot(|config| {
+                rebalance::command_plan_rebalance(config, &cmd_opts, &rebalancing)
+            });
+            let output = result.ok_or_abort_with("Failed to plan rebalancing.");
+            print_output(output_mode, &output);
+        }
         SubCommand::RunMaintainer(cmd_opts) => {
-            daemon::main(&mut config, &cmd_opts);
+            let rebalancing = RebalancingConfig::from_config_file_or_exit(config_file.as_ref());
+            daemon::main(&mut config, &cmd_opts, &rebalancing);
         }
         SubCommand::AddValidator(cmd_opts) => {
             let result = config.with_snapshot(|config| command_add_validator(config, &cmd_opts));
@@ -381,6 +562,29 @@ fn main() {
             let output = result.ok_or_abort_with("Failed to deactivate validator.");
             print_output(output_mode, &output);
         }
+        SubCommand::RemoveValidator(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_remove_validator(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to remove validator.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::ClaimValidatorFee(cmd_opts) => {
+            let result =
+                config.with_snapshot(|config| command_claim_validator_fee(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to claim validator fee.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::MergeValidatorStake(cmd_opts) => {
+            let result =
+                config.with_snapshot(|config| command_merge_validator_stake(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to merge validator stake.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::ChangeRewardDistribution(cmd_opts) => {
+            let result = config
+                .with_snapshot(|config| command_change_reward_distribution(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to change reward distribution.");
+            print_output(output_mode, &output);
+        }
         SubCommand::AddMaintainer(cmd_opts) => {
             let result = config.with_snapshot(|config| command_add_maintainer(config, &cmd_opts));
             let output = result.ok_or_abort_with("Failed to add maintainer.");
@@ -392,11 +596,28 @@ fn main() {
             let output = result.ok_or_abort_with("Failed to remove maintainer.");
             print_output(output_mode, &output);
         }
+        SubCommand::MigrateState(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_migrate_state(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to migrate Solido state.");
+            print_output(output_mode, &output);
+        }
         SubCommand::ShowSolido(cmd_opts) => {
             let result = config.with_snapshot(|config| command_show_solido(config, &cmd_opts));
             let output = result.ok_or_abort_with("Failed to show Solido data.");
             print_output(output_mode, &output);
         }
+        SubCommand::History(cmd_opts) => {
+            let rpc_client = match config.client.rpc_client() {
+                Some(rpc_client) => rpc_client,
+                None => {
+                    eprintln!("History is not available when replaying snapshots.");
+                    std::process::exit(1);
+                }
+            };
+            let result = history::command_history(rpc_client, &cmd_opts);
+            let output = result.ok_or_abort_with("Failed to get history.");
+            print_output(output_mode, &output);
+        }
         SubCommand::ShowAuthorities(solido_pubkey) => {
             let result =
                 config.with_snapshot(|_config| command_show_solido_authorities(&solido_pubkey));
@@ -404,6 +625,15 @@ fn main() {
                 result.ok_or_abort_with("Failed to show authorities for Solido public key.");
             print_output(output_mode, &output);
         }
+        SubCommand::VerifyProgram(cmd_opts) => {
+            let result = config
+                .with_snapshot(|config| verify_program::command_verify_program(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to verify program.");
+            print_output(output_mode, &output);
+            if !output.is_match() {
+                std::process::exit(1);
+            }
+        }
         SubCommand::Deposit(cmd_opts) => {
             let result = command_deposit(&mut config, &cmd_opts);
             let output = result.ok_or_abort_with("Failed to deposit.");
@@ -414,6 +644,26 @@ fn main() {
             let output = result.ok_or_abort_with("Failed to withdraw.");
             print_output(output_mode, &output);
         }
+        SubCommand::StakeAccounts(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_stake_accounts(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to list stake accounts.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::DeactivateStake(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_deactivate_stake(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to deactivate stake account.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::WithdrawStake(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_withdraw_stake(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to withdraw from stake account.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::MergeStake(cmd_opts) => {
+            let result = config.with_snapshot(|config| command_merge_stake(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to merge stake accounts.");
+            print_output(output_mode, &output);
+        }
     }
 }
 
@@ -427,14 +677,30 @@ fn merge_with_config_and_environment(
         SubCommand::DeactivateValidator(opts) => {
             opts.merge_with_config_and_environment(config_file)
         }
+        SubCommand::RemoveValidator(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::ClaimValidatorFee(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::MergeValidatorStake(opts) => {
+            opts.merge_with_config_and_environment(config_file)
+        }
+        SubCommand::ChangeRewardDistribution(opts) => {
+            opts.merge_with_config_and_environment(config_file)
+        }
         SubCommand::AddMaintainer(opts) | SubCommand::RemoveMaintainer(opts) => {
             opts.merge_with_config_and_environment(config_file)
         }
+        SubCommand::MigrateState(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::Deposit(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::Withdraw(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::StakeAccounts(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::DeactivateStake(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::WithdrawStake(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::MergeStake(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::ShowSolido(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::History(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::ShowAuthorities(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::VerifyProgram(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::PerformMaintenance(opts) => opts.merge_with_config_and_environment(config_file),
+        SubCommand::PlanRebalance(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::Multisig(opts) => opts.merge_with_config_and_environment(config_file),
         SubCommand::RunMaintainer(opts) => opts.merge_with_config_and_environment(config_file),
     }
@@ -9,14 +9,16 @@ use std::time::SystemTime;
 
 use itertools::izip;
 
+use lido::error::LidoError;
 use lido::processor::StakeType;
 use lido::token;
 use lido::token::Rational;
 use lido::REWARDS_WITHDRAW_AUTHORITY;
-use serde::Serialize;
+use num_traits::cast::FromPrimitive;
+use serde::{Serialize, Serializer};
 use solana_program::program_pack::Pack;
 use solana_program::{
-    clock::{Clock, Slot},
+    clock::{Clock, Epoch, Slot},
     epoch_schedule::EpochSchedule,
     pubkey::Pubkey,
     rent::Rent,
@@ -24,6 +26,10 @@ use solana_program::{
 };
 use solana_sdk::account::ReadableAccount;
 use solana_sdk::fee_calculator::DEFAULT_TARGET_LAMPORTS_PER_SIGNATURE;
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
 use solana_sdk::{account::Account, instruction::Instruction};
 use solana_vote_program::vote_state::VoteState;
 use spl_token::state::Mint;
@@ -35,21 +41,23 @@ use lido::{
     util::serialize_b58,
 };
 use lido::{
-    state::{Lido, Validator},
+    state::{Lido, Validator, Validators},
     token::Lamports,
     MINIMUM_STAKE_ACCOUNT_BALANCE, STAKE_AUTHORITY,
 };
 
-use crate::config::StakeTime;
-use crate::error::MaintenanceError;
+use crate::budget::FeeBudget;
+use crate::config::{RebalancingConfig, StakeTime};
+use crate::error::{failed_in_preflight, MaintenanceError};
+use crate::journal::{resolve_pending, MaintenanceJournal, Outcome};
 use crate::snapshot::Result;
 use crate::validator_info_utils::ValidatorInfo;
 use crate::{config::PerformMaintenanceOpts, SnapshotConfig};
 
-/// A brief description of the maintenance performed. Not relevant functionally,
-/// but helpful for automated testing, and just for info.
-#[derive(Debug, Eq, PartialEq, Serialize)]
-pub enum MaintenanceOutput {
+/// A brief description of a single maintenance operation. Not relevant
+/// functionally, but helpful for automated testing, and just for info.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum MaintenanceOperation {
     StakeDeposit {
         #[serde(serialize_with = "serialize_b58")]
         validator_vote_account: Pubkey,
@@ -113,17 +121,49 @@ pub enum MaintenanceOutput {
     UnstakeFromActiveValidator(Unstake),
 }
 
-#[derive(Debug, Eq, PartialEq, Serialize)]
+impl MaintenanceOperation {
+    /// The names of all kinds of operations, see [`MaintenanceOperation::kind`].
+    pub const KINDS: [&'static str; 9] = [
+        "StakeDeposit",
+        "UpdateExchangeRate",
+        "WithdrawInactiveStake",
+        "CollectValidatorFee",
+        "ClaimValidatorFee",
+        "MergeStake",
+        "UnstakeFromInactiveValidator",
+        "RemoveValidator",
+        "UnstakeFromActiveValidator",
+    ];
+
+    /// Return the name of the kind of operation, without its details.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MaintenanceOperation::StakeDeposit { .. } => "StakeDeposit",
+            MaintenanceOperation::UpdateExchangeRate => "UpdateExchangeRate",
+            MaintenanceOperation::WithdrawInactiveStake { .. } => "WithdrawInactiveStake",
+            MaintenanceOperation::CollectValidatorFee { .. } => "CollectValidatorFee",
+            MaintenanceOperation::ClaimValidatorFee { .. } => "ClaimValidatorFee",
+            MaintenanceOperation::MergeStake { .. } => "MergeStake",
+            MaintenanceOperation::UnstakeFromInactiveValidator(..) => {
+                "UnstakeFromInactiveValidator"
+            }
+            MaintenanceOperation::RemoveValidator { .. } => "RemoveValidator",
+            MaintenanceOperation::UnstakeFromActiveValidator(..) => "UnstakeFromActiveValidator",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct Unstake {
     #[serde(serialize_with = "serialize_b58")]
-    validator_vote_account: Pubkey,
+    pub validator_vote_account: Pubkey,
     #[serde(serialize_with = "serialize_b58")]
-    from_stake_account: Pubkey,
+    pub from_stake_account: Pubkey,
     #[serde(serialize_with = "serialize_b58")]
-    to_unstake_account: Pubkey,
-    from_stake_seed: u64,
-    to_unstake_seed: u64,
-    amount: Lamports,
+    pub to_unstake_account: Pubkey,
+    pub from_stake_seed: u64,
+    pub to_unstake_seed: u64,
+    pub amount: Lamports,
 }
 
 impl fmt::Display for Unstake {
@@ -148,10 +188,10 @@ impl fmt::Display for Unstake {
     }
 }
 
-impl fmt::Display for MaintenanceOutput {
+impl fmt::Display for MaintenanceOperation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            MaintenanceOutput::StakeDeposit {
+            MaintenanceOperation::StakeDeposit {
                 validator_vote_account,
                 stake_account,
                 amount,
@@ -161,10 +201,10 @@ impl fmt::Display for MaintenanceOutput {
                 writeln!(f, "  Stake account:          {}", stake_account)?;
                 writeln!(f, "  Amount staked:          {}", amount)?;
             }
-            MaintenanceOutput::UpdateExchangeRate => {
+            MaintenanceOperation::UpdateExchangeRate => {
                 writeln!(f, "Updated exchange rate.")?;
             }
-            MaintenanceOutput::WithdrawInactiveStake {
+            MaintenanceOperation::WithdrawInactiveStake {
                 validator_vote_account,
                 expected_difference_stake,
                 unstake_withdrawn_to_reserve,
@@ -186,7 +226,7 @@ impl fmt::Display for MaintenanceOutput {
                     unstake_withdrawn_to_reserve
                 )?;
             }
-            MaintenanceOutput::CollectValidatorFee {
+            MaintenanceOperation::CollectValidatorFee {
                 validator_vote_account,
                 fee_rewards,
             } => {
@@ -195,7 +235,7 @@ impl fmt::Display for MaintenanceOutput {
                 writeln!(f, "  Collected fee rewards:  {}", fee_rewards)?;
             }
 
-            MaintenanceOutput::ClaimValidatorFee {
+            MaintenanceOperation::ClaimValidatorFee {
                 validator_vote_account,
                 fee_rewards,
             } => {
@@ -203,7 +243,7 @@ impl fmt::Display for MaintenanceOutput {
                 writeln!(f, "  Validator vote account: {}", validator_vote_account)?;
                 writeln!(f, "  Claimed fee:            {}", fee_rewards)?;
             }
-            MaintenanceOutput::MergeStake {
+            MaintenanceOperation::MergeStake {
                 validator_vote_account,
                 from_stake,
                 to_stake,
@@ -223,13 +263,13 @@ impl fmt::Display for MaintenanceOutput {
                     to_stake, to_stake_seed
                 )?;
             }
-            MaintenanceOutput::UnstakeFromInactiveValidator(unstake) => {
+            MaintenanceOperation::UnstakeFromInactiveValidator(unstake) => {
                 writeln!(f, "Unstake from inactive validator\n{}", unstake)?;
             }
-            MaintenanceOutput::UnstakeFromActiveValidator(unstake) => {
+            MaintenanceOperation::UnstakeFromActiveValidator(unstake) => {
                 writeln!(f, "Unstake from active validator\n{}", unstake)?;
             }
-            MaintenanceOutput::RemoveValidator {
+            MaintenanceOperation::RemoveValidator {
                 validator_vote_account,
             } => {
                 writeln!(f, "Remove validator")?;
@@ -240,7 +280,128 @@ impl fmt::Display for MaintenanceOutput {
     }
 }
 
+/// The outcome of simulating a maintenance transaction during a dry run.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SimulationResult {
+    /// The error that the transaction would have failed with, if any.
+    pub error: Option<String>,
+
+    /// The Solido error that caused the failure, if we found one in the logs.
+    pub lido_error: Option<String>,
+
+    pub logs: Vec<String>,
+}
+
+/// The operations that we packed into a single maintenance transaction.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MaintenanceTransaction {
+    pub operations: Vec<MaintenanceOperation>,
+
+    /// The signature of the transaction, if we sent it.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_signature"
+    )]
+    pub signature: Option<Signature>,
+
+    /// In a dry run, the outcome of simulating the transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<SimulationResult>,
+
+    /// If we failed to send the transaction, the reason why.
+    ///
+    /// Unless it failed in preflight, the transaction may still have executed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn serialize_signature<S: Serializer>(
+    signature: &Option<Signature>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match signature {
+        Some(signature) => serializer.serialize_some(&signature.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl MaintenanceTransaction {
+    /// Whether the transaction failed in simulation during a dry run.
+    pub fn simulation_failed(&self) -> bool {
+        matches!(
+            &self.simulation,
+            Some(SimulationResult { error: Some(_), .. })
+        )
+    }
+
+    /// Whether sending the transaction failed.
+    pub fn send_failed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+/// A description of the maintenance performed in one step, which may span
+/// multiple transactions.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MaintenanceOutput {
+    pub transactions: Vec<MaintenanceTransaction>,
+}
+
+impl MaintenanceOutput {
+    /// Whether sending one of the transactions failed.
+    pub fn send_failed(&self) -> bool {
+        self.transactions.iter().any(|tx| tx.send_failed())
+    }
+}
+
+impl fmt::Display for MaintenanceOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let num_transactions = self.transactions.len();
+        for (i, transaction) in self.transactions.iter().enumerate() {
+            writeln!(
+                f,
+                "Transaction {} of {}, {} operations:",
+                i + 1,
+                num_transactions,
+                transaction.operations.len()
+            )?;
+            if let Some(signature) = &transaction.signature {
+                writeln!(f, "Signature: {}", signature)?;
+            }
+            if let Some(error) = &transaction.error {
+                writeln!(f, "Failed to send: {}", error)?;
+            }
+            for operation in &transaction.operations {
+                write!(f, "{}", operation)?;
+            }
+            match &transaction.simulation {
+                None => {}
+                Some(SimulationResult { error: None, .. }) => {
+                    writeln!(f, "Simulation succeeded.")?;
+                }
+                Some(SimulationResult {
+                    error: Some(error),
+                    lido_error,
+                    logs,
+                }) => {
+                    writeln!(f, "Simulation failed.")?;
+                    writeln!(f, "  Error:        {}", error)?;
+                    if let Some(lido_error) = lido_error {
+                        writeln!(f, "  Solido error: {}", lido_error)?;
+                    }
+                    writeln!(f, "  Logs:")?;
+                    for line in logs {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// A snapshot of on-chain accounts relevant to Solido.
+#[derive(Clone)]
 pub struct SolidoState {
     /// The label for the time at which we finished querying the Solido state.
     ///
@@ -263,8 +424,9 @@ pub struct SolidoState {
     pub solido_program_id: Pubkey,
     pub solido_address: Pubkey,
     pub solido: Lido,
+    pub validators: Validators,
 
-    /// For each validator, in the same order as in `solido.validators`, holds
+    /// For each validator, in the same order as in `validators`, holds
     /// the stake balance of the derived stake accounts from the begin seed until
     /// end seed.
     pub validator_stake_accounts: Vec<Vec<(Pubkey, StakeAccount)>>,
@@ -273,20 +435,20 @@ pub struct SolidoState {
     /// unstake accounts from the begin seed until end seed.
     pub validator_unstake_accounts: Vec<Vec<(Pubkey, StakeAccount)>>,
 
-    /// For each validator, in the same order as in `solido.validators`, holds
+    /// For each validator, in the same order as in `validators`, holds
     /// the number of Lamports of the validator's vote account.
     pub validator_vote_account_balances: Vec<Lamports>,
 
-    /// For each validator, in the same order as in `solido.validators`, holds
+    /// For each validator, in the same order as in `validators`, holds
     /// the deserialized vote account.
     pub validator_vote_accounts: Vec<VoteState>,
 
-    /// For each validator, in the same order as in `solido.validators`, holds
+    /// For each validator, in the same order as in `validators`, holds
     /// the balance of the validator's identity account (which pays for the
     /// votes).
     pub validator_identity_account_balances: Vec<Lamports>,
 
-    /// For each validator, in the same order as in `solido.validators`, holds
+    /// For each validator, in the same order as in `validators`, holds
     /// the validator info (name and Keybase username).
     pub validator_infos: Vec<ValidatorInfo>,
 
@@ -313,6 +475,9 @@ pub struct SolidoState {
     /// whenever possible. If set to StakeTime::OnlyNearEpochEnd the
     /// instructions are issued only close to the end of epoch.
     pub stake_time: StakeTime,
+
+    /// Parameters for how to balance stake across validators.
+    pub rebalancing: RebalancingConfig,
 }
 
 fn get_validator_stake_accounts(
@@ -390,19 +555,9 @@ impl SolidoState {
     // transaction cost.
     const MINIMUM_WITHDRAW_AMOUNT: Lamports = Lamports(DEFAULT_TARGET_LAMPORTS_PER_SIGNATURE * 100);
 
-    // Threshold that will trigger unstake on validators.
-    const UNBALANCE_THRESHOLD: Rational = Rational {
-        numerator: 1,
-        denominator: 10,
-    };
-
-    /// Threshold for when to consider the end of an epoch.
-    /// E.g. if set to 19/20, the end of epoch would be considered if the system
-    /// is past 95% of the epoch's time.
-    const END_OF_EPOCH_THRESHOLD: Rational = Rational {
-        numerator: 19,
-        denominator: 20,
-    };
+    /// Below this balance, we refuse to perform maintenance, because the
+    /// maintainer would soon be unable to pay the transaction fees.
+    pub const MINIMUM_MAINTAINER_BALANCE: Lamports = Lamports(100_000_000);
 
     /// Read the state from the on-chain data.
     pub fn new(
@@ -410,8 +565,10 @@ impl SolidoState {
         solido_program_id: &Pubkey,
         solido_address: &Pubkey,
         stake_time: StakeTime,
+        rebalancing: &RebalancingConfig,
     ) -> Result<SolidoState> {
         let solido = config.client.get_solido(solido_address)?;
+        let validators = config.client.get_validators(&solido)?;
 
         let reserve_address = solido.get_reserve_account(solido_program_id, solido_address)?;
         let reserve_account = config.client.get_account(&reserve_address)?;
@@ -430,7 +587,7 @@ impl SolidoState {
         let mut validator_identity_account_balances = Vec::new();
         let mut validator_vote_accounts = Vec::new();
         let mut validator_infos = Vec::new();
-        for validator in solido.validators.entries.iter() {
+        for validator in validators.entries.iter() {
             let vote_account = config.client.get_account(&validator.pubkey)?;
             let vote_state = config.client.get_vote_account(&validator.pubkey)?;
             let validator_info = config.client.get_validator_info(&vote_state.node_pubkey)?;
@@ -479,6 +636,7 @@ impl SolidoState {
             solido_program_id: *solido_program_id,
             solido_address: *solido_address,
             solido,
+            validators,
             validator_stake_accounts,
             validator_unstake_accounts,
             validator_vote_account_balances,
@@ -495,6 +653,7 @@ impl SolidoState {
             stake_history,
             maintainer_address,
             stake_time,
+            rebalancing: rebalancing.clone(),
         })
     }
 
@@ -508,31 +667,55 @@ impl SolidoState {
         )
     }
 
+    /// Return the amount of SOL in the reserve that we can stake, which is the
+    /// effective reserve minus the buffer that we keep unstaked.
+    pub fn get_stakeable_reserve(&self) -> Lamports {
+        Lamports(
+            self.get_effective_reserve()
+                .0
+                .saturating_sub(self.rebalancing.reserve_buffer.0),
+        )
+    }
+
+    /// Return the amount that we unstaked from active validators in the current epoch.
+    ///
+    /// Unstake accounts from earlier epochs may still be deactivating, and when
+    /// we unstake from inactive validators to remove them, that is not
+    /// rebalancing, so neither of those count.
+    pub fn get_unstaked_in_current_epoch(&self) -> Lamports {
+        self.validators
+            .entries
+            .iter()
+            .zip(self.validator_unstake_accounts.iter())
+            .filter(|(validator, _)| validator.entry.active)
+            .flat_map(|(_, unstake_accounts)| unstake_accounts.iter())
+            .filter(|(_addr, account)| account.deactivation_epoch == self.clock.epoch)
+            .map(|(_addr, account)| account.balance.total())
+            .sum::<token::Result<Lamports>>()
+            .expect("Does not overflow, the unstaked SOL exists.")
+    }
+
     /// If there is a deposit that can be staked, return the instructions to do so.
-    pub fn try_stake_deposit(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    pub fn try_stake_deposit(&self) -> Option<(Instruction, MaintenanceOperation)> {
         self.confirm_should_stake_unstake_in_current_slot()?;
         // We can only stake if there is an active validator. If there is none,
         // this will short-circuit and return None.
-        self.solido.validators.iter_active().next()?;
+        self.validators.iter_active().next()?;
 
-        let reserve_balance = self.get_effective_reserve();
+        let reserve_balance = self.get_stakeable_reserve();
 
         // If there is enough reserve, we can make a deposit. To keep the pool
         // balanced, find the validator furthest below its target balance, and
         // deposit to that validator. If we get here there is at least one active
         // validator, so computing the target balance should not fail.
         let undelegated_lamports = reserve_balance;
-        let targets =
-            lido::balance::get_target_balance(undelegated_lamports, &self.solido.validators)
-                .expect("Failed to compute target balance.");
+        let targets = lido::balance::get_target_balance(undelegated_lamports, &self.validators)
+            .expect("Failed to compute target balance.");
 
         let (validator_index, amount_below_target) =
-            lido::balance::get_minimum_stake_validator_index_amount(
-                &self.solido.validators,
-                &targets[..],
-            );
+            lido::balance::get_minimum_stake_validator_index_amount(&self.validators, &targets[..]);
 
-        let validator = &self.solido.validators.entries[validator_index];
+        let validator = &self.validators.entries[validator_index];
 
         let (stake_account_end, _bump_seed_end) = validator.find_stake_account_address(
             &self.solido_program_id,
@@ -550,8 +733,9 @@ impl SolidoState {
         // until there is `MINIMUM_STAKE_ACCOUNT_BALANCE * num_validators` in the
         // reserve (assuming they are currently balanced) before we stake anything,
         // which would be wasteful. In this case, we rather overshoot the target
-        // temporarily, and future deposits will restore the balance.
-        amount_to_deposit = amount_to_deposit.max(MINIMUM_STAKE_ACCOUNT_BALANCE);
+        // temporarily, and future deposits will restore the balance. The
+        // configured minimum deposit is at least the minimum stake balance.
+        amount_to_deposit = amount_to_deposit.max(self.rebalancing.min_stake_deposit);
 
         // The minimum stake account balance might be more than what's in the
         // reserve. If so, we cannot stake.
@@ -574,6 +758,7 @@ impl SolidoState {
             &self.solido_program_id,
             &lido::instruction::StakeDepositAccountsMeta {
                 lido: self.solido_address,
+                validator_list: self.solido.validator_list,
                 maintainer: self.maintainer_address,
                 reserve: self.reserve_address,
                 validator_vote_account: validator.pubkey,
@@ -583,7 +768,7 @@ impl SolidoState {
             },
             amount_to_deposit,
         );
-        let task = MaintenanceOutput::StakeDeposit {
+        let task = MaintenanceOperation::StakeDeposit {
             validator_vote_account: validator.pubkey,
             amount: amount_to_deposit,
             stake_account: stake_account_end,
@@ -612,6 +797,7 @@ impl SolidoState {
                 &self.solido_program_id,
                 &lido::instruction::UnstakeAccountsMeta {
                     lido: self.solido_address,
+                    validator_list: self.solido.validator_list,
                     maintainer: self.maintainer_address,
                     validator_vote_account: validator.pubkey,
                     source_stake_account: *stake_account_address,
@@ -623,10 +809,10 @@ impl SolidoState {
         )
     }
 
-    /// If there is a validator being deactivated, try to unstake its funds.
-    pub fn try_unstake_from_inactive_validator(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    /// For every validator being deactivated, try to unstake its funds.
+    pub fn try_unstake_from_inactive_validator(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
         for (validator, stake_accounts) in self
-            .solido
             .validators
             .entries
             .iter()
@@ -653,7 +839,7 @@ impl SolidoState {
                 &stake_accounts[0],
                 stake_account_balance.balance.total(),
             );
-            let task = MaintenanceOutput::UnstakeFromInactiveValidator(Unstake {
+            let task = MaintenanceOperation::UnstakeFromInactiveValidator(Unstake {
                 validator_vote_account: validator.pubkey,
                 from_stake_account: stake_account_address,
                 to_unstake_account: unstake_account,
@@ -662,34 +848,36 @@ impl SolidoState {
                 amount: stake_account_balance.balance.total(),
             });
 
-            return Some((unstake_instruction, task));
+            result.push((unstake_instruction, task));
         }
-        None
+        result
     }
 
-    /// If there is a validator ready for removal, try to remove it.
-    pub fn try_remove_validator(&self) -> Option<(Instruction, MaintenanceOutput)> {
-        for validator in &self.solido.validators.entries {
+    /// Remove all validators that are ready for removal.
+    pub fn try_remove_validator(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
+        for validator in &self.validators.entries {
             // We are only interested in validators that can be removed.
             if validator.entry.check_can_be_removed().is_err() {
                 continue;
             }
-            let task = MaintenanceOutput::RemoveValidator {
+            let task = MaintenanceOperation::RemoveValidator {
                 validator_vote_account: validator.pubkey,
             };
 
-            return Some((
+            result.push((
                 lido::instruction::remove_validator(
                     &self.solido_program_id,
                     &lido::instruction::RemoveValidatorMeta {
                         lido: self.solido_address,
+                        validator_list: self.solido.validator_list,
                         validator_vote_account_to_remove: validator.pubkey,
                     },
                 ),
                 task,
             ));
         }
-        None
+        result
     }
 
     /// Get an instruction to merge accounts.
@@ -717,6 +905,7 @@ impl SolidoState {
             &self.solido_program_id,
             &lido::instruction::MergeStakeMeta {
                 lido: self.solido_address,
+                validator_list: self.solido.validator_list,
                 validator_vote_account: validator.pubkey,
                 from_stake,
                 to_stake,
@@ -725,11 +914,11 @@ impl SolidoState {
         )
     }
 
-    // Tries to merge accounts from the beginning of the validator's
-    // stake accounts.  May return None or one instruction.
-    pub fn try_merge_on_all_stakes(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    // Tries to merge accounts from the beginning of every validator's
+    // stake accounts. Returns at most one instruction per validator.
+    pub fn try_merge_on_all_stakes(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
         for (validator, stake_accounts) in self
-            .solido
             .validators
             .entries
             .iter()
@@ -742,22 +931,22 @@ impl SolidoState {
                 if to_stake.1.can_merge(&from_stake.1) {
                     let instruction =
                         self.get_merge_instruction(validator, from_stake.1.seed, to_stake.1.seed);
-                    let task = MaintenanceOutput::MergeStake {
+                    let task = MaintenanceOperation::MergeStake {
                         validator_vote_account: validator.pubkey,
                         from_stake: from_stake.0,
                         to_stake: to_stake.0,
                         from_stake_seed: from_stake.1.seed,
                         to_stake_seed: to_stake.1.seed,
                     };
-                    return Some((instruction, task));
+                    result.push((instruction, task));
                 }
             }
         }
-        None
+        result
     }
 
     /// If a new epoch started, and we haven't updated the exchange rate yet, do so.
-    pub fn try_update_exchange_rate(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    pub fn try_update_exchange_rate(&self) -> Option<(Instruction, MaintenanceOperation)> {
         if self.solido.exchange_rate.computed_in_epoch >= self.clock.epoch {
             // The exchange rate has already been updated in this epoch, nothing to do.
             return None;
@@ -767,23 +956,25 @@ impl SolidoState {
             &self.solido_program_id,
             &lido::instruction::UpdateExchangeRateAccountsMeta {
                 lido: self.solido_address,
+                validator_list: self.solido.validator_list,
                 reserve: self.reserve_address,
                 st_sol_mint: self.solido.st_sol_mint,
             },
         );
-        let task = MaintenanceOutput::UpdateExchangeRate;
+        let task = MaintenanceOperation::UpdateExchangeRate;
 
         Some((instruction, task))
     }
 
-    /// Check if any validator's balance is outdated, and if so, update it.
+    /// Check for every validator whether its balance is outdated, and if so, update it.
     ///
     /// Merging stakes generates inactive stake that could be withdrawn with this transaction,
     /// or if some joker donates to one of the stake accounts we can use the same function
     /// to claim these rewards back to the reserve account so they can be re-staked.
-    pub fn try_withdraw_inactive_stake(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    pub fn try_withdraw_inactive_stake(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
         for (validator, stake_accounts, unstake_accounts) in izip!(
-            self.solido.validators.entries.iter(),
+            self.validators.entries.iter(),
             self.validator_stake_accounts.iter(),
             self.validator_unstake_accounts.iter()
         ) {
@@ -826,33 +1017,34 @@ impl SolidoState {
                     &self.solido_program_id,
                     &lido::instruction::WithdrawInactiveStakeMeta {
                         lido: self.solido_address,
+                        validator_list: self.solido.validator_list,
                         validator_vote_account: validator.pubkey,
                         stake_accounts: stake_account_addrs,
                         reserve: self.reserve_address,
                         stake_authority: self.get_stake_authority(),
                     },
                 );
-                let task = MaintenanceOutput::WithdrawInactiveStake {
+                let task = MaintenanceOperation::WithdrawInactiveStake {
                     validator_vote_account: validator.pubkey,
                     expected_difference_stake,
                     unstake_withdrawn_to_reserve: removed_unstake,
                 };
-                return Some((instruction, task));
+                result.push((instruction, task));
             }
         }
 
-        None
+        result
     }
 
-    /// Check if any validator's vote account is eligible for fee collection, and if
-    /// so, collects it.
+    /// Check every validator's vote account for eligibility for fee collection,
+    /// and collect the fees of the ones that are.
     ///
     /// As validator's vote accounts accumulate rewards, at the beginning of
     /// every epoch, they should be collected and the fees they've generated
     /// should be spread to the Solido participants.
-    pub fn try_collect_validator_fee(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    pub fn try_collect_validator_fee(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
         for (validator, vote_account_balance) in self
-            .solido
             .validators
             .entries
             .iter()
@@ -865,6 +1057,7 @@ impl SolidoState {
                     &self.solido_program_id,
                     &lido::instruction::CollectValidatorFeeMeta {
                         lido: self.solido_address,
+                        validator_list: self.solido.validator_list,
                         validator_vote_account: validator.pubkey,
                         mint_authority: self.get_mint_authority(),
                         st_sol_mint: self.solido.st_sol_mint,
@@ -874,21 +1067,22 @@ impl SolidoState {
                         rewards_withdraw_authority: self.get_rewards_withdraw_authority(),
                     },
                 );
-                let task = MaintenanceOutput::CollectValidatorFee {
+                let task = MaintenanceOperation::CollectValidatorFee {
                     validator_vote_account: validator.pubkey,
                     fee_rewards: *vote_account_balance,
                 };
-                return Some((instruction, task));
+                result.push((instruction, task));
             }
         }
 
-        None
+        result
     }
 
-    /// Checks if any of the validators has unclaimed fees in stSOL. If so,
-    /// claims it on behalf of the validator.
-    pub fn try_claim_validator_fee(&self) -> Option<(Instruction, MaintenanceOutput)> {
-        for validator in self.solido.validators.entries.iter() {
+    /// Checks if any of the validators has unclaimed fees in stSOL, and claims
+    /// them on behalf of every such validator.
+    pub fn try_claim_validator_fee(&self) -> Vec<(Instruction, MaintenanceOperation)> {
+        let mut result = Vec::new();
+        for validator in self.validators.entries.iter() {
             if validator.entry.fee_credit == StLamports(0) {
                 continue;
             }
@@ -897,50 +1091,63 @@ impl SolidoState {
                 &self.solido_program_id,
                 &lido::instruction::ClaimValidatorFeeMeta {
                     lido: self.solido_address,
+                    validator_list: self.solido.validator_list,
                     st_sol_mint: self.solido.st_sol_mint,
                     mint_authority: self.get_mint_authority(),
                     validator_fee_st_sol_account: validator.entry.fee_address,
                 },
             );
-            let task = MaintenanceOutput::ClaimValidatorFee {
+            let task = MaintenanceOperation::ClaimValidatorFee {
                 validator_vote_account: validator.pubkey,
                 fee_rewards: validator.entry.fee_credit,
             };
 
-            return Some((instruction, task));
+            result.push((instruction, task));
         }
 
-        None
+        result
     }
 
     /// Unstake from active validators in order to rebalance validators.
-    pub fn try_unstake_from_active_validators(&self) -> Option<(Instruction, MaintenanceOutput)> {
+    pub fn try_unstake_from_active_validators(
+        &self,
+    ) -> Option<(Instruction, MaintenanceOperation)> {
         self.confirm_should_stake_unstake_in_current_slot()?;
         // Return None if there's no active validator to unstake from.
-        self.solido.validators.iter_active().next()?;
+        self.validators.iter_active().next()?;
 
         // Get the target for each validator. Undelegated Lamports can be
         // sent when staking with validators.
-        let targets = lido::balance::get_target_balance(
-            self.get_effective_reserve(),
-            &self.solido.validators,
-        )
-        .expect("Failed to compute target balance.");
+        let targets =
+            lido::balance::get_target_balance(self.get_stakeable_reserve(), &self.validators)
+                .expect("Failed to compute target balance.");
 
         let (validator_index, unstake_amount) = lido::balance::get_unstake_validator_index(
-            &self.solido.validators,
+            &self.validators,
             &targets,
-            SolidoState::UNBALANCE_THRESHOLD,
+            self.rebalancing.unstake_threshold,
         )?;
-        let validator = &self.solido.validators.entries[validator_index];
-        let stake_account = &self.validator_stake_accounts[validator_index][0];
+        let validator = &self.validators.entries[validator_index];
+        // When no validator is above its target, the validator with the largest
+        // excess may have no stake accounts at all, then there is nothing to unstake.
+        let stake_account = self.validator_stake_accounts[validator_index].first()?;
 
         let maximum_unstake = (stake_account.1.balance.total() - MINIMUM_STAKE_ACCOUNT_BALANCE)
             .expect("Stake account should always have the minimum amount.");
         // Get the maximum that can be unstaked from the stake account.  The
         // minimum amongst the value to be unstaked, and the maximum that can be
         // unstaked from the validator.
-        let amount = unstake_amount.min(maximum_unstake);
+        let mut amount = unstake_amount.min(maximum_unstake);
+
+        // Do not exceed the configured maximum unstake per epoch, if any.
+        if let Some(max_unstake_per_epoch) = self.rebalancing.max_unstake_per_epoch {
+            let remaining = Lamports(
+                max_unstake_per_epoch
+                    .0
+                    .saturating_sub(self.get_unstaked_in_current_epoch().0),
+            );
+            amount = amount.min(remaining);
+        }
 
         // If the amount unstaked would leave a stake account with less than
         // `MINIMUM_STAKE_ACCOUNT_BALANCE` we shouldn't unstake it.
@@ -950,7 +1157,7 @@ impl SolidoState {
 
         let (unstake_account, instruction) =
             self.get_unstake_instruction(validator, stake_account, amount);
-        let task = MaintenanceOutput::UnstakeFromActiveValidator(Unstake {
+        let task = MaintenanceOperation::UnstakeFromActiveValidator(Unstake {
             validator_vote_account: validator.pubkey,
             from_stake_account: stake_account.0,
             to_unstake_account: unstake_account,
@@ -1072,7 +1279,6 @@ impl SolidoState {
         let mut unclaimed_fees = StLamports(0);
 
         for ((((validator, stake_accounts), vote_account), identity_account_balance), info) in self
-            .solido
             .validators
             .entries
             .iter()
@@ -1247,11 +1453,73 @@ impl SolidoState {
             },
         )?;
 
+        self.write_rebalancing_prometheus(out)?;
         write_solido_metrics_as_prometheus(&self.solido.metrics, self.produced_at, out)?;
 
         Ok(())
     }
 
+    /// Write the rebalancing configuration as gauges in Prometheus format.
+    fn write_rebalancing_prometheus<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        use crate::prometheus::{write_metric, Metric, MetricFamily, MetricValue};
+
+        // Render fractions as fixed-point numbers.
+        let fraction = |r: Rational| {
+            MetricValue::Nano((r.numerator as u128 * 1_000_000_000 / r.denominator as u128) as u64)
+        };
+        let config = &self.rebalancing;
+
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_rebalancing_unstake_threshold",
+                help:
+                    "Fraction of its target that a validator can be away from it before we unstake.",
+                type_: "gauge",
+                metrics: vec![Metric::new(fraction(config.unstake_threshold))],
+            },
+        )?;
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_rebalancing_stake_after_epoch_fraction",
+                help: "Fraction of the epoch after which we stake and unstake, if we only do so near the epoch end.",
+                type_: "gauge",
+                metrics: vec![Metric::new(fraction(config.stake_after_epoch_fraction))],
+            },
+        )?;
+        if let Some(max_unstake_per_epoch) = config.max_unstake_per_epoch {
+            write_metric(
+                out,
+                &MetricFamily {
+                    name: "solido_rebalancing_max_unstake_per_epoch_sol",
+                    help: "Maximum amount of SOL that we unstake in a single epoch.",
+                    type_: "gauge",
+                    metrics: vec![Metric::new_sol(max_unstake_per_epoch)],
+                },
+            )?;
+        }
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_rebalancing_reserve_buffer_sol",
+                help: "Amount of SOL that we keep in the reserve rather than staking it.",
+                type_: "gauge",
+                metrics: vec![Metric::new_sol(config.reserve_buffer)],
+            },
+        )?;
+        write_metric(
+            out,
+            &MetricFamily {
+                name: "solido_rebalancing_min_stake_deposit_sol",
+                help: "Minimum amount of SOL that we stake in a single stake deposit.",
+                type_: "gauge",
+                metrics: vec![Metric::new_sol(config.min_stake_deposit)],
+            },
+        )?;
+        Ok(())
+    }
+
     fn get_stake_authority(&self) -> Pubkey {
         let (stake_authority, _bump_seed_authority) = lido::find_authority_program_address(
             &self.solido_program_id,
@@ -1288,6 +1556,10 @@ impl SolidoState {
     /// is on duty, see [`get_current_maintainer_duty`].
     const MAINTAINER_DUTY_PAUSE_LENGTH: Slot = 10;
 
+    /// The number of slots into a duty slice after which the next maintainer
+    /// takes over overdue maintenance, see [`get_fallback_maintainer_duty`].
+    const MAINTAINER_FALLBACK_DELAY: Slot = 50;
+
     /// Return the maintainer who is currently on "maintainer duty".
     ///
     /// The maintenance tasks need to be executed by somebody. Every task
@@ -1341,6 +1613,111 @@ impl SolidoState {
         Some(self.solido.maintainers.entries[maintainer_index as usize].pubkey)
     }
 
+    /// Return whether there is maintenance pending that the maintainer on duty
+    /// should perform at the start of its slice.
+    ///
+    /// This is work that becomes necessary at the start of an epoch: updating
+    /// the exchange rate, merging stake accounts, and collecting validator fees.
+    /// Like the duty itself, this is a pure function of the on-chain state, so
+    /// all maintainers agree on it.
+    pub fn has_overdue_maintenance(&self) -> bool {
+        self.solido.exchange_rate.computed_in_epoch < self.clock.epoch
+            || !self.try_merge_on_all_stakes().is_empty()
+            || !self.try_collect_validator_fee().is_empty()
+    }
+
+    /// Return the maintainer who may stand in for the maintainer on duty.
+    ///
+    /// If the maintainer on duty is offline, its duty slices are lost, and
+    /// epoch-start work gets delayed until the next maintainer's slice. To
+    /// avoid that, when maintenance is still overdue [`MAINTAINER_FALLBACK_DELAY`]
+    /// slots into the slice, the next maintainer in order may perform it as
+    /// well. It then performs only the overdue operations, see
+    /// [`has_overdue_maintenance`].
+    ///
+    /// If the maintainer on duty is online but slow, both may submit the same
+    /// transaction. The program rejects a second exchange rate update in the
+    /// same epoch, and a second merge of the same stake accounts, but a second
+    /// fee collection succeeds and collects whatever rewards accrued since.
+    /// Either way, this costs an extra transaction fee, but it does not unstake
+    /// or stake twice, which is why the fallback does nothing else.
+    pub fn get_fallback_maintainer_duty(&self) -> Option<Pubkey> {
+        let num_maintainers = self.solido.maintainers.len() as u64;
+        if num_maintainers < 2 || !self.has_overdue_maintenance() {
+            return None;
+        }
+
+        let duty_slice = self.clock.slot / Self::MAINTAINER_DUTY_SLICE_LENGTH;
+        let slot_in_duty_slice = self.clock.slot % Self::MAINTAINER_DUTY_SLICE_LENGTH;
+
+        // The fallback ends where the duty ends, before the pause.
+        let fallback_slots = Self::MAINTAINER_FALLBACK_DELAY
+            ..Self::MAINTAINER_DUTY_SLICE_LENGTH - Self::MAINTAINER_DUTY_PAUSE_LENGTH;
+        if !fallback_slots.contains(&slot_in_duty_slice) {
+            return None;
+        }
+
+        let maintainer_index = (duty_slice + 1) % num_maintainers;
+        Some(self.solido.maintainers.entries[maintainer_index as usize].pubkey)
+    }
+
+    /// Return whether the maintainer should perform maintenance now, either
+    /// because it is on duty, or because it stands in for the one on duty.
+    pub fn is_on_duty(&self, maintainer: &Pubkey) -> bool {
+        self.get_current_maintainer_duty() == Some(*maintainer) || self.is_standing_in(maintainer)
+    }
+
+    /// Return whether the maintainer only stands in for the one on duty, and
+    /// should therefore only perform the overdue maintenance.
+    pub fn is_standing_in(&self, maintainer: &Pubkey) -> bool {
+        self.get_current_maintainer_duty() != Some(*maintainer)
+            && self.get_fallback_maintainer_duty() == Some(*maintainer)
+    }
+
+    /// Return the balance of the maintainer that we act as, if it is a member
+    /// of the maintainer set.
+    pub fn get_maintainer_balance(&self) -> Option<Lamports> {
+        self.solido
+            .maintainers
+            .entries
+            .iter()
+            .zip(&self.maintainer_balances)
+            .find(|(m, _)| m.pubkey == self.maintainer_address)
+            .map(|(_, balance)| *balance)
+    }
+
+    /// Return the accounts whose changes can make maintenance necessary.
+    ///
+    /// This excludes the validators' vote accounts, they change with every vote.
+    pub fn get_watched_accounts(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![
+            self.solido_address,
+            self.solido.validator_list,
+            self.reserve_address,
+        ];
+        accounts.extend(
+            self.validator_stake_accounts
+                .iter()
+                .chain(self.validator_unstake_accounts.iter())
+                .flatten()
+                .map(|(address, _)| *address),
+        );
+        accounts.sort();
+        accounts.dedup();
+        accounts
+    }
+
+    /// Act as the maintainer that is currently on duty, if there is one.
+    ///
+    /// In a dry run we only simulate transactions, so we do not need the
+    /// maintainer's private key. Acting as the maintainer on duty lets us
+    /// shadow the real maintainers.
+    pub fn act_as_maintainer_on_duty(&mut self) {
+        if let Some(maintainer) = self.get_current_maintainer_duty() {
+            self.maintainer_address = maintainer;
+        }
+    }
+
     /// Return the slot at which the given maintainer's next duty slice starts.
     ///
     /// If the maintainer is currently on duty, this returns the start of its
@@ -1377,10 +1754,41 @@ impl SolidoState {
         }
     }
 
-    /// Return None if we observe we moved past `1 -
-    /// SolidoState::END_OF_EPOCH_THRESHOLD`%. Return Some(()) if the above
-    /// condition fails or `self.stake_unstake_any_time` is set to
-    /// `true`.
+    /// Return the slot at which the given maintainer may next stand in for the
+    /// maintainer on duty, if maintenance is overdue by then.
+    ///
+    /// See also [`get_fallback_maintainer_duty`].
+    pub fn get_next_fallback_duty_slot(&self, maintainer: &Pubkey) -> Option<Slot> {
+        let num_maintainers = self.solido.maintainers.entries.len() as u64;
+        if num_maintainers < 2 {
+            return None;
+        }
+
+        let cycle_length = num_maintainers * Self::MAINTAINER_DUTY_SLICE_LENGTH;
+        let current_cycle_start_slot = (self.clock.slot / cycle_length) * cycle_length;
+
+        // We stand in during the slice of the maintainer before us.
+        let self_index = self
+            .solido
+            .maintainers
+            .entries
+            .iter()
+            .position(|m| m.pubkey == *maintainer)? as u64;
+        let previous_index = (self_index + num_maintainers - 1) % num_maintainers;
+        let fallback_start_slot = current_cycle_start_slot
+            + previous_index * Self::MAINTAINER_DUTY_SLICE_LENGTH
+            + Self::MAINTAINER_FALLBACK_DELAY;
+
+        if fallback_start_slot <= self.clock.slot {
+            Some(fallback_start_slot + cycle_length)
+        } else {
+            Some(fallback_start_slot)
+        }
+    }
+
+    /// Return None if we observe we did not yet move past the configured
+    /// `stake_after_epoch_fraction` of the epoch. Return Some(()) if the above
+    /// condition fails or `self.stake_time` is set to `StakeTime::Anytime`.
     pub fn confirm_should_stake_unstake_in_current_slot(&self) -> Option<()> {
         match self.stake_time {
             StakeTime::Anytime => Some(()),
@@ -1402,7 +1810,7 @@ impl SolidoState {
                     numerator: slot_past_epoch,
                     denominator: slots_per_epoch,
                 };
-                if ratio > SolidoState::END_OF_EPOCH_THRESHOLD {
+                if ratio > self.rebalancing.stake_after_epoch_fraction {
                     Some(())
                 } else {
                     None
@@ -1412,68 +1820,335 @@ impl SolidoState {
     }
 }
 
+/// Find the Solido error that made the transaction fail, if any, in the logs of
+/// a simulated transaction.
+pub fn find_lido_error(solido_program_id: &Pubkey, logs: &[String]) -> Option<LidoError> {
+    let prefix = format!(
+        "Program {} failed: custom program error: 0x",
+        solido_program_id
+    );
+    logs.iter()
+        .filter_map(|line| line.strip_prefix(&prefix))
+        .filter_map(|code| u32::from_str_radix(code, 16).ok())
+        .find_map(LidoError::from_u32)
+}
+
+/// Select the maintenance operations to perform next.
+///
+/// With `overdue_only`, this selects only from the operations that are overdue
+/// at the start of an epoch, see [`try_perform_maintenance`].
+fn select_operations(
+    state: &SolidoState,
+    overdue_only: bool,
+) -> Vec<(Instruction, MaintenanceOperation)> {
+    // Try all of these kinds of operations one by one, and select the first
+    // kind that produces instructions. Operations of the same kind apply to
+    // different validators, so they do not depend on each other and we can
+    // execute all of them at once. Operations of different kinds often do
+    // depend on each other, so we leave those for the next iteration.
+    if overdue_only {
+        // The same operations that `has_overdue_maintenance` checks for, in
+        // the same order as below.
+        None.or_else(|| non_empty(state.try_merge_on_all_stakes()))
+            .or_else(|| state.try_update_exchange_rate().map(|op| vec![op]))
+            .or_else(|| non_empty(state.try_collect_validator_fee()))
+            .unwrap_or_default()
+    } else {
+        None
+            // Merging stake accounts goes before updating validator balance, to
+            // ensure that the balance update needs to reference as few accounts
+            // as possible.
+            .or_else(|| non_empty(state.try_merge_on_all_stakes()))
+            .or_else(|| state.try_update_exchange_rate().map(|op| vec![op]))
+            .or_else(|| non_empty(state.try_unstake_from_inactive_validator()))
+            // Collecting validator fees goes after updating the exchange rate,
+            // because it may be rejected if the exchange rate is outdated.
+            .or_else(|| non_empty(state.try_collect_validator_fee()))
+            // Same for updating the validator balance.
+            .or_else(|| non_empty(state.try_withdraw_inactive_stake()))
+            .or_else(|| state.try_stake_deposit().map(|op| vec![op]))
+            .or_else(|| {
+                state
+                    .try_unstake_from_active_validators()
+                    .map(|op| vec![op])
+            })
+            .or_else(|| non_empty(state.try_claim_validator_fee()))
+            .or_else(|| non_empty(state.try_remove_validator()))
+            .unwrap_or_default()
+    }
+}
+
+/// Perform the maintenance that `state` calls for.
+///
+/// In a dry run, we simulate the transactions instead of sending them. The
+/// transactions are then all simulated against the current state, so a
+/// transaction does not observe the effect of the ones before it.
+///
+/// If a journal is provided, we record the transactions in it before sending
+/// them, and while it holds transactions with an unknown outcome, we only try
+/// to find out what happened to those.
+///
+/// When a transaction fails to send, we do not send the ones after it. The
+/// output then ends with the failed transaction, see
+/// [`MaintenanceOutput::send_failed`].
+///
+/// With `overdue_only`, we only perform the maintenance that is overdue at the
+/// start of an epoch, as a fallback maintainer does, see
+/// [`SolidoState::get_fallback_maintainer_duty`].
 pub fn try_perform_maintenance(
     config: &mut SnapshotConfig,
     state: &SolidoState,
+    dry_run: bool,
+    overdue_only: bool,
+    mut journal: Option<&mut MaintenanceJournal>,
+    mut budget: Option<&mut FeeBudget>,
 ) -> Result<Option<MaintenanceOutput>> {
+    // If we sent transactions before whose outcome we do not know, performing
+    // maintenance now could repeat them. Find out what happened to them first,
+    // and continue based on a state that we read after that.
+    if let Some(journal) = journal.as_mut() {
+        if !journal.pending().is_empty() {
+            resolve_pending(config, journal)?;
+            return Ok(None);
+        }
+    }
+
     // To prevent the maintenance transactions failing with mysterious errors
     // that are difficult to debug, before we do any maintenance, do a sanity
     // check to ensure that the maintainer has at least some SOL to pay the
     // transaction fees.
-    let minimum_maintainer_balance = Lamports(100_000_000);
-    match state
-        .solido
-        .maintainers
-        .entries
-        .iter()
-        .zip(&state.maintainer_balances)
-        .filter(|(m, _)| m.pubkey == state.maintainer_address)
-        .map(|(_, balance)| balance)
-        .next()
-    {
-        Some(balance) if balance < &minimum_maintainer_balance => {
+    match state.get_maintainer_balance() {
+        Some(balance) if balance < SolidoState::MINIMUM_MAINTAINER_BALANCE => {
             return Err(MaintenanceError::new(format!(
                 "Balance of the maintainer account {} is less than {}. \
                 Please fund the maintainer account.",
-                state.maintainer_address, minimum_maintainer_balance,
+                state.maintainer_address,
+                SolidoState::MINIMUM_MAINTAINER_BALANCE,
             ))
             .into())
         }
         _ => {}
     }
 
-    // Try all of these operations one by one, and select the first one that
-    // produces an instruction.
-    let instruction_output: Option<(Instruction, MaintenanceOutput)> = None
-        // Merging stake accounts goes before updating validator balance, to
-        // ensure that the balance update needs to reference as few accounts
-        // as possible.
-        .or_else(|| state.try_merge_on_all_stakes())
-        .or_else(|| state.try_update_exchange_rate())
-        .or_else(|| state.try_unstake_from_inactive_validator())
-        // Collecting validator fees goes after updating the exchange rate,
-        // because it may be rejected if the exchange rate is outdated.
-        .or_else(|| state.try_collect_validator_fee())
-        // Same for updating the validator balance.
-        .or_else(|| state.try_withdraw_inactive_stake())
-        .or_else(|| state.try_stake_deposit())
-        .or_else(|| state.try_unstake_from_active_validators())
-        .or_else(|| state.try_claim_validator_fee())
-        .or_else(|| state.try_remove_validator());
-
-    match instruction_output {
-        Some((instruction, output)) => {
+    let operations = select_operations(state, overdue_only);
+
+    if operations.is_empty() {
+        return Ok(None);
+    }
+
+    let mut output = MaintenanceOutput {
+        transactions: Vec::new(),
+    };
+    for (instructions, mut transaction) in pack_transactions(&state.maintainer_address, operations)
+    {
+        if dry_run {
+            let message = Message::new(&instructions, Some(&state.maintainer_address));
+            let result = config
+                .client
+                .simulate_transaction(&Transaction::new_unsigned(message))?;
+            let logs = result.logs.unwrap_or_default();
+            transaction.simulation = Some(SimulationResult {
+                error: result.err.map(|err| err.to_string()),
+                lido_error: find_lido_error(&state.solido_program_id, &logs)
+                    .map(|err| format!("{:?}", err)),
+                logs,
+            });
+        } else {
             // For maintenance operations, the maintainer is the only signer,
             // and that should be sufficient.
-            config.sign_and_send_transaction(&[instruction], &[config.signer])?;
-            Ok(Some(output))
+            let signed_transaction = config.sign_transaction(&instructions, &[config.signer])?;
+            let fee = estimate_transaction_fee(config, &signed_transaction)?;
+            if let Some(budget) = budget.as_mut() {
+                if let Err(message) = budget.check(state.clock.epoch, fee, &transaction.operations)
+                {
+                    transaction.error = Some(message);
+                }
+            }
+            if let (None, Some(journal)) = (&transaction.error, journal.as_mut()) {
+                if let Err(err) = journal.record_submitted(
+                    &signed_transaction,
+                    state.clock.slot,
+                    &transaction.operations,
+                ) {
+                    transaction.error = Some(format!(
+                        "Failed to record the transaction in the journal: {}",
+                        err
+                    ));
+                }
+            }
+            if transaction.error.is_none() {
+                match config.send_transaction(&signed_transaction) {
+                    Ok(signature) => {
+                        // The transaction is confirmed, so we can charge the
+                        // fee that it actually paid. If the RPC node can't
+                        // tell us, we charge the estimate.
+                        let fee = config
+                            .client
+                            .get_transaction_fee(&signature)
+                            .ok()
+                            .flatten()
+                            .unwrap_or(fee);
+                        charge_fee(
+                            budget.as_deref_mut(),
+                            journal.as_deref_mut(),
+                            state.clock.epoch,
+                            fee,
+                            &transaction.operations,
+                        )?;
+                        if let Some(journal) = journal.as_mut() {
+                            journal.record_resolved(&signature, Outcome::Executed)?;
+                        }
+                        transaction.signature = Some(signature);
+                    }
+                    Err(err) => {
+                        // A transaction that failed in preflight did not pay a fee.
+                        // Any other failure may have, so count it against the budget.
+                        if !failed_in_preflight(&err) {
+                            charge_fee(
+                                budget.as_deref_mut(),
+                                journal.as_deref_mut(),
+                                state.clock.epoch,
+                                fee,
+                                &transaction.operations,
+                            )?;
+                        }
+                        // Only when the transaction failed in preflight do we know
+                        // that it did not execute. Otherwise it stays pending in the
+                        // journal, until we know what happened to it.
+                        if let Some(journal) = journal.as_mut() {
+                            if failed_in_preflight(&err) {
+                                journal.record_resolved(
+                                    &signed_transaction.signatures[0],
+                                    Outcome::Failed,
+                                )?;
+                            } else {
+                                journal.counters.ambiguous += 1;
+                            }
+                        }
+                        transaction.error = Some(err.to_string());
+                    }
+                }
+            }
+        }
+
+        // The transactions that we sent before did execute, so if this one
+        // failed, we still report them. We don't send the remaining ones: the
+        // cause of the failure likely affects them too, and if the journal now
+        // holds a pending transaction, we must wait for its outcome first.
+        let failed = transaction.send_failed();
+        output.transactions.push(transaction);
+        if failed {
+            break;
+        }
+    }
+    Ok(Some(output))
+}
+
+fn non_empty<T>(xs: Vec<T>) -> Option<Vec<T>> {
+    if xs.is_empty() {
+        None
+    } else {
+        Some(xs)
+    }
+}
+
+/// Return the fee that the transaction will pay, at the fee rate of its blockhash.
+fn estimate_transaction_fee(
+    config: &mut SnapshotConfig,
+    transaction: &Transaction,
+) -> Result<Lamports> {
+    let blockhashes = config.client.get_recent_blockhashes()?;
+    let fee_calculator = blockhashes
+        .iter()
+        .find(|entry| entry.blockhash == transaction.message.recent_blockhash)
+        // We sign with the most recent blockhash, so we should find it.
+        .or_else(|| blockhashes.first())
+        .map(|entry| &entry.fee_calculator)
+        .ok_or_else(|| {
+            MaintenanceError::new("The RecentBlockhashes sysvar is empty.".to_string())
+        })?;
+    Ok(Lamports(fee_calculator.calculate_fee(&transaction.message)))
+}
+
+/// Charge the fee of a transaction to the budget, and record it in the
+/// journal, so the budget survives a restart.
+fn charge_fee(
+    budget: Option<&mut FeeBudget>,
+    journal: Option<&mut MaintenanceJournal>,
+    epoch: Epoch,
+    fee: Lamports,
+    operations: &[MaintenanceOperation],
+) -> Result<()> {
+    if let Some(budget) = budget {
+        budget.record(fee, operations);
+    }
+    if let Some(journal) = journal {
+        journal.record_fee_paid(epoch, fee, operations)?;
+    }
+    Ok(())
+}
+
+/// Return the size of the serialized transaction that contains the instructions.
+fn get_transaction_size(payer: &Pubkey, instructions: &[Instruction]) -> usize {
+    let transaction = Transaction::new_unsigned(Message::new(instructions, Some(payer)));
+    bincode::serialized_size(&transaction).expect("Serializing a transaction does not fail.")
+        as usize
+}
+
+/// The maximum number of compute units that a single instruction may consume.
+const MAX_COMPUTE_UNITS_PER_INSTRUCTION: u64 = 200_000;
+
+/// The maximum number of compute units that a transaction may consume in total.
+///
+/// Solana 1.7 meters compute per instruction, but later versions also cap the
+/// total of the transaction at this.
+const MAX_COMPUTE_UNITS_PER_TRANSACTION: u64 = 1_400_000;
+
+/// The number of instructions that we put in one transaction at most.
+///
+/// If every instruction used its full compute budget, the transaction would
+/// still stay within the transaction-wide limit.
+const MAX_INSTRUCTIONS_PER_TRANSACTION: usize =
+    (MAX_COMPUTE_UNITS_PER_TRANSACTION / MAX_COMPUTE_UNITS_PER_INSTRUCTION) as usize;
+
+/// Group the operations into as few transactions as possible, while preserving
+/// their order, and keeping every transaction within the packet size limit and
+/// the compute limit.
+///
+/// An instruction that does not fit in a transaction by itself still gets its
+/// own transaction, sending that will fail with an error from the RPC.
+pub fn pack_transactions(
+    payer: &Pubkey,
+    operations: Vec<(Instruction, MaintenanceOperation)>,
+) -> Vec<(Vec<Instruction>, MaintenanceTransaction)> {
+    let mut result: Vec<(Vec<Instruction>, MaintenanceTransaction)> = Vec::new();
+    for (mut instruction, operation) in operations {
+        if let Some((instructions, transaction)) = result
+            .last_mut()
+            .filter(|(instructions, _)| instructions.len() < MAX_INSTRUCTIONS_PER_TRANSACTION)
+        {
+            instructions.push(instruction);
+            if get_transaction_size(payer, instructions) <= PACKET_DATA_SIZE {
+                transaction.operations.push(operation);
+                continue;
+            }
+            instruction = instructions.pop().expect("We just pushed an instruction.");
         }
-        None => Ok(None),
+        result.push((
+            vec![instruction],
+            MaintenanceTransaction {
+                operations: vec![operation],
+                signature: None,
+                simulation: None,
+                error: None,
+            },
+        ));
     }
+    result
 }
 
 /// Inspect the on-chain Solido state, and if there is maintenance that can be
-/// performed, do so. Returns a description of the task performed, if any.
+/// performed, do so. Returns a description of the tasks performed, if any.
 ///
 /// This takes only one step, there might be more work left to do after this
 /// function returns. Call it in a loop until it returns `None`. (And then still
@@ -1481,14 +2156,19 @@ pub fn try_perform_maintenance(
 pub fn run_perform_maintenance(
     config: &mut SnapshotConfig,
     opts: &PerformMaintenanceOpts,
+    rebalancing: &RebalancingConfig,
 ) -> Result<Option<MaintenanceOutput>> {
-    let state = SolidoState::new(
+    let mut state = SolidoState::new(
         config,
         opts.solido_program_id(),
         opts.solido_address(),
         *opts.stake_time(),
+        rebalancing,
     )?;
-    try_perform_maintenance(config, &state)
+    if *opts.dry_run() {
+        state.act_as_maintainer_on_duty();
+    }
+    try_perform_maintenance(config, &state, *opts.dry_run(), false, None, None)
 }
 
 #[cfg(test)]
@@ -1496,36 +2176,7 @@ mod test {
 
     use super::*;
 
-    /// Produce a new state with `default` Solido instance in it, and random pubkeys.
-    fn new_empty_solido() -> SolidoState {
-        let mut state = SolidoState {
-            produced_at: SystemTime::UNIX_EPOCH,
-            solido_program_id: Pubkey::new_unique(),
-            solido_address: Pubkey::new_unique(),
-            solido: Lido::default(),
-            validator_stake_accounts: vec![],
-            validator_unstake_accounts: vec![],
-            validator_vote_account_balances: vec![],
-            validator_vote_accounts: vec![],
-            validator_identity_account_balances: vec![],
-            validator_infos: vec![],
-            maintainer_balances: vec![],
-            st_sol_mint: Mint::default(),
-            reserve_address: Pubkey::new_unique(),
-            reserve_account: Account::default(),
-            rent: Rent::default(),
-            clock: Clock::default(),
-            epoch_schedule: EpochSchedule::default(),
-            stake_history: StakeHistory::default(),
-            maintainer_address: Pubkey::new_unique(),
-            stake_time: StakeTime::Anytime,
-        };
-
-        // The reserve should be rent-exempt.
-        state.reserve_account.lamports = state.rent.minimum_balance(0);
-
-        state
-    }
+    use crate::test_utils::new_empty_solido;
 
     /// This is a regression test. In the past we checked for the minimum stake
     /// balance before capping it at the amount below target, which meant that
@@ -1538,9 +2189,8 @@ mod test {
         let mut state = new_empty_solido();
 
         // Add a validators, without any stake accounts yet.
-        state.solido.validators.maximum_entries = 1;
+        state.validators.maximum_entries = 1;
         state
-            .solido
             .validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
@@ -1568,14 +2218,12 @@ mod test {
         let mut state = new_empty_solido();
 
         // Add two validators, both without any stake account yet.
-        state.solido.validators.maximum_entries = 2;
+        state.validators.maximum_entries = 2;
         state
-            .solido
             .validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
         state
-            .solido
             .validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
@@ -1586,7 +2234,7 @@ mod test {
         // balance.
         state.reserve_account.lamports += 4 * MINIMUM_STAKE_ACCOUNT_BALANCE.0;
 
-        let stake_account_0 = state.solido.validators.entries[0].find_stake_account_address(
+        let stake_account_0 = state.validators.entries[0].find_stake_account_address(
             &state.solido_program_id,
             &state.solido_address,
             0,
@@ -1596,14 +2244,14 @@ mod test {
         // The first attempt should stake with the first validator.
         assert_eq!(
             state.try_stake_deposit().unwrap().1,
-            MaintenanceOutput::StakeDeposit {
-                validator_vote_account: state.solido.validators.entries[0].pubkey,
+            MaintenanceOperation::StakeDeposit {
+                validator_vote_account: state.validators.entries[0].pubkey,
                 amount: (MINIMUM_STAKE_ACCOUNT_BALANCE * 2).unwrap(),
                 stake_account: stake_account_0.0,
             }
         );
 
-        let stake_account_1 = state.solido.validators.entries[1].find_stake_account_address(
+        let stake_account_1 = state.validators.entries[1].find_stake_account_address(
             &state.solido_program_id,
             &state.solido_address,
             0,
@@ -1612,7 +2260,7 @@ mod test {
 
         // Pretend that the amount was actually staked.
         state.reserve_account.lamports -= 2 * MINIMUM_STAKE_ACCOUNT_BALANCE.0;
-        let validator = &mut state.solido.validators.entries[0].entry;
+        let validator = &mut state.validators.entries[0].entry;
         validator.stake_accounts_balance = validator
             .stake_accounts_balance
             .add((MINIMUM_STAKE_ACCOUNT_BALANCE * 2).unwrap())
@@ -1622,8 +2270,8 @@ mod test {
         // should be the same as before.
         assert_eq!(
             state.try_stake_deposit().unwrap().1,
-            MaintenanceOutput::StakeDeposit {
-                validator_vote_account: state.solido.validators.entries[1].pubkey,
+            MaintenanceOperation::StakeDeposit {
+                validator_vote_account: state.validators.entries[1].pubkey,
                 amount: (MINIMUM_STAKE_ACCOUNT_BALANCE * 2).unwrap(),
                 stake_account: stake_account_1.0,
             }
@@ -1695,6 +2343,142 @@ mod test {
         }
     }
 
+    /// Return a state with the given number of maintainers, and the exchange
+    /// rate not yet updated in the current epoch.
+    fn new_solido_with_overdue_maintenance(num_maintainers: u32) -> SolidoState {
+        let mut state = new_empty_solido();
+        state.solido.maintainers.maximum_entries = num_maintainers;
+        for _ in 0..num_maintainers {
+            state
+                .solido
+                .maintainers
+                .add(Pubkey::new_unique(), ())
+                .unwrap();
+        }
+        state.clock.epoch = 1;
+        state
+    }
+
+    #[test]
+    fn fallback_duty_goes_to_the_next_maintainer_when_maintenance_is_overdue() {
+        let mut state = new_solido_with_overdue_maintenance(3);
+        let maintainers: Vec<Pubkey> = state
+            .solido
+            .maintainers
+            .entries
+            .iter()
+            .map(|m| m.pubkey)
+            .collect();
+        assert!(state.has_overdue_maintenance());
+
+        let fallback_end =
+            SolidoState::MAINTAINER_DUTY_SLICE_LENGTH - SolidoState::MAINTAINER_DUTY_PAUSE_LENGTH;
+        for slice in 0..6 {
+            let slice_start = slice * SolidoState::MAINTAINER_DUTY_SLICE_LENGTH;
+            let next_maintainer = maintainers[(slice as usize + 1) % maintainers.len()];
+            for offset in 0..SolidoState::MAINTAINER_DUTY_SLICE_LENGTH {
+                state.clock.slot = slice_start + offset;
+                let expected =
+                    if (SolidoState::MAINTAINER_FALLBACK_DELAY..fallback_end).contains(&offset) {
+                        Some(next_maintainer)
+                    } else {
+                        None
+                    };
+                assert_eq!(
+                    state.get_fallback_maintainer_duty(),
+                    expected,
+                    "Unexpected fallback duty in slot {}.",
+                    state.clock.slot
+                );
+            }
+        }
+
+        // Both the maintainer on duty and the fallback may act.
+        state.clock.slot = SolidoState::MAINTAINER_FALLBACK_DELAY;
+        assert!(state.is_on_duty(&maintainers[0]));
+        assert!(state.is_on_duty(&maintainers[1]));
+        assert!(!state.is_on_duty(&maintainers[2]));
+
+        // Only the fallback is limited to the overdue maintenance.
+        assert!(!state.is_standing_in(&maintainers[0]));
+        assert!(state.is_standing_in(&maintainers[1]));
+        assert!(!state.is_standing_in(&maintainers[2]));
+    }
+
+    #[test]
+    fn fallback_only_performs_overdue_maintenance() {
+        let mut state = new_empty_solido();
+        state.validators.maximum_entries = 1;
+        state
+            .validators
+            .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+            .unwrap();
+        state.validator_stake_accounts.push(vec![]);
+        state.validator_unstake_accounts.push(vec![]);
+        state.reserve_account.lamports += MINIMUM_STAKE_ACCOUNT_BALANCE.0;
+
+        // Staking the deposit is not overdue maintenance.
+        assert!(!state.has_overdue_maintenance());
+        assert!(matches!(
+            &select_operations(&state, false)[..],
+            [(_, MaintenanceOperation::StakeDeposit { .. })]
+        ));
+        assert!(select_operations(&state, true).is_empty());
+
+        // But updating the exchange rate is.
+        state.clock.epoch = 1;
+        assert!(state.has_overdue_maintenance());
+        assert!(matches!(
+            &select_operations(&state, true)[..],
+            [(_, MaintenanceOperation::UpdateExchangeRate)]
+        ));
+    }
+
+    #[test]
+    fn no_fallback_duty_without_overdue_maintenance() {
+        let mut state = new_solido_with_overdue_maintenance(3);
+        state.solido.exchange_rate.computed_in_epoch = state.clock.epoch;
+        assert!(!state.has_overdue_maintenance());
+        for slot in 0..3 * SolidoState::MAINTAINER_DUTY_SLICE_LENGTH {
+            state.clock.slot = slot;
+            assert_eq!(state.get_fallback_maintainer_duty(), None);
+        }
+
+        // With a single maintainer, there is nobody to fall back to.
+        let mut state = new_solido_with_overdue_maintenance(1);
+        for slot in 0..SolidoState::MAINTAINER_DUTY_SLICE_LENGTH {
+            state.clock.slot = slot;
+            assert_eq!(state.get_fallback_maintainer_duty(), None);
+        }
+        let maintainer = state.solido.maintainers.entries[0].pubkey;
+        assert_eq!(state.get_next_fallback_duty_slot(&maintainer), None);
+    }
+
+    #[test]
+    fn next_fallback_duty_slot_agrees_with_fallback_duty() {
+        for num_maintainers in 2..6 {
+            let mut state = new_solido_with_overdue_maintenance(num_maintainers);
+            let maintainers: Vec<Pubkey> = state
+                .solido
+                .maintainers
+                .entries
+                .iter()
+                .map(|m| m.pubkey)
+                .collect();
+            state.clock.slot = SolidoState::MAINTAINER_DUTY_SLICE_LENGTH;
+            for maintainer in maintainers.iter().rev().chain(maintainers.iter()) {
+                let start_slot = state
+                    .get_next_fallback_duty_slot(maintainer)
+                    .expect("The maintainer is part of the set, it should have a fallback slot.");
+                assert!(start_slot > state.clock.slot);
+                state.clock.slot = start_slot - 1;
+                assert_ne!(state.get_fallback_maintainer_duty(), Some(*maintainer));
+                state.clock.slot = start_slot;
+                assert_eq!(state.get_fallback_maintainer_duty(), Some(*maintainer));
+            }
+        }
+    }
+
     #[test]
     fn next_maintainer_duty_returns_slot_greater_than_current_slot() {
         let mut state = new_empty_solido();
@@ -1751,4 +2535,231 @@ mod test {
             Some(())
         );
     }
+
+    #[test]
+    fn stake_deposit_leaves_the_reserve_buffer() {
+        let mut state = new_empty_solido();
+        state.validators.maximum_entries = 1;
+        state
+            .validators
+            .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+            .unwrap();
+        state.validator_stake_accounts.push(vec![]);
+        state.reserve_account.lamports += 3 * MINIMUM_STAKE_ACCOUNT_BALANCE.0;
+
+        // With the buffer, what is left is not enough for a stake account.
+        state.rebalancing.reserve_buffer = (MINIMUM_STAKE_ACCOUNT_BALANCE * 5).unwrap();
+        assert_eq!(state.try_stake_deposit(), None);
+
+        state.rebalancing.reserve_buffer = MINIMUM_STAKE_ACCOUNT_BALANCE;
+        match state.try_stake_deposit() {
+            Some((_, MaintenanceOperation::StakeDeposit { amount, .. })) => {
+                assert_eq!(amount, (MINIMUM_STAKE_ACCOUNT_BALANCE * 2).unwrap())
+            }
+            other => panic!("Expected a stake deposit, got {:?}.", other),
+        }
+    }
+
+    #[test]
+    fn unstake_does_not_exceed_max_unstake_per_epoch() {
+        let mut state = new_empty_solido();
+        state.validators.maximum_entries = 2;
+        for _ in 0..2 {
+            state
+                .validators
+                .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+                .unwrap();
+        }
+        // The first validator holds all the stake, so we should move half of it.
+        let stake = (MINIMUM_STAKE_ACCOUNT_BALANCE * 10).unwrap();
+        state.validators.entries[0].entry.stake_accounts_balance = stake;
+        let stake_account = StakeAccount {
+            balance: StakeBalance {
+                active: stake,
+                ..StakeBalance::zero()
+            },
+            credits_observed: 0,
+            activation_epoch: 0,
+            deactivation_epoch: u64::MAX,
+            seed: 0,
+        };
+        state.validator_stake_accounts = vec![vec![(Pubkey::new_unique(), stake_account)], vec![]];
+        state.validator_unstake_accounts = vec![vec![], vec![]];
+
+        let max_unstake = (MINIMUM_STAKE_ACCOUNT_BALANCE * 2).unwrap();
+        state.rebalancing.max_unstake_per_epoch = Some(max_unstake);
+        match state.try_unstake_from_active_validators() {
+            Some((_, MaintenanceOperation::UnstakeFromActiveValidator(unstake))) => {
+                assert_eq!(unstake.amount, max_unstake)
+            }
+            other => panic!("Expected an unstake, got {:?}.", other),
+        }
+
+        // Unstake accounts that started deactivating in an earlier epoch do not
+        // count towards the maximum.
+        state.clock.epoch = 5;
+        let unstake_account = StakeAccount {
+            balance: StakeBalance {
+                deactivating: max_unstake,
+                ..StakeBalance::zero()
+            },
+            deactivation_epoch: 4,
+            ..stake_account
+        };
+        state.validators.entries[0].entry.unstake_accounts_balance = max_unstake;
+        state.validator_unstake_accounts[0].push((Pubkey::new_unique(), unstake_account));
+        assert_eq!(state.get_unstaked_in_current_epoch(), Lamports(0));
+        assert!(state.try_unstake_from_active_validators().is_some());
+
+        // After we unstaked the maximum, we don't unstake more in this epoch.
+        state.validator_unstake_accounts[0][0].1.deactivation_epoch = 5;
+        assert_eq!(state.get_unstaked_in_current_epoch(), max_unstake);
+        assert_eq!(state.try_unstake_from_active_validators(), None);
+
+        // Unstaking from an inactive validator to remove it is not rebalancing.
+        state.validators.entries[0].entry.active = false;
+        assert_eq!(state.get_unstaked_in_current_epoch(), Lamports(0));
+    }
+
+    #[test]
+    fn pack_transactions_respects_the_compute_limit() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let accounts = lido::instruction::UpdateExchangeRateAccountsMeta {
+            lido: Pubkey::new_unique(),
+            reserve: Pubkey::new_unique(),
+            st_sol_mint: Pubkey::new_unique(),
+            validator_list: Pubkey::new_unique(),
+        };
+        // These instructions share all their accounts, so many of them fit in
+        // a packet, but not within the compute limit.
+        let operations: Vec<(Instruction, MaintenanceOperation)> = (0..20)
+            .map(|_| {
+                (
+                    lido::instruction::update_exchange_rate(&program_id, &accounts),
+                    MaintenanceOperation::UpdateExchangeRate,
+                )
+            })
+            .collect();
+
+        let packed = pack_transactions(&payer, operations);
+        let lengths: Vec<usize> = packed.iter().map(|(ixs, _)| ixs.len()).collect();
+        assert_eq!(lengths, vec![7, 7, 6]);
+    }
+
+    #[test]
+    fn pack_transactions_fills_transactions_up_to_the_size_limit() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let solido_address = Pubkey::new_unique();
+        let validator_list = Pubkey::new_unique();
+        let stake_authority = Pubkey::new_unique();
+
+        let operations: Vec<(Instruction, MaintenanceOperation)> = (0..30)
+            .map(|i| {
+                let validator_vote_account = Pubkey::new_unique();
+                let from_stake = Pubkey::new_unique();
+                let to_stake = Pubkey::new_unique();
+                let instruction = lido::instruction::merge_stake(
+                    &program_id,
+                    &lido::instruction::MergeStakeMeta {
+                        lido: solido_address,
+                        validator_list,
+                        validator_vote_account,
+                        from_stake,
+                        to_stake,
+                        stake_authority,
+                    },
+                );
+                let operation = MaintenanceOperation::MergeStake {
+                    validator_vote_account,
+                    from_stake,
+                    to_stake,
+                    from_stake_seed: i,
+                    to_stake_seed: i + 1,
+                };
+                (instruction, operation)
+            })
+            .collect();
+
+        let instructions: Vec<Instruction> = operations.iter().map(|(i, _)| i.clone()).collect();
+        let expected_operations: Vec<MaintenanceOperation> =
+            operations.iter().map(|(_, op)| op.clone()).collect();
+
+        let packed = pack_transactions(&payer, operations);
+
+        // Everything does not fit in one transaction, but we do need fewer
+        // transactions than operations.
+        assert!(packed.len() > 1);
+        assert!(packed.len() < instructions.len());
+
+        let mut offset = 0;
+        for (tx_instructions, transaction) in packed.iter() {
+            assert_eq!(tx_instructions.len(), transaction.operations.len());
+            assert!(get_transaction_size(&payer, tx_instructions) <= PACKET_DATA_SIZE);
+
+            // The order is preserved.
+            let end = offset + tx_instructions.len();
+            assert_eq!(&tx_instructions[..], &instructions[offset..end]);
+            assert_eq!(
+                &transaction.operations[..],
+                &expected_operations[offset..end]
+            );
+
+            // Every transaction but the last is full: the next instruction
+            // would not have fit.
+            assert!(tx_instructions.len() <= MAX_INSTRUCTIONS_PER_TRANSACTION);
+            if end < instructions.len() {
+                assert!(
+                    tx_instructions.len() == MAX_INSTRUCTIONS_PER_TRANSACTION
+                        || get_transaction_size(&payer, &instructions[offset..end + 1])
+                            > PACKET_DATA_SIZE
+                );
+            }
+            offset = end;
+        }
+        assert_eq!(offset, instructions.len());
+    }
+
+    #[test]
+    fn find_lido_error_ignores_errors_from_other_programs() {
+        let solido_program_id = Pubkey::new_unique();
+        let logs: Vec<String> = vec![
+            format!("Program {} invoke [1]", solido_program_id),
+            "Program Stake11111111111111111111111111111111111 invoke [2]".to_string(),
+            "Program Stake11111111111111111111111111111111111 failed: custom program error: 0x6"
+                .to_string(),
+            format!(
+                "Program {} failed: custom program error: 0x6",
+                solido_program_id
+            ),
+        ];
+        assert_eq!(
+            find_lido_error(&solido_program_id, &logs),
+            Some(LidoError::WrongStakeState)
+        );
+        assert_eq!(find_lido_error(&Pubkey::new_unique(), &logs), None);
+        assert_eq!(find_lido_error(&solido_program_id, &logs[..3]), None);
+    }
+
+    /// This is a regression test. When no validator was above its target, we
+    /// would pick a validator without stake accounts to unstake from, and then
+    /// panic on the missing stake account.
+    #[test]
+    fn unstake_ignores_validators_without_stake_accounts() {
+        let mut state = new_empty_solido();
+        state.validators.maximum_entries = 2;
+        for _ in 0..2 {
+            state
+                .validators
+                .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+                .unwrap();
+        }
+        state.validator_stake_accounts = vec![vec![], vec![]];
+        state.validator_unstake_accounts = vec![vec![], vec![]];
+
+        // Both validators are below their target, so nothing needs unstaking.
+        state.reserve_account.lamports += 10_000_000_000;
+        assert_eq!(state.try_unstake_from_active_validators(), None);
+    }
 }
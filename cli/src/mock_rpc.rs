@@ -0,0 +1,373 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! An in-process cluster to run CLI commands against in tests.
+//!
+//! [`MockRpc`] executes transactions against the Solido and Multisig programs
+//! in a bank, with the same machinery that the program tests use, so commands
+//! and the maintainer can be tested end to end, without a validator or network.
+//!
+//! We drive the bank directly rather than through `ProgramTestContext`, because
+//! the latter has no way to simulate transactions, and we need that for
+//! dry runs.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Once};
+
+use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_filter::RpcFilterType;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_program_test::{find_file, processor, read_file, tokio, ProgramTest};
+use solana_runtime::bank::Bank;
+use solana_runtime::genesis_utils::create_genesis_config_with_leader_ex;
+use solana_sdk::account::Account;
+use solana_sdk::bpf_loader;
+use solana_sdk::clock::Slot;
+use solana_sdk::fee_calculator::FeeRateGovernor;
+use solana_sdk::genesis_config::ClusterType;
+use solana_sdk::hash::Hash;
+use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::process_instruction::ProcessInstructionWithContext;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::{self, Transaction};
+use solana_transaction_status::TransactionStatus;
+use solana_vote_program::vote_state::VoteState;
+
+use lido::token::Lamports;
+
+use crate::error::Error;
+use crate::snapshot::SnapshotSource;
+
+/// Install the syscall stubs that natively running programs need to call other programs.
+///
+/// `solana_program_test` installs them when the first `ProgramTest` starts,
+/// and offers no other way to install them.
+fn install_syscall_stubs() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to start Tokio runtime.");
+        runtime.block_on(ProgramTest::default().start());
+    });
+}
+
+/// Deploy a program, like `ProgramTest::add_program` does.
+///
+/// If `BPF_OUT_DIR` is set, as `cargo test-bpf` does, we load the BPF build of
+/// the program. Otherwise we run it natively, so we don't need to build it for
+/// BPF, but then the program cannot resize accounts through cross-program
+/// invocations, which rules out creating stake accounts.
+fn add_program(
+    bank: &mut Bank,
+    name: &str,
+    program_id: Pubkey,
+    process_instruction: ProcessInstructionWithContext,
+) {
+    if std::env::var("BPF_OUT_DIR").is_err() {
+        bank.add_builtin(name, program_id, process_instruction);
+        return;
+    }
+    let filename = format!("{}.so", name);
+    let path = find_file(&filename)
+        .unwrap_or_else(|| panic!("Program file {} not found in BPF_OUT_DIR.", filename));
+    let data = read_file(path);
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()).max(1),
+        data,
+        owner: bpf_loader::id(),
+        executable: true,
+        rent_epoch: 0,
+    };
+    bank.store_account(&program_id, &account.into());
+}
+
+/// Convert the result of executing a transaction into the error that the RPC
+/// returns when preflight fails.
+fn into_preflight_result(result: transaction::Result<()>) -> ClientResult<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) => Err(RpcError::RpcResponseError {
+            code: -32002,
+            message: format!("Transaction simulation failed: {}", err),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    err: Some(err),
+                    logs: Some(Vec::new()),
+                    accounts: None,
+                },
+            ),
+        }
+        .into()),
+    }
+}
+
+/// A handle to an in-process cluster, that can serve as [`SnapshotSource`].
+///
+/// Clones refer to the same cluster, so a test can keep a handle to inspect
+/// the cluster or advance time, while a [`crate::snapshot::SnapshotClient`]
+/// owns another.
+#[derive(Clone)]
+pub struct MockRpc {
+    /// The bank at the most recent slot.
+    ///
+    /// We only simulate transactions against a frozen bank, after that we
+    /// continue in a new bank at the next slot.
+    bank: Rc<RefCell<Arc<Bank>>>,
+
+    /// The fees that the executed transactions paid.
+    fees: Rc<RefCell<HashMap<Signature, Lamports>>>,
+
+    pub solido_program_id: Pubkey,
+    pub multisig_program_id: Pubkey,
+}
+
+impl MockRpc {
+    /// The fee per signature, which unlike on a real cluster, does not vary.
+    pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+    /// Start a cluster with the Solido and Multisig programs deployed.
+    ///
+    /// Returns the cluster and a funded keypair.
+    pub fn start() -> (MockRpc, Keypair) {
+        install_syscall_stubs();
+
+        let solido_program_id = Pubkey::new_unique();
+        let multisig_program_id = Pubkey::new_unique();
+
+        // The same genesis as `ProgramTest` uses, but with a fixed fee.
+        let rent = Rent::default();
+        let payer = Keypair::new();
+        let genesis_config = create_genesis_config_with_leader_ex(
+            sol_to_lamports(1_000_000.0),
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            rent.minimum_balance(VoteState::size_of()) + sol_to_lamports(1_000_000.0),
+            42,
+            FeeRateGovernor::new(Self::LAMPORTS_PER_SIGNATURE, 0),
+            rent,
+            ClusterType::Development,
+            vec![],
+        );
+
+        let mut bank = Bank::new(&genesis_config);
+        bank.add_builtin(
+            "solana_bpf_loader_program",
+            bpf_loader::id(),
+            solana_bpf_loader_program::process_instruction,
+        );
+        for (program_id, account) in solana_program_test::programs::spl_programs(&rent) {
+            bank.store_account(&program_id, &account);
+        }
+
+        add_program(
+            &mut bank,
+            "lido",
+            solido_program_id,
+            processor!(lido::processor::process).unwrap(),
+        );
+        add_program(
+            &mut bank,
+            "serum_multisig",
+            multisig_program_id,
+            processor!(serum_multisig::entry).unwrap(),
+        );
+
+        let mock = MockRpc {
+            bank: Rc::new(RefCell::new(Arc::new(bank))),
+            fees: Rc::new(RefCell::new(HashMap::new())),
+            solido_program_id,
+            multisig_program_id,
+        };
+        (mock, payer)
+    }
+
+    /// Return the bank to execute transactions in.
+    fn working_bank(&self) -> Arc<Bank> {
+        let mut bank = self.bank.borrow_mut();
+        if bank.is_frozen() {
+            let child = Bank::new_from_parent(&bank, bank.collector_id(), bank.slot() + 1);
+            *bank = Arc::new(child);
+        }
+        bank.clone()
+    }
+
+    /// Return the bank to simulate transactions in.
+    fn frozen_bank(&self) -> Arc<Bank> {
+        let bank = self.bank.borrow().clone();
+        bank.freeze();
+        bank
+    }
+
+    pub fn get_account(&self, address: &Pubkey) -> Option<Account> {
+        self.bank.borrow().get_account(address).map(Account::from)
+    }
+
+    /// Overwrite an account, for state that we can't produce with transactions.
+    pub fn set_account(&self, address: &Pubkey, account: Account) {
+        self.working_bank().store_account(address, &account.into());
+    }
+
+    /// Advance the cluster to the first slot of the given epoch.
+    pub fn warp_to_epoch(&self, epoch: u64) {
+        let mut bank = self.bank.borrow_mut();
+        let slot = bank.epoch_schedule().get_first_slot_in_epoch(epoch);
+        // Warping produces a frozen bank, so like `ProgramTestContext`, we warp
+        // to the slot before, and continue in a new bank from there.
+        let warp_bank = Arc::new(Bank::warp_from_parent(&bank, &Pubkey::default(), slot - 1));
+        *bank = Arc::new(Bank::new_from_parent(&warp_bank, &Pubkey::default(), slot));
+    }
+
+    /// Execute the transaction, and produce a new blockhash afterwards.
+    ///
+    /// With a new blockhash, sending an otherwise identical transaction later
+    /// produces a new signature.
+    fn process_transaction(&self, transaction: &Transaction) -> transaction::Result<()> {
+        transaction.verify()?;
+        let bank = self.working_bank();
+        let fee_calculator = bank.get_fee_calculator(&transaction.message.recent_blockhash);
+        let result = bank.process_transaction(transaction);
+        // Failed transactions count as failed in preflight, so only the ones
+        // that succeed pay a fee.
+        if let (Ok(()), Some(fee_calculator)) = (&result, fee_calculator) {
+            self.fees.borrow_mut().insert(
+                transaction.signatures[0],
+                Lamports(fee_calculator.calculate_fee(&transaction.message)),
+            );
+        }
+        let last_blockhash = bank.last_blockhash();
+        while last_blockhash == bank.last_blockhash() {
+            bank.register_tick(&Hash::new_unique());
+        }
+        result
+    }
+}
+
+impl SnapshotSource for MockRpc {
+    fn get_multiple_accounts(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> Result<(Vec<Option<Account>>, Option<Slot>), Error> {
+        // Nothing executes between these reads, so they are consistent.
+        let bank = self.bank.borrow();
+        let accounts = addresses
+            .iter()
+            .map(|address| bank.get_account(address).map(Account::from))
+            .collect();
+        Ok((accounts, Some(bank.slot())))
+    }
+
+    fn get_validator_info_addrs(&mut self) -> Result<HashMap<Pubkey, Pubkey>, Error> {
+        let config_accounts = self.get_program_accounts_with_config(
+            &solana_sdk::config::program::id(),
+            RpcProgramAccountsConfig::default(),
+        )?;
+        Ok(crate::validator_info_utils::find_validator_info_accounts(
+            &config_accounts,
+        ))
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        let filters = config.filters.unwrap_or_default();
+        let accounts = self
+            .bank
+            .borrow()
+            .get_program_accounts(program_id)
+            .map_err(|err| ClientErrorKind::Custom(err.to_string()))?;
+        let mut result = Vec::new();
+        for (address, account) in accounts {
+            let mut account = Account::from(account);
+            let is_match = filters.iter().all(|filter| match filter {
+                RpcFilterType::DataSize(size) => account.data.len() as u64 == *size,
+                RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&account.data),
+            });
+            if !is_match {
+                continue;
+            }
+            if let Some(slice) = config.account_config.data_slice {
+                let start = slice.offset.min(account.data.len());
+                let end = (slice.offset + slice.length).min(account.data.len());
+                account.data = account.data[start..end].to_vec();
+            }
+            result.push((address, account));
+        }
+        Ok(result)
+    }
+
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<RpcSimulateTransactionResult> {
+        // Like the RPC client, we let the node replace the blockhash, so
+        // callers can simulate unsigned transactions without one.
+        let bank = self.frozen_bank();
+        let mut transaction = transaction.clone();
+        transaction.message.recent_blockhash = bank.last_blockhash();
+        let (result, logs, _accounts) = bank.simulate_transaction(&transaction);
+        Ok(RpcSimulateTransactionResult {
+            err: result.err(),
+            logs: Some(logs),
+            accounts: None,
+        })
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<TransactionStatus>>> {
+        let bank = self.bank.borrow();
+        Ok(signatures
+            .iter()
+            .map(|signature| {
+                bank.get_signature_status_slot(signature)
+                    .map(|(slot, status)| TransactionStatus {
+                        slot,
+                        // Every slot is final as soon as we process it.
+                        confirmations: None,
+                        err: status.clone().err(),
+                        status,
+                        confirmation_status: None,
+                    })
+            })
+            .collect())
+    }
+
+    fn get_transaction_fee(&self, signature: &Signature) -> ClientResult<Option<Lamports>> {
+        Ok(self.fees.borrow().get(signature).cloned())
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        // The bank executes the transaction right away, so a failure is like a
+        // failure in preflight: we know it did not execute.
+        into_preflight_result(self.process_transaction(transaction))?;
+        Ok(transaction.signatures[0])
+    }
+
+    fn confirm_transaction(&self, _signature: &Signature) -> ClientResult<bool> {
+        // Transactions are final as soon as `send_transaction` returns.
+        Ok(true)
+    }
+
+    fn confirm_transaction_with_spinner(
+        &self,
+        _signature: &Signature,
+        _recent_blockhash: &Hash,
+    ) -> ClientResult<()> {
+        Ok(())
+    }
+
+    fn rpc_client(&self) -> Option<&RpcClient> {
+        None
+    }
+}
@@ -3,6 +3,7 @@
 
 use std::collections::HashSet;
 use std::fmt;
+use std::path::Path;
 
 use anchor_lang::prelude::{AccountMeta, ToAccountMetas};
 use anchor_lang::{Discriminator, InstructionData};
@@ -14,29 +15,40 @@ use serum_multisig::accounts as multisig_accounts;
 use serum_multisig::instruction as multisig_instruction;
 use solana_sdk::bpf_loader_upgradeable;
 use solana_sdk::instruction::Instruction;
+use solana_sdk::program_error::ProgramError;
 use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::stake::{self, state::StakeAuthorize};
 use solana_sdk::system_instruction;
+use solana_sdk::system_program;
 use solana_sdk::sysvar;
+use solana_sdk::vote;
+use solana_vote_program::vote_instruction;
 
 use lido::{
     instruction::{
-        AddMaintainerMeta, AddValidatorMeta, ChangeRewardDistributionMeta, DeactivateValidatorMeta,
-        LidoInstruction, RemoveMaintainerMeta,
+        AddMaintainerMeta, AddValidatorMeta, ChangeRewardDistributionMeta, ClaimValidatorFeeMeta,
+        CollectValidatorFeeMeta, DeactivateValidatorMeta, DepositAccountsMeta,
+        InitializeAccountsMeta, LidoInstruction, MergeStakeMeta, MigrateStateToV1Meta,
+        RemoveMaintainerMeta, RemoveValidatorMeta, StakeDepositAccountsMeta, UnstakeAccountsMeta,
+        UpdateExchangeRateAccountsMeta, WithdrawAccountsMeta, WithdrawInactiveStakeMeta,
     },
     state::{FeeRecipients, Lido, RewardDistribution},
-    util::{serialize_b58, serialize_b58_slice},
+    token::{Lamports, StLamports},
+    util::{serialize_b58, serialize_b58_option, serialize_b58_slice},
 };
 
 use crate::config::{
     ApproveBatchOpts, ApproveOpts, ConfigFile, CreateMultisigOpts, ExecuteTransactionOpts,
-    ProposeChangeMultisigOpts, ProposeUpgradeOpts, ShowMultisigOpts, ShowTransactionOpts,
-    TransferTokenOpts,
+    ExportApprovalOpts, ListTransactionsOpts, ProposeChangeMultisigOpts, ProposeUpgradeOpts,
+    ShowMultisigOpts, ShowTransactionOpts, SignOfflineOpts, SubmitApprovalOpts, TransferTokenOpts,
 };
 use crate::error::{Abort, AsPrettyError};
+use crate::offline_approval;
 use crate::print_output;
 use crate::snapshot::{Result, SnapshotError};
+use crate::verify_program::{verify_buffer, ProgramVerification};
 use crate::{SnapshotClientConfig, SnapshotConfig};
 
 #[derive(Clap, Debug)]
@@ -59,6 +71,9 @@ impl MultisigOpts {
             SubCommand::ShowTransaction(opts) => {
                 opts.merge_with_config_and_environment(config_file)
             }
+            SubCommand::ListTransactions(opts) => {
+                opts.merge_with_config_and_environment(config_file)
+            }
             SubCommand::ProposeUpgrade(opts) => opts.merge_with_config_and_environment(config_file),
             SubCommand::ProposeChangeMultisig(opts) => {
                 opts.merge_with_config_and_environment(config_file)
@@ -68,6 +83,9 @@ impl MultisigOpts {
                 opts.merge_with_config_and_environment(config_file)
             }
             SubCommand::ApproveBatch(opts) => opts.merge_with_config_and_environment(config_file),
+            SubCommand::ExportApproval(opts) => opts.merge_with_config_and_environment(config_file),
+            SubCommand::SignOffline(opts) => opts.merge_with_config_and_environment(config_file),
+            SubCommand::SubmitApproval(opts) => opts.merge_with_config_and_environment(config_file),
             SubCommand::Token(token_sub_command) => match token_sub_command {
                 TokenSubCommand::Transfer(opts) => {
                     opts.merge_with_config_and_environment(config_file)
@@ -88,6 +106,11 @@ enum SubCommand {
     /// Show the details of a transaction.
     ShowTransaction(ShowTransactionOpts),
 
+    /// List the transactions of a multisig, with their approvals.
+    ///
+    /// Transactions that are not yet executed come first.
+    ListTransactions(ListTransactionsOpts),
+
     /// Propose replacing a program with that in the given buffer account.
     ProposeUpgrade(ProposeUpgradeOpts),
 
@@ -103,6 +126,20 @@ enum SubCommand {
     /// Approve a batch of multisig transactions one by one.
     ApproveBatch(ApproveBatchOpts),
 
+    /// Write a request to approve a transaction, to sign on an offline machine.
+    ///
+    /// Use a durable nonce with --nonce-account when the signed request cannot
+    /// be submitted within a minute.
+    ExportApproval(ExportApprovalOpts),
+
+    /// Sign an approval request. This does not connect to the cluster.
+    ///
+    /// Set --keypair-path to the owner's key, for example usb://ledger.
+    SignOffline(SignOfflineOpts),
+
+    /// Submit an approval request that was signed with 'sign-offline'.
+    SubmitApproval(SubmitApprovalOpts),
+
     /// Execute SPL token operations.
     Token(TokenSubCommand),
 }
@@ -133,11 +170,17 @@ pub fn main(config: &mut SnapshotClientConfig, multisig_opts: MultisigOpts) {
                     cmd_opts.transaction_address(),
                     cmd_opts.multisig_program_id(),
                     cmd_opts.solido_program_id(),
+                    cmd_opts.verify_against(),
                 )
             });
             let output = result.ok_or_abort_with("Failed to read multisig.");
             print_output(output_mode, &output);
         }
+        SubCommand::ListTransactions(cmd_opts) => {
+            let result = config.with_snapshot(|config| list_transactions(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to list multisig transactions.");
+            print_output(output_mode, &output);
+        }
         SubCommand::ProposeUpgrade(cmd_opts) => {
             let result = config.with_snapshot(|config| propose_upgrade(config, &cmd_opts));
             let output = result.ok_or_abort_with("Failed to propose upgrade.");
@@ -174,6 +217,22 @@ pub fn main(config: &mut SnapshotClientConfig, multisig_opts: MultisigOpts) {
             let result = approve_batch(config, &cmd_opts);
             result.ok_or_abort_with("Failed to batch-approve multisig transactions.");
         }
+        SubCommand::ExportApproval(cmd_opts) => {
+            let result =
+                config.with_snapshot(|config| offline_approval::export_approval(config, &cmd_opts));
+            let output = result.ok_or_abort_with("Failed to export approval request.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::SignOffline(cmd_opts) => {
+            let result = offline_approval::sign_offline(config.signer, output_mode, &cmd_opts);
+            let output = result.ok_or_abort_with("Failed to sign approval request.");
+            print_output(output_mode, &output);
+        }
+        SubCommand::SubmitApproval(cmd_opts) => {
+            let result = offline_approval::command_submit_approval(config, &cmd_opts);
+            let output = result.ok_or_abort_with("Failed to submit approval.");
+            print_output(output_mode, &output);
+        }
         SubCommand::Token(token_sub_command) => match token_sub_command {
             TokenSubCommand::Transfer(cmd_opts) => {
                 let result = config.with_snapshot(|config| transfer_token(config, &cmd_opts));
@@ -193,12 +252,12 @@ pub fn get_multisig_program_address(
 }
 
 #[derive(Serialize)]
-struct CreateMultisigOutput {
+pub struct CreateMultisigOutput {
     #[serde(serialize_with = "serialize_b58")]
-    multisig_address: Pubkey,
+    pub multisig_address: Pubkey,
 
     #[serde(serialize_with = "serialize_b58")]
-    multisig_program_derived_address: Pubkey,
+    pub multisig_program_derived_address: Pubkey,
 }
 
 impl fmt::Display for CreateMultisigOutput {
@@ -214,7 +273,7 @@ impl fmt::Display for CreateMultisigOutput {
     }
 }
 
-fn create_multisig(
+pub fn create_multisig(
     config: &mut SnapshotConfig,
     opts: &CreateMultisigOpts,
 ) -> Result<CreateMultisigOutput> {
@@ -352,6 +411,17 @@ enum ShowTransactionSigners {
     },
 }
 
+impl ShowTransactionSigners {
+    fn num_signed(&self) -> usize {
+        match self {
+            ShowTransactionSigners::Current { signers } => {
+                signers.iter().filter(|signer| signer.did_sign).count()
+            }
+            ShowTransactionSigners::Outdated { num_signed, .. } => *num_signed,
+        }
+    }
+}
+
 /// If an `Instruction` is a known one, this contains its details.
 #[derive(Serialize)]
 enum ParsedInstruction {
@@ -381,12 +451,153 @@ enum ParsedInstruction {
     },
     SolidoInstruction(SolidoInstruction),
     TokenInstruction(TokenInstruction),
+    SystemInstruction(SystemInstruction),
+    StakeInstruction(StakeInstruction),
+    VoteInstruction(VoteInstruction),
     InvalidSolidoInstruction,
+    InvalidInstruction,
     Unrecognized,
 }
 
 #[derive(Serialize)]
 enum SolidoInstruction {
+    Initialize {
+        reward_distribution: RewardDistribution,
+        max_validators: u32,
+        max_maintainers: u32,
+
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        manager: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        st_sol_mint: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        treasury_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        developer_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        reserve_account: Pubkey,
+    },
+    Deposit {
+        amount: Lamports,
+
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        user: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        recipient: Pubkey,
+    },
+    Withdraw {
+        amount: StLamports,
+
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        st_sol_account_owner: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        st_sol_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        source_stake_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        destination_stake_account: Pubkey,
+    },
+    StakeDeposit {
+        amount: Lamports,
+
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+    },
+    Unstake {
+        amount: Lamports,
+
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        maintainer: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        source_stake_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        destination_unstake_account: Pubkey,
+    },
+    UpdateExchangeRate {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+    },
+    WithdrawInactiveStake {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58_slice")]
+        stake_accounts: Vec<Pubkey>,
+    },
+    CollectValidatorFee {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    ClaimValidatorFee {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_fee_st_sol_account: Pubkey,
+    },
+    RemoveValidator {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+    },
+    MergeStake {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_vote_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        from_stake_account: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        to_stake_account: Pubkey,
+    },
     AddValidator {
         #[serde(serialize_with = "serialize_b58")]
         solido_instance: Pubkey,
@@ -430,6 +641,16 @@ enum SolidoInstruction {
         #[serde(serialize_with = "serialize_b58")]
         maintainer: Pubkey,
     },
+    MigrateStateToV1 {
+        #[serde(serialize_with = "serialize_b58")]
+        solido_instance: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        manager: Pubkey,
+
+        #[serde(serialize_with = "serialize_b58")]
+        validator_list: Pubkey,
+    },
     ChangeRewardDistribution {
         current_solido: Box<Lido>,
         reward_distribution: RewardDistribution,
@@ -455,11 +676,123 @@ enum TokenInstruction {
         token_address: Pubkey,
         amount: u64,
     },
+    Approve {
+        #[serde(serialize_with = "serialize_b58")]
+        source_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        delegate_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        owner_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        token_address: Pubkey,
+        amount: u64,
+    },
+    MintTo {
+        #[serde(serialize_with = "serialize_b58")]
+        token_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        to_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        mint_authority: Pubkey,
+        amount: u64,
+    },
+    Burn {
+        #[serde(serialize_with = "serialize_b58")]
+        from_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        token_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        owner_address: Pubkey,
+        amount: u64,
+    },
+    SetAuthority {
+        /// The mint or token account whose authority changes.
+        #[serde(serialize_with = "serialize_b58")]
+        address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        current_authority: Pubkey,
+        /// `None` removes the authority, which cannot be undone.
+        #[serde(serialize_with = "serialize_b58_option")]
+        new_authority: Option<Pubkey>,
+        authority_type: &'static str,
+    },
+    Unsupported,
+}
+
+#[derive(Serialize)]
+enum SystemInstruction {
+    Transfer {
+        #[serde(serialize_with = "serialize_b58")]
+        from_address: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        to_address: Pubkey,
+        amount: Lamports,
+    },
+    Unsupported,
+}
+
+#[derive(Serialize)]
+enum StakeInstruction {
+    Authorize {
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        current_authority: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        new_authority: Pubkey,
+        authority_type: StakeAuthorize,
+    },
+    Delegate {
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        stake_authority: Pubkey,
+    },
+    Deactivate {
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        stake_authority: Pubkey,
+    },
+    Withdraw {
+        #[serde(serialize_with = "serialize_b58")]
+        stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        recipient: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        withdraw_authority: Pubkey,
+        amount: Lamports,
+    },
+    Split {
+        #[serde(serialize_with = "serialize_b58")]
+        source_stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        destination_stake_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        stake_authority: Pubkey,
+        amount: Lamports,
+    },
     Unsupported,
 }
 
 #[derive(Serialize)]
-struct ShowTransactionOutput {
+enum VoteInstruction {
+    Withdraw {
+        #[serde(serialize_with = "serialize_b58")]
+        vote_account: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        recipient: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        withdraw_authority: Pubkey,
+        amount: Lamports,
+    },
+    Unsupported,
+}
+
+#[derive(Serialize)]
+pub struct ShowTransactionOutput {
     #[serde(serialize_with = "serialize_b58")]
     multisig_address: Pubkey,
     did_execute: bool,
@@ -472,6 +805,9 @@ struct ShowTransactionOutput {
     // one when needed.
     instruction: Instruction,
     parsed_instruction: ParsedInstruction,
+
+    /// For an upgrade, the buffer compared against a local build, if requested.
+    buffer_verification: Option<ProgramVerification>,
 }
 
 impl fmt::Display for ShowTransactionOutput {
@@ -534,6 +870,10 @@ impl fmt::Display for ShowTransactionOutput {
                 writeln!(f, "    Program data address:    {}", program_data_address)?;
                 writeln!(f, "    Buffer with new program: {}", buffer_address)?;
                 writeln!(f, "    Spill address:           {}", spill_address)?;
+                if let Some(verification) = &self.buffer_verification {
+                    writeln!(f, "\n  Buffer verification:")?;
+                    write!(f, "{}", verification)?;
+                }
             }
             ParsedInstruction::MultisigChange {
                 old_threshold,
@@ -550,6 +890,181 @@ impl fmt::Display for ShowTransactionOutput {
             ParsedInstruction::SolidoInstruction(solido_instruction) => {
                 write!(f, "  This is a Solido instruction. ")?;
                 match solido_instruction {
+                    SolidoInstruction::Initialize {
+                        reward_distribution,
+                        max_validators,
+                        max_maintainers,
+                        solido_instance,
+                        manager,
+                        st_sol_mint,
+                        treasury_account,
+                        developer_account,
+                        reserve_account,
+                    } => {
+                        writeln!(f, "It initializes a new Solido instance.")?;
+                        writeln!(f, "    Solido instance:   {}", solido_instance)?;
+                        writeln!(f, "    Manager:           {}", manager)?;
+                        writeln!(f, "    stSOL mint:        {}", st_sol_mint)?;
+                        writeln!(f, "    Treasury account:  {}", treasury_account)?;
+                        writeln!(f, "    Developer account: {}", developer_account)?;
+                        writeln!(f, "    Reserve account:   {}", reserve_account)?;
+                        writeln!(f, "    Max validators:    {}", max_validators)?;
+                        writeln!(f, "    Max maintainers:   {}", max_maintainers)?;
+                        let sum = reward_distribution.sum();
+                        writeln!(f, "    Reward distribution:")?;
+                        writeln!(
+                            f,
+                            "      treasury:           {}/{}",
+                            reward_distribution.treasury_fee, sum
+                        )?;
+                        writeln!(
+                            f,
+                            "      validation:         {}/{}",
+                            reward_distribution.validation_fee, sum
+                        )?;
+                        writeln!(
+                            f,
+                            "      developer:          {}/{}",
+                            reward_distribution.developer_fee, sum
+                        )?;
+                        writeln!(
+                            f,
+                            "      stSOL appreciation: {}/{}",
+                            reward_distribution.st_sol_appreciation, sum
+                        )?;
+                    }
+                    SolidoInstruction::Deposit {
+                        amount,
+                        solido_instance,
+                        user,
+                        recipient,
+                    } => {
+                        writeln!(f, "It deposits SOL into Solido.")?;
+                        writeln!(f, "    Solido instance:         {}", solido_instance)?;
+                        writeln!(f, "    Depositor:               {}", user)?;
+                        writeln!(f, "    stSOL recipient account: {}", recipient)?;
+                        writeln!(f, "    Amount:                  {}", amount)?;
+                    }
+                    SolidoInstruction::Withdraw {
+                        amount,
+                        solido_instance,
+                        st_sol_account_owner,
+                        st_sol_account,
+                        validator_vote_account,
+                        source_stake_account,
+                        destination_stake_account,
+                    } => {
+                        writeln!(f, "It withdraws stSOL from Solido into a stake account.")?;
+                        writeln!(f, "    Solido instance:           {}", solido_instance)?;
+                        writeln!(f, "    stSOL account owner:       {}", st_sol_account_owner)?;
+                        writeln!(f, "    stSOL account:             {}", st_sol_account)?;
+                        writeln!(
+                            f,
+                            "    Validator vote account:    {}",
+                            validator_vote_account
+                        )?;
+                        writeln!(f, "    Source stake account:      {}", source_stake_account)?;
+                        writeln!(
+                            f,
+                            "    Destination stake account: {}",
+                            destination_stake_account
+                        )?;
+                        writeln!(f, "    Amount:                    {}", amount)?;
+                    }
+                    SolidoInstruction::StakeDeposit {
+                        amount,
+                        solido_instance,
+                        maintainer,
+                        validator_vote_account,
+                        stake_account,
+                    } => {
+                        writeln!(f, "It stakes SOL from the reserve with a validator.")?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Maintainer:             {}", maintainer)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                        writeln!(f, "    Stake account:          {}", stake_account)?;
+                        writeln!(f, "    Amount:                 {}", amount)?;
+                    }
+                    SolidoInstruction::Unstake {
+                        amount,
+                        solido_instance,
+                        maintainer,
+                        validator_vote_account,
+                        source_stake_account,
+                        destination_unstake_account,
+                    } => {
+                        writeln!(f, "It unstakes SOL from a validator.")?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Maintainer:             {}", maintainer)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                        writeln!(f, "    Source stake account:   {}", source_stake_account)?;
+                        writeln!(
+                            f,
+                            "    Unstake account:        {}",
+                            destination_unstake_account
+                        )?;
+                        writeln!(f, "    Amount:                 {}", amount)?;
+                    }
+                    SolidoInstruction::UpdateExchangeRate { solido_instance } => {
+                        writeln!(f, "It updates the exchange rate.")?;
+                        writeln!(f, "    Solido instance: {}", solido_instance)?;
+                    }
+                    SolidoInstruction::WithdrawInactiveStake {
+                        solido_instance,
+                        validator_vote_account,
+                        stake_accounts,
+                    } => {
+                        writeln!(
+                            f,
+                            "It withdraws inactive stake of a validator to the reserve."
+                        )?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                        writeln!(f, "    Stake accounts:")?;
+                        for stake_account in stake_accounts {
+                            writeln!(f, "      {}", stake_account)?;
+                        }
+                    }
+                    SolidoInstruction::CollectValidatorFee {
+                        solido_instance,
+                        validator_vote_account,
+                    } => {
+                        writeln!(f, "It collects the fees of a validator.")?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                    }
+                    SolidoInstruction::ClaimValidatorFee {
+                        solido_instance,
+                        validator_fee_st_sol_account,
+                    } => {
+                        writeln!(f, "It pays out the fees owed to a validator.")?;
+                        writeln!(f, "    Solido instance:       {}", solido_instance)?;
+                        writeln!(
+                            f,
+                            "    Validator fee account: {}",
+                            validator_fee_st_sol_account
+                        )?;
+                    }
+                    SolidoInstruction::RemoveValidator {
+                        solido_instance,
+                        validator_vote_account,
+                    } => {
+                        writeln!(f, "It removes a validator.")?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                    }
+                    SolidoInstruction::MergeStake {
+                        solido_instance,
+                        validator_vote_account,
+                        from_stake_account,
+                        to_stake_account,
+                    } => {
+                        writeln!(f, "It merges two stake accounts of a validator.")?;
+                        writeln!(f, "    Solido instance:        {}", solido_instance)?;
+                        writeln!(f, "    Validator vote account: {}", validator_vote_account)?;
+                        writeln!(f, "    Merge from:             {}", from_stake_account)?;
+                        writeln!(f, "    Merge into:             {}", to_stake_account)?;
+                    }
                     SolidoInstruction::AddValidator {
                         solido_instance,
                         manager,
@@ -596,6 +1111,20 @@ impl fmt::Display for ShowTransactionOutput {
                         writeln!(f, "    Manager:         {}", manager)?;
                         writeln!(f, "    Maintainer:      {}", maintainer)?;
                     }
+                    SolidoInstruction::MigrateStateToV1 {
+                        solido_instance,
+                        manager,
+                        validator_list,
+                    } => {
+                        writeln!(
+                            f,
+                            "It migrates the Solido state to version 1, moving the \
+                            validators into a separate validator list."
+                        )?;
+                        writeln!(f, "    Solido instance: {}", solido_instance)?;
+                        writeln!(f, "    Manager:         {}", manager)?;
+                        writeln!(f, "    Validator list:  {}", validator_list)?;
+                    }
                     SolidoInstruction::ChangeRewardDistribution {
                         current_solido,
                         reward_distribution,
@@ -621,6 +1150,9 @@ impl fmt::Display for ShowTransactionOutput {
                     "  Tried to deserialize a Solido instruction, but failed."
                 )?;
             }
+            ParsedInstruction::InvalidInstruction => {
+                writeln!(f, "  Tried to deserialize the instruction, but failed.")?;
+            }
             ParsedInstruction::TokenInstruction(token_instruction) => {
                 write!(f, "  This is a Token instruction. ")?;
                 match token_instruction {
@@ -640,11 +1172,185 @@ impl fmt::Display for ShowTransactionOutput {
                             amount
                         )?;
                     }
+                    TokenInstruction::Approve {
+                        source_address,
+                        delegate_address,
+                        owner_address,
+                        token_address,
+                        amount,
+                    } => {
+                        writeln!(f, "It lets a delegate transfer tokens.")?;
+                        writeln!(f, "    Token address:  {}", token_address)?;
+                        writeln!(f, "    Source address: {}", source_address)?;
+                        writeln!(f, "    Owner:          {}", owner_address)?;
+                        writeln!(f, "    Delegate:       {}", delegate_address)?;
+                        writeln!(
+                            f,
+                            "    Amount:         {}, of the token's smallest denomination",
+                            amount
+                        )?;
+                    }
+                    TokenInstruction::MintTo {
+                        token_address,
+                        to_address,
+                        mint_authority,
+                        amount,
+                    } => {
+                        writeln!(f, "It mints new tokens.")?;
+                        writeln!(f, "    Token address:  {}", token_address)?;
+                        writeln!(f, "    To address:     {}", to_address)?;
+                        writeln!(f, "    Mint authority: {}", mint_authority)?;
+                        writeln!(
+                            f,
+                            "    Amount:         {}, of the token's smallest denomination",
+                            amount
+                        )?;
+                    }
+                    TokenInstruction::Burn {
+                        from_address,
+                        token_address,
+                        owner_address,
+                        amount,
+                    } => {
+                        writeln!(f, "It burns tokens.")?;
+                        writeln!(f, "    Token address: {}", token_address)?;
+                        writeln!(f, "    From address:  {}", from_address)?;
+                        writeln!(f, "    Owner:         {}", owner_address)?;
+                        writeln!(
+                            f,
+                            "    Amount:        {}, of the token's smallest denomination",
+                            amount
+                        )?;
+                    }
+                    TokenInstruction::SetAuthority {
+                        address,
+                        current_authority,
+                        new_authority,
+                        authority_type,
+                    } => {
+                        writeln!(f, "It changes the authority of a mint or token account.")?;
+                        writeln!(f, "    Mint or account:   {}", address)?;
+                        writeln!(f, "    Authority type:    {}", authority_type)?;
+                        writeln!(f, "    Current authority: {}", current_authority)?;
+                        match new_authority {
+                            Some(new_authority) => {
+                                writeln!(f, "    New authority:     {}", new_authority)?
+                            }
+                            None => writeln!(
+                                f,
+                                "    New authority:     none, the authority is removed forever"
+                            )?,
+                        }
+                    }
                     TokenInstruction::Unsupported => {
                         writeln!(f, "The instruction is currently unsupported.")?;
                     }
                 }
             }
+            ParsedInstruction::SystemInstruction(system_instruction) => {
+                write!(f, "  This is a System instruction. ")?;
+                match system_instruction {
+                    SystemInstruction::Transfer {
+                        from_address,
+                        to_address,
+                        amount,
+                    } => {
+                        writeln!(f, "It transfers SOL.")?;
+                        writeln!(f, "    From address: {}", from_address)?;
+                        writeln!(f, "    To address:   {}", to_address)?;
+                        writeln!(f, "    Amount:       {}", amount)?;
+                    }
+                    SystemInstruction::Unsupported => {
+                        writeln!(f, "The instruction is currently unsupported.")?;
+                    }
+                }
+            }
+            ParsedInstruction::StakeInstruction(stake_instruction) => {
+                write!(f, "  This is a Stake instruction. ")?;
+                match stake_instruction {
+                    StakeInstruction::Authorize {
+                        stake_account,
+                        current_authority,
+                        new_authority,
+                        authority_type,
+                    } => {
+                        writeln!(f, "It changes the authority of a stake account.")?;
+                        writeln!(f, "    Stake account:     {}", stake_account)?;
+                        writeln!(f, "    Authority type:    {:?}", authority_type)?;
+                        writeln!(f, "    Current authority: {}", current_authority)?;
+                        writeln!(f, "    New authority:     {}", new_authority)?;
+                    }
+                    StakeInstruction::Delegate {
+                        stake_account,
+                        vote_account,
+                        stake_authority,
+                    } => {
+                        writeln!(f, "It delegates a stake account to a validator.")?;
+                        writeln!(f, "    Stake account:   {}", stake_account)?;
+                        writeln!(f, "    Vote account:    {}", vote_account)?;
+                        writeln!(f, "    Stake authority: {}", stake_authority)?;
+                    }
+                    StakeInstruction::Deactivate {
+                        stake_account,
+                        stake_authority,
+                    } => {
+                        writeln!(f, "It deactivates a stake account.")?;
+                        writeln!(f, "    Stake account:   {}", stake_account)?;
+                        writeln!(f, "    Stake authority: {}", stake_authority)?;
+                    }
+                    StakeInstruction::Withdraw {
+                        stake_account,
+                        recipient,
+                        withdraw_authority,
+                        amount,
+                    } => {
+                        writeln!(f, "It withdraws SOL from a stake account.")?;
+                        writeln!(f, "    Stake account:      {}", stake_account)?;
+                        writeln!(f, "    Recipient:          {}", recipient)?;
+                        writeln!(f, "    Withdraw authority: {}", withdraw_authority)?;
+                        writeln!(f, "    Amount:             {}", amount)?;
+                    }
+                    StakeInstruction::Split {
+                        source_stake_account,
+                        destination_stake_account,
+                        stake_authority,
+                        amount,
+                    } => {
+                        writeln!(f, "It splits a stake account.")?;
+                        writeln!(f, "    Source stake account:      {}", source_stake_account)?;
+                        writeln!(
+                            f,
+                            "    Destination stake account: {}",
+                            destination_stake_account
+                        )?;
+                        writeln!(f, "    Stake authority:           {}", stake_authority)?;
+                        writeln!(f, "    Amount:                    {}", amount)?;
+                    }
+                    StakeInstruction::Unsupported => {
+                        writeln!(f, "The instruction is currently unsupported.")?;
+                    }
+                }
+            }
+            ParsedInstruction::VoteInstruction(vote_instruction) => {
+                write!(f, "  This is a Vote instruction. ")?;
+                match vote_instruction {
+                    VoteInstruction::Withdraw {
+                        vote_account,
+                        recipient,
+                        withdraw_authority,
+                        amount,
+                    } => {
+                        writeln!(f, "It withdraws SOL from a vote account.")?;
+                        writeln!(f, "    Vote account:       {}", vote_account)?;
+                        writeln!(f, "    Recipient:          {}", recipient)?;
+                        writeln!(f, "    Withdraw authority: {}", withdraw_authority)?;
+                        writeln!(f, "    Amount:             {}", amount)?;
+                    }
+                    VoteInstruction::Unsupported => {
+                        writeln!(f, "The instruction is currently unsupported.")?;
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -789,11 +1495,12 @@ fn print_diff_multisig(
     Ok(())
 }
 
-fn show_transaction(
+pub fn show_transaction(
     config: &mut SnapshotConfig,
     transaction_address: &Pubkey,
     multisig_program_id: &Pubkey,
     solido_program_id: &Pubkey,
+    verify_against: &Path,
 ) -> Result<ShowTransactionOutput> {
     let transaction: serum_multisig::Transaction =
         config.client.get_account_deserialize(transaction_address)?;
@@ -851,7 +1558,7 @@ fn show_transaction(
     // hard-code the tag here (it is stable as long as the namespace and
     // function name do not change).
     else if instr.program_id == *multisig_program_id
-        && instr.data[..8] == [55, 144, 74, 245, 249, 230, 14, 53]
+        && instr.data.get(..8) == Some(&[55, 144, 74, 245, 249, 230, 14, 53][..])
     {
         if let Ok(instr) =
             multisig_instruction::SetOwnersAndChangeThreshold::try_from_slice(&instr.data[8..])
@@ -867,51 +1574,338 @@ fn show_transaction(
         }
     } else if &instr.program_id == solido_program_id {
         // Probably a Solido instruction
-        match try_parse_solido_instruction(config, &instr) {
-            Ok(instr) => instr,
-            Err(SnapshotError::MissingAccount) => return Err(SnapshotError::MissingAccount),
-            Err(SnapshotError::MissingValidatorIdentity(addr)) => {
-                return Err(SnapshotError::MissingValidatorIdentity(addr))
-            }
-            Err(SnapshotError::OtherError(err)) => {
-                println!("Warning: Failed to parse Solido instruction.");
-                err.print_pretty();
-                ParsedInstruction::InvalidSolidoInstruction
-            }
-        }
+        warn_on_parse_error(
+            try_parse_solido_instruction(config, &instr),
+            "Solido",
+            ParsedInstruction::InvalidSolidoInstruction,
+        )?
     } else if instr.program_id == spl_token::id() {
-        match try_parse_token_instruction(config, &instr) {
-            Ok(instr) => instr,
-            Err(SnapshotError::MissingAccount) => return Err(SnapshotError::MissingAccount),
-            Err(SnapshotError::MissingValidatorIdentity(addr)) => {
-                return Err(SnapshotError::MissingValidatorIdentity(addr))
-            }
-            Err(SnapshotError::OtherError(err)) => {
-                println!("Warning: Failed to parse Token instruction.");
-                err.print_pretty();
-                ParsedInstruction::InvalidSolidoInstruction
-            }
-        }
+        warn_on_parse_error(
+            try_parse_token_instruction(config, &instr),
+            "Token",
+            ParsedInstruction::InvalidInstruction,
+        )?
+    } else if instr.program_id == system_program::id() {
+        warn_on_parse_error(
+            try_parse_system_instruction(&instr),
+            "System",
+            ParsedInstruction::InvalidInstruction,
+        )?
+    } else if instr.program_id == stake::program::id() {
+        warn_on_parse_error(
+            try_parse_stake_instruction(&instr),
+            "Stake",
+            ParsedInstruction::InvalidInstruction,
+        )?
+    } else if instr.program_id == vote::program::id() {
+        warn_on_parse_error(
+            try_parse_vote_instruction(&instr),
+            "Vote",
+            ParsedInstruction::InvalidInstruction,
+        )?
     } else {
         ParsedInstruction::Unrecognized
     };
 
+    let buffer_verification = match &parsed_instr {
+        ParsedInstruction::BpfLoaderUpgrade { buffer_address, .. }
+            if verify_against != Path::new("") =>
+        {
+            Some(verify_buffer(config, buffer_address, verify_against)?)
+        }
+        _ => None,
+    };
+
     let result = ShowTransactionOutput {
         multisig_address: transaction.multisig,
         did_execute: transaction.did_execute,
         signers,
         instruction: instr,
         parsed_instruction: parsed_instr,
+        buffer_verification,
     };
     Ok(result)
 }
 
+#[derive(Serialize)]
+struct ListTransactionsEntry {
+    #[serde(serialize_with = "serialize_b58")]
+    transaction_address: Pubkey,
+    num_approvals: usize,
+
+    /// Whether the transaction has enough approvals, and was not yet executed.
+    ///
+    /// When the owners of the multisig change, pending transactions can no
+    /// longer be executed.
+    is_executable: bool,
+
+    #[serde(flatten)]
+    transaction: ShowTransactionOutput,
+}
+
+#[derive(Serialize)]
+struct ListTransactionsOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    multisig_address: Pubkey,
+    threshold: u64,
+    num_owners: usize,
+
+    /// Whether `transactions` includes transactions that were executed already.
+    #[serde(skip)]
+    includes_executed: bool,
+
+    transactions: Vec<ListTransactionsEntry>,
+
+    /// Transactions that we failed to read or decode.
+    #[serde(serialize_with = "serialize_b58_slice")]
+    unreadable_transactions: Vec<Pubkey>,
+}
+
+impl fmt::Display for ListTransactionsOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Multisig:  {}", self.multisig_address)?;
+        writeln!(
+            f,
+            "Threshold: {} out of {}",
+            self.threshold, self.num_owners
+        )?;
+        if self.includes_executed {
+            writeln!(f, "Found {} transactions.", self.transactions.len())?;
+        } else {
+            writeln!(f, "Found {} pending transactions.", self.transactions.len())?;
+        }
+        for entry in &self.transactions {
+            writeln!(f, "\nTransaction {}", entry.transaction_address)?;
+            let status = if entry.transaction.did_execute {
+                "executed".to_string()
+            } else if entry.is_executable {
+                "ready to execute".to_string()
+            } else if let ShowTransactionSigners::Outdated { .. } = entry.transaction.signers {
+                "cannot execute, the owners changed".to_string()
+            } else {
+                format!(
+                    "awaiting approval, {} of {} approvals",
+                    entry.num_approvals, self.threshold
+                )
+            };
+            writeln!(f, "Status: {}\n", status)?;
+            write!(f, "{}", entry.transaction)?;
+        }
+        if !self.unreadable_transactions.is_empty() {
+            writeln!(
+                f,
+                "\nFailed to read {} transactions:",
+                self.unreadable_transactions.len()
+            )?;
+            for transaction_address in &self.unreadable_transactions {
+                writeln!(f, "  {}", transaction_address)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn list_transactions(
+    config: &mut SnapshotConfig,
+    opts: &ListTransactionsOpts,
+) -> Result<ListTransactionsOutput> {
+    let multisig: serum_multisig::Multisig = config
+        .client
+        .get_account_deserialize(opts.multisig_address())?;
+    let addresses = config
+        .client
+        .get_multisig_transaction_addresses(opts.multisig_program_id(), opts.multisig_address())?;
+
+    let mut transactions = Vec::with_capacity(addresses.len());
+    let mut unreadable_transactions = Vec::new();
+    for transaction_address in addresses {
+        let result = show_transaction(
+            config,
+            &transaction_address,
+            opts.multisig_program_id(),
+            opts.solido_program_id(),
+            Path::new(""),
+        );
+        // One malformed transaction should not hide the others. Snapshot
+        // errors that a retry can resolve, we still return as errors.
+        let transaction = match result {
+            Ok(transaction) => transaction,
+            Err(SnapshotError::OtherError(err)) => {
+                eprintln!(
+                    "Warning: Failed to read transaction {}.",
+                    transaction_address
+                );
+                err.print_pretty();
+                unreadable_transactions.push(transaction_address);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        if transaction.did_execute && !*opts.include_executed() {
+            continue;
+        }
+        let num_approvals = transaction.signers.num_signed();
+        let is_current = matches!(transaction.signers, ShowTransactionSigners::Current { .. });
+        transactions.push(ListTransactionsEntry {
+            transaction_address,
+            num_approvals,
+            is_executable: is_current
+                && !transaction.did_execute
+                && num_approvals as u64 >= multisig.threshold,
+            transaction,
+        });
+    }
+    // Show pending transactions first, and otherwise keep a stable order.
+    transactions.sort_by_key(|entry| (entry.transaction.did_execute, entry.transaction_address));
+    unreadable_transactions.sort();
+
+    Ok(ListTransactionsOutput {
+        multisig_address: *opts.multisig_address(),
+        threshold: multisig.threshold,
+        num_owners: multisig.owners.len(),
+        includes_executed: *opts.include_executed(),
+        transactions,
+        unreadable_transactions,
+    })
+}
+
+/// Replace an error while parsing an instruction with a warning and `fallback`.
+///
+/// Snapshot errors that a retry can resolve, we still return as errors.
+fn warn_on_parse_error(
+    result: Result<ParsedInstruction>,
+    program_name: &str,
+    fallback: ParsedInstruction,
+) -> Result<ParsedInstruction> {
+    match result {
+        Err(SnapshotError::OtherError(err)) => {
+            eprintln!("Warning: Failed to parse {} instruction.", program_name);
+            err.print_pretty();
+            Ok(fallback)
+        }
+        result => result,
+    }
+}
+
+/// Return the address of the account at `index` in the instruction's accounts.
+fn get_account_address(instr: &Instruction, index: usize) -> Result<Pubkey> {
+    let account = instr
+        .accounts
+        .get(index)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    Ok(account.pubkey)
+}
+
 fn try_parse_solido_instruction(
     config: &mut SnapshotConfig,
     instr: &Instruction,
 ) -> Result<ParsedInstruction> {
-    let instruction: LidoInstruction = BorshDeserialize::deserialize(&mut instr.data.as_slice())?;
+    let instruction = LidoInstruction::try_from_slice(&instr.data)?;
     Ok(match instruction {
+        LidoInstruction::Initialize {
+            reward_distribution,
+            max_validators,
+            max_maintainers,
+        } => {
+            let accounts = InitializeAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::Initialize {
+                reward_distribution,
+                max_validators,
+                max_maintainers,
+                solido_instance: accounts.lido,
+                manager: accounts.manager,
+                st_sol_mint: accounts.st_sol_mint,
+                treasury_account: accounts.treasury_account,
+                developer_account: accounts.developer_account,
+                reserve_account: accounts.reserve_account,
+            })
+        }
+        LidoInstruction::Deposit { amount } => {
+            let accounts = DepositAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::Deposit {
+                amount,
+                solido_instance: accounts.lido,
+                user: accounts.user,
+                recipient: accounts.recipient,
+            })
+        }
+        LidoInstruction::Withdraw { amount } => {
+            let accounts = WithdrawAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::Withdraw {
+                amount,
+                solido_instance: accounts.lido,
+                st_sol_account_owner: accounts.st_sol_account_owner,
+                st_sol_account: accounts.st_sol_account,
+                validator_vote_account: accounts.validator_vote_account,
+                source_stake_account: accounts.source_stake_account,
+                destination_stake_account: accounts.destination_stake_account,
+            })
+        }
+        LidoInstruction::StakeDeposit { amount } => {
+            let accounts = StakeDepositAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::StakeDeposit {
+                amount,
+                solido_instance: accounts.lido,
+                maintainer: accounts.maintainer,
+                validator_vote_account: accounts.validator_vote_account,
+                stake_account: accounts.stake_account_end,
+            })
+        }
+        LidoInstruction::Unstake { amount } => {
+            let accounts = UnstakeAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::Unstake {
+                amount,
+                solido_instance: accounts.lido,
+                maintainer: accounts.maintainer,
+                validator_vote_account: accounts.validator_vote_account,
+                source_stake_account: accounts.source_stake_account,
+                destination_unstake_account: accounts.destination_unstake_account,
+            })
+        }
+        LidoInstruction::UpdateExchangeRate => {
+            let accounts = UpdateExchangeRateAccountsMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::UpdateExchangeRate {
+                solido_instance: accounts.lido,
+            })
+        }
+        LidoInstruction::WithdrawInactiveStake => {
+            let accounts = WithdrawInactiveStakeMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::WithdrawInactiveStake {
+                solido_instance: accounts.lido,
+                validator_vote_account: accounts.validator_vote_account,
+                stake_accounts: accounts.stake_accounts,
+            })
+        }
+        LidoInstruction::CollectValidatorFee => {
+            let accounts = CollectValidatorFeeMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::CollectValidatorFee {
+                solido_instance: accounts.lido,
+                validator_vote_account: accounts.validator_vote_account,
+            })
+        }
+        LidoInstruction::ClaimValidatorFee => {
+            let accounts = ClaimValidatorFeeMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::ClaimValidatorFee {
+                solido_instance: accounts.lido,
+                validator_fee_st_sol_account: accounts.validator_fee_st_sol_account,
+            })
+        }
+        LidoInstruction::RemoveValidator => {
+            let accounts = RemoveValidatorMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::RemoveValidator {
+                solido_instance: accounts.lido,
+                validator_vote_account: accounts.validator_vote_account_to_remove,
+            })
+        }
+        LidoInstruction::MergeStake => {
+            let accounts = MergeStakeMeta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::MergeStake {
+                solido_instance: accounts.lido,
+                validator_vote_account: accounts.validator_vote_account,
+                from_stake_account: accounts.from_stake,
+                to_stake_account: accounts.to_stake,
+            })
+        }
         LidoInstruction::ChangeRewardDistribution {
             new_reward_distribution,
         } => {
@@ -961,7 +1955,14 @@ fn try_parse_solido_instruction(
                 maintainer: accounts.maintainer,
             })
         }
-        _ => ParsedInstruction::InvalidSolidoInstruction,
+        LidoInstruction::MigrateStateToV1 => {
+            let accounts = MigrateStateToV1Meta::try_from_slice(&instr.accounts)?;
+            ParsedInstruction::SolidoInstruction(SolidoInstruction::MigrateStateToV1 {
+                solido_instance: accounts.lido,
+                manager: accounts.manager,
+                validator_list: accounts.validator_list,
+            })
+        }
     })
 }
 
@@ -969,32 +1970,138 @@ fn try_parse_token_instruction(
     config: &mut SnapshotConfig,
     instr: &Instruction,
 ) -> Result<ParsedInstruction> {
+    use spl_token::instruction::AuthorityType;
+
     let instruction = spl_token::instruction::TokenInstruction::unpack(instr.data.as_slice())?;
 
-    // Get the from account and deserialize it to an `spl_token`. This is done
-    // to get the mint address for the token. If the mint addresses differ, the
-    // instruction simulation will fail when proposing.
-    let from_account = config.client.get_account(&instr.accounts[0].pubkey)?;
-    let spl_token_from = spl_token::state::Account::unpack(&from_account.data)?;
-    match instruction {
-        spl_token::instruction::TokenInstruction::Transfer { amount } => Ok(
-            ParsedInstruction::TokenInstruction(TokenInstruction::Transfer {
-                from_address: instr.accounts[0].pubkey,
-                to_address: instr.accounts[1].pubkey,
-                token_address: spl_token_from.mint,
+    // Get the account at `index` and deserialize it to an `spl_token`. This is
+    // done to get the mint address for the token. If the mint addresses differ,
+    // the instruction simulation will fail when proposing.
+    let mut get_mint = |index: usize| -> Result<Pubkey> {
+        let account = config
+            .client
+            .get_account(&get_account_address(instr, index)?)?;
+        Ok(spl_token::state::Account::unpack(&account.data)?.mint)
+    };
+
+    let parsed = match instruction {
+        spl_token::instruction::TokenInstruction::Transfer { amount } => {
+            TokenInstruction::Transfer {
+                from_address: get_account_address(instr, 0)?,
+                to_address: get_account_address(instr, 1)?,
+                token_address: get_mint(0)?,
                 amount,
-            }),
-        ),
-        _ => Ok(ParsedInstruction::TokenInstruction(
-            TokenInstruction::Unsupported,
-        )),
-    }
+            }
+        }
+        spl_token::instruction::TokenInstruction::Approve { amount } => TokenInstruction::Approve {
+            source_address: get_account_address(instr, 0)?,
+            delegate_address: get_account_address(instr, 1)?,
+            owner_address: get_account_address(instr, 2)?,
+            token_address: get_mint(0)?,
+            amount,
+        },
+        spl_token::instruction::TokenInstruction::MintTo { amount } => TokenInstruction::MintTo {
+            token_address: get_account_address(instr, 0)?,
+            to_address: get_account_address(instr, 1)?,
+            mint_authority: get_account_address(instr, 2)?,
+            amount,
+        },
+        spl_token::instruction::TokenInstruction::Burn { amount } => TokenInstruction::Burn {
+            from_address: get_account_address(instr, 0)?,
+            token_address: get_account_address(instr, 1)?,
+            owner_address: get_account_address(instr, 2)?,
+            amount,
+        },
+        spl_token::instruction::TokenInstruction::SetAuthority {
+            authority_type,
+            new_authority,
+        } => TokenInstruction::SetAuthority {
+            address: get_account_address(instr, 0)?,
+            current_authority: get_account_address(instr, 1)?,
+            new_authority: new_authority.into(),
+            authority_type: match authority_type {
+                AuthorityType::MintTokens => "mint tokens",
+                AuthorityType::FreezeAccount => "freeze account",
+                AuthorityType::AccountOwner => "account owner",
+                AuthorityType::CloseAccount => "close account",
+            },
+        },
+        _ => TokenInstruction::Unsupported,
+    };
+    Ok(ParsedInstruction::TokenInstruction(parsed))
+}
+
+fn try_parse_system_instruction(instr: &Instruction) -> Result<ParsedInstruction> {
+    let instruction: system_instruction::SystemInstruction = bincode::deserialize(&instr.data)?;
+    let parsed = match instruction {
+        system_instruction::SystemInstruction::Transfer { lamports } => {
+            SystemInstruction::Transfer {
+                from_address: get_account_address(instr, 0)?,
+                to_address: get_account_address(instr, 1)?,
+                amount: Lamports(lamports),
+            }
+        }
+        _ => SystemInstruction::Unsupported,
+    };
+    Ok(ParsedInstruction::SystemInstruction(parsed))
+}
+
+fn try_parse_stake_instruction(instr: &Instruction) -> Result<ParsedInstruction> {
+    // Account meaning, according to the docs of `StakeInstruction`.
+    let instruction: stake::instruction::StakeInstruction = bincode::deserialize(&instr.data)?;
+    let parsed = match instruction {
+        stake::instruction::StakeInstruction::Authorize(new_authority, authority_type) => {
+            StakeInstruction::Authorize {
+                stake_account: get_account_address(instr, 0)?,
+                current_authority: get_account_address(instr, 2)?,
+                new_authority,
+                authority_type,
+            }
+        }
+        stake::instruction::StakeInstruction::DelegateStake => StakeInstruction::Delegate {
+            stake_account: get_account_address(instr, 0)?,
+            vote_account: get_account_address(instr, 1)?,
+            stake_authority: get_account_address(instr, 5)?,
+        },
+        stake::instruction::StakeInstruction::Deactivate => StakeInstruction::Deactivate {
+            stake_account: get_account_address(instr, 0)?,
+            stake_authority: get_account_address(instr, 2)?,
+        },
+        stake::instruction::StakeInstruction::Withdraw(amount) => StakeInstruction::Withdraw {
+            stake_account: get_account_address(instr, 0)?,
+            recipient: get_account_address(instr, 1)?,
+            withdraw_authority: get_account_address(instr, 4)?,
+            amount: Lamports(amount),
+        },
+        stake::instruction::StakeInstruction::Split(amount) => StakeInstruction::Split {
+            source_stake_account: get_account_address(instr, 0)?,
+            destination_stake_account: get_account_address(instr, 1)?,
+            stake_authority: get_account_address(instr, 2)?,
+            amount: Lamports(amount),
+        },
+        _ => StakeInstruction::Unsupported,
+    };
+    Ok(ParsedInstruction::StakeInstruction(parsed))
+}
+
+fn try_parse_vote_instruction(instr: &Instruction) -> Result<ParsedInstruction> {
+    let instruction: vote_instruction::VoteInstruction = bincode::deserialize(&instr.data)?;
+    let parsed = match instruction {
+        vote_instruction::VoteInstruction::Withdraw(amount) => VoteInstruction::Withdraw {
+            vote_account: get_account_address(instr, 0)?,
+            recipient: get_account_address(instr, 1)?,
+            withdraw_authority: get_account_address(instr, 2)?,
+            amount: Lamports(amount),
+        },
+        _ => VoteInstruction::Unsupported,
+    };
+    Ok(ParsedInstruction::VoteInstruction(parsed))
 }
 
 #[derive(Serialize)]
 pub struct ProposeInstructionOutput {
     #[serde(serialize_with = "serialize_b58")]
-    transaction_address: Pubkey,
+    pub transaction_address: Pubkey,
 }
 
 impl fmt::Display for ProposeInstructionOutput {
@@ -1281,25 +2388,36 @@ fn approve_batch(
 }
 
 /// Prompt the user to enter 'Y' or 'N'.
-fn ask_user_y_n(prompt: &'static str) -> bool {
-    use std::io::{BufRead, Write};
+///
+/// The prompt goes to stderr, so it does not mix with the output on stdout.
+/// When stdin is closed, for example in a script, the answer is 'N'.
+pub fn ask_user_y_n(prompt: &'static str) -> bool {
+    read_y_n(&mut std::io::stdin().lock(), prompt)
+}
+
+/// Prompt for 'Y' or 'N' like [`ask_user_y_n`], but read the answer from `input`.
+fn read_y_n<R: std::io::BufRead>(input: &mut R, prompt: &'static str) -> bool {
+    use std::io::Write;
     let mut buf = String::new();
     loop {
-        print!("{} [Y/N] ", prompt);
-        std::io::stdout()
+        eprint!("{} [Y/N] ", prompt);
+        std::io::stderr()
             .lock()
             .flush()
-            .expect("Failed to flush to stdout.");
-        std::io::stdin()
-            .lock()
+            .expect("Failed to flush to stderr.");
+        let num_read = input
             .read_line(&mut buf)
             .expect("Failed to read from stdin.");
+        if num_read == 0 {
+            eprintln!();
+            return false;
+        }
 
         match &buf[..] {
             "Y\n" => return true,
             "N\n" => return false,
             _ => {
-                println!("Please enter the character 'Y' or 'N' (case-sensitive).");
+                eprintln!("Please enter the character 'Y' or 'N' (case-sensitive).");
                 buf.clear();
             }
         }
@@ -1317,6 +2435,7 @@ fn approve_transaction_interactive(
             transaction_address,
             opts.multisig_program_id(),
             opts.solido_program_id(),
+            opts.verify_against(),
         )?;
         println!("{}", output);
         Ok(())
@@ -1396,7 +2515,7 @@ impl anchor_lang::ToAccountMetas for TransactionAccounts {
 }
 
 #[derive(Serialize)]
-struct ExecuteOutput {
+pub struct ExecuteOutput {
     pub transaction_id: Signature,
 }
 
@@ -1412,7 +2531,7 @@ impl fmt::Display for ExecuteOutput {
     }
 }
 
-fn execute_transaction(
+pub fn execute_transaction(
     config: &mut SnapshotConfig,
     transaction_address: &Pubkey,
     multisig_program_id: &Pubkey,
@@ -1471,3 +2590,896 @@ fn transfer_token(
         instruction,
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::config::OutputMode;
+    use crate::mock_rpc::MockRpc;
+    use crate::snapshot::SnapshotClient;
+    use crate::Config;
+
+    #[test]
+    fn list_transactions_shows_approvals_and_execution() {
+        let (mock, payer) = MockRpc::start();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(mock.clone())),
+            signer: &payer,
+            output_mode: OutputMode::Json,
+        };
+        let multisig_program_id = mock.multisig_program_id.to_string();
+        let owners = format!("{},{}", payer.pubkey(), Pubkey::new_unique());
+
+        let mut opts = CreateMultisigOpts::parse_from(&[
+            "create-multisig",
+            "--threshold",
+            "1",
+            "--owners",
+            &owners,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let multisig = config
+            .with_snapshot(|config| create_multisig(config, &opts))
+            .ok()
+            .unwrap();
+        let multisig_address = multisig.multisig_address.to_string();
+
+        // Propose to raise the threshold. The proposer approves right away.
+        let mut opts = ProposeChangeMultisigOpts::parse_from(&[
+            "propose-change-multisig",
+            "--multisig-address",
+            &multisig_address,
+            "--threshold",
+            "2",
+            "--owners",
+            &owners,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let proposal = config
+            .with_snapshot(|config| propose_change_multisig(config, &opts))
+            .ok()
+            .unwrap();
+
+        let mut opts = ListTransactionsOpts::parse_from(&[
+            "list-transactions",
+            "--multisig-address",
+            &multisig_address,
+            "--solido-program-id",
+            &mock.solido_program_id.to_string(),
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let output = config
+            .with_snapshot(|config| list_transactions(config, &opts))
+            .ok()
+            .unwrap();
+        assert_eq!(output.threshold, 1);
+        assert_eq!(output.transactions.len(), 1);
+        let entry = &output.transactions[0];
+        assert_eq!(entry.transaction_address, proposal.transaction_address);
+        assert_eq!(entry.num_approvals, 1);
+        assert!(entry.is_executable);
+        assert!(!entry.transaction.did_execute);
+        assert!(matches!(
+            entry.transaction.parsed_instruction,
+            ParsedInstruction::MultisigChange {
+                new_threshold: 2,
+                ..
+            }
+        ));
+
+        config
+            .with_snapshot(|config| {
+                execute_transaction(
+                    config,
+                    &proposal.transaction_address,
+                    &mock.multisig_program_id,
+                    &multisig.multisig_address,
+                )
+            })
+            .ok()
+            .unwrap();
+
+        // By default, executed transactions are no longer listed.
+        let output = config
+            .with_snapshot(|config| list_transactions(config, &opts))
+            .ok()
+            .unwrap();
+        assert_eq!(output.threshold, 2);
+        assert!(output.transactions.is_empty());
+
+        let mut opts = ListTransactionsOpts::parse_from(&[
+            "list-transactions",
+            "--multisig-address",
+            &multisig_address,
+            "--solido-program-id",
+            &mock.solido_program_id.to_string(),
+            "--multisig-program-id",
+            &multisig_program_id,
+            "--include-executed",
+            "true",
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let output = config
+            .with_snapshot(|config| list_transactions(config, &opts))
+            .ok()
+            .unwrap();
+        assert_eq!(output.transactions.len(), 1);
+        let entry = &output.transactions[0];
+        assert!(entry.transaction.did_execute);
+        assert!(!entry.is_executable);
+
+        // A transaction account that we cannot deserialize is reported, and
+        // does not prevent listing the others.
+        let unreadable_address = Pubkey::new_unique();
+        let mut data = serum_multisig::Transaction::discriminator().to_vec();
+        data.extend_from_slice(multisig.multisig_address.as_ref());
+        mock.set_account(
+            &unreadable_address,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: mock.multisig_program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let output = config
+            .with_snapshot(|config| list_transactions(config, &opts))
+            .ok()
+            .unwrap();
+        assert_eq!(output.transactions.len(), 1);
+        assert_eq!(output.unreadable_transactions, vec![unreadable_address]);
+    }
+
+    #[test]
+    fn approve_offline_with_recent_blockhash_and_durable_nonce() {
+        let (mock, payer) = MockRpc::start();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(mock.clone())),
+            signer: &payer,
+            output_mode: OutputMode::Json,
+        };
+        let multisig_program_id = mock.multisig_program_id.to_string();
+        let solido_program_id = mock.solido_program_id.to_string();
+
+        // The offline owner pays for its approvals, so give it some SOL, and a
+        // durable nonce account.
+        let offline_owner = Keypair::new();
+        let nonce_account = Keypair::new();
+        let mut instructions = vec![system_instruction::transfer(
+            &payer.pubkey(),
+            &offline_owner.pubkey(),
+            1_000_000_000,
+        )];
+        instructions.extend(system_instruction::create_nonce_account(
+            &payer.pubkey(),
+            &nonce_account.pubkey(),
+            &offline_owner.pubkey(),
+            1_000_000_000,
+        ));
+        config
+            .with_snapshot(|config| {
+                config.sign_and_send_transaction(&instructions, &[&payer, &nonce_account])
+            })
+            .ok()
+            .unwrap();
+
+        let owners = format!("{},{}", payer.pubkey(), offline_owner.pubkey());
+        let mut opts = CreateMultisigOpts::parse_from(&[
+            "create-multisig",
+            "--threshold",
+            "2",
+            "--owners",
+            &owners,
+            "--multisig-program-id",
+            &multisig_program_id,
+        ]);
+        opts.merge_with_config_and_environment(None);
+        let multisig = config
+            .with_snapshot(|config| create_multisig(config, &opts))
+            .ok()
+            .unwrap();
+        let multisig_address = multisig.multisig_address.to_string();
+
+        let approval_path =
+            std::env::temp_dir().join(format!("solido-approval-{}.json", Pubkey::new_unique()));
+        let approval_path = approval_path.to_str().unwrap();
+
+        for use_nonce in [false, true] {
+            let mut opts = ProposeChangeMultisigOpts::parse_from(&[
+                "propose-change-multisig",
+                "--multisig-address",
+                &multisig_address,
+                "--threshold",
+                "2",
+                "--owners",
+                &owners,
+                "--multisig-program-id",
+                &multisig_program_id,
+            ]);
+            opts.merge_with_config_and_environment(None);
+            let proposal = config
+                .with_snapshot(|config| propose_change_multisig(config, &opts))
+                .ok()
+                .unwrap();
+
+            let nonce_address = if use_nonce {
+                // The nonce must differ from the most recent blockhash to advance it.
+                mock.warp_to_epoch(1);
+                nonce_account.pubkey()
+            } else {
+                Pubkey::default()
+            };
+            let mut opts = ExportApprovalOpts::parse_from(&[
+                "export-approval",
+                "--multisig-address",
+                &multisig_address,
+                "--transaction-address",
+                &proposal.transaction_address.to_string(),
+                "--owner",
+                &offline_owner.pubkey().to_string(),
+                "--nonce-account",
+                &nonce_address.to_string(),
+                "--approval-path",
+                approval_path,
+                "--multisig-program-id",
+                &multisig_program_id,
+                "--solido-program-id",
+                &solido_program_id,
+            ]);
+            opts.merge_with_config_and_environment(None);
+            let mut request = config
+                .with_snapshot(|config| offline_approval::export_approval(config, &opts))
+                .ok()
+                .unwrap();
+            assert_eq!(request.nonce_account.is_some(), use_nonce);
+
+            // Only the owner in the request can sign it.
+            assert!(request.sign(&payer).is_err());
+            request.sign(&offline_owner).ok().unwrap();
+
+            // The request survives the round trip through the file.
+            let json = serde_json::to_string(&request).unwrap();
+            let mut request: offline_approval::ApprovalRequest =
+                serde_json::from_str(&json).unwrap();
+
+            // The offline machine decodes the transaction from the request alone.
+            let transaction = request
+                .show_transaction(&offline_owner, OutputMode::Text)
+                .ok()
+                .unwrap();
+            assert!(matches!(
+                transaction.parsed_instruction,
+                ParsedInstruction::MultisigChange {
+                    new_threshold: 2,
+                    ..
+                }
+            ));
+
+            // It refuses a transaction that belongs to a different multisig.
+            request.multisig_address = Pubkey::new_unique();
+            assert!(request
+                .show_transaction(&offline_owner, OutputMode::Text)
+                .is_err());
+            request.multisig_address = multisig.multisig_address;
+
+            let output = offline_approval::submit_approval(&mut config, &request)
+                .ok()
+                .unwrap();
+            assert_eq!(output.num_approvals, 2);
+            assert_eq!(output.threshold, 2);
+        }
+        std::fs::remove_file(approval_path).unwrap();
+    }
+
+    #[test]
+    fn read_y_n_treats_end_of_input_as_no() {
+        assert!(read_y_n(&mut &b"Y\n"[..], "Continue?"));
+        assert!(!read_y_n(&mut &b"N\n"[..], "Continue?"));
+        // Invalid answers are asked again.
+        assert!(read_y_n(&mut &b"yes\nY\n"[..], "Continue?"));
+        // A closed stdin must not loop forever.
+        assert!(!read_y_n(&mut &b""[..], "Continue?"));
+        assert!(!read_y_n(&mut &b"maybe\n"[..], "Continue?"));
+    }
+
+    #[test]
+    fn parse_system_stake_and_vote_instructions_with_named_accounts() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+
+        let instr = system_instruction::transfer(&from, &to, 1_000_000_000);
+        match try_parse_system_instruction(&instr).ok().unwrap() {
+            ParsedInstruction::SystemInstruction(SystemInstruction::Transfer {
+                from_address,
+                to_address,
+                amount,
+            }) => {
+                assert_eq!(from_address, from);
+                assert_eq!(to_address, to);
+                assert_eq!(amount, Lamports(1_000_000_000));
+            }
+            _ => panic!("Expected a system transfer."),
+        }
+
+        let instr = stake::instruction::deactivate_stake(&from, &authority);
+        match try_parse_stake_instruction(&instr).ok().unwrap() {
+            ParsedInstruction::StakeInstruction(StakeInstruction::Deactivate {
+                stake_account,
+                stake_authority,
+            }) => {
+                assert_eq!(stake_account, from);
+                assert_eq!(stake_authority, authority);
+            }
+            _ => panic!("Expected a stake deactivation."),
+        }
+
+        let instr = stake::instruction::withdraw(&from, &authority, &to, 42, None);
+        match try_parse_stake_instruction(&instr).ok().unwrap() {
+            ParsedInstruction::StakeInstruction(StakeInstruction::Withdraw {
+                stake_account,
+                recipient,
+                withdraw_authority,
+                amount,
+            }) => {
+                assert_eq!(stake_account, from);
+                assert_eq!(recipient, to);
+                assert_eq!(withdraw_authority, authority);
+                assert_eq!(amount, Lamports(42));
+            }
+            _ => panic!("Expected a stake withdrawal."),
+        }
+
+        let instr = vote_instruction::withdraw(&from, &authority, 7, &to);
+        match try_parse_vote_instruction(&instr).ok().unwrap() {
+            ParsedInstruction::VoteInstruction(VoteInstruction::Withdraw {
+                vote_account,
+                recipient,
+                withdraw_authority,
+                amount,
+            }) => {
+                assert_eq!(vote_account, from);
+                assert_eq!(recipient, to);
+                assert_eq!(withdraw_authority, authority);
+                assert_eq!(amount, Lamports(7));
+            }
+            _ => panic!("Expected a vote withdrawal."),
+        }
+
+        // An instruction that is missing accounts fails to parse, it does not panic.
+        let mut instr = system_instruction::transfer(&from, &to, 1);
+        instr.accounts.truncate(1);
+        assert!(try_parse_system_instruction(&instr).is_err());
+    }
+
+    #[test]
+    fn parse_solido_instructions_with_named_accounts() {
+        use lido::instruction;
+
+        let (mock, payer) = MockRpc::start();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(mock.clone())),
+            signer: &payer,
+            output_mode: OutputMode::Json,
+        };
+        let program_id = mock.solido_program_id;
+        let mut parse = |instr: Instruction| match config
+            .with_snapshot(|config| try_parse_solido_instruction(config, &instr))
+            .ok()
+            .unwrap()
+        {
+            ParsedInstruction::SolidoInstruction(parsed) => parsed,
+            _ => panic!("Expected a Solido instruction."),
+        };
+
+        let lido = Pubkey::new_unique();
+        let validator_list = Pubkey::new_unique();
+        let manager = Pubkey::new_unique();
+        let maintainer = Pubkey::new_unique();
+        let st_sol_mint = Pubkey::new_unique();
+        let reserve = Pubkey::new_unique();
+        let mint_authority = Pubkey::new_unique();
+        let stake_authority = Pubkey::new_unique();
+        let vote_account = Pubkey::new_unique();
+        let stake_a = Pubkey::new_unique();
+        let stake_b = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let st_sol_account = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+        let developer = Pubkey::new_unique();
+
+        let reward_distribution = RewardDistribution {
+            treasury_fee: 2,
+            validation_fee: 3,
+            developer_fee: 1,
+            st_sol_appreciation: 94,
+        };
+        let instr = instruction::initialize(
+            &program_id,
+            reward_distribution.clone(),
+            10,
+            3,
+            &InitializeAccountsMeta {
+                lido,
+                validator_list,
+                manager,
+                st_sol_mint,
+                treasury_account: treasury,
+                developer_account: developer,
+                reserve_account: reserve,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::Initialize {
+                reward_distribution: parsed_distribution,
+                max_validators: 10,
+                max_maintainers: 3,
+                solido_instance,
+                manager: parsed_manager,
+                st_sol_mint: parsed_mint,
+                treasury_account,
+                developer_account,
+                reserve_account,
+            } => {
+                assert_eq!(parsed_distribution, reward_distribution);
+                assert_eq!(solido_instance, lido);
+                assert_eq!(parsed_manager, manager);
+                assert_eq!(parsed_mint, st_sol_mint);
+                assert_eq!(treasury_account, treasury);
+                assert_eq!(developer_account, developer);
+                assert_eq!(reserve_account, reserve);
+            }
+            _ => panic!("Expected Initialize."),
+        }
+
+        let instr = instruction::deposit(
+            &program_id,
+            &DepositAccountsMeta {
+                lido,
+                user,
+                recipient: st_sol_account,
+                st_sol_mint,
+                reserve_account: reserve,
+                mint_authority,
+            },
+            Lamports(1_000),
+        );
+        match parse(instr) {
+            SolidoInstruction::Deposit {
+                amount,
+                solido_instance,
+                user: parsed_user,
+                recipient,
+            } => {
+                assert_eq!(amount, Lamports(1_000));
+                assert_eq!(solido_instance, lido);
+                assert_eq!(parsed_user, user);
+                assert_eq!(recipient, st_sol_account);
+            }
+            _ => panic!("Expected Deposit."),
+        }
+
+        let instr = instruction::withdraw(
+            &program_id,
+            &WithdrawAccountsMeta {
+                lido,
+                validator_list,
+                st_sol_account_owner: user,
+                st_sol_account,
+                st_sol_mint,
+                validator_vote_account: vote_account,
+                source_stake_account: stake_a,
+                destination_stake_account: stake_b,
+                stake_authority,
+            },
+            StLamports(2_000),
+        );
+        match parse(instr) {
+            SolidoInstruction::Withdraw {
+                amount,
+                solido_instance,
+                st_sol_account_owner,
+                st_sol_account: parsed_st_sol_account,
+                validator_vote_account,
+                source_stake_account,
+                destination_stake_account,
+            } => {
+                assert_eq!(amount, StLamports(2_000));
+                assert_eq!(solido_instance, lido);
+                assert_eq!(st_sol_account_owner, user);
+                assert_eq!(parsed_st_sol_account, st_sol_account);
+                assert_eq!(validator_vote_account, vote_account);
+                assert_eq!(source_stake_account, stake_a);
+                assert_eq!(destination_stake_account, stake_b);
+            }
+            _ => panic!("Expected Withdraw."),
+        }
+
+        // The parsed stake account is the one that receives the deposit, not
+        // the one it may be merged into.
+        let instr = instruction::stake_deposit(
+            &program_id,
+            &StakeDepositAccountsMeta {
+                lido,
+                validator_list,
+                maintainer,
+                reserve,
+                validator_vote_account: vote_account,
+                stake_account_merge_into: stake_a,
+                stake_account_end: stake_b,
+                stake_authority,
+            },
+            Lamports(3_000),
+        );
+        match parse(instr) {
+            SolidoInstruction::StakeDeposit {
+                amount,
+                solido_instance,
+                maintainer: parsed_maintainer,
+                validator_vote_account,
+                stake_account,
+            } => {
+                assert_eq!(amount, Lamports(3_000));
+                assert_eq!(solido_instance, lido);
+                assert_eq!(parsed_maintainer, maintainer);
+                assert_eq!(validator_vote_account, vote_account);
+                assert_eq!(stake_account, stake_b);
+            }
+            _ => panic!("Expected StakeDeposit."),
+        }
+
+        let instr = instruction::unstake(
+            &program_id,
+            &UnstakeAccountsMeta {
+                lido,
+                validator_list,
+                maintainer,
+                validator_vote_account: vote_account,
+                source_stake_account: stake_a,
+                destination_unstake_account: stake_b,
+                stake_authority,
+            },
+            Lamports(4_000),
+        );
+        match parse(instr) {
+            SolidoInstruction::Unstake {
+                amount,
+                solido_instance,
+                maintainer: parsed_maintainer,
+                validator_vote_account,
+                source_stake_account,
+                destination_unstake_account,
+            } => {
+                assert_eq!(amount, Lamports(4_000));
+                assert_eq!(solido_instance, lido);
+                assert_eq!(parsed_maintainer, maintainer);
+                assert_eq!(validator_vote_account, vote_account);
+                assert_eq!(source_stake_account, stake_a);
+                assert_eq!(destination_unstake_account, stake_b);
+            }
+            _ => panic!("Expected Unstake."),
+        }
+
+        let instr = instruction::update_exchange_rate(
+            &program_id,
+            &UpdateExchangeRateAccountsMeta {
+                lido,
+                validator_list,
+                reserve,
+                st_sol_mint,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::UpdateExchangeRate { solido_instance } => {
+                assert_eq!(solido_instance, lido);
+            }
+            _ => panic!("Expected UpdateExchangeRate."),
+        }
+
+        let instr = instruction::withdraw_inactive_stake(
+            &program_id,
+            &WithdrawInactiveStakeMeta {
+                lido,
+                validator_list,
+                validator_vote_account: vote_account,
+                stake_authority,
+                reserve,
+                stake_accounts: vec![stake_a, stake_b],
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::WithdrawInactiveStake {
+                solido_instance,
+                validator_vote_account,
+                stake_accounts,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(validator_vote_account, vote_account);
+                assert_eq!(stake_accounts, vec![stake_a, stake_b]);
+            }
+            _ => panic!("Expected WithdrawInactiveStake."),
+        }
+
+        let instr = instruction::collect_validator_fee(
+            &program_id,
+            &CollectValidatorFeeMeta {
+                lido,
+                validator_list,
+                validator_vote_account: vote_account,
+                st_sol_mint,
+                mint_authority,
+                treasury_st_sol_account: treasury,
+                developer_st_sol_account: developer,
+                reserve,
+                rewards_withdraw_authority: stake_authority,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::CollectValidatorFee {
+                solido_instance,
+                validator_vote_account,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(validator_vote_account, vote_account);
+            }
+            _ => panic!("Expected CollectValidatorFee."),
+        }
+
+        let instr = instruction::claim_validator_fee(
+            &program_id,
+            &ClaimValidatorFeeMeta {
+                lido,
+                validator_list,
+                st_sol_mint,
+                mint_authority,
+                validator_fee_st_sol_account: st_sol_account,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::ClaimValidatorFee {
+                solido_instance,
+                validator_fee_st_sol_account,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(validator_fee_st_sol_account, st_sol_account);
+            }
+            _ => panic!("Expected ClaimValidatorFee."),
+        }
+
+        let instr = instruction::remove_validator(
+            &program_id,
+            &RemoveValidatorMeta {
+                lido,
+                validator_list,
+                validator_vote_account_to_remove: vote_account,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::RemoveValidator {
+                solido_instance,
+                validator_vote_account,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(validator_vote_account, vote_account);
+            }
+            _ => panic!("Expected RemoveValidator."),
+        }
+
+        let instr = instruction::merge_stake(
+            &program_id,
+            &MergeStakeMeta {
+                lido,
+                validator_list,
+                validator_vote_account: vote_account,
+                from_stake: stake_a,
+                to_stake: stake_b,
+                stake_authority,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::MergeStake {
+                solido_instance,
+                validator_vote_account,
+                from_stake_account,
+                to_stake_account,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(validator_vote_account, vote_account);
+                assert_eq!(from_stake_account, stake_a);
+                assert_eq!(to_stake_account, stake_b);
+            }
+            _ => panic!("Expected MergeStake."),
+        }
+
+        let instr = instruction::migrate_state_to_v1(
+            &program_id,
+            &MigrateStateToV1Meta {
+                lido,
+                manager,
+                validator_list,
+            },
+        );
+        match parse(instr) {
+            SolidoInstruction::MigrateStateToV1 {
+                solido_instance,
+                manager: parsed_manager,
+                validator_list: parsed_validator_list,
+            } => {
+                assert_eq!(solido_instance, lido);
+                assert_eq!(parsed_manager, manager);
+                assert_eq!(parsed_validator_list, validator_list);
+            }
+            _ => panic!("Expected MigrateStateToV1."),
+        }
+
+        // Instruction data that matches no Solido instruction fails to parse.
+        let mut instr = instruction::remove_validator(
+            &program_id,
+            &RemoveValidatorMeta {
+                lido,
+                validator_list,
+                validator_vote_account_to_remove: vote_account,
+            },
+        );
+        instr.data = vec![255];
+        assert!(config
+            .with_snapshot(|config| try_parse_solido_instruction(config, &instr))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_token_instructions_with_named_accounts() {
+        let (mock, payer) = MockRpc::start();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(mock.clone())),
+            signer: &payer,
+            output_mode: OutputMode::Json,
+        };
+
+        let mint = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        // `Approve` looks up the mint of the source account on the chain.
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut data);
+        mock.set_account(
+            &source,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: spl_token::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        let mut parse = |instr: Instruction| match config
+            .with_snapshot(|config| try_parse_token_instruction(config, &instr))
+            .ok()
+            .unwrap()
+        {
+            ParsedInstruction::TokenInstruction(parsed) => parsed,
+            _ => panic!("Expected a token instruction."),
+        };
+
+        let instr =
+            spl_token::instruction::approve(&spl_token::id(), &source, &delegate, &owner, &[], 5)
+                .unwrap();
+        match parse(instr) {
+            TokenInstruction::Approve {
+                source_address,
+                delegate_address,
+                owner_address,
+                token_address,
+                amount,
+            } => {
+                assert_eq!(source_address, source);
+                assert_eq!(delegate_address, delegate);
+                assert_eq!(owner_address, owner);
+                assert_eq!(token_address, mint);
+                assert_eq!(amount, 5);
+            }
+            _ => panic!("Expected Approve."),
+        }
+
+        let instr =
+            spl_token::instruction::mint_to(&spl_token::id(), &mint, &source, &owner, &[], 6)
+                .unwrap();
+        match parse(instr) {
+            TokenInstruction::MintTo {
+                token_address,
+                to_address,
+                mint_authority,
+                amount,
+            } => {
+                assert_eq!(token_address, mint);
+                assert_eq!(to_address, source);
+                assert_eq!(mint_authority, owner);
+                assert_eq!(amount, 6);
+            }
+            _ => panic!("Expected MintTo."),
+        }
+
+        let instr =
+            spl_token::instruction::burn(&spl_token::id(), &source, &mint, &owner, &[], 7).unwrap();
+        match parse(instr) {
+            TokenInstruction::Burn {
+                from_address,
+                token_address,
+                owner_address,
+                amount,
+            } => {
+                assert_eq!(from_address, source);
+                assert_eq!(token_address, mint);
+                assert_eq!(owner_address, owner);
+                assert_eq!(amount, 7);
+            }
+            _ => panic!("Expected Burn."),
+        }
+
+        let instr = spl_token::instruction::set_authority(
+            &spl_token::id(),
+            &mint,
+            Some(&delegate),
+            spl_token::instruction::AuthorityType::MintTokens,
+            &owner,
+            &[],
+        )
+        .unwrap();
+        match parse(instr) {
+            TokenInstruction::SetAuthority {
+                address,
+                current_authority,
+                new_authority,
+                authority_type,
+            } => {
+                assert_eq!(address, mint);
+                assert_eq!(current_authority, owner);
+                assert_eq!(new_authority, Some(delegate));
+                assert_eq!(authority_type, "mint tokens");
+            }
+            _ => panic!("Expected SetAuthority."),
+        }
+
+        // Removing an authority decodes to `None`, and serializes as `null`.
+        let instr = spl_token::instruction::set_authority(
+            &spl_token::id(),
+            &source,
+            None,
+            spl_token::instruction::AuthorityType::CloseAccount,
+            &owner,
+            &[],
+        )
+        .unwrap();
+        let parsed = parse(instr);
+        match &parsed {
+            TokenInstruction::SetAuthority {
+                new_authority: None,
+                authority_type: "close account",
+                ..
+            } => {}
+            _ => panic!("Expected SetAuthority that removes the authority."),
+        }
+        let json = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(
+            json["SetAuthority"]["new_authority"],
+            serde_json::Value::Null
+        );
+    }
+}
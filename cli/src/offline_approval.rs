@@ -0,0 +1,420 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Approve multisig transactions with a key on an offline machine.
+//!
+//! The workflow has three steps:
+//!
+//! 1. On an online machine, `export-approval` writes an approval request to a
+//!    file. It contains everything needed to build the approval transaction,
+//!    and the raw accounts needed to decode the multisig transaction to approve.
+//! 2. On the offline machine, `sign-offline` decodes the multisig transaction
+//!    from those accounts, shows it, builds the approval transaction from the
+//!    request, and adds the owner's signature to the file.
+//! 3. Back online, `submit-approval` rebuilds the same transaction, attaches
+//!    the signature, and sends it.
+//!
+//! The offline machine cannot check the recorded accounts against the cluster,
+//! so it trusts that they are what the online machine read. The protection is
+//! in the last step: `submit-approval` refuses to send the approval when the
+//! multisig transaction on the cluster differs from the recorded one.
+//!
+//! A transaction is only valid for about a minute after its blockhash, which
+//! is not enough time to carry a file to an air-gapped machine and back. For
+//! that, the request can use a durable nonce instead of a recent blockhash.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use anchor_lang::prelude::ToAccountMetas;
+use anchor_lang::{AccountDeserialize, InstructionData};
+use serde::{Deserialize, Serialize};
+use serum_multisig::accounts as multisig_accounts;
+use serum_multisig::instruction as multisig_instruction;
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::nonce;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+
+use lido::util::{deserialize_b58, deserialize_b58_option, serialize_b58, serialize_b58_option};
+
+use crate::config::{ExportApprovalOpts, OutputMode, SignOfflineOpts, SubmitApprovalOpts};
+use crate::error::{CliError, Error};
+use crate::multisig::{ask_user_y_n, show_transaction, ShowTransactionOutput};
+use crate::replay::{RecordedSnapshot, RecordedSnapshots, ReplaySource};
+use crate::snapshot::{Result, SnapshotClient};
+use crate::{sign_transaction_with_blockhash, Config, SnapshotClientConfig, SnapshotConfig};
+
+/// Everything needed to sign and submit an approval of a multisig transaction.
+///
+/// This is the contents of the file that moves between the online and the
+/// offline machine.
+#[derive(Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub multisig_program_id: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub multisig_address: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub transaction_address: Pubkey,
+
+    /// Address of the Solido program, to decode Solido instructions.
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub solido_program_id: Pubkey,
+
+    /// The owner who approves, and who pays the transaction fee.
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub owner: Pubkey,
+
+    /// Durable nonce account, whose authority is the owner. When set,
+    /// `blockhash` is the nonce value stored in this account.
+    #[serde(
+        serialize_with = "serialize_b58_option",
+        deserialize_with = "deserialize_b58_option"
+    )]
+    pub nonce_account: Option<Pubkey>,
+
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub blockhash: Hash,
+
+    /// The multisig transaction account, and the other accounts that
+    /// `show-transaction` reads to decode it.
+    ///
+    /// The offline machine decodes the transaction from these accounts, and
+    /// trusts that they match the cluster. `submit_approval` checks that the
+    /// multisig transaction on the cluster is the recorded one before sending.
+    pub accounts: RecordedSnapshot,
+
+    /// The owner's signature, added by `sign-offline`.
+    #[serde(
+        serialize_with = "serialize_b58_option",
+        deserialize_with = "deserialize_b58_option"
+    )]
+    pub signature: Option<Signature>,
+}
+
+impl ApprovalRequest {
+    /// Return the instructions of the approval transaction.
+    fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = Vec::with_capacity(2);
+        if let Some(nonce_account) = &self.nonce_account {
+            // With a durable nonce, advancing the nonce must be the first instruction.
+            instructions.push(system_instruction::advance_nonce_account(
+                nonce_account,
+                &self.owner,
+            ));
+        }
+        let approve_accounts = multisig_accounts::Approve {
+            multisig: self.multisig_address,
+            transaction: self.transaction_address,
+            owner: self.owner,
+        };
+        instructions.push(Instruction {
+            program_id: self.multisig_program_id,
+            data: multisig_instruction::Approve.data(),
+            accounts: approve_accounts.to_account_metas(None),
+        });
+        instructions
+    }
+
+    /// Return the multisig transaction to approve, from the recorded accounts.
+    fn recorded_transaction(&self) -> std::result::Result<serum_multisig::Transaction, Error> {
+        let account: Account = self
+            .accounts
+            .accounts
+            .get(&self.transaction_address)
+            .and_then(|ui_account| ui_account.as_ref())
+            .and_then(|ui_account| ui_account.decode())
+            .ok_or_else(|| {
+                CliError::with_cause(
+                    "The approval request does not contain the multisig transaction.",
+                    format!("Transaction address: {}", self.transaction_address),
+                )
+            })?;
+        Ok(serum_multisig::Transaction::try_deserialize(
+            &mut &account.data[..],
+        )?)
+    }
+
+    /// Decode the multisig transaction from the recorded accounts.
+    ///
+    /// This runs the same decoding as `show-transaction`, but it does not need
+    /// a connection to the cluster.
+    pub fn show_transaction(
+        &self,
+        signer: &dyn Signer,
+        output_mode: OutputMode,
+    ) -> std::result::Result<ShowTransactionOutput, Error> {
+        let recording = RecordedSnapshots {
+            validator_info_addrs: BTreeMap::new(),
+            snapshots: vec![self.accounts.clone()],
+        };
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(ReplaySource::new(recording)?)),
+            signer,
+            output_mode,
+        };
+        config.with_snapshot(|config| {
+            let transaction: serum_multisig::Transaction = config
+                .client
+                .get_account_deserialize(&self.transaction_address)?;
+            if transaction.multisig != self.multisig_address {
+                return Err(CliError::with_cause(
+                    "The multisig transaction belongs to a different multisig than the approval.",
+                    format!(
+                        "Transaction is for {}, but the approval is for {}.",
+                        transaction.multisig, self.multisig_address
+                    ),
+                )
+                .into());
+            }
+            show_transaction(
+                config,
+                &self.transaction_address,
+                &self.multisig_program_id,
+                &self.solido_program_id,
+                Path::new(""),
+            )
+        })
+    }
+
+    /// Return the approval transaction, with the signature if there is one.
+    fn transaction(&self) -> Transaction {
+        let mut transaction = Transaction::new_with_payer(&self.instructions(), Some(&self.owner));
+        transaction.message.recent_blockhash = self.blockhash;
+        if let Some(signature) = self.signature {
+            transaction.signatures = vec![signature];
+        }
+        transaction
+    }
+
+    /// Sign the approval transaction, and store the signature.
+    pub fn sign(&mut self, signer: &dyn Signer) -> std::result::Result<Signature, Error> {
+        if signer.pubkey() != self.owner {
+            return Err(CliError::with_cause(
+                "The signer is not the owner that the approval request is for.",
+                format!(
+                    "Expected {}, but the signer is {}.",
+                    self.owner,
+                    signer.pubkey()
+                ),
+            ));
+        }
+        let transaction = sign_transaction_with_blockhash(
+            &self.owner,
+            &self.instructions(),
+            &[signer],
+            self.blockhash,
+        )?;
+        let signature = transaction.signatures[0];
+        self.signature = Some(signature);
+        Ok(signature)
+    }
+
+    fn load(path: &Path) -> std::result::Result<ApprovalRequest, Error> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn save(&self, path: &Path) -> std::result::Result<(), Error> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ApprovalRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Approval:")?;
+        writeln!(f, "  Multisig program:  {}", self.multisig_program_id)?;
+        writeln!(f, "  Multisig:          {}", self.multisig_address)?;
+        writeln!(f, "  Transaction:       {}", self.transaction_address)?;
+        writeln!(f, "  Owner and payer:   {}", self.owner)?;
+        match &self.nonce_account {
+            Some(nonce_account) => {
+                writeln!(f, "  Nonce account:     {}", nonce_account)?;
+                writeln!(f, "  Nonce:             {}", self.blockhash)?;
+            }
+            None => writeln!(f, "  Recent blockhash:  {}", self.blockhash)?,
+        }
+        match &self.signature {
+            Some(signature) => writeln!(f, "  Signature:         {}", signature)?,
+            None => writeln!(f, "  Signature:         not yet signed")?,
+        }
+        Ok(())
+    }
+}
+
+/// Read the nonce value from a durable nonce account, and check its authority.
+fn get_nonce(config: &mut SnapshotConfig, nonce_account: &Pubkey, owner: &Pubkey) -> Result<Hash> {
+    let account = config.client.get_account(nonce_account)?;
+    let versions: nonce::state::Versions = bincode::deserialize(&account.data)?;
+    match versions.convert_to_current() {
+        nonce::State::Initialized(data) if data.authority == *owner => Ok(data.blockhash),
+        nonce::State::Initialized(data) => Err(CliError::with_cause(
+            "The owner is not the authority of the nonce account.",
+            format!(
+                "Nonce account {} has authority {}, but the owner is {}.",
+                nonce_account, data.authority, owner
+            ),
+        )
+        .into()),
+        nonce::State::Uninitialized => Err(CliError::with_cause(
+            "The nonce account is not initialized.",
+            format!("Nonce account: {}", nonce_account),
+        )
+        .into()),
+    }
+}
+
+pub fn export_approval(
+    config: &mut SnapshotConfig,
+    opts: &ExportApprovalOpts,
+) -> Result<ApprovalRequest> {
+    // Decode the transaction once here, to record every account that decoding
+    // it reads, so the offline machine can repeat it.
+    show_transaction(
+        config,
+        opts.transaction_address(),
+        opts.multisig_program_id(),
+        opts.solido_program_id(),
+        Path::new(""),
+    )?;
+    let accounts = config.client.record_referenced_accounts();
+
+    let nonce_account = if opts.nonce_account() == &Pubkey::default() {
+        None
+    } else {
+        Some(*opts.nonce_account())
+    };
+    let blockhash = match &nonce_account {
+        Some(nonce_account) => get_nonce(config, nonce_account, opts.owner())?,
+        None => config.client.get_recent_blockhash()?,
+    };
+
+    let request = ApprovalRequest {
+        multisig_program_id: *opts.multisig_program_id(),
+        multisig_address: *opts.multisig_address(),
+        transaction_address: *opts.transaction_address(),
+        solido_program_id: *opts.solido_program_id(),
+        owner: *opts.owner(),
+        nonce_account,
+        blockhash,
+        accounts,
+        signature: None,
+    };
+    request.save(opts.approval_path())?;
+    Ok(request)
+}
+
+/// Sign an approval request. This does not need a connection to the cluster.
+pub fn sign_offline(
+    signer: &dyn Signer,
+    output_mode: OutputMode,
+    opts: &SignOfflineOpts,
+) -> std::result::Result<ApprovalRequest, Error> {
+    let mut request = ApprovalRequest::load(opts.approval_path())?;
+    let transaction = request.show_transaction(signer, output_mode)?;
+    // The review goes to stderr, the signed request is the output on stdout.
+    eprintln!("{}", transaction);
+    eprintln!("{}", request);
+    if !ask_user_y_n("Sign the approval?") {
+        return Err(CliError::new("Signing cancelled by the user."));
+    }
+    request.sign(signer)?;
+    request.save(opts.approval_path())?;
+    Ok(request)
+}
+
+#[derive(Serialize)]
+pub struct SubmitApprovalOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_id: Signature,
+    pub num_approvals: u64,
+    pub threshold: u64,
+}
+
+impl fmt::Display for SubmitApprovalOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Transaction approved.")?;
+        writeln!(
+            f,
+            "  Solana transaction id of approval: {}",
+            self.transaction_id
+        )?;
+        writeln!(
+            f,
+            "  Multisig transaction now has {} out of {} required approvals.",
+            self.num_approvals, self.threshold
+        )?;
+        Ok(())
+    }
+}
+
+pub fn submit_approval(
+    config: &mut SnapshotClientConfig,
+    request: &ApprovalRequest,
+) -> std::result::Result<SubmitApprovalOutput, Error> {
+    if request.signature.is_none() {
+        return Err(CliError::new(
+            "The approval request is not signed yet, sign it with 'sign-offline' first.",
+        ));
+    }
+    let transaction = request.transaction();
+    if transaction.verify().is_err() {
+        return Err(CliError::new(
+            "The signature does not match the approval request.",
+        ));
+    }
+
+    // The owner reviewed the transaction in the request, confirm that it is
+    // the one on the cluster.
+    let recorded = request.recorded_transaction()?;
+    config.with_snapshot(|config| {
+        let current: serum_multisig::Transaction = config
+            .client
+            .get_account_deserialize(&request.transaction_address)?;
+        if current.multisig != recorded.multisig
+            || Instruction::from(&current) != Instruction::from(&recorded)
+        {
+            return Err(CliError::new(
+                "The multisig transaction on the cluster differs from the one in the approval request.",
+            )
+            .into());
+        }
+        Ok(())
+    })?;
+
+    let transaction_id =
+        config.with_snapshot(|config| Ok(config.send_transaction(&transaction)?))?;
+
+    config.with_snapshot(|config| {
+        let multisig: serum_multisig::Multisig = config
+            .client
+            .get_account_deserialize(&request.multisig_address)?;
+        let transaction: serum_multisig::Transaction = config
+            .client
+            .get_account_deserialize(&request.transaction_address)?;
+        Ok(SubmitApprovalOutput {
+            transaction_id,
+            num_approvals: transaction.signers.iter().filter(|x| **x).count() as u64,
+            threshold: multisig.threshold,
+        })
+    })
+}
+
+pub fn command_submit_approval(
+    config: &mut SnapshotClientConfig,
+    opts: &SubmitApprovalOpts,
+) -> std::result::Result<SubmitApprovalOutput, Error> {
+    let request = ApprovalRequest::load(opts.approval_path())?;
+    submit_approval(config, &request)
+}
@@ -0,0 +1,413 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Planning the stake deposits and unstakes that balance the stake pool.
+//!
+//! The maintainer only looks at the current state, and takes one step at a
+//! time. To show operators where that leads, we run the same selection logic
+//! against a copy of the state, apply the operations to it as the program
+//! would, and move to the next epoch until there is nothing left to do.
+
+use std::fmt;
+
+use lido::stake_account::{StakeAccount, StakeBalance};
+use lido::token::{self, Lamports};
+use serde::Serialize;
+use solana_program::clock::Epoch;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::Instruction;
+
+use crate::config::{PlanRebalanceOpts, RebalancingConfig, StakeTime};
+use crate::maintenance::{pack_transactions, MaintenanceOperation, SolidoState, Unstake};
+use crate::snapshot::Result;
+use crate::SnapshotConfig;
+
+/// Number of epochs we simulate before concluding the plan does not converge.
+const MAX_PLANNED_EPOCHS: u64 = 10;
+
+/// Number of operations per epoch after which we stop planning that epoch.
+///
+/// Every operation brings the pool closer to balance, so we should never reach
+/// this, but it guarantees that planning terminates.
+const MAX_OPERATIONS_PER_EPOCH: usize = 1_000;
+
+/// The operations that the maintainer would perform in a single epoch.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct EpochPlan {
+    pub epoch: Epoch,
+    pub operations: Vec<MaintenanceOperation>,
+    pub num_transactions: usize,
+    pub amount_staked: Lamports,
+    pub amount_unstaked: Lamports,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RebalancePlan {
+    /// The epochs in which the maintainer has work to do, starting at the current epoch.
+    pub epochs: Vec<EpochPlan>,
+
+    pub amount_staked: Lamports,
+    pub amount_unstaked: Lamports,
+    pub num_transactions: usize,
+    pub estimated_fees: Lamports,
+
+    /// Number of epochs after which the pool is balanced, and all stake that
+    /// we moved is active, or `None` if that takes more than `MAX_PLANNED_EPOCHS`.
+    pub epochs_until_balanced: Option<u64>,
+}
+
+impl fmt::Display for RebalancePlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for epoch in &self.epochs {
+            writeln!(
+                f,
+                "Epoch {}, {} operations in {} transactions:",
+                epoch.epoch,
+                epoch.operations.len(),
+                epoch.num_transactions,
+            )?;
+            writeln!(f, "  Staked:   {}", epoch.amount_staked)?;
+            writeln!(f, "  Unstaked: {}", epoch.amount_unstaked)?;
+            for operation in &epoch.operations {
+                write!(f, "{}", operation)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "Total staked:   {}", self.amount_staked)?;
+        writeln!(f, "Total unstaked: {}", self.amount_unstaked)?;
+        writeln!(f, "Transactions:   {}", self.num_transactions)?;
+        writeln!(f, "Estimated fees: {}", self.estimated_fees)?;
+        match self.epochs_until_balanced {
+            Some(0) => writeln!(f, "The pool is balanced, there is nothing to do.")?,
+            Some(n) => writeln!(f, "The pool is balanced after {} epochs.", n)?,
+            None => writeln!(
+                f,
+                "The pool is not balanced after {} epochs.",
+                MAX_PLANNED_EPOCHS
+            )?,
+        }
+        Ok(())
+    }
+}
+
+fn find_validator_index(state: &SolidoState, vote_account: &Pubkey) -> usize {
+    state
+        .validators
+        .entries
+        .iter()
+        .position(|v| v.pubkey == *vote_account)
+        .expect("Operations only reference validators that exist.")
+}
+
+/// Update the state as executing the stake deposit would.
+fn apply_stake_deposit(
+    state: &mut SolidoState,
+    validator_vote_account: &Pubkey,
+    stake_account: &Pubkey,
+    amount: Lamports,
+) {
+    let index = find_validator_index(state, validator_vote_account);
+    state.reserve_account.lamports -= amount.0;
+
+    let validator = &mut state.validators.entries[index].entry;
+    validator.stake_accounts_balance = (validator.stake_accounts_balance + amount)
+        .expect("Does not overflow, the stake came from the reserve.");
+
+    // Like the program, merge into the preceding account if it was activated
+    // in this epoch, and create a new stake account otherwise.
+    let stake_accounts = &mut state.validator_stake_accounts[index];
+    match stake_accounts.last_mut() {
+        Some((_, account)) if account.activation_epoch == state.clock.epoch => {
+            account.balance.activating = (account.balance.activating + amount)
+                .expect("Does not overflow, the stake came from the reserve.");
+        }
+        _ => {
+            stake_accounts.push((
+                *stake_account,
+                StakeAccount {
+                    balance: StakeBalance {
+                        activating: amount,
+                        ..StakeBalance::zero()
+                    },
+                    credits_observed: 0,
+                    activation_epoch: state.clock.epoch,
+                    deactivation_epoch: Epoch::MAX,
+                    seed: validator.stake_seeds.end,
+                },
+            ));
+            validator.stake_seeds.end += 1;
+        }
+    }
+}
+
+/// Update the state as executing the unstake would.
+fn apply_unstake(state: &mut SolidoState, unstake: &Unstake) {
+    let index = find_validator_index(state, &unstake.validator_vote_account);
+
+    let validator = &mut state.validators.entries[index].entry;
+    validator.unstake_accounts_balance = (validator.unstake_accounts_balance + unstake.amount)
+        .expect("Does not overflow, the unstake balance is part of the stake balance.");
+    validator.unstake_seeds.end += 1;
+
+    // Split the amount off the source account, from the active part first.
+    let (_, source) = &mut state.validator_stake_accounts[index][0];
+    let from_active = source.balance.active.min(unstake.amount);
+    let from_activating = (unstake.amount - from_active)
+        .expect("Does not underflow, from_active is at most the amount.");
+    source.balance.active = (source.balance.active - from_active)
+        .expect("Does not underflow, we take at most the active balance.");
+    source.balance.activating = (source.balance.activating - from_activating)
+        .expect("The maintainer does not unstake more than the account holds.");
+
+    state.validator_unstake_accounts[index].push((
+        unstake.to_unstake_account,
+        StakeAccount {
+            balance: StakeBalance {
+                deactivating: unstake.amount,
+                ..StakeBalance::zero()
+            },
+            credits_observed: 0,
+            activation_epoch: source.activation_epoch,
+            deactivation_epoch: state.clock.epoch,
+            seed: unstake.to_unstake_seed,
+        },
+    ));
+}
+
+/// Return the stake of unstake accounts that finished deactivating to the
+/// reserve, as the maintainer does before it stakes deposits.
+fn withdraw_inactive_unstake(state: &mut SolidoState) {
+    for (validator, unstake_accounts) in state
+        .validators
+        .entries
+        .iter_mut()
+        .zip(state.validator_unstake_accounts.iter_mut())
+    {
+        while let Some((_, account)) = unstake_accounts.first() {
+            let amount = account.balance.total();
+            if account.balance.inactive != amount {
+                break;
+            }
+            let validator = &mut validator.entry;
+            validator.stake_accounts_balance = (validator.stake_accounts_balance - amount)
+                .expect("Unstaked stake is part of the stake balance.");
+            validator.unstake_accounts_balance = (validator.unstake_accounts_balance - amount)
+                .expect("Unstaked stake is part of the unstake balance.");
+            validator.unstake_seeds.begin += 1;
+            state.reserve_account.lamports += amount.0;
+            unstake_accounts.remove(0);
+        }
+    }
+}
+
+/// Move the state to the next epoch.
+///
+/// This assumes that stake (de)activates fully in one epoch. When the cluster
+/// limits how much stake can warm up or cool down per epoch, it takes longer.
+fn advance_epoch(state: &mut SolidoState) {
+    state.clock.epoch += 1;
+    state.clock.slot = state
+        .epoch_schedule
+        .get_first_slot_in_epoch(state.clock.epoch);
+
+    for (_, account) in state.validator_stake_accounts.iter_mut().flatten() {
+        account.balance.active = (account.balance.active + account.balance.activating)
+            .expect("Does not overflow, both are part of the account balance.");
+        account.balance.activating = Lamports(0);
+    }
+    for (_, account) in state.validator_unstake_accounts.iter_mut().flatten() {
+        account.balance.inactive = (account.balance.inactive + account.balance.deactivating)
+            .expect("Does not overflow, both are part of the account balance.");
+        account.balance.deactivating = Lamports(0);
+    }
+}
+
+/// Collect the operations that the maintainer would perform in the current
+/// epoch of `state`, and apply them to it.
+fn plan_epoch(state: &mut SolidoState) -> Vec<(Instruction, MaintenanceOperation)> {
+    withdraw_inactive_unstake(state);
+
+    // Stake deposits go before unstakes, in the same order as in `try_perform_maintenance`.
+    let mut operations = Vec::new();
+    while operations.len() < MAX_OPERATIONS_PER_EPOCH {
+        let (instruction, operation) = match state
+            .try_stake_deposit()
+            .or_else(|| state.try_unstake_from_active_validators())
+        {
+            Some(op) => op,
+            None => break,
+        };
+        match &operation {
+            MaintenanceOperation::StakeDeposit {
+                validator_vote_account,
+                amount,
+                stake_account,
+            } => apply_stake_deposit(state, validator_vote_account, stake_account, *amount),
+            MaintenanceOperation::UnstakeFromActiveValidator(unstake) => {
+                apply_unstake(state, unstake)
+            }
+            _ => unreachable!("We only plan stake deposits and unstakes."),
+        }
+        operations.push((instruction, operation));
+    }
+    operations
+}
+
+/// Plan the stake deposits and unstakes needed to balance the pool.
+pub fn plan_rebalance(state: &SolidoState, lamports_per_signature: Lamports) -> RebalancePlan {
+    let mut state = state.clone();
+    // We plan what to do, not when in the epoch the maintainer does it.
+    state.stake_time = StakeTime::Anytime;
+
+    let mut epochs = Vec::new();
+    let mut epochs_until_balanced = None;
+    for i in 0..MAX_PLANNED_EPOCHS {
+        let epoch = state.clock.epoch;
+        let operations = plan_epoch(&mut state);
+        if operations.is_empty() {
+            epochs_until_balanced = Some(i);
+            break;
+        }
+
+        let transactions = pack_transactions(&state.maintainer_address, operations);
+        let num_transactions = transactions.len();
+        let operations: Vec<MaintenanceOperation> = transactions
+            .into_iter()
+            .flat_map(|(_instructions, transaction)| transaction.operations)
+            .collect();
+        let mut amount_staked = Lamports(0);
+        let mut amount_unstaked = Lamports(0);
+        for operation in &operations {
+            match operation {
+                MaintenanceOperation::StakeDeposit { amount, .. } => {
+                    amount_staked = (amount_staked + *amount)
+                        .expect("Does not overflow, we only move SOL that exists.");
+                }
+                MaintenanceOperation::UnstakeFromActiveValidator(unstake) => {
+                    amount_unstaked = (amount_unstaked + unstake.amount)
+                        .expect("Does not overflow, we only move SOL that exists.");
+                }
+                _ => {}
+            }
+        }
+        epochs.push(EpochPlan {
+            epoch,
+            operations,
+            num_transactions,
+            amount_staked,
+            amount_unstaked,
+        });
+
+        advance_epoch(&mut state);
+    }
+
+    let num_transactions = epochs.iter().map(|epoch| epoch.num_transactions).sum();
+    RebalancePlan {
+        amount_staked: epochs
+            .iter()
+            .map(|epoch| epoch.amount_staked)
+            .sum::<token::Result<Lamports>>()
+            .expect("Does not overflow, we only move SOL that exists."),
+        amount_unstaked: epochs
+            .iter()
+            .map(|epoch| epoch.amount_unstaked)
+            .sum::<token::Result<Lamports>>()
+            .expect("Does not overflow, we only move SOL that exists."),
+        num_transactions,
+        // Maintenance transactions have a single signature, the maintainer's.
+        estimated_fees: (lamports_per_signature * num_transactions as u64)
+            .expect("Fees do not overflow."),
+        epochs,
+        epochs_until_balanced,
+    }
+}
+
+/// Show the stake deposits and unstakes that the maintainer will perform to
+/// balance the pool, if nothing else changes.
+pub fn command_plan_rebalance(
+    config: &mut SnapshotConfig,
+    opts: &PlanRebalanceOpts,
+    rebalancing: &RebalancingConfig,
+) -> Result<RebalancePlan> {
+    let state = SolidoState::new(
+        config,
+        opts.solido_program_id(),
+        opts.solido_address(),
+        StakeTime::Anytime,
+        rebalancing,
+    )?;
+    Ok(plan_rebalance(
+        &state,
+        Lamports(*opts.lamports_per_signature()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use lido::state::Validator;
+
+    use crate::test_utils::new_empty_solido;
+
+    #[test]
+    fn plan_rebalance_moves_stake_to_the_new_validator() {
+        let mut state = new_empty_solido();
+        state.validators.maximum_entries = 2;
+        for _ in 0..2 {
+            state
+                .validators
+                .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+                .unwrap();
+        }
+
+        // The first validator has 10 SOL of active stake, the second one was
+        // just added and has nothing yet.
+        let stake = Lamports(10_000_000_000);
+        let validator = &mut state.validators.entries[0].entry;
+        validator.stake_accounts_balance = stake;
+        validator.stake_seeds.end = 1;
+        state.validator_stake_accounts = vec![
+            vec![(
+                Pubkey::new_unique(),
+                StakeAccount {
+                    balance: StakeBalance {
+                        active: stake,
+                        ..StakeBalance::zero()
+                    },
+                    credits_observed: 0,
+                    activation_epoch: 0,
+                    deactivation_epoch: Epoch::MAX,
+                    seed: 0,
+                },
+            )],
+            vec![],
+        ];
+        state.validator_unstake_accounts = vec![vec![], vec![]];
+        state.clock.epoch = 5;
+
+        let plan = plan_rebalance(&state, Lamports(5_000));
+
+        // We can only stake with the second validator after the unstaked SOL
+        // is back in the reserve, and that stake activates in the epoch after.
+        let half = Lamports(5_000_000_000);
+        assert_eq!(plan.epochs.len(), 2);
+        assert_eq!(plan.epochs[0].epoch, 5);
+        assert_eq!(plan.epochs[0].amount_unstaked, half);
+        assert_eq!(plan.epochs[0].amount_staked, Lamports(0));
+        assert_eq!(plan.epochs[1].epoch, 6);
+        assert_eq!(plan.epochs[1].amount_unstaked, Lamports(0));
+        assert_eq!(plan.epochs[1].amount_staked, half);
+        assert!(matches!(
+            &plan.epochs[1].operations[..],
+            [MaintenanceOperation::StakeDeposit { validator_vote_account, .. }]
+                if *validator_vote_account == state.validators.entries[1].pubkey
+        ));
+        assert_eq!(plan.num_transactions, 2);
+        assert_eq!(plan.estimated_fees, Lamports(10_000));
+        assert_eq!(plan.epochs_until_balanced, Some(2));
+
+        // Planning does not modify the state it started from.
+        assert_eq!(state.get_effective_reserve(), Lamports(0));
+    }
+}
@@ -0,0 +1,536 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Record snapshots from a live cluster, and replay them later.
+//!
+//! The recording contains every account returned by `GetMultipleAccounts`,
+//! including sysvars, grouped per snapshot, and the validator info map. This
+//! lets us capture a situation on mainnet, and then reproduce what the
+//! maintainer would do in that situation in a deterministic test, without a
+//! running RPC node.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_request::RpcError;
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_sdk::account::Account;
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::TransactionStatus;
+
+use lido::token::Lamports;
+use lido::util::{
+    deserialize_b58_keys, deserialize_b58_map, serialize_b58_keys, serialize_b58_map,
+};
+
+use crate::endpoint::EndpointMetrics;
+use crate::error::{CliError, Error};
+use crate::snapshot::SnapshotSource;
+
+/// The accounts that we read during one call to `with_snapshot`.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct RecordedSnapshot {
+    /// Slot of the most recent `GetMultipleAccounts` response, if we made any call.
+    pub slot: Option<Slot>,
+
+    /// Accounts by address, `None` for accounts that did not exist.
+    ///
+    /// When the snapshot took multiple calls, this holds the union of the
+    /// responses, where later responses overwrite earlier ones.
+    #[serde(
+        serialize_with = "serialize_b58_keys",
+        deserialize_with = "deserialize_b58_keys"
+    )]
+    pub accounts: BTreeMap<Pubkey, Option<UiAccount>>,
+}
+
+impl RecordedSnapshot {
+    pub fn insert(&mut self, address: &Pubkey, account: Option<&Account>) {
+        let ui_account = account.map(|account| {
+            UiAccount::encode(address, account, UiAccountEncoding::Base64, None, None)
+        });
+        self.accounts.insert(*address, ui_account);
+    }
+}
+
+/// Responses of `GetMultipleAccounts` and the validator info map, recorded earlier.
+#[derive(Default, Deserialize, Serialize)]
+pub struct RecordedSnapshots {
+    /// Map from validator identity account address to config account address.
+    #[serde(
+        serialize_with = "serialize_b58_map",
+        deserialize_with = "deserialize_b58_map"
+    )]
+    pub validator_info_addrs: BTreeMap<Pubkey, Pubkey>,
+
+    /// Snapshots in the order in which we read them.
+    pub snapshots: Vec<RecordedSnapshot>,
+}
+
+/// Snapshot source that records everything it reads from another source to a file.
+///
+/// The file is rewritten after every response, so it is complete even when
+/// the process gets killed. Beware that it grows with every snapshot, so record
+/// only for as long as needed.
+pub struct RecordingSource {
+    inner: Box<dyn SnapshotSource>,
+    path: PathBuf,
+    recording: RecordedSnapshots,
+}
+
+impl RecordingSource {
+    pub fn new(inner: Box<dyn SnapshotSource>, path: PathBuf) -> RecordingSource {
+        RecordingSource {
+            inner,
+            path,
+            recording: RecordedSnapshots::default(),
+        }
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(&self.recording)?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+}
+
+impl SnapshotSource for RecordingSource {
+    fn begin_snapshot(&mut self) {
+        self.inner.begin_snapshot();
+        self.recording.snapshots.push(RecordedSnapshot::default());
+    }
+
+    fn get_multiple_accounts(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> Result<(Vec<Option<Account>>, Option<Slot>), Error> {
+        let (accounts, slot) = self.inner.get_multiple_accounts(addresses)?;
+        if self.recording.snapshots.is_empty() {
+            self.recording.snapshots.push(RecordedSnapshot::default());
+        }
+        let snapshot = self
+            .recording
+            .snapshots
+            .last_mut()
+            .expect("We ensured there is a snapshot.");
+        snapshot.slot = slot.or(snapshot.slot);
+        for (address, account) in addresses.iter().zip(accounts.iter()) {
+            snapshot.insert(address, account.as_ref());
+        }
+        self.save()?;
+        Ok((accounts, slot))
+    }
+
+    fn get_validator_info_addrs(&mut self) -> Result<HashMap<Pubkey, Pubkey>, Error> {
+        let validator_info_addrs = self.inner.get_validator_info_addrs()?;
+        self.recording.validator_info_addrs = validator_info_addrs
+            .iter()
+            .map(|(identity, config)| (*identity, *config))
+            .collect();
+        self.save()?;
+        Ok(validator_info_addrs)
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.inner
+            .get_program_accounts_with_config(program_id, config)
+    }
+
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<RpcSimulateTransactionResult> {
+        self.inner.simulate_transaction(transaction)
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<TransactionStatus>>> {
+        self.inner.get_signature_statuses(signatures)
+    }
+
+    fn get_transaction_fee(&self, signature: &Signature) -> ClientResult<Option<Lamports>> {
+        self.inner.get_transaction_fee(signature)
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        self.inner.send_transaction(transaction)
+    }
+
+    fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool> {
+        self.inner.confirm_transaction(signature)
+    }
+
+    fn confirm_transaction_with_spinner(
+        &self,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+    ) -> ClientResult<()> {
+        self.inner
+            .confirm_transaction_with_spinner(signature, recent_blockhash)
+    }
+
+    fn rpc_client(&self) -> Option<&RpcClient> {
+        self.inner.rpc_client()
+    }
+
+    fn get_endpoint_metrics(&self) -> Vec<EndpointMetrics> {
+        self.inner.get_endpoint_metrics()
+    }
+}
+
+/// Snapshot source that serves snapshots from a recording.
+///
+/// Every call to `with_snapshot` gets the next recorded snapshot. Sending a
+/// transaction succeeds without executing anything, so the replayed state does
+/// not change after a transaction.
+pub struct ReplaySource {
+    snapshots: Vec<HashMap<Pubkey, Option<Account>>>,
+    slots: Vec<Option<Slot>>,
+    validator_info_addrs: HashMap<Pubkey, Pubkey>,
+
+    /// Number of snapshots that we started so far. We serve the last one started.
+    num_started: usize,
+}
+
+impl ReplaySource {
+    pub fn new(recording: RecordedSnapshots) -> Result<ReplaySource, Error> {
+        let mut snapshots = Vec::with_capacity(recording.snapshots.len());
+        let mut slots = Vec::with_capacity(recording.snapshots.len());
+        for snapshot in recording.snapshots {
+            let mut accounts = HashMap::with_capacity(snapshot.accounts.len());
+            for (address, ui_account) in snapshot.accounts {
+                let account = match ui_account {
+                    None => None,
+                    Some(ui_account) => Some(ui_account.decode().ok_or_else(|| {
+                        CliError::with_cause("Failed to decode account in recording.", address)
+                    })?),
+                };
+                accounts.insert(address, account);
+            }
+            snapshots.push(accounts);
+            slots.push(snapshot.slot);
+        }
+
+        Ok(ReplaySource {
+            snapshots,
+            slots,
+            validator_info_addrs: recording.validator_info_addrs.into_iter().collect(),
+            num_started: 0,
+        })
+    }
+
+    /// Load a recording made with [`RecordingSource`].
+    pub fn load(path: &std::path::Path) -> Result<ReplaySource, Error> {
+        let file = std::fs::File::open(path)?;
+        let recording: RecordedSnapshots = serde_json::from_reader(file)?;
+        ReplaySource::new(recording)
+    }
+}
+
+fn unsupported<T>(what: &str) -> ClientResult<T> {
+    Err(RpcError::ForUser(format!(
+        "{} is not supported when replaying snapshots.",
+        what
+    ))
+    .into())
+}
+
+impl SnapshotSource for ReplaySource {
+    fn begin_snapshot(&mut self) {
+        self.num_started += 1;
+    }
+
+    fn get_multiple_accounts(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> Result<(Vec<Option<Account>>, Option<Slot>), Error> {
+        let index = self.num_started.saturating_sub(1);
+        let snapshot = match self.snapshots.get(index) {
+            Some(snapshot) => snapshot,
+            None => return Err(CliError::new("The recording contains no more snapshots.")),
+        };
+        let mut result = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            match snapshot.get(address) {
+                Some(account) => result.push(account.clone()),
+                None => {
+                    return Err(CliError::with_cause(
+                        "The recorded snapshot does not contain the account.",
+                        address,
+                    ))
+                }
+            }
+        }
+        let slot = if addresses.is_empty() {
+            None
+        } else {
+            self.slots[index]
+        };
+        Ok((result, slot))
+    }
+
+    fn get_validator_info_addrs(&mut self) -> Result<HashMap<Pubkey, Pubkey>, Error> {
+        Ok(self.validator_info_addrs.clone())
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        _program_id: &Pubkey,
+        _config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        unsupported("GetProgramAccounts")
+    }
+
+    fn simulate_transaction(
+        &self,
+        _transaction: &Transaction,
+    ) -> ClientResult<RpcSimulateTransactionResult> {
+        Ok(RpcSimulateTransactionResult {
+            err: None,
+            logs: Some(Vec::new()),
+            accounts: None,
+        })
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<TransactionStatus>>> {
+        Ok(vec![None; signatures.len()])
+    }
+
+    fn get_transaction_fee(&self, _signature: &Signature) -> ClientResult<Option<Lamports>> {
+        // Replayed transactions do not execute, so they pay no fee.
+        Ok(None)
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        Ok(transaction.signatures[0])
+    }
+
+    fn confirm_transaction(&self, _signature: &Signature) -> ClientResult<bool> {
+        Ok(true)
+    }
+
+    fn confirm_transaction_with_spinner(
+        &self,
+        _signature: &Signature,
+        _recent_blockhash: &Hash,
+    ) -> ClientResult<()> {
+        Ok(())
+    }
+
+    fn rpc_client(&self) -> Option<&RpcClient> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use borsh::BorshSerialize;
+    use lido::state::{Lido, Validators, LIDO_VERSION};
+    use solana_program::fee_calculator::FeeCalculator;
+    use solana_program::program_pack::Pack;
+    use solana_program::sysvar::recent_blockhashes::{IterItem, RecentBlockhashes};
+    use solana_sdk::clock::Clock;
+    use solana_sdk::epoch_schedule::EpochSchedule;
+    use solana_sdk::rent::Rent;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::stake_history::StakeHistory;
+    use solana_sdk::sysvar::{self, Sysvar};
+    use spl_token::state::Mint;
+
+    use crate::budget::FeeBudget;
+    use crate::config::{OutputMode, RebalancingConfig, StakeTime};
+    use crate::maintenance::{try_perform_maintenance, MaintenanceOperation, SolidoState};
+    use crate::snapshot::SnapshotClient;
+    use crate::Config;
+
+    fn new_account(data: Vec<u8>, owner: Pubkey) -> Account {
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn new_sysvar_account<T: Sysvar>(value: &T) -> Account {
+        new_account(bincode::serialize(value).unwrap(), sysvar::id())
+    }
+
+    /// Return a recording of a single snapshot in which the exchange rate needs
+    /// an update, and the program id and Solido address in it.
+    fn new_recording() -> (Pubkey, Pubkey, RecordedSnapshots) {
+        let program_id = Pubkey::new_unique();
+        let solido_address = Pubkey::new_unique();
+        let (reserve_address, reserve_bump_seed) = Pubkey::find_program_address(
+            &[&solido_address.to_bytes(), lido::RESERVE_ACCOUNT],
+            &program_id,
+        );
+
+        // A Solido instance without validators, whose exchange rate was last
+        // updated in epoch 0, while the clock is in epoch 1.
+        let solido = Lido {
+            lido_version: LIDO_VERSION,
+            st_sol_mint: Pubkey::new_unique(),
+            sol_reserve_account_bump_seed: reserve_bump_seed,
+            validator_list: Pubkey::new_unique(),
+            ..Lido::default()
+        };
+        let mint = Mint {
+            is_initialized: true,
+            ..Mint::default()
+        };
+        let mut mint_data = vec![0; Mint::LEN];
+        Mint::pack(mint, &mut mint_data).unwrap();
+        let clock = Clock {
+            slot: 432_000,
+            epoch: 1,
+            ..Clock::default()
+        };
+        let fee_calculator = FeeCalculator::default();
+        let blockhash = Hash::new_unique();
+        let recent_blockhashes: RecentBlockhashes = vec![IterItem(0, &blockhash, &fee_calculator)]
+            .into_iter()
+            .collect();
+
+        let mut snapshot = RecordedSnapshot {
+            slot: Some(clock.slot),
+            ..RecordedSnapshot::default()
+        };
+        let accounts = vec![
+            (
+                solido_address,
+                new_account(solido.try_to_vec().unwrap(), program_id),
+            ),
+            (
+                solido.validator_list,
+                new_account(Validators::new(0).try_to_vec().unwrap(), program_id),
+            ),
+            (
+                reserve_address,
+                new_account(Vec::new(), solana_sdk::system_program::id()),
+            ),
+            (solido.st_sol_mint, new_account(mint_data, spl_token::id())),
+            (sysvar::rent::id(), new_sysvar_account(&Rent::default())),
+            (sysvar::clock::id(), new_sysvar_account(&clock)),
+            (
+                sysvar::epoch_schedule::id(),
+                new_sysvar_account(&EpochSchedule::default()),
+            ),
+            (
+                sysvar::stake_history::id(),
+                new_sysvar_account(&StakeHistory::default()),
+            ),
+            (
+                sysvar::recent_blockhashes::id(),
+                new_sysvar_account(&recent_blockhashes),
+            ),
+        ];
+        for (address, account) in &accounts {
+            snapshot.insert(address, Some(account));
+        }
+        let recording = RecordedSnapshots {
+            validator_info_addrs: BTreeMap::new(),
+            snapshots: vec![snapshot],
+        };
+        (program_id, solido_address, recording)
+    }
+
+    #[test]
+    fn replayed_snapshot_drives_maintenance() {
+        let (program_id, solido_address, recording) = new_recording();
+
+        // Go through json, like a recording on disk would.
+        let json = serde_json::to_string(&recording).unwrap();
+        let recording: RecordedSnapshots = serde_json::from_str(&json).unwrap();
+        let source = ReplaySource::new(recording).ok().unwrap();
+
+        let maintainer = Keypair::new();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(source)),
+            signer: &maintainer,
+            output_mode: OutputMode::Json,
+        };
+        let output = config
+            .with_snapshot(|config| {
+                let state = SolidoState::new(
+                    config,
+                    &program_id,
+                    &solido_address,
+                    StakeTime::Anytime,
+                    &RebalancingConfig::default(),
+                )?;
+                try_perform_maintenance(config, &state, false, false, None, None)
+            })
+            .ok()
+            .unwrap()
+            .expect("Expected maintenance to update the exchange rate.");
+
+        assert_eq!(output.transactions.len(), 1);
+        assert_eq!(
+            output.transactions[0].operations,
+            vec![MaintenanceOperation::UpdateExchangeRate]
+        );
+        assert!(output.transactions[0].signature.is_some());
+
+        // There was only one snapshot in the recording.
+        assert!(config.with_snapshot(|_config| Ok(())).is_err());
+    }
+
+    #[test]
+    fn maintenance_reports_transactions_that_failed_to_send() {
+        let (program_id, solido_address, recording) = new_recording();
+        let source = ReplaySource::new(recording).ok().unwrap();
+        let maintainer = Keypair::new();
+        let mut config = Config {
+            client: SnapshotClient::new(Box::new(source)),
+            signer: &maintainer,
+            output_mode: OutputMode::Json,
+        };
+        // A budget without room for any operation makes us refuse to send.
+        let mut budget = FeeBudget::new(Lamports(1_000_000), 0);
+        let output = config
+            .with_snapshot(|config| {
+                let state = SolidoState::new(
+                    config,
+                    &program_id,
+                    &solido_address,
+                    StakeTime::Anytime,
+                    &RebalancingConfig::default(),
+                )?;
+                try_perform_maintenance(config, &state, false, false, None, Some(&mut budget))
+            })
+            .ok()
+            .unwrap()
+            .expect("Expected the failed transaction in the output.");
+
+        assert!(output.send_failed());
+        assert_eq!(output.transactions.len(), 1);
+        assert!(output.transactions[0].signature.is_none());
+        assert!(output.transactions[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Refusing to perform"));
+    }
+}
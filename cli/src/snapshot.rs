@@ -25,14 +25,21 @@
 use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-use anchor_lang::AccountDeserialize;
-use solana_client::client_error::{ClientError, ClientErrorKind};
+use anchor_lang::{AccountDeserialize, Discriminator};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
 use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcSendTransactionConfig;
-use solana_client::rpc_request::RpcError;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSendTransactionConfig,
+    RpcSimulateTransactionConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
 use solana_sdk::account::{Account, ReadableAccount};
 use solana_sdk::borsh::try_from_slice_unchecked;
-use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::clock::Slot;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
 use solana_sdk::program_pack::{IsInitialized, Pack};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
@@ -42,13 +49,20 @@ use solana_sdk::sysvar::{
     rent::Rent, Sysvar,
 };
 use solana_sdk::transaction::Transaction;
+use solana_transaction_status::{
+    EncodedConfirmedTransaction, TransactionStatus, UiTransactionEncoding,
+};
 use solana_vote_program::vote_state::VoteState;
 
-use lido::state::Lido;
+use lido::state::{Lido, Validators, LIDO_VERSION};
 use lido::token::Lamports;
 use spl_token::solana_program::hash::Hash;
 
-use crate::error::{Error, MissingAccountError, MissingValidatorInfoError, SerializationError};
+use crate::endpoint::{is_endpoint_failure, EndpointMetrics, Endpoints};
+use crate::error::{
+    CliError, Error, MissingAccountError, MissingValidatorInfoError, SerializationError,
+};
+use crate::replay::RecordedSnapshot;
 use crate::validator_info_utils::ValidatorInfo;
 
 pub enum SnapshotError {
@@ -159,8 +173,8 @@ pub struct Snapshot<'a> {
     ///   any accounts in the snapshot that we did not reference.
     accounts_referenced: &'a mut OrderedSet<Pubkey>,
 
-    /// The wrapped client, so we can still send transactions.
-    rpc_client: &'a RpcClient,
+    /// The source that we read the snapshot from, so we can still send transactions.
+    source: &'a dyn SnapshotSource,
 
     /// Whether we sent at least one transaction.
     ///
@@ -204,6 +218,19 @@ impl<'a> Snapshot<'a> {
         }
     }
 
+    /// Return the accounts referenced so far, so they can be replayed without a cluster.
+    pub fn record_referenced_accounts(&self) -> RecordedSnapshot {
+        let mut recording = RecordedSnapshot::default();
+        for address in self.accounts_referenced.iter() {
+            // An account that is not in the snapshot causes a retry, so after a
+            // successful run, every referenced account is in there.
+            if let Some(account) = self.accounts.get(address) {
+                recording.insert(address, account.as_ref());
+            }
+        }
+        recording
+    }
+
     /// Read an account and immediately bincode-deserialize it.
     pub fn get_bincode<T: Sysvar>(&mut self, address: &Pubkey) -> Result<T> {
         let account = self.get_account(address)?;
@@ -309,6 +336,17 @@ impl<'a> Snapshot<'a> {
     /// Read the account and deserialize the Solido struct.
     pub fn get_solido(&mut self, solido_address: &Pubkey) -> Result<Lido> {
         let account = self.get_account(solido_address)?;
+        if account.data.first() != Some(&LIDO_VERSION) {
+            let error: Error = CliError::with_cause(
+                "The Solido instance uses a state version that this program does not understand.",
+                format!(
+                    "Expected version {}, found {:?}. If the version is 0, run 'solido migrate-state' first.",
+                    LIDO_VERSION,
+                    account.data.first(),
+                ),
+            );
+            return Err(error.into());
+        }
         match try_from_slice_unchecked::<Lido>(&account.data) {
             Ok(solido) => Ok(solido),
             Err(err) => {
@@ -325,12 +363,143 @@ impl<'a> Snapshot<'a> {
         }
     }
 
+    /// Read the validator list referenced by the Solido instance, and deserialize it.
+    pub fn get_validators(&mut self, solido: &Lido) -> Result<Validators> {
+        let account = self.get_account(&solido.validator_list)?;
+        match try_from_slice_unchecked::<Validators>(&account.data) {
+            Ok(validators) => Ok(validators),
+            Err(err) => {
+                let error: Error = Box::new(SerializationError {
+                    cause: Some(err.into()),
+                    address: solido.validator_list,
+                    context: format!(
+                        "Failed to deserialize validator list, data length is {} bytes.",
+                        account.data.len()
+                    ),
+                });
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Return the addresses of all stake accounts whose withdraw authority is `withdrawer`.
+    ///
+    /// This calls `GetProgramAccounts` directly, so the set of addresses is not
+    /// part of the snapshot. Read the accounts themselves with [`get_account`]
+    /// afterwards to get a consistent view of them.
+    pub fn get_stake_account_addresses_by_withdrawer(
+        &mut self,
+        withdrawer: &Pubkey,
+    ) -> Result<Vec<Pubkey>> {
+        // A stake account starts with a 4-byte enum tag, followed by the `Meta`
+        // struct, which starts with the 8-byte rent-exempt reserve, followed by
+        // the staker and withdrawer pubkeys.
+        let withdrawer_offset = 4 + 8 + 32;
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp {
+                offset: withdrawer_offset,
+                bytes: MemcmpEncodedBytes::Binary(withdrawer.to_string()),
+                encoding: None,
+            })]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                // We only need the addresses, we read the accounts later.
+                data_slice: Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                }),
+                commitment: None,
+            },
+            with_context: None,
+        };
+        let accounts = self
+            .source
+            .get_program_accounts_with_config(&solana_sdk::stake::program::id(), config)?;
+        Ok(accounts.into_iter().map(|(address, _)| address).collect())
+    }
+
+    /// Return the addresses of all multisig transactions that belong to `multisig_address`.
+    ///
+    /// Like [`get_stake_account_addresses_by_withdrawer`], this calls
+    /// `GetProgramAccounts` directly, so read the transactions themselves with
+    /// [`get_account`] afterwards.
+    pub fn get_multisig_transaction_addresses(
+        &mut self,
+        multisig_program_id: &Pubkey,
+        multisig_address: &Pubkey,
+    ) -> Result<Vec<Pubkey>> {
+        // An Anchor account starts with an 8-byte discriminator, and the
+        // `Transaction` struct starts with the multisig pubkey.
+        let discriminator = serum_multisig::Transaction::discriminator();
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 0,
+                    bytes: MemcmpEncodedBytes::Binary(bs58::encode(discriminator).into_string()),
+                    encoding: None,
+                }),
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: discriminator.len(),
+                    bytes: MemcmpEncodedBytes::Binary(multisig_address.to_string()),
+                    encoding: None,
+                }),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                // We only need the addresses, we read the accounts later.
+                data_slice: Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                }),
+                commitment: None,
+            },
+            with_context: None,
+        };
+        let accounts = self
+            .source
+            .get_program_accounts_with_config(multisig_program_id, config)?;
+        Ok(accounts.into_iter().map(|(address, _)| address).collect())
+    }
+
     /// Return the amount in an SPL token account.
     pub fn get_spl_token_balance(&mut self, address: &Pubkey) -> Result<u64> {
         let account: spl_token::state::Account = self.get_unpack(address)?;
         Ok(account.amount)
     }
 
+    /// Simulate a transaction against the most recent state.
+    ///
+    /// Signatures are not verified, so the transaction does not need to be
+    /// signed, and its blockhash is replaced with a recent one. Unlike sending,
+    /// simulating does not change the on-chain state, so reads after this are
+    /// still fine.
+    pub fn simulate_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<RpcSimulateTransactionResult> {
+        let result = self.source.simulate_transaction(transaction)?;
+        Ok(result)
+    }
+
+    /// Return the statuses of the transactions with the given signatures.
+    ///
+    /// This also finds transactions that are no longer in the status cache of
+    /// the RPC node. The status is `None` for transactions that did not execute
+    /// (yet).
+    pub fn get_signature_statuses(
+        &mut self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>> {
+        let result = self.source.get_signature_statuses(signatures)?;
+        Ok(result)
+    }
+
+    /// Return the fee that a confirmed transaction paid, if the RPC node knows it.
+    pub fn get_transaction_fee(&mut self, signature: &Signature) -> Result<Option<Lamports>> {
+        let result = self.source.get_transaction_fee(signature)?;
+        Ok(result)
+    }
+
     /// Send a transaction without printing to stdout.
     ///
     /// After this, avoid reads from accounts not accessed before. Note, you
@@ -341,19 +510,7 @@ impl<'a> Snapshot<'a> {
         transaction: &Transaction,
     ) -> solana_client::client_error::Result<Signature> {
         *self.sent_transaction = true;
-        let signature = self.rpc_client.send_transaction_with_config(
-            transaction,
-            // We want to preflight transactions against the most recent known
-            // state, even if that state has not been confirmed yet. If we
-            // preflight against an older state, it can happen that some
-            // transaction executed after that state, and the preflight succeeds,
-            // but the real execution fails. Using a more recent state for
-            // preflighting reduces this risk.
-            RpcSendTransactionConfig {
-                preflight_commitment: Some(CommitmentLevel::Processed),
-                ..RpcSendTransactionConfig::default()
-            },
-        )?;
+        let signature = self.source.send_transaction(transaction)?;
 
         // Beware of the Solana footgun: `confirm_transaction` is something
         // completely different from `confirm_transaction_with_spinner`. The
@@ -361,7 +518,7 @@ impl<'a> Snapshot<'a> {
         // confirmed, the latter waits until the transaction is confirmed (and
         // prints the spinner). So here we have to wait manually.
         for _ in 0..32 {
-            let is_confirmed = self.rpc_client.confirm_transaction(&signature)?;
+            let is_confirmed = self.source.confirm_transaction(&signature)?;
             if is_confirmed {
                 return Ok(signature);
             }
@@ -390,50 +547,89 @@ impl<'a> Snapshot<'a> {
         transaction: &Transaction,
     ) -> solana_client::client_error::Result<Signature> {
         *self.sent_transaction = true;
-        let signature = self.rpc_client.send_transaction_with_config(
-            transaction,
-            // See also the comment in `send_and_confirm_transaction`.
-            RpcSendTransactionConfig {
-                preflight_commitment: Some(CommitmentLevel::Processed),
-                ..RpcSendTransactionConfig::default()
-            },
-        )?;
-        self.rpc_client.confirm_transaction_with_spinner(
-            &signature,
-            &transaction.message.recent_blockhash,
-            self.rpc_client.commitment(),
-        )?;
+        let signature = self.source.send_transaction(transaction)?;
+        self.source
+            .confirm_transaction_with_spinner(&signature, &transaction.message.recent_blockhash)?;
         Ok(signature)
     }
 }
 
-/// A wrapper around [`RpcClient`] that enables reading consistent snapshots of multiple accounts.
-pub struct SnapshotClient {
-    rpc_client: RpcClient,
+/// Something that we can read account snapshots from, and send transactions to.
+///
+/// Usually this is an RPC node, see [`RpcSource`]. For testing, the snapshots
+/// can also come from a recording, see [`crate::replay`].
+pub trait SnapshotSource {
+    /// Called once at the start of every [`SnapshotClient::with_snapshot`] call.
+    ///
+    /// All `get_multiple_accounts` calls until the next call to this belong to
+    /// the same snapshot, they differ only in which accounts they query.
+    fn begin_snapshot(&mut self) {}
 
-    /// The initial set of accounts to query.
+    /// Return the accounts at `addresses`, and the slot at which we observed them.
     ///
-    /// We store the set here to reuse it between `with_snapshot` calls, so that
-    /// a next call doesn't need to learn from scratch which accounts we are
-    /// going to access.
-    accounts_to_query: OrderedSet<Pubkey>,
+    /// The slot is `None` if `addresses` is empty.
+    fn get_multiple_accounts(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> std::result::Result<(Vec<Option<Account>>, Option<Slot>), Error>;
 
-    /// Map from validator identity account address to config account address.
-    validator_info_addrs: HashMap<Pubkey, Pubkey>,
+    /// Return a map from validator identity account address to config account address.
+    ///
+    /// See [`crate::validator_info_utils::get_validator_info_accounts`].
+    fn get_validator_info_addrs(&mut self) -> std::result::Result<HashMap<Pubkey, Pubkey>, Error>;
 
-    /// The maximum number of accounts that we can request per `GetMultipleAccounts` call.
+    /// Call `GetProgramAccounts`.
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>>;
+
+    /// Simulate the transaction, without verifying signatures.
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<RpcSimulateTransactionResult>;
+
+    /// Return the statuses of the transactions, including those no longer in the status cache.
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<TransactionStatus>>>;
+
+    /// Return the fee that a confirmed transaction paid, from its status meta.
     ///
-    /// This is an empirical observation: initially we set it to `usize::MAX`,
-    /// and when we get a too-many-accounts error when requesting `n` accounts,
-    /// we set this to `n - 1`, so we should quickly learn an upper bound.
-    max_items_per_call: usize,
+    /// The fee is `None` if the transaction is not confirmed, or the RPC node
+    /// does not have it.
+    fn get_transaction_fee(&self, signature: &Signature) -> ClientResult<Option<Lamports>>;
+
+    /// Send the transaction, without waiting for confirmation.
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature>;
+
+    /// Return whether the transaction is confirmed.
+    fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool>;
+
+    /// Wait for the transaction to be confirmed, show a spinner on stdout.
+    fn confirm_transaction_with_spinner(
+        &self,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+    ) -> ClientResult<()>;
+
+    /// Return the underlying RPC client, if there is one.
+    fn rpc_client(&self) -> Option<&RpcClient>;
+
+    /// Return metrics about the requests made to every RPC endpoint.
+    fn get_endpoint_metrics(&self) -> Vec<EndpointMetrics> {
+        Vec::new()
+    }
 }
 
 /// Return whether a call to `GetMultipleAccounts` failed due to the RPC account limit.
 ///
 /// If this happens, the RPC operator must increase `--rpc-max-multiple-accounts`
 /// on their validator. At the time of writing, it defaults to 100.
-fn is_too_many_inputs_error(error: &ClientError) -> bool {
+pub fn is_too_many_inputs_error(error: &ClientError) -> bool {
     match error.kind() {
         ClientErrorKind::RpcError(RpcError::RpcRequestError(message)) => {
             // Unfortunately, there is no way to get a structured error; all we
@@ -450,17 +646,77 @@ fn is_too_many_inputs_error(error: &ClientError) -> bool {
     }
 }
 
-impl SnapshotClient {
-    pub fn new(rpc_client: RpcClient) -> SnapshotClient {
-        SnapshotClient {
-            rpc_client,
-            accounts_to_query: OrderedSet::new(),
-            validator_info_addrs: HashMap::new(),
+/// How many slots a snapshot can be behind the highest slot that we observed
+/// from any endpoint, before we refuse to use it.
+///
+/// Endpoints are never exactly in sync, and we observe their slots at slightly
+/// different times, so we allow a bit of lag.
+const MAX_SLOT_LAG: Slot = 25;
+
+/// Snapshot source that reads from RPC endpoints.
+///
+/// It can wrap multiple clients for different RPC endpoints, and fails over to
+/// the next endpoint when one is unhealthy.
+pub struct RpcSource {
+    endpoints: Endpoints,
+
+    /// The maximum number of accounts that we can request per `GetMultipleAccounts` call.
+    ///
+    /// This is an empirical observation: initially we set it to `usize::MAX`,
+    /// and when we get a too-many-accounts error when requesting `n` accounts,
+    /// we set this to `n - 1`, so we should quickly learn an upper bound.
+    max_items_per_call: usize,
+}
+
+impl RpcSource {
+    /// Create a source from pairs of a url and a client for that url, in order of preference.
+    pub fn new(endpoints: Vec<(String, RpcClient)>) -> RpcSource {
+        RpcSource {
+            endpoints: Endpoints::new(endpoints),
             max_items_per_call: usize::MAX,
         }
     }
 
-    /// Call `GetMultipleAccounts` to get `self.accounts_to_query`.
+    /// Get the accounts from the most preferred healthy endpoint.
+    ///
+    /// Also returns the slot of the snapshot, if we queried any accounts. If an
+    /// endpoint fails, or its state is too far behind the highest slot that we
+    /// observed, we try the next one.
+    fn get_multiple_accounts_with_failover(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> std::result::Result<(Vec<Option<Account>>, Option<Slot>), crate::error::Error> {
+        if self.endpoints.len() > 1 {
+            self.endpoints.probe_slots();
+        }
+
+        let mut last_error = None;
+        for _ in 0..self.endpoints.len() {
+            let highest_slot = self.endpoints.get_highest_slot();
+            self.endpoints.select();
+            match self.get_multiple_accounts_chunked(addresses) {
+                Ok((_, Some(slot))) if slot + MAX_SLOT_LAG < highest_slot => {
+                    let endpoint = self.endpoints.active();
+                    endpoint.mark_unhealthy();
+                    last_error = Some(CliError::with_cause(
+                        "The state from the RPC endpoint is behind the most recent state we observed.",
+                        format!(
+                            "{} returned a state at slot {}, but we observed slot {} before.",
+                            endpoint.label(),
+                            slot,
+                            highest_slot
+                        ),
+                    ));
+                }
+                Ok(result) => return Ok(result),
+                Err(err) if is_endpoint_failure(&err) => last_error = Some(err.into()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(last_error.expect("There is at least one endpoint, so we tried at least once."))
+    }
+
+    /// Call `GetMultipleAccounts` to get the accounts at `addresses`.
     ///
     /// Ideally, we do a single `GetMultipleAccounts` call for the accounts we
     /// need, and then we have a consistent snapshot. But unfortunately, the
@@ -474,21 +730,28 @@ impl SnapshotClient {
     /// call, `max_items_per_call` (set to `usize::MAX` initially, when this is
     /// unknown). If we learn a tighter upper bound, this function updates the
     /// maximum.
+    ///
+    /// Also returns the lowest slot of the chunks, if we queried any accounts.
     fn get_multiple_accounts_chunked(
         &mut self,
-    ) -> std::result::Result<Vec<Option<Account>>, crate::error::Error> {
+        addresses: &[Pubkey],
+    ) -> solana_client::client_error::Result<(Vec<Option<Account>>, Option<Slot>)> {
         let mut result = Vec::new();
 
         // Handle the empty case first, because otherwise we try to make chunks
         // of length 0 below.
-        if self.accounts_to_query.is_empty() {
-            return Ok(result);
+        if addresses.is_empty() {
+            return Ok((result, None));
         }
 
+        let endpoint = self.endpoints.active();
+        let commitment = endpoint.rpc_client.commitment();
+
         'num_chunks: for num_chunks in 1.. {
             result.clear();
+            let mut min_slot = Slot::MAX;
 
-            let items_per_chunk = self.accounts_to_query.len() / num_chunks;
+            let items_per_chunk = addresses.len() / num_chunks;
             assert!(
                 items_per_chunk > 0,
                 "We should be able to get at least *one* account with GetMultipleAccounts."
@@ -499,20 +762,24 @@ impl SnapshotClient {
                 continue;
             }
 
-            for chunk in self.accounts_to_query.chunks(items_per_chunk) {
-                match self.rpc_client.get_multiple_accounts(chunk) {
-                    Ok(accounts) => {
-                        result.extend(accounts);
+            for chunk in addresses.chunks(items_per_chunk) {
+                match endpoint.call(|rpc_client| {
+                    rpc_client.get_multiple_accounts_with_commitment(chunk, commitment)
+                }) {
+                    Ok(response) => {
+                        endpoint.observe_slot(response.context.slot);
+                        min_slot = min_slot.min(response.context.slot);
+                        result.extend(response.value);
                     }
                     Err(ref err) if is_too_many_inputs_error(err) => {
                         self.max_items_per_call = chunk.len() - 1;
                         continue 'num_chunks;
                     }
-                    Err(err) => return Err(err.into()),
+                    Err(err) => return Err(err),
                 };
             }
 
-            assert_eq!(result.len(), self.accounts_to_query.len());
+            assert_eq!(result.len(), addresses.len());
 
             // Warn every time if this was not a consistent read, but only warn
             // once per successful read.
@@ -526,14 +793,175 @@ impl SnapshotClient {
                     "Please ask the RPC node operator to bump \
                         --rpc-max-multiple-accounts to {}, or connect to a \
                         different RPC node.",
-                    self.accounts_to_query.len()
+                    addresses.len()
                 );
             }
 
-            return Ok(result);
+            return Ok((result, Some(min_slot)));
         }
 
-        unreachable!("Above loop fails the assertion when items_per_chunk > accounts_to_query.len");
+        unreachable!("Above loop fails the assertion when items_per_chunk > addresses.len");
+    }
+}
+
+impl SnapshotSource for RpcSource {
+    fn get_multiple_accounts(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> std::result::Result<(Vec<Option<Account>>, Option<Slot>), Error> {
+        self.get_multiple_accounts_with_failover(addresses)
+    }
+
+    fn get_validator_info_addrs(&mut self) -> std::result::Result<HashMap<Pubkey, Pubkey>, Error> {
+        crate::validator_info_utils::get_validator_info_accounts(
+            &mut self.endpoints.active_mut().rpc_client,
+        )
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.endpoints
+            .active()
+            .call(|rpc_client| rpc_client.get_program_accounts_with_config(program_id, config))
+    }
+
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<RpcSimulateTransactionResult> {
+        let result = self.endpoints.active().call(|rpc_client| {
+            rpc_client.simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    // See also the comment in `send_transaction`.
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+        })?;
+        Ok(result.value)
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<TransactionStatus>>> {
+        let result = self
+            .endpoints
+            .active()
+            .call(|rpc_client| rpc_client.get_signature_statuses_with_history(signatures))?;
+        Ok(result.value)
+    }
+
+    fn get_transaction_fee(&self, signature: &Signature) -> ClientResult<Option<Lamports>> {
+        // `RpcClient::get_transaction_with_config` fails to deserialize the
+        // null result for a transaction that the node does not have, so we
+        // make the request ourselves.
+        let transaction: Option<EncodedConfirmedTransaction> =
+            self.endpoints.active().call(|rpc_client| {
+                rpc_client.send(
+                    RpcRequest::GetTransaction,
+                    serde_json::json!([
+                        signature.to_string(),
+                        RpcTransactionConfig {
+                            encoding: Some(UiTransactionEncoding::Base64),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                        }
+                    ]),
+                )
+            })?;
+        Ok(transaction
+            .and_then(|transaction| transaction.transaction.meta)
+            .map(|meta| Lamports(meta.fee)))
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        self.endpoints.active().call(|rpc_client| {
+            rpc_client.send_transaction_with_config(
+                transaction,
+                // We want to preflight transactions against the most recent known
+                // state, even if that state has not been confirmed yet. If we
+                // preflight against an older state, it can happen that some
+                // transaction executed after that state, and the preflight succeeds,
+                // but the real execution fails. Using a more recent state for
+                // preflighting reduces this risk.
+                RpcSendTransactionConfig {
+                    preflight_commitment: Some(CommitmentLevel::Processed),
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+        })
+    }
+
+    fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool> {
+        self.endpoints
+            .active()
+            .call(|rpc_client| rpc_client.confirm_transaction(signature))
+    }
+
+    fn confirm_transaction_with_spinner(
+        &self,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+    ) -> ClientResult<()> {
+        self.endpoints.active().call(|rpc_client| {
+            rpc_client.confirm_transaction_with_spinner(
+                signature,
+                recent_blockhash,
+                rpc_client.commitment(),
+            )
+        })
+    }
+
+    fn rpc_client(&self) -> Option<&RpcClient> {
+        Some(&self.endpoints.active().rpc_client)
+    }
+
+    fn get_endpoint_metrics(&self) -> Vec<EndpointMetrics> {
+        self.endpoints.get_metrics()
+    }
+}
+
+/// A wrapper around a [`SnapshotSource`] that enables reading consistent snapshots of multiple accounts.
+pub struct SnapshotClient {
+    source: Box<dyn SnapshotSource>,
+
+    /// The initial set of accounts to query.
+    ///
+    /// We store the set here to reuse it between `with_snapshot` calls, so that
+    /// a next call doesn't need to learn from scratch which accounts we are
+    /// going to access.
+    accounts_to_query: OrderedSet<Pubkey>,
+
+    /// Map from validator identity account address to config account address.
+    validator_info_addrs: HashMap<Pubkey, Pubkey>,
+}
+
+impl SnapshotClient {
+    pub fn new(source: Box<dyn SnapshotSource>) -> SnapshotClient {
+        SnapshotClient {
+            source,
+            accounts_to_query: OrderedSet::new(),
+            validator_info_addrs: HashMap::new(),
+        }
+    }
+
+    /// Return the wrapped RPC client, if the source is backed by one.
+    ///
+    /// Use this only for queries that are not about account state, such as
+    /// transaction history. For accounts, use [`with_snapshot`].
+    pub fn rpc_client(&self) -> Option<&RpcClient> {
+        self.source.rpc_client()
+    }
+
+    /// Return metrics about the requests made to every RPC endpoint.
+    pub fn get_endpoint_metrics(&self) -> Vec<EndpointMetrics> {
+        self.source.get_endpoint_metrics()
     }
 
     /// Run the function `f`, which has access to a consistent snapshot of accounts.
@@ -553,8 +981,10 @@ impl SnapshotClient {
     where
         F: FnMut(Snapshot) -> Result<T>,
     {
+        self.source.begin_snapshot();
         loop {
-            let account_values = self.get_multiple_accounts_chunked()?;
+            let (account_values, _slot) =
+                self.source.get_multiple_accounts(&self.accounts_to_query)?;
             let accounts: HashMap<_, _> = self
                 .accounts_to_query
                 .iter()
@@ -569,7 +999,7 @@ impl SnapshotClient {
                 accounts: &accounts,
                 accounts_referenced: &mut accounts_referenced,
                 validator_info_addrs: &self.validator_info_addrs,
-                rpc_client: &self.rpc_client,
+                source: &*self.source,
                 sent_transaction: &mut sent_transaction,
             };
 
@@ -591,10 +1021,7 @@ impl SnapshotClient {
                     // account for, so we need to reload those. After we do,
                     // confirm that the validator identity is there, otherwise
                     // we would get stuck in an infinite loop.
-                    self.validator_info_addrs =
-                        crate::validator_info_utils::get_validator_info_accounts(
-                            &mut self.rpc_client,
-                        )?;
+                    self.validator_info_addrs = self.source.get_validator_info_addrs()?;
 
                     if !self.validator_info_addrs.contains_key(&identity_addr) {
                         return Err(Box::new(MissingValidatorInfoError {
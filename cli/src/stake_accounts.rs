@@ -0,0 +1,501 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Commands for managing the stake accounts that `solido withdraw` returns to users.
+
+use std::fmt;
+
+use serde::Serialize;
+use solana_program::stake::state::{Lockup, Meta, Stake, StakeState};
+use solana_program::{clock::Clock, pubkey::Pubkey, stake_history::StakeHistory};
+use solana_sdk::signature::Signature;
+
+use lido::{stake_account::StakeAccount, state::Validators, token::Lamports, util::serialize_b58};
+
+use crate::config::{DeactivateStakeOpts, MergeStakeOpts, StakeAccountsOpts, WithdrawStakeOpts};
+use crate::error::CliError;
+use crate::snapshot::Result;
+use crate::SnapshotConfig;
+
+/// A delegated stake account, with its balance split by activation state.
+#[derive(Serialize)]
+pub struct UserStakeAccount {
+    #[serde(serialize_with = "serialize_b58")]
+    pub address: Pubkey,
+
+    /// Vote account of the validator that the stake is delegated to.
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_vote_account: Pubkey,
+
+    pub balance: Lamports,
+    pub inactive: Lamports,
+    pub activating: Lamports,
+    pub active: Lamports,
+    pub deactivating: Lamports,
+}
+
+impl UserStakeAccount {
+    fn new(address: Pubkey, stake: &Stake, stake_account: &StakeAccount) -> UserStakeAccount {
+        UserStakeAccount {
+            address,
+            validator_vote_account: stake.delegation.voter_pubkey,
+            balance: stake_account.balance.total(),
+            inactive: stake_account.balance.inactive,
+            activating: stake_account.balance.activating,
+            active: stake_account.balance.active,
+            deactivating: stake_account.balance.deactivating,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct StakeAccountsOutput {
+    pub stake_accounts: Vec<UserStakeAccount>,
+}
+
+impl fmt::Display for StakeAccountsOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.stake_accounts.is_empty() {
+            writeln!(f, "No stake accounts found.")?;
+        }
+        for account in &self.stake_accounts {
+            writeln!(f, "\nStake account {}:", account.address)?;
+            writeln!(f, "  Validator:    {}", account.validator_vote_account)?;
+            writeln!(f, "  Balance:      {}", account.balance)?;
+            writeln!(f, "  Inactive:     {}", account.inactive)?;
+            writeln!(f, "  Activating:   {}", account.activating)?;
+            writeln!(f, "  Active:       {}", account.active)?;
+            writeln!(f, "  Deactivating: {}", account.deactivating)?;
+        }
+        Ok(())
+    }
+}
+
+/// A stake account read from the snapshot, with its balance at the current epoch.
+struct DelegatedStakeAccount {
+    lamports: Lamports,
+    meta: Meta,
+    stake: Stake,
+    stake_account: StakeAccount,
+}
+
+/// Read a delegated stake account, and compute its activation state.
+fn get_delegated_stake_account(
+    config: &mut SnapshotConfig,
+    clock: &Clock,
+    stake_history: &StakeHistory,
+    address: &Pubkey,
+) -> Result<Option<DelegatedStakeAccount>> {
+    let account = config.client.get_account(address)?;
+    match bincode::deserialize::<StakeState>(&account.data) {
+        Ok(StakeState::Stake(meta, stake)) => {
+            let lamports = Lamports(account.lamports);
+            // The seed is only relevant for Solido's own stake accounts.
+            let stake_account =
+                StakeAccount::from_delegated_account(lamports, &stake, clock, stake_history, 0);
+            Ok(Some(DelegatedStakeAccount {
+                lamports,
+                meta,
+                stake,
+                stake_account,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Like [`get_delegated_stake_account`], but fail if the account is not delegated.
+fn get_user_stake_account(
+    config: &mut SnapshotConfig,
+    address: &Pubkey,
+) -> Result<DelegatedStakeAccount> {
+    let clock = config.client.get_clock()?;
+    let stake_history = config.client.get_stake_history()?;
+    match get_delegated_stake_account(config, &clock, &stake_history, address)? {
+        Some(account) => Ok(account),
+        None => Err(
+            CliError::with_cause("The account is not a delegated stake account.", address).into(),
+        ),
+    }
+}
+
+/// Return whether the stake account looks like one that `Withdraw` created for `owner`.
+///
+/// `Withdraw` splits the stake off one of Solido's stake accounts, which
+/// delegate to a Solido validator and have no lockup, and then it sets both the
+/// stake authority and the withdraw authority to the owner of the stSOL. A
+/// stake account that the user delegated to a Solido validator in some other
+/// way can still match this, but stake accounts with a different stake
+/// authority, or with a lockup, did not originate from Solido.
+fn is_withdrawn_from_solido(
+    meta: &Meta,
+    stake: &Stake,
+    owner: &Pubkey,
+    validators: &Validators,
+) -> bool {
+    meta.authorized.staker == *owner
+        && meta.authorized.withdrawer == *owner
+        && meta.lockup == Lockup::default()
+        && validators.get(&stake.delegation.voter_pubkey).is_ok()
+}
+
+/// CLI entry point to list the signer's stake accounts that delegate to Solido validators.
+pub fn command_stake_accounts(
+    config: &mut SnapshotConfig,
+    opts: &StakeAccountsOpts,
+) -> Result<StakeAccountsOutput> {
+    let solido = config.client.get_solido(opts.solido_address())?;
+    let validators = config.client.get_validators(&solido)?;
+    let clock = config.client.get_clock()?;
+    let stake_history = config.client.get_stake_history()?;
+
+    let addresses = config
+        .client
+        .get_stake_account_addresses_by_withdrawer(&config.signer.pubkey())?;
+
+    let mut stake_accounts = Vec::new();
+    for address in addresses {
+        let account = match get_delegated_stake_account(config, &clock, &stake_history, &address)? {
+            Some(account) => account,
+            None => continue,
+        };
+        // The user may have other stake accounts, but those are not our business.
+        if !is_withdrawn_from_solido(
+            &account.meta,
+            &account.stake,
+            &config.signer.pubkey(),
+            &validators,
+        ) {
+            continue;
+        }
+        stake_accounts.push(UserStakeAccount::new(
+            address,
+            &account.stake,
+            &account.stake_account,
+        ));
+    }
+
+    Ok(StakeAccountsOutput { stake_accounts })
+}
+
+#[derive(Serialize)]
+pub struct DeactivateStakeOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub stake_account: Pubkey,
+
+    /// Amount that will become inactive at the end of the deactivation.
+    pub deactivating: Lamports,
+
+    pub transaction_id: Signature,
+}
+
+impl fmt::Display for DeactivateStakeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Deactivated stake account {}.", self.stake_account)?;
+        writeln!(f, "Stake that is deactivating: {}", self.deactivating)?;
+        writeln!(f, "Transaction id:             {}", self.transaction_id)?;
+        Ok(())
+    }
+}
+
+/// CLI entry point to deactivate one of the signer's stake accounts.
+pub fn command_deactivate_stake(
+    config: &mut SnapshotConfig,
+    opts: &DeactivateStakeOpts,
+) -> Result<DeactivateStakeOutput> {
+    let account = get_user_stake_account(config, opts.stake_account())?;
+
+    if account.meta.authorized.staker != config.signer.pubkey() {
+        return Err(CliError::with_cause(
+            "The signer is not the stake authority of this stake account.",
+            format!("The stake authority is {}.", account.meta.authorized.staker),
+        )
+        .into());
+    }
+    if account.stake.delegation.deactivation_epoch != u64::MAX {
+        return Err(CliError::with_cause(
+            "The stake account is already deactivated.",
+            format!(
+                "Deactivation started in epoch {}.",
+                account.stake.delegation.deactivation_epoch
+            ),
+        )
+        .into());
+    }
+
+    let instr = solana_program::stake::instruction::deactivate_stake(
+        opts.stake_account(),
+        &config.signer.pubkey(),
+    );
+    let transaction_id = config.sign_and_send_transaction(&[instr], &[config.signer])?;
+
+    let deactivating = (account.stake_account.balance.active
+        + account.stake_account.balance.activating)
+        .expect("Does not overflow, it is at most the stake account balance.");
+    Ok(DeactivateStakeOutput {
+        stake_account: *opts.stake_account(),
+        deactivating,
+        transaction_id,
+    })
+}
+
+#[derive(Serialize)]
+pub struct WithdrawStakeOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub stake_account: Pubkey,
+
+    /// Address that received the SOL, this is the signer.
+    #[serde(serialize_with = "serialize_b58")]
+    pub recipient: Pubkey,
+
+    pub withdrawn: Lamports,
+
+    /// Whether we withdrew the full balance, which deletes the stake account.
+    pub closed_stake_account: bool,
+
+    pub transaction_id: Signature,
+}
+
+impl fmt::Display for WithdrawStakeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Withdrew {} from stake account {} to {}.",
+            self.withdrawn, self.stake_account, self.recipient
+        )?;
+        if self.closed_stake_account {
+            writeln!(f, "The stake account is now empty and has been closed.")?;
+        }
+        writeln!(f, "Transaction id: {}", self.transaction_id)?;
+        Ok(())
+    }
+}
+
+/// CLI entry point to move the inactive SOL in one of the signer's stake accounts to the signer.
+pub fn command_withdraw_stake(
+    config: &mut SnapshotConfig,
+    opts: &WithdrawStakeOpts,
+) -> Result<WithdrawStakeOutput> {
+    let account = get_user_stake_account(config, opts.stake_account())?;
+
+    if account.meta.authorized.withdrawer != config.signer.pubkey() {
+        return Err(CliError::with_cause(
+            "The signer is not the withdraw authority of this stake account.",
+            format!(
+                "The withdraw authority is {}.",
+                account.meta.authorized.withdrawer
+            ),
+        )
+        .into());
+    }
+
+    // When the account is fully inactive, we can withdraw everything, and the
+    // stake program deletes the account. Otherwise, the rent-exempt reserve
+    // must stay behind, next to the stake.
+    let closed_stake_account = account.stake_account.is_inactive();
+    let amount = if closed_stake_account {
+        account.lamports
+    } else {
+        Lamports(
+            account
+                .stake_account
+                .balance
+                .inactive
+                .0
+                .saturating_sub(account.meta.rent_exempt_reserve),
+        )
+    };
+
+    if amount == Lamports(0) {
+        return Err(CliError::with_cause(
+            "There is no inactive SOL in this stake account.",
+            "Deactivate the stake account with 'solido deactivate-stake', and \
+            wait for the deactivation to complete at the end of the epoch.",
+        )
+        .into());
+    }
+
+    let recipient = config.signer.pubkey();
+    let instr = solana_program::stake::instruction::withdraw(
+        opts.stake_account(),
+        &recipient,
+        &recipient,
+        amount.0,
+        None,
+    );
+    let transaction_id = config.sign_and_send_transaction(&[instr], &[config.signer])?;
+
+    Ok(WithdrawStakeOutput {
+        stake_account: *opts.stake_account(),
+        recipient,
+        withdrawn: amount,
+        closed_stake_account,
+        transaction_id,
+    })
+}
+
+#[derive(Serialize)]
+pub struct MergeStakeOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub source_stake_account: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub destination_stake_account: Pubkey,
+
+    /// Balance of the destination stake account after the merge.
+    pub balance: Lamports,
+
+    pub transaction_id: Signature,
+}
+
+impl fmt::Display for MergeStakeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Merged stake account {} into {}.",
+            self.source_stake_account, self.destination_stake_account
+        )?;
+        writeln!(f, "New balance:    {}", self.balance)?;
+        writeln!(f, "Transaction id: {}", self.transaction_id)?;
+        Ok(())
+    }
+}
+
+/// CLI entry point to merge two of the signer's stake accounts.
+pub fn command_merge_stake(
+    config: &mut SnapshotConfig,
+    opts: &MergeStakeOpts,
+) -> Result<MergeStakeOutput> {
+    let source = get_user_stake_account(config, opts.source_stake_account())?;
+    let destination = get_user_stake_account(config, opts.destination_stake_account())?;
+
+    for account in [&source, &destination].iter() {
+        if account.meta.authorized.staker != config.signer.pubkey() {
+            return Err(CliError::with_cause(
+                "The signer is not the stake authority of both stake accounts.",
+                format!("The stake authority is {}.", account.meta.authorized.staker),
+            )
+            .into());
+        }
+    }
+
+    if source.stake.delegation.voter_pubkey != destination.stake.delegation.voter_pubkey {
+        return Err(CliError::with_cause(
+            "Can only merge stake accounts that delegate to the same validator.",
+            format!(
+                "The source delegates to {}, the destination to {}.",
+                source.stake.delegation.voter_pubkey, destination.stake.delegation.voter_pubkey,
+            ),
+        )
+        .into());
+    }
+
+    if !destination.stake_account.can_merge(&source.stake_account) {
+        return Err(CliError::with_cause(
+            "The stake accounts cannot be merged in their current activation state.",
+            format!(
+                "Source: {:?}, destination: {:?}.",
+                source.stake_account.balance, destination.stake_account.balance,
+            ),
+        )
+        .into());
+    }
+
+    let merge_instructions = solana_program::stake::instruction::merge(
+        opts.destination_stake_account(),
+        opts.source_stake_account(),
+        &config.signer.pubkey(),
+    );
+    let transaction_id = config.sign_and_send_transaction(&merge_instructions, &[config.signer])?;
+
+    Ok(MergeStakeOutput {
+        source_stake_account: *opts.source_stake_account(),
+        destination_stake_account: *opts.destination_stake_account(),
+        balance: (source.lamports + destination.lamports)
+            .expect("Does not overflow, there is not that much SOL in existence."),
+        transaction_id,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use lido::state::Validator;
+    use solana_program::stake::state::{Authorized, Delegation};
+
+    /// Return a stake account as `Withdraw` leaves it for `owner`, and the
+    /// validator list that contains the validator it delegates to.
+    fn new_withdrawn_stake_account(owner: &Pubkey) -> (Meta, Stake, Validators) {
+        let vote_account = Pubkey::new_unique();
+        let mut validators = Validators::new(1);
+        validators
+            .add(vote_account, Validator::new(Pubkey::new_unique()))
+            .unwrap();
+        let meta = Meta {
+            authorized: Authorized::auto(owner),
+            ..Meta::default()
+        };
+        let stake = Stake {
+            delegation: Delegation {
+                voter_pubkey: vote_account,
+                stake: 1_000_000_000,
+                ..Delegation::default()
+            },
+            ..Stake::default()
+        };
+        (meta, stake, validators)
+    }
+
+    #[test]
+    fn test_is_withdrawn_from_solido_matches_withdrawn_stake_account() {
+        let owner = Pubkey::new_unique();
+        let (meta, stake, validators) = new_withdrawn_stake_account(&owner);
+        assert!(is_withdrawn_from_solido(&meta, &stake, &owner, &validators));
+    }
+
+    #[test]
+    fn test_is_withdrawn_from_solido_rejects_other_stake_accounts() {
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let (meta, stake, validators) = new_withdrawn_stake_account(&owner);
+
+        // The stake authority must also be the owner.
+        let mut other_staker = meta;
+        other_staker.authorized.staker = other;
+        assert!(!is_withdrawn_from_solido(
+            &other_staker,
+            &stake,
+            &owner,
+            &validators
+        ));
+
+        // Stake accounts that we don't own are not ours to list.
+        assert!(!is_withdrawn_from_solido(
+            &meta,
+            &stake,
+            &other,
+            &validators
+        ));
+
+        // Solido's stake accounts have no lockup, so splits of them don't either.
+        let mut locked = meta;
+        locked.lockup.epoch = 10;
+        assert!(!is_withdrawn_from_solido(
+            &locked,
+            &stake,
+            &owner,
+            &validators
+        ));
+
+        // The stake must be delegated to a Solido validator.
+        let mut other_validator = stake;
+        other_validator.delegation.voter_pubkey = other;
+        assert!(!is_withdrawn_from_solido(
+            &meta,
+            &other_validator,
+            &owner,
+            &validators
+        ));
+    }
+}
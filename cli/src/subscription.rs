@@ -0,0 +1,340 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Push-based notifications of on-chain changes, over the websocket RPC.
+//!
+//! The `PubsubClient` in `solana-client` does not support `accountSubscribe`,
+//! and it needs one connection per subscription. We watch many accounts, so we
+//! subscribe to all of them over a single connection instead. The subscriptions
+//! are fixed for the lifetime of the connection; to watch a different set of
+//! accounts, connect a new [`AccountWatcher`].
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use tungstenite::Message;
+
+use crate::error::{CliError, Error};
+
+/// Something that happened on-chain that we subscribed to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Notification {
+    /// One of the watched accounts changed.
+    AccountChanged(Pubkey),
+
+    /// A new slot was processed.
+    Slot(Slot),
+}
+
+/// What a subscription request subscribes to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    Account(Pubkey),
+    Slot,
+}
+
+/// Tracks subscriptions on a single connection, and turns the messages that we
+/// receive over it into notifications.
+struct Subscriptions {
+    /// Target of every subscription request, by request id.
+    requests: HashMap<u64, Target>,
+
+    /// Target of every confirmed subscription, by subscription id.
+    subscriptions: HashMap<u64, Target>,
+}
+
+impl Subscriptions {
+    /// Return the subscription requests to send, and the state to track them.
+    fn new(addresses: &[Pubkey]) -> (Vec<String>, Subscriptions) {
+        let mut requests = HashMap::new();
+        let mut bodies = Vec::with_capacity(addresses.len() + 1);
+
+        requests.insert(0, Target::Slot);
+        bodies.push(json!({"jsonrpc": "2.0", "id": 0, "method": "slotSubscribe"}).to_string());
+
+        for (i, address) in addresses.iter().enumerate() {
+            let id = i as u64 + 1;
+            requests.insert(id, Target::Account(*address));
+            bodies.push(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "accountSubscribe",
+                    "params": [
+                        address.to_string(),
+                        {"encoding": "base64", "commitment": "confirmed"},
+                    ],
+                })
+                .to_string(),
+            );
+        }
+
+        let subscriptions = Subscriptions {
+            requests,
+            subscriptions: HashMap::new(),
+        };
+        (bodies, subscriptions)
+    }
+
+    /// Handle a message received over the websocket.
+    ///
+    /// Returns a notification if the message was one, an error if the server
+    /// rejected a subscription.
+    fn handle_message(&mut self, text: &str) -> std::result::Result<Option<Notification>, String> {
+        let message: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+
+        // A response to one of our subscription requests.
+        if let Some(request_id) = message.get("id").and_then(Value::as_u64) {
+            let target = self
+                .requests
+                .remove(&request_id)
+                .ok_or_else(|| format!("Response to unknown request: {}", text))?;
+            return match message.get("result").and_then(Value::as_u64) {
+                Some(subscription_id) => {
+                    self.subscriptions.insert(subscription_id, target);
+                    Ok(None)
+                }
+                None => Err(format!("Subscription for {:?} failed: {}", target, text)),
+            };
+        }
+
+        let params = match message.get("params") {
+            Some(params) => params,
+            None => return Ok(None),
+        };
+        let target = params
+            .get("subscription")
+            .and_then(Value::as_u64)
+            .and_then(|id| self.subscriptions.get(&id));
+        match (message.get("method").and_then(Value::as_str), target) {
+            (Some("accountNotification"), Some(Target::Account(address))) => {
+                Ok(Some(Notification::AccountChanged(*address)))
+            }
+            (Some("slotNotification"), Some(Target::Slot)) => Ok(params
+                .get("result")
+                .and_then(|result| result.get("slot"))
+                .and_then(Value::as_u64)
+                .map(Notification::Slot)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Watches a set of accounts and the slot over a websocket connection.
+pub struct AccountWatcher {
+    /// The accounts that we watch, in the order that they were passed to `connect`.
+    addresses: Vec<Pubkey>,
+
+    /// Notifications from the thread that reads the websocket, or an error
+    /// when the connection broke. The thread stops after the receiver is dropped.
+    receiver: Receiver<std::result::Result<Notification, String>>,
+}
+
+/// Why [`AccountWatcher::wait`] returned.
+#[derive(Debug, Eq, PartialEq)]
+pub enum WakeReason {
+    AccountChanged(Pubkey),
+    ReachedSlot(Slot),
+    Timeout,
+    Disconnected(String),
+}
+
+impl AccountWatcher {
+    /// Connect to the websocket RPC, and subscribe to the accounts and the slot.
+    pub fn connect(
+        url: &str,
+        addresses: Vec<Pubkey>,
+    ) -> std::result::Result<AccountWatcher, Error> {
+        let (mut socket, _response) = tungstenite::connect(url)
+            .map_err(|err| CliError::with_cause("Failed to connect to the websocket RPC.", err))?;
+
+        let (bodies, mut subscriptions) = Subscriptions::new(&addresses);
+        for body in bodies {
+            socket
+                .write_message(Message::Text(body))
+                .map_err(|err| CliError::with_cause("Failed to subscribe over websocket.", err))?;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("account_watcher".to_string())
+            .spawn(move || loop {
+                let result = match socket.read_message() {
+                    Ok(Message::Text(text)) => subscriptions.handle_message(&text),
+                    Ok(Message::Close(..)) => Err("The server closed the connection.".to_string()),
+                    // Tungstenite responds to pings for us, and we expect no binary messages.
+                    Ok(..) => Ok(None),
+                    Err(err) => Err(err.to_string()),
+                };
+                let is_err = result.is_err();
+                if let Some(notification) = result.transpose() {
+                    if sender.send(notification).is_err() {
+                        // The watcher was dropped, nobody is interested any more.
+                        let _ = socket.close(None);
+                        break;
+                    }
+                }
+                if is_err {
+                    break;
+                }
+            })
+            .expect("Failed to spawn account watcher thread.");
+
+        Ok(AccountWatcher {
+            addresses,
+            receiver,
+        })
+    }
+
+    /// Return the accounts that this watcher watches.
+    pub fn addresses(&self) -> &[Pubkey] {
+        &self.addresses
+    }
+
+    /// Discard notifications that we received so far.
+    pub fn drain(&self) {
+        while let Ok(Ok(..)) = self.receiver.try_recv() {}
+    }
+
+    /// Wait until one of the watched accounts changes, or the slot reaches
+    /// `wake_at_slot`, but no longer than `timeout`.
+    pub fn wait(&self, timeout: Duration, wake_at_slot: Slot) -> WakeReason {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let time_left = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(time_left) {
+                Ok(Ok(Notification::AccountChanged(address))) => {
+                    return WakeReason::AccountChanged(address)
+                }
+                Ok(Ok(Notification::Slot(slot))) if slot >= wake_at_slot => {
+                    return WakeReason::ReachedSlot(slot)
+                }
+                Ok(Ok(Notification::Slot(..))) => continue,
+                Ok(Err(err)) => return WakeReason::Disconnected(err),
+                Err(RecvTimeoutError::Timeout) => return WakeReason::Timeout,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return WakeReason::Disconnected("The connection was closed.".to_string())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn subscriptions_map_notifications_to_accounts() {
+        let address = Pubkey::new_unique();
+        let (bodies, mut subscriptions) = Subscriptions::new(&[address]);
+        assert_eq!(bodies.len(), 2);
+
+        // Confirm both subscriptions, with subscription ids that differ from
+        // the request ids.
+        assert_eq!(
+            subscriptions.handle_message(r#"{"jsonrpc":"2.0","result":7,"id":0}"#),
+            Ok(None)
+        );
+        assert_eq!(
+            subscriptions.handle_message(r#"{"jsonrpc":"2.0","result":8,"id":1}"#),
+            Ok(None)
+        );
+
+        assert_eq!(
+            subscriptions.handle_message(
+                r#"{"jsonrpc":"2.0","method":"accountNotification","params":{"result":{},"subscription":8}}"#
+            ),
+            Ok(Some(Notification::AccountChanged(address)))
+        );
+        assert_eq!(
+            subscriptions.handle_message(
+                r#"{"jsonrpc":"2.0","method":"slotNotification","params":{"result":{"parent":41,"root":10,"slot":42},"subscription":7}}"#
+            ),
+            Ok(Some(Notification::Slot(42)))
+        );
+
+        // Notifications for subscriptions we don't know are ignored.
+        assert_eq!(
+            subscriptions.handle_message(
+                r#"{"jsonrpc":"2.0","method":"accountNotification","params":{"result":{},"subscription":9}}"#
+            ),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn subscriptions_report_rejected_requests() {
+        let (_bodies, mut subscriptions) = Subscriptions::new(&[Pubkey::new_unique()]);
+        assert!(subscriptions
+            .handle_message(
+                r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params"},"id":1}"#
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn account_watcher_wakes_on_account_change() {
+        let address = Pubkey::new_unique();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // A stand-in for the websocket RPC, that confirms the subscriptions,
+        // and then sends a slot notification and an account notification, and
+        // when we ask for it, a notification for a later slot.
+        let (send_next_slot, next_slot_requested) = mpsc::channel();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            for subscription_id in 0..2 {
+                let request: Value =
+                    serde_json::from_str(&socket.read_message().unwrap().into_text().unwrap())
+                        .unwrap();
+                let response =
+                    json!({"jsonrpc": "2.0", "result": subscription_id, "id": request["id"]});
+                socket
+                    .write_message(Message::Text(response.to_string()))
+                    .unwrap();
+            }
+            let slot = json!({"jsonrpc": "2.0", "method": "slotNotification", "params": {"result": {"slot": 5}, "subscription": 0}});
+            let account = json!({"jsonrpc": "2.0", "method": "accountNotification", "params": {"result": {}, "subscription": 1}});
+            socket
+                .write_message(Message::Text(slot.to_string()))
+                .unwrap();
+            socket
+                .write_message(Message::Text(account.to_string()))
+                .unwrap();
+            next_slot_requested.recv().unwrap();
+            let slot = json!({"jsonrpc": "2.0", "method": "slotNotification", "params": {"result": {"slot": 100}, "subscription": 0}});
+            socket
+                .write_message(Message::Text(slot.to_string()))
+                .unwrap();
+        });
+
+        let watcher = AccountWatcher::connect(&url, vec![address]).ok().unwrap();
+        assert_eq!(watcher.addresses(), &[address]);
+
+        // Slot 5 is before the slot we wait for, so the slot notification does
+        // not wake us, but the account change does.
+        assert_eq!(
+            watcher.wait(Duration::from_secs(10), 100),
+            WakeReason::AccountChanged(address)
+        );
+        assert_eq!(
+            watcher.wait(Duration::from_millis(10), 100),
+            WakeReason::Timeout
+        );
+
+        send_next_slot.send(()).unwrap();
+        assert_eq!(
+            watcher.wait(Duration::from_secs(10), 100),
+            WakeReason::ReachedSlot(100)
+        );
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Fixtures shared by the tests of several modules.
+
+use std::time::SystemTime;
+
+use solana_program::{
+    clock::Clock, epoch_schedule::EpochSchedule, pubkey::Pubkey, rent::Rent,
+    stake_history::StakeHistory,
+};
+use solana_sdk::account::Account;
+use spl_token::state::Mint;
+
+use lido::state::{Lido, Validators};
+
+use crate::config::{RebalancingConfig, StakeTime};
+use crate::maintenance::SolidoState;
+
+/// Produce a new state with `default` Solido instance in it, and random pubkeys.
+pub fn new_empty_solido() -> SolidoState {
+    let mut state = SolidoState {
+        produced_at: SystemTime::UNIX_EPOCH,
+        solido_program_id: Pubkey::new_unique(),
+        solido_address: Pubkey::new_unique(),
+        solido: Lido::default(),
+        validators: Validators::default(),
+        validator_stake_accounts: vec![],
+        validator_unstake_accounts: vec![],
+        validator_vote_account_balances: vec![],
+        validator_vote_accounts: vec![],
+        validator_identity_account_balances: vec![],
+        validator_infos: vec![],
+        maintainer_balances: vec![],
+        st_sol_mint: Mint::default(),
+        reserve_address: Pubkey::new_unique(),
+        reserve_account: Account::default(),
+        rent: Rent::default(),
+        clock: Clock::default(),
+        epoch_schedule: EpochSchedule::default(),
+        stake_history: StakeHistory::default(),
+        maintainer_address: Pubkey::new_unique(),
+        stake_time: StakeTime::Anytime,
+        rebalancing: RebalancingConfig::default(),
+    };
+
+    // The reserve should be rent-exempt.
+    state.reserve_account.lamports = state.rent.minimum_balance(0);
+
+    state
+}
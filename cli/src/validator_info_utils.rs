@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use solana_account_decoder::validator_info;
 use solana_client::rpc_client::RpcClient;
 use solana_config_program::ConfigKeys;
+use solana_sdk::account::Account;
 use solana_sdk::pubkey::Pubkey;
 
 use crate::error::{Error, SerializationError};
@@ -14,7 +15,7 @@ use crate::error::{Error, SerializationError};
 type Result<T> = std::result::Result<T, Error>;
 
 /// Validator metadata stored in a config account managed by the config program.
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct ValidatorInfo {
     pub name: String,
 
@@ -89,6 +90,15 @@ pub fn get_validator_info_accounts(rpc_client: &mut RpcClient) -> Result<HashMap
     use solana_sdk::config::program as config_program;
 
     let all_config_accounts = rpc_client.get_program_accounts(&config_program::id())?;
+    Ok(find_validator_info_accounts(&all_config_accounts))
+}
+
+/// Build the map from identity account to config account, from all config accounts.
+///
+/// See [`get_validator_info_accounts`].
+pub fn find_validator_info_accounts(
+    all_config_accounts: &[(Pubkey, Account)],
+) -> HashMap<Pubkey, Pubkey> {
     let mut mapping = HashMap::new();
 
     // Due to the structure of validator info (config accounts pointing to identity
@@ -98,7 +108,7 @@ pub fn get_validator_info_accounts(rpc_client: &mut RpcClient) -> Result<HashMap
     // ignore all validator infos for that identity.
     let mut bad_identities = HashSet::new();
 
-    for (config_addr, account) in &all_config_accounts {
+    for (config_addr, account) in all_config_accounts {
         if let Ok((validator_identity, _info)) =
             deserialize_validator_info(*config_addr, &account.data)
         {
@@ -121,7 +131,7 @@ pub fn get_validator_info_accounts(rpc_client: &mut RpcClient) -> Result<HashMap
         mapping.remove(bad_identity);
     }
 
-    Ok(mapping)
+    mapping
 }
 
 #[cfg(test)]
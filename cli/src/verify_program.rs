@@ -0,0 +1,261 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! Compare on-chain programs against a local build.
+//!
+//! The upgradeable BPF loader stores a program behind a header, in a buffer
+//! account before an upgrade, and in a program data account after. Both
+//! accounts can be larger than the program itself, in which case the remainder
+//! is zero-filled. We strip the header and the zero padding, and compare what
+//! is left byte for byte against a local `.so` file.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+
+use lido::util::serialize_b58;
+
+use crate::config::VerifyProgramOpts;
+use crate::error::CliError;
+use crate::snapshot::Result;
+use crate::SnapshotConfig;
+
+#[derive(Serialize)]
+pub struct ProgramVerification {
+    /// The buffer or program data account that holds the on-chain program.
+    #[serde(serialize_with = "serialize_b58")]
+    pub account: Pubkey,
+
+    /// SHA256 of the program, in hex, like `sha256sum` prints it.
+    pub on_chain_sha256: String,
+    pub on_chain_len: usize,
+
+    pub local_sha256: String,
+    pub local_len: usize,
+
+    /// Offset of the first byte that differs, if the programs differ.
+    ///
+    /// When one program is a prefix of the other, this is the length of the
+    /// shorter one.
+    pub first_difference: Option<usize>,
+}
+
+impl ProgramVerification {
+    pub fn is_match(&self) -> bool {
+        self.first_difference.is_none()
+    }
+}
+
+impl fmt::Display for ProgramVerification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    On-chain account: {}", self.account)?;
+        writeln!(
+            f,
+            "    On-chain SHA256:  {} ({} bytes)",
+            self.on_chain_sha256, self.on_chain_len
+        )?;
+        writeln!(
+            f,
+            "    Local SHA256:     {} ({} bytes)",
+            self.local_sha256, self.local_len
+        )?;
+        match self.first_difference {
+            None => writeln!(f, "    The on-chain program matches the local build.")?,
+            Some(offset) => writeln!(
+                f,
+                "    MISMATCH: The programs differ, starting at byte offset {}.",
+                offset
+            )?,
+        }
+        Ok(())
+    }
+}
+
+/// Return the hex-encoded SHA256 hash of the data.
+fn sha256_hex(data: &[u8]) -> String {
+    hash(data)
+        .to_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Strip trailing zero bytes.
+///
+/// If the program itself ends in zero bytes, those are stripped too, so to
+/// compare with a local build, use [`strip_padding_like`] instead.
+fn strip_padding(program: &[u8]) -> &[u8] {
+    let len = program
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |last_nonzero| last_nonzero + 1);
+    &program[..len]
+}
+
+/// Return the on-chain program bytes, with the padding beyond the length of
+/// `local` stripped, if that padding consists of zeros only.
+fn strip_padding_like<'a>(on_chain: &'a [u8], local: &[u8]) -> &'a [u8] {
+    if on_chain.len() >= local.len() && on_chain[local.len()..].iter().all(|&b| b == 0) {
+        &on_chain[..local.len()]
+    } else {
+        strip_padding(on_chain)
+    }
+}
+
+/// Compare the program bytes stored after `header_len` bytes of `account_data`
+/// against the contents of the local program.
+pub fn compare_program(
+    account: Pubkey,
+    account_data: &[u8],
+    header_len: usize,
+    local_program: &[u8],
+) -> ProgramVerification {
+    let on_chain_program = account_data.get(header_len..).unwrap_or(&[]);
+    let on_chain_program = strip_padding_like(on_chain_program, local_program);
+
+    let first_difference = on_chain_program
+        .iter()
+        .zip(local_program)
+        .position(|(a, b)| a != b)
+        .or_else(|| {
+            if on_chain_program.len() == local_program.len() {
+                None
+            } else {
+                Some(on_chain_program.len().min(local_program.len()))
+            }
+        });
+
+    ProgramVerification {
+        account,
+        on_chain_sha256: sha256_hex(on_chain_program),
+        on_chain_len: on_chain_program.len(),
+        local_sha256: sha256_hex(local_program),
+        local_len: local_program.len(),
+        first_difference,
+    }
+}
+
+fn read_local_program(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|err| {
+        CliError::with_cause(
+            "Failed to read the local program.",
+            format!("{}: {}", path.display(), err),
+        )
+        .into()
+    })
+}
+
+/// Compare the program in an upgradeable loader buffer account against a local build.
+pub fn verify_buffer(
+    config: &mut SnapshotConfig,
+    buffer_address: &Pubkey,
+    local_program_path: &Path,
+) -> Result<ProgramVerification> {
+    let local_program = read_local_program(local_program_path)?;
+    if !config.client.account_exists(buffer_address)? {
+        return Err(CliError::with_cause(
+            "The buffer account does not exist.",
+            format!(
+                "Buffer {} was closed, or consumed by executing the upgrade. \
+                Use 'solido verify-program' to check a deployed program.",
+                buffer_address,
+            ),
+        )
+        .into());
+    }
+    let buffer = config.client.get_account(buffer_address)?;
+    let header_len = UpgradeableLoaderState::buffer_data_offset()?;
+    Ok(compare_program(
+        *buffer_address,
+        &buffer.data,
+        header_len,
+        &local_program,
+    ))
+}
+
+/// Compare the currently deployed program against a local build.
+pub fn verify_deployed_program(
+    config: &mut SnapshotConfig,
+    program_id: &Pubkey,
+    local_program_path: &Path,
+) -> Result<ProgramVerification> {
+    let local_program = read_local_program(local_program_path)?;
+    let program_account = config.client.get_account(program_id)?;
+    if program_account.owner != bpf_loader_upgradeable::id() {
+        return Err(CliError::with_cause(
+            "The program is not owned by the upgradeable BPF loader.",
+            format!(
+                "Program {} is owned by {}.",
+                program_id, program_account.owner
+            ),
+        )
+        .into());
+    }
+    let programdata_address = match bincode::deserialize(&program_account.data)? {
+        UpgradeableLoaderState::Program {
+            programdata_address,
+        } => programdata_address,
+        _ => {
+            return Err(CliError::with_cause(
+                "The account is not an upgradeable program.",
+                format!("Account {} does not hold a program.", program_id),
+            )
+            .into())
+        }
+    };
+    let programdata = config.client.get_account(&programdata_address)?;
+    let header_len = UpgradeableLoaderState::programdata_data_offset()?;
+    Ok(compare_program(
+        programdata_address,
+        &programdata.data,
+        header_len,
+        &local_program,
+    ))
+}
+
+pub fn command_verify_program(
+    config: &mut SnapshotConfig,
+    opts: &VerifyProgramOpts,
+) -> Result<ProgramVerification> {
+    verify_deployed_program(config, opts.program_id(), opts.verify_against())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compare_program_strips_header_and_padding() {
+        let account = Pubkey::new_unique();
+        let local = [1, 2, 3, 0, 5, 0];
+        let mut data = vec![9; 4];
+        data.extend_from_slice(&local);
+        data.extend_from_slice(&[0; 10]);
+
+        let result = compare_program(account, &data, 4, &local);
+        assert!(result.is_match());
+        assert_eq!(result.on_chain_sha256, result.local_sha256);
+        assert_eq!(result.on_chain_len, local.len());
+
+        // A byte that differs is reported at its offset in the program.
+        data[4 + 2] = 7;
+        let result = compare_program(account, &data, 4, &local);
+        assert_eq!(result.first_difference, Some(2));
+        assert_ne!(result.on_chain_sha256, result.local_sha256);
+
+        // A truncated program differs at the end of the shorter one.
+        let result = compare_program(account, &data[..4 + 2], 4, &local);
+        assert_eq!(result.first_difference, Some(2));
+
+        // Non-zero bytes after the local program are a difference too.
+        let mut data = vec![9; 4];
+        data.extend_from_slice(&local);
+        data.extend_from_slice(&[0, 8, 0]);
+        let result = compare_program(account, &data, 4, &local);
+        assert_eq!(result.first_difference, Some(local.len()));
+    }
+}
@@ -0,0 +1,361 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! A file that records the progress of a withdrawal that needs multiple transactions.
+//!
+//! `solido withdraw` plans all withdrawals up front, and then sends them one by
+//! one. When a progress path is set, we first record the plan, and then the
+//! signature of every withdrawal transaction before we send it. If the command
+//! is interrupted, or a transaction fails, running it again with the same
+//! progress path resumes the withdrawal: we look up the outcome of every
+//! recorded transaction, and only send the withdrawals that did not execute.
+//!
+//! Like the maintenance journal, the file holds one json record per line, and
+//! we only append to it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+
+use lido::token::{Lamports, StLamports};
+use lido::util::{deserialize_b58, serialize_b58};
+
+use crate::error::{CliError, Error};
+use crate::helpers::{PlannedWithdrawal, WithdrawPlan};
+
+/// A withdrawal in the plan record.
+#[derive(Deserialize, Serialize)]
+struct PlannedRecord {
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    validator_vote_account: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    source_stake_account: Pubkey,
+    amount_st_sol: u64,
+    amount_sol: u64,
+}
+
+/// A line in the progress file.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Record {
+    Planned {
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        solido_address: Pubkey,
+        amount_st_sol: u64,
+        withdrawals: Vec<PlannedRecord>,
+    },
+    Submitted {
+        /// Index of the withdrawal in the plan.
+        step: usize,
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        signature: Signature,
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        recent_blockhash: Hash,
+        #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+        stake_account: Pubkey,
+    },
+}
+
+/// The last transaction that we sent for a step of the plan.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubmittedWithdrawal {
+    pub signature: Signature,
+    pub recent_blockhash: Hash,
+
+    /// The stake account that the transaction creates.
+    pub stake_account: Pubkey,
+}
+
+pub struct WithdrawProgress {
+    /// The progress file, opened for appending.
+    file: File,
+
+    pub solido_address: Pubkey,
+    pub amount_st_sol: StLamports,
+    pub plan: WithdrawPlan,
+
+    /// For every withdrawal in the plan, the last transaction we sent for it.
+    submitted: Vec<Option<SubmittedWithdrawal>>,
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record).expect("Serializing a record does not fail.");
+    line.push(b'\n');
+    out.write_all(&line)
+}
+
+impl WithdrawProgress {
+    /// Create a new progress file that records the plan.
+    ///
+    /// Fails if the file exists already, so we never lose track of an earlier
+    /// withdrawal.
+    pub fn create(
+        path: &Path,
+        solido_address: Pubkey,
+        amount_st_sol: StLamports,
+        plan: WithdrawPlan,
+    ) -> std::result::Result<WithdrawProgress, Error> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| {
+                CliError::with_cause("Failed to create the withdrawal progress file.", err)
+            })?;
+        let record = Record::Planned {
+            solido_address,
+            amount_st_sol: amount_st_sol.0,
+            withdrawals: plan
+                .withdrawals
+                .iter()
+                .map(|w| PlannedRecord {
+                    validator_vote_account: w.validator_vote_account,
+                    source_stake_account: w.source_stake_account,
+                    amount_st_sol: w.amount_st_sol.0,
+                    amount_sol: w.amount_sol.0,
+                })
+                .collect(),
+        };
+        write_record(&mut file, &record)?;
+        file.sync_data()?;
+
+        Ok(WithdrawProgress {
+            file,
+            solido_address,
+            amount_st_sol,
+            submitted: vec![None; plan.withdrawals.len()],
+            plan,
+        })
+    }
+
+    /// Open an existing progress file, or return `None` if it does not exist.
+    pub fn open(path: &Path) -> std::result::Result<Option<WithdrawProgress>, Error> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(CliError::with_cause(
+                    "Failed to open the withdrawal progress file.",
+                    err,
+                ))
+            }
+        };
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<io::Result<Vec<String>>>()?;
+
+        let mut progress: Option<WithdrawProgress> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let record: Record = match serde_json::from_str(line) {
+                Ok(record) => record,
+                // If we crashed while appending a record, the last line can be
+                // incomplete. That transaction was not sent yet.
+                Err(..) if i + 1 == lines.len() => break,
+                Err(err) => return Err(err.into()),
+            };
+            match (record, progress.as_mut()) {
+                (
+                    Record::Planned {
+                        solido_address,
+                        amount_st_sol,
+                        withdrawals,
+                    },
+                    None,
+                ) => {
+                    let withdrawals: Vec<_> = withdrawals
+                        .iter()
+                        .map(|w| PlannedWithdrawal {
+                            validator_vote_account: w.validator_vote_account,
+                            source_stake_account: w.source_stake_account,
+                            amount_st_sol: StLamports(w.amount_st_sol),
+                            amount_sol: Lamports(w.amount_sol),
+                        })
+                        .collect();
+                    progress = Some(WithdrawProgress {
+                        file: OpenOptions::new().append(true).open(path)?,
+                        solido_address,
+                        amount_st_sol: StLamports(amount_st_sol),
+                        submitted: vec![None; withdrawals.len()],
+                        plan: WithdrawPlan { withdrawals },
+                    });
+                }
+                (
+                    Record::Submitted {
+                        step,
+                        signature,
+                        recent_blockhash,
+                        stake_account,
+                    },
+                    Some(progress),
+                ) if step < progress.submitted.len() => {
+                    progress.submitted[step] = Some(SubmittedWithdrawal {
+                        signature,
+                        recent_blockhash,
+                        stake_account,
+                    });
+                }
+                _ => {
+                    return Err(CliError::with_cause(
+                        "Invalid withdrawal progress file.",
+                        format!("Unexpected record on line {}.", i + 1),
+                    ))
+                }
+            }
+        }
+
+        match progress {
+            Some(progress) => Ok(Some(progress)),
+            None => Err(CliError::with_cause(
+                "Invalid withdrawal progress file.",
+                "The file does not contain a withdrawal plan.",
+            )),
+        }
+    }
+
+    /// Return the last transaction that we sent for the given step, if any.
+    pub fn submitted(&self, step: usize) -> Option<&SubmittedWithdrawal> {
+        self.submitted[step].as_ref()
+    }
+
+    /// Record that we are about to send the signed transaction for the given step.
+    ///
+    /// Only returns after the record is on disk.
+    pub fn record_submitted(
+        &mut self,
+        step: usize,
+        transaction: &Transaction,
+        stake_account: Pubkey,
+    ) -> io::Result<()> {
+        let submitted = SubmittedWithdrawal {
+            signature: transaction.signatures[0],
+            recent_blockhash: transaction.message.recent_blockhash,
+            stake_account,
+        };
+        write_record(
+            &mut self.file,
+            &Record::Submitted {
+                step,
+                signature: submitted.signature,
+                recent_blockhash: submitted.recent_blockhash,
+                stake_account: submitted.stake_account,
+            },
+        )?;
+        self.file.sync_data()?;
+        self.submitted[step] = Some(submitted);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use std::path::PathBuf;
+
+    fn new_progress_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "solido-withdraw-progress-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn new_plan(n: usize) -> WithdrawPlan {
+        WithdrawPlan {
+            withdrawals: (0..n)
+                .map(|i| PlannedWithdrawal {
+                    validator_vote_account: Pubkey::new_unique(),
+                    source_stake_account: Pubkey::new_unique(),
+                    amount_st_sol: StLamports(i as u64 + 1),
+                    amount_sol: Lamports(i as u64 + 2),
+                })
+                .collect(),
+        }
+    }
+
+    fn new_signed_transaction() -> Transaction {
+        let payer = Keypair::new();
+        let message = Message::new(&[], Some(&payer.pubkey()));
+        Transaction::new(&[&payer], message, Hash::new_unique())
+    }
+
+    #[test]
+    fn test_progress_survives_reopening() {
+        let path = new_progress_path("reopen");
+        let solido_address = Pubkey::new_unique();
+        let plan = new_plan(3);
+        let tx_a = new_signed_transaction();
+        let tx_b = new_signed_transaction();
+        let stake_a = Pubkey::new_unique();
+        let stake_b = Pubkey::new_unique();
+
+        let mut progress =
+            WithdrawProgress::create(&path, solido_address, StLamports(6), new_plan(0))
+                .ok()
+                .unwrap();
+        drop(progress);
+        // Creating the file again fails, we must not lose an earlier withdrawal.
+        assert!(
+            WithdrawProgress::create(&path, solido_address, StLamports(6), new_plan(0)).is_err()
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        progress = WithdrawProgress::create(&path, solido_address, StLamports(6), plan.clone())
+            .ok()
+            .unwrap();
+        progress.record_submitted(0, &tx_a, stake_a).unwrap();
+        progress.record_submitted(0, &tx_b, stake_b).unwrap();
+        drop(progress);
+
+        let progress = WithdrawProgress::open(&path).ok().unwrap().unwrap();
+        assert_eq!(progress.solido_address, solido_address);
+        assert_eq!(progress.amount_st_sol, StLamports(6));
+        assert_eq!(progress.plan, plan);
+        // Only the last transaction for a step counts.
+        assert_eq!(
+            progress.submitted(0),
+            Some(&SubmittedWithdrawal {
+                signature: tx_b.signatures[0],
+                recent_blockhash: tx_b.message.recent_blockhash,
+                stake_account: stake_b,
+            })
+        );
+        assert_eq!(progress.submitted(1), None);
+        assert_eq!(progress.submitted(2), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_progress_ignores_incomplete_last_record() {
+        let path = new_progress_path("incomplete");
+        let progress =
+            WithdrawProgress::create(&path, Pubkey::new_unique(), StLamports(1), new_plan(1))
+                .ok()
+                .unwrap();
+        drop(progress);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"event":"submitted","step":0,"#)
+            .unwrap();
+        drop(file);
+
+        let progress = WithdrawProgress::open(&path).ok().unwrap().unwrap();
+        assert_eq!(progress.submitted(0), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_progress_open_returns_none_if_file_does_not_exist() {
+        let path = new_progress_path("missing");
+        assert!(WithdrawProgress::open(&path).ok().unwrap().is_none());
+    }
+}
@@ -165,6 +165,15 @@ pub enum LidoError {
 
     /// We expected the StSol account to be owned by the SPL token program.
     InvalidStSolAccountOwner = 46,
+
+    /// The validator list account is not the one referenced by the Solido instance.
+    InvalidValidatorList = 47,
+
+    /// The Solido state is stored in the layout of a different version.
+    ///
+    /// Instances created before version 1 need to be migrated with
+    /// `MigrateStateToV1` before they can be used.
+    InvalidLidoVersion = 48,
 }
 
 // Just reuse the generated Debug impl for Display. It shows the variant names.
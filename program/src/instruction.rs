@@ -101,6 +101,15 @@ pub enum LidoInstruction {
     AddMaintainer,
     RemoveMaintainer,
     MergeStake,
+
+    /// Migrate the Solido state from version 0 to version 1.
+    ///
+    /// Moves the validators out of the Solido account, into a new validator
+    /// list account, which must be zeroed, owned by the program, and large
+    /// enough to hold the maximum number of validators of the instance.
+    ///
+    /// Requires the manager to sign.
+    MigrateStateToV1,
 }
 
 impl LidoInstruction {
@@ -120,6 +129,12 @@ accounts_struct! {
             is_signer: false,
             is_writable: true,
         },
+        // Account that will hold the validator list. It must be owned by the
+        // program, and it must be zeroed.
+        pub validator_list {
+            is_signer: false,
+            is_writable: true,
+        },
         pub manager {
             is_signer: false,
             is_writable: false,
@@ -224,6 +239,10 @@ accounts_struct! {
             // Needs to be writable for us to update the metrics.
             is_writable: true,
         },
+        pub validator_list {
+            is_signer: false,
+            is_writable: true,
+        },
         pub st_sol_account_owner {
             is_signer: true,
             is_writable: false,
@@ -284,6 +303,10 @@ pub fn withdraw(
 accounts_struct! {
     StakeDepositAccountsMeta, StakeDepositAccountsInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -351,6 +374,10 @@ pub fn stake_deposit(
 accounts_struct! {
     UnstakeAccountsMeta, UnstakeAccountsInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -411,6 +438,10 @@ accounts_struct! {
             is_signer: false,
             is_writable: true,
         },
+        pub validator_list {
+            is_signer: false,
+            is_writable: false,
+        },
         pub reserve {
             is_signer: false,
             is_writable: false,
@@ -440,6 +471,10 @@ accounts_struct! {
     // balance is permissionless, anybody can do it.
     WithdrawInactiveStakeMeta, WithdrawInactiveStakeInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -504,6 +539,10 @@ accounts_struct! {
             is_signer: false,
             is_writable: true,
         },
+        pub validator_list {
+            is_signer: false,
+            is_writable: true,
+        },
         // The validator to update the balance for.
         // Needs to be writable so we withdraw from it.
         pub validator_vote_account {
@@ -618,6 +657,10 @@ pub fn change_reward_distribution(
 accounts_struct! {
     AddValidatorMeta, AddValidatorInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -648,6 +691,10 @@ pub fn add_validator(program_id: &Pubkey, accounts: &AddValidatorMeta) -> Instru
 accounts_struct! {
     RemoveValidatorMeta, RemoveValidatorInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -669,6 +716,10 @@ pub fn remove_validator(program_id: &Pubkey, accounts: &RemoveValidatorMeta) ->
 accounts_struct! {
     DeactivateValidatorMeta, DeactivateValidatorInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -697,6 +748,10 @@ pub fn deactivate_validator(
 accounts_struct! {
     ClaimValidatorFeeMeta, ClaimValidatorFeeInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -781,6 +836,10 @@ pub fn remove_maintainer(program_id: &Pubkey, accounts: &RemoveMaintainerMeta) -
 accounts_struct! {
     MergeStakeMeta, MergeStakeInfo {
         pub lido {
+            is_signer: false,
+            is_writable: false,
+        },
+        pub validator_list {
             is_signer: false,
             is_writable: true,
         },
@@ -818,3 +877,31 @@ pub fn merge_stake(program_id: &Pubkey, accounts: &MergeStakeMeta) -> Instructio
         data: LidoInstruction::MergeStake.try_to_vec().unwrap(), // This should never fail.
     }
 }
+
+accounts_struct! {
+    MigrateStateToV1Meta, MigrateStateToV1Info {
+        pub lido {
+            is_signer: false,
+            is_writable: true,
+        },
+        pub manager {
+            is_signer: true,
+            is_writable: false,
+        },
+        // Account that will hold the validator list. It must be owned by the
+        // program, and it must be zeroed.
+        pub validator_list {
+            is_signer: false,
+            is_writable: true,
+        },
+        const sysvar_rent = sysvar::rent::id(),
+    }
+}
+
+pub fn migrate_state_to_v1(program_id: &Pubkey, accounts: &MigrateStateToV1Meta) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_vec(),
+        data: LidoInstruction::MigrateStateToV1.to_vec(),
+    }
+}
@@ -11,6 +11,7 @@ pub mod accounts;
 pub mod balance;
 pub mod error;
 pub mod instruction;
+pub mod lido_view;
 pub(crate) mod logic;
 pub mod metrics;
 pub(crate) mod process_management;
@@ -0,0 +1,504 @@
+// SPDX-FileCopyrightText: 2021 Chorus One AG
+// SPDX-License-Identifier: GPL-3.0
+
+//! In-place access to the fixed-size part of a serialized `Lido` struct.
+//!
+//! Deserializing a [`Lido`] decodes every maintainer, and saving it serializes
+//! all of them again, which costs compute units proportional to the number of
+//! maintainers. Instructions that only touch a few fields in the
+//! fixed-size prefix of the account, such as `Deposit` and `Withdraw`, can use
+//! [`LidoView`] instead, which reads and writes just those fields, at the
+//! offsets that Borsh would put them. Only instructions that check or change
+//! the maintainers need to deserialize the full struct.
+//!
+//! [`Lido`]: crate::state::Lido
+
+use std::cell::{Ref, RefMut};
+use std::ops::{Deref, DerefMut};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult, msg,
+    program_error::ProgramError, pubkey::Pubkey,
+};
+
+use crate::{
+    error::LidoError,
+    logic::{check_lido_owner, check_lido_version},
+    metrics::{LamportsHistogram, Metrics, WithdrawMetric},
+    state::{
+        check_is_st_sol_account, check_manager, check_mint_is_st_sol_mint, check_validator_list,
+        get_program_derived_address, ExchangeRate, FeeRecipients, RewardDistribution,
+        LIDO_CONSTANT_SIZE,
+    },
+    token::{Lamports, StLamports},
+    RESERVE_ACCOUNT, REWARDS_WITHDRAW_AUTHORITY,
+};
+
+const PUBKEY_SIZE: usize = 32;
+const EXCHANGE_RATE_SIZE: usize = 8 + 8 + 8;
+const REWARD_DISTRIBUTION_SIZE: usize = 4 * 4;
+const FEE_RECIPIENTS_SIZE: usize = 2 * PUBKEY_SIZE;
+const LAMPORTS_HISTOGRAM_SIZE: usize = 12 * 8 + 8;
+const WITHDRAW_METRIC_SIZE: usize = 3 * 8;
+const METRICS_SIZE: usize = 7 * 8 + LAMPORTS_HISTOGRAM_SIZE + WITHDRAW_METRIC_SIZE;
+
+// Offsets of the fields of `Lido`, in declaration order.
+const OFFSET_LIDO_VERSION: usize = 0;
+const OFFSET_MANAGER: usize = OFFSET_LIDO_VERSION + 1;
+const OFFSET_ST_SOL_MINT: usize = OFFSET_MANAGER + PUBKEY_SIZE;
+const OFFSET_EXCHANGE_RATE: usize = OFFSET_ST_SOL_MINT + PUBKEY_SIZE;
+const OFFSET_SOL_RESERVE_ACCOUNT_BUMP_SEED: usize = OFFSET_EXCHANGE_RATE + EXCHANGE_RATE_SIZE;
+const OFFSET_STAKE_AUTHORITY_BUMP_SEED: usize = OFFSET_SOL_RESERVE_ACCOUNT_BUMP_SEED + 1;
+const OFFSET_MINT_AUTHORITY_BUMP_SEED: usize = OFFSET_STAKE_AUTHORITY_BUMP_SEED + 1;
+const OFFSET_REWARDS_WITHDRAW_AUTHORITY_BUMP_SEED: usize = OFFSET_MINT_AUTHORITY_BUMP_SEED + 1;
+const OFFSET_REWARD_DISTRIBUTION: usize = OFFSET_REWARDS_WITHDRAW_AUTHORITY_BUMP_SEED + 1;
+const OFFSET_FEE_RECIPIENTS: usize = OFFSET_REWARD_DISTRIBUTION + REWARD_DISTRIBUTION_SIZE;
+const OFFSET_METRICS: usize = OFFSET_FEE_RECIPIENTS + FEE_RECIPIENTS_SIZE;
+const OFFSET_METRICS_DEPOSIT_AMOUNT: usize = OFFSET_METRICS + 7 * 8;
+const OFFSET_METRICS_WITHDRAW_AMOUNT: usize =
+    OFFSET_METRICS_DEPOSIT_AMOUNT + LAMPORTS_HISTOGRAM_SIZE;
+const OFFSET_VALIDATOR_LIST: usize = OFFSET_METRICS + METRICS_SIZE;
+
+/// A view into the account data of a serialized `Lido` struct.
+///
+/// `D` is the buffer that holds the data, usually a `Ref<[u8]>` or `RefMut<[u8]>`
+/// borrowed from an `AccountInfo`. Only the fixed-size prefix of the struct is
+/// accessible; the maintainers are never touched.
+pub struct LidoView<D> {
+    data: D,
+}
+
+impl<D: Deref<Target = [u8]>> LidoView<D> {
+    /// Wrap the data, confirming that it is large enough to hold the fixed-size fields.
+    pub fn new(data: D) -> Result<LidoView<D>, ProgramError> {
+        if data.len() < LIDO_CONSTANT_SIZE {
+            msg!(
+                "Lido state is {} bytes, but it should be at least {} bytes.",
+                data.len(),
+                LIDO_CONSTANT_SIZE
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(LidoView { data })
+    }
+
+    fn read<T: BorshDeserialize>(&self, offset: usize, size: usize) -> Result<T, ProgramError> {
+        Ok(T::try_from_slice(&self.data[offset..offset + size])?)
+    }
+
+    fn read_pubkey(&self, offset: usize) -> Pubkey {
+        Pubkey::new(&self.data[offset..offset + PUBKEY_SIZE])
+    }
+
+    pub fn lido_version(&self) -> u8 {
+        self.data[OFFSET_LIDO_VERSION]
+    }
+
+    pub fn manager(&self) -> Pubkey {
+        self.read_pubkey(OFFSET_MANAGER)
+    }
+
+    pub fn st_sol_mint(&self) -> Pubkey {
+        self.read_pubkey(OFFSET_ST_SOL_MINT)
+    }
+
+    pub fn exchange_rate(&self) -> Result<ExchangeRate, ProgramError> {
+        self.read(OFFSET_EXCHANGE_RATE, EXCHANGE_RATE_SIZE)
+    }
+
+    pub fn sol_reserve_account_bump_seed(&self) -> u8 {
+        self.data[OFFSET_SOL_RESERVE_ACCOUNT_BUMP_SEED]
+    }
+
+    pub fn stake_authority_bump_seed(&self) -> u8 {
+        self.data[OFFSET_STAKE_AUTHORITY_BUMP_SEED]
+    }
+
+    pub fn mint_authority_bump_seed(&self) -> u8 {
+        self.data[OFFSET_MINT_AUTHORITY_BUMP_SEED]
+    }
+
+    pub fn rewards_withdraw_authority_bump_seed(&self) -> u8 {
+        self.data[OFFSET_REWARDS_WITHDRAW_AUTHORITY_BUMP_SEED]
+    }
+
+    pub fn reward_distribution(&self) -> Result<RewardDistribution, ProgramError> {
+        self.read(OFFSET_REWARD_DISTRIBUTION, REWARD_DISTRIBUTION_SIZE)
+    }
+
+    pub fn fee_recipients(&self) -> Result<FeeRecipients, ProgramError> {
+        self.read(OFFSET_FEE_RECIPIENTS, FEE_RECIPIENTS_SIZE)
+    }
+
+    pub fn metrics(&self) -> Result<Metrics, ProgramError> {
+        self.read(OFFSET_METRICS, METRICS_SIZE)
+    }
+
+    pub fn validator_list(&self) -> Pubkey {
+        self.read_pubkey(OFFSET_VALIDATOR_LIST)
+    }
+
+    /// Confirm that the given account is the validator list of this instance.
+    ///
+    /// See also [`Lido::check_validator_list`](crate::state::Lido::check_validator_list).
+    pub fn check_validator_list(&self, validator_list: &AccountInfo) -> ProgramResult {
+        check_validator_list(&self.validator_list(), validator_list)
+    }
+
+    /// Checks if the passed manager is the same as the one stored in the state.
+    ///
+    /// See also [`Lido::check_manager`](crate::state::Lido::check_manager).
+    pub fn check_manager(&self, manager: &AccountInfo) -> ProgramResult {
+        check_manager(&self.manager(), manager)
+    }
+
+    /// Confirm that the given account is Solido's stSOL mint.
+    ///
+    /// See also [`Lido::check_mint_is_st_sol_mint`](crate::state::Lido::check_mint_is_st_sol_mint).
+    pub fn check_mint_is_st_sol_mint(&self, mint_account_info: &AccountInfo) -> ProgramResult {
+        check_mint_is_st_sol_mint(&self.st_sol_mint(), mint_account_info)
+    }
+
+    /// Confirm that the given account is an SPL token account with our stSOL mint as mint.
+    ///
+    /// See also [`Lido::check_is_st_sol_account`](crate::state::Lido::check_is_st_sol_account).
+    pub fn check_is_st_sol_account(&self, token_account_info: &AccountInfo) -> ProgramResult {
+        check_is_st_sol_account(&self.st_sol_mint(), token_account_info)
+    }
+
+    /// Check if the passed treasury fee account is the one configured.
+    ///
+    /// See also [`Lido::check_treasury_fee_st_sol_account`](crate::state::Lido::check_treasury_fee_st_sol_account).
+    pub fn check_treasury_fee_st_sol_account(&self, st_sol_account: &AccountInfo) -> ProgramResult {
+        self.fee_recipients()?
+            .check_treasury_fee_st_sol_account(&self.st_sol_mint(), st_sol_account)
+    }
+
+    /// Check if the passed developer fee account is the one configured.
+    ///
+    /// See also [`Lido::check_developer_fee_st_sol_account`](crate::state::Lido::check_developer_fee_st_sol_account).
+    pub fn check_developer_fee_st_sol_account(
+        &self,
+        st_sol_account: &AccountInfo,
+    ) -> ProgramResult {
+        self.fee_recipients()?
+            .check_developer_fee_st_sol_account(&self.st_sol_mint(), st_sol_account)
+    }
+
+    /// Confirm that the exchange rate was computed in the current epoch.
+    ///
+    /// See also [`Lido::check_exchange_rate_last_epoch`](crate::state::Lido::check_exchange_rate_last_epoch).
+    pub fn check_exchange_rate_last_epoch(&self, clock: &Clock, method: &str) -> ProgramResult {
+        self.exchange_rate()?
+            .check_computed_in_epoch(clock, method)
+            .map_err(ProgramError::from)
+    }
+
+    /// Confirm that the rewards withdraw authority belongs to this Lido
+    /// instance, return the rewards authority address.
+    ///
+    /// See also [`Lido::check_rewards_withdraw_authority`](crate::state::Lido::check_rewards_withdraw_authority).
+    pub fn check_rewards_withdraw_authority(
+        &self,
+        program_id: &Pubkey,
+        solido_address: &Pubkey,
+        rewards_withdraw_authority_account_info: &AccountInfo,
+    ) -> Result<Pubkey, ProgramError> {
+        let authority = get_program_derived_address(
+            program_id,
+            solido_address,
+            REWARDS_WITHDRAW_AUTHORITY,
+            self.rewards_withdraw_authority_bump_seed(),
+        )?;
+        if &authority != rewards_withdraw_authority_account_info.key {
+            msg!(
+                "Invalid rewards withdraw authority, expected {} but got {}.",
+                authority,
+                rewards_withdraw_authority_account_info.key
+            );
+            return Err(LidoError::InvalidRewardsWithdrawAuthority.into());
+        }
+        Ok(authority)
+    }
+
+    /// Confirm that the reserve account belongs to this Lido instance.
+    ///
+    /// See also [`Lido::check_reserve_account`](crate::state::Lido::check_reserve_account).
+    pub fn check_reserve_account(
+        &self,
+        program_id: &Pubkey,
+        solido_address: &Pubkey,
+        reserve_account_info: &AccountInfo,
+    ) -> Result<Pubkey, ProgramError> {
+        let reserve_id = Pubkey::create_program_address(
+            &[
+                &solido_address.to_bytes()[..],
+                RESERVE_ACCOUNT,
+                &[self.sol_reserve_account_bump_seed()],
+            ],
+            program_id,
+        )
+        .map_err(|_| ProgramError::from(LidoError::InvalidReserveAccount))?;
+        if reserve_id != *reserve_account_info.key {
+            msg!("Invalid reserve account");
+            return Err(LidoError::InvalidReserveAccount.into());
+        }
+        Ok(reserve_id)
+    }
+}
+
+impl<D: DerefMut<Target = [u8]>> LidoView<D> {
+    fn write<T: BorshSerialize>(&mut self, offset: usize, size: usize, value: &T) -> ProgramResult {
+        let mut target = &mut self.data[offset..offset + size];
+        value.serialize(&mut target)?;
+        // Every field has a fixed size, so if we did not fill the range
+        // exactly, the offsets above are wrong.
+        assert!(target.is_empty());
+        Ok(())
+    }
+
+    pub fn set_exchange_rate(&mut self, exchange_rate: &ExchangeRate) -> ProgramResult {
+        self.write(OFFSET_EXCHANGE_RATE, EXCHANGE_RATE_SIZE, exchange_rate)
+    }
+
+    pub fn set_reward_distribution(
+        &mut self,
+        reward_distribution: &RewardDistribution,
+    ) -> ProgramResult {
+        self.write(
+            OFFSET_REWARD_DISTRIBUTION,
+            REWARD_DISTRIBUTION_SIZE,
+            reward_distribution,
+        )
+    }
+
+    pub fn set_fee_recipients(&mut self, fee_recipients: &FeeRecipients) -> ProgramResult {
+        self.write(OFFSET_FEE_RECIPIENTS, FEE_RECIPIENTS_SIZE, fee_recipients)
+    }
+
+    pub fn set_metrics(&mut self, metrics: &Metrics) -> ProgramResult {
+        self.write(OFFSET_METRICS, METRICS_SIZE, metrics)
+    }
+
+    /// Record a deposit in the deposit histogram of the metrics.
+    ///
+    /// Equivalent to `lido.metrics.observe_deposit(amount)`.
+    pub fn observe_deposit(&mut self, amount: Lamports) -> ProgramResult {
+        let mut histogram: LamportsHistogram =
+            self.read(OFFSET_METRICS_DEPOSIT_AMOUNT, LAMPORTS_HISTOGRAM_SIZE)?;
+        histogram.observe(amount)?;
+        self.write(
+            OFFSET_METRICS_DEPOSIT_AMOUNT,
+            LAMPORTS_HISTOGRAM_SIZE,
+            &histogram,
+        )
+    }
+
+    /// Record a withdrawal in the withdrawal metrics.
+    ///
+    /// Equivalent to `lido.metrics.observe_withdrawal(st_sol_amount, sol_amount)`.
+    pub fn observe_withdrawal(
+        &mut self,
+        st_sol_amount: StLamports,
+        sol_amount: Lamports,
+    ) -> ProgramResult {
+        let mut withdraw_amount: WithdrawMetric =
+            self.read(OFFSET_METRICS_WITHDRAW_AMOUNT, WITHDRAW_METRIC_SIZE)?;
+        withdraw_amount.observe(st_sol_amount, sol_amount)?;
+        self.write(
+            OFFSET_METRICS_WITHDRAW_AMOUNT,
+            WITHDRAW_METRIC_SIZE,
+            &withdraw_amount,
+        )
+    }
+}
+
+impl<'a> LidoView<Ref<'a, [u8]>> {
+    /// Borrow the data of the Lido account for reading.
+    ///
+    /// Like [`deserialize_lido`](crate::logic::deserialize_lido), this checks
+    /// that the account is owned by the program, and that the state has the
+    /// layout of the current version.
+    pub fn from_account(
+        program_id: &Pubkey,
+        lido: &'a AccountInfo,
+    ) -> Result<LidoView<Ref<'a, [u8]>>, ProgramError> {
+        check_lido_owner(program_id, lido)?;
+        check_lido_version(&lido.data.borrow())?;
+        LidoView::new(Ref::map(lido.data.borrow(), |data| &**data))
+    }
+}
+
+impl<'a> LidoView<RefMut<'a, [u8]>> {
+    /// Borrow the data of the Lido account for reading and writing.
+    ///
+    /// Like [`deserialize_lido`](crate::logic::deserialize_lido), this checks
+    /// that the account is owned by the program, and that the state has the
+    /// layout of the current version.
+    pub fn from_account_mut(
+        program_id: &Pubkey,
+        lido: &'a AccountInfo,
+    ) -> Result<LidoView<RefMut<'a, [u8]>>, ProgramError> {
+        check_lido_owner(program_id, lido)?;
+        check_lido_version(&lido.data.borrow())?;
+        LidoView::new(RefMut::map(lido.data.borrow_mut(), |data| &mut **data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{Lido, Maintainers, LIDO_VERSION};
+    use crate::token::StLamports;
+
+    fn get_lido() -> Lido {
+        let mut maintainers = Maintainers::new(2);
+        maintainers.add(Pubkey::new_unique(), ()).unwrap();
+        let mut metrics = Metrics::new();
+        metrics
+            .observe_fee_treasury(Lamports(1), StLamports(2))
+            .unwrap();
+        metrics
+            .observe_fee_validation(Lamports(3), StLamports(4))
+            .unwrap();
+        metrics
+            .observe_fee_developer(Lamports(5), StLamports(6))
+            .unwrap();
+        metrics
+            .observe_reward_st_sol_appreciation(Lamports(7))
+            .unwrap();
+        metrics.observe_deposit(Lamports(8_000_000)).unwrap();
+        metrics
+            .observe_withdrawal(StLamports(9), Lamports(10))
+            .unwrap();
+        Lido {
+            lido_version: LIDO_VERSION,
+            manager: Pubkey::new_unique(),
+            st_sol_mint: Pubkey::new_unique(),
+            exchange_rate: ExchangeRate {
+                computed_in_epoch: 11,
+                st_sol_supply: StLamports(13),
+                sol_balance: Lamports(17),
+            },
+            sol_reserve_account_bump_seed: 1,
+            stake_authority_bump_seed: 2,
+            mint_authority_bump_seed: 3,
+            rewards_withdraw_authority_bump_seed: 4,
+            reward_distribution: RewardDistribution {
+                treasury_fee: 5,
+                validation_fee: 6,
+                developer_fee: 7,
+                st_sol_appreciation: 8,
+            },
+            fee_recipients: FeeRecipients {
+                treasury_account: Pubkey::new_unique(),
+                developer_account: Pubkey::new_unique(),
+            },
+            metrics,
+            validator_list: Pubkey::new_unique(),
+            maintainers,
+        }
+    }
+
+    #[test]
+    fn test_view_covers_lido_constant_size() {
+        // The maintainers follow directly after the validator list address.
+        assert_eq!(OFFSET_VALIDATOR_LIST + PUBKEY_SIZE, LIDO_CONSTANT_SIZE);
+    }
+
+    #[test]
+    fn test_view_reads_match_borsh() {
+        let lido = get_lido();
+        let data = lido.try_to_vec().unwrap();
+        let view = LidoView::new(&data[..]).unwrap();
+
+        assert_eq!(view.lido_version(), lido.lido_version);
+        assert_eq!(view.manager(), lido.manager);
+        assert_eq!(view.st_sol_mint(), lido.st_sol_mint);
+        assert_eq!(view.exchange_rate().unwrap(), lido.exchange_rate);
+        assert_eq!(
+            view.sol_reserve_account_bump_seed(),
+            lido.sol_reserve_account_bump_seed
+        );
+        assert_eq!(
+            view.stake_authority_bump_seed(),
+            lido.stake_authority_bump_seed
+        );
+        assert_eq!(
+            view.mint_authority_bump_seed(),
+            lido.mint_authority_bump_seed
+        );
+        assert_eq!(
+            view.rewards_withdraw_authority_bump_seed(),
+            lido.rewards_withdraw_authority_bump_seed
+        );
+        assert_eq!(
+            view.reward_distribution().unwrap(),
+            lido.reward_distribution
+        );
+        assert_eq!(view.fee_recipients().unwrap(), lido.fee_recipients);
+        assert_eq!(view.metrics().unwrap(), lido.metrics);
+        assert_eq!(view.validator_list(), lido.validator_list);
+    }
+
+    #[test]
+    fn test_view_writes_match_borsh() {
+        let mut lido = get_lido();
+        let mut data = lido.try_to_vec().unwrap();
+
+        let new_exchange_rate = ExchangeRate {
+            computed_in_epoch: 19,
+            st_sol_supply: StLamports(23),
+            sol_balance: Lamports(29),
+        };
+        let mut view = LidoView::new(&mut data[..]).unwrap();
+        view.set_exchange_rate(&new_exchange_rate).unwrap();
+        view.observe_deposit(Lamports(31)).unwrap();
+        view.observe_deposit(Lamports(1_000_000_000_000)).unwrap();
+
+        lido.exchange_rate = new_exchange_rate;
+        lido.metrics.observe_deposit(Lamports(31)).unwrap();
+        lido.metrics
+            .observe_deposit(Lamports(1_000_000_000_000))
+            .unwrap();
+        assert_eq!(data, lido.try_to_vec().unwrap());
+
+        lido.metrics
+            .observe_withdrawal(StLamports(37), Lamports(41))
+            .unwrap();
+        let mut view = LidoView::new(&mut data[..]).unwrap();
+        view.set_metrics(&lido.metrics).unwrap();
+        assert_eq!(data, lido.try_to_vec().unwrap());
+
+        let mut view = LidoView::new(&mut data[..]).unwrap();
+        view.observe_withdrawal(StLamports(43), Lamports(47))
+            .unwrap();
+        lido.metrics
+            .observe_withdrawal(StLamports(43), Lamports(47))
+            .unwrap();
+        assert_eq!(data, lido.try_to_vec().unwrap());
+
+        lido.reward_distribution = RewardDistribution {
+            treasury_fee: 53,
+            validation_fee: 59,
+            developer_fee: 61,
+            st_sol_appreciation: 67,
+        };
+        lido.fee_recipients = FeeRecipients {
+            treasury_account: Pubkey::new_unique(),
+            developer_account: Pubkey::new_unique(),
+        };
+        let mut view = LidoView::new(&mut data[..]).unwrap();
+        view.set_reward_distribution(&lido.reward_distribution)
+            .unwrap();
+        view.set_fee_recipients(&lido.fee_recipients).unwrap();
+        assert_eq!(data, lido.try_to_vec().unwrap());
+    }
+
+    #[test]
+    fn test_view_rejects_short_data() {
+        let data = vec![0_u8; LIDO_CONSTANT_SIZE - 1];
+        assert!(LidoView::new(&data[..]).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 // SPDX-FileCopyrightText: 2021 Chorus One AG
 // SPDX-License-Identifier: GPL-3.0
 
+use std::ops::Deref;
+
 use solana_program::entrypoint::ProgramResult;
 use solana_program::program_option::COption;
 use solana_program::program_pack::Pack;
@@ -16,8 +18,13 @@ use crate::STAKE_AUTHORITY;
 use crate::{
     error::LidoError,
     instruction::{CollectValidatorFeeInfo, UnstakeAccountsInfo, WithdrawAccountsInfo},
+    lido_view::LidoView,
+    metrics::Metrics,
     state::Fees,
-    state::Lido,
+    state::{
+        check_is_st_sol_account, check_mint_is_st_sol_mint, check_validator_list, LIDO_VERSION,
+    },
+    state::{Lido, Validators},
     token::{Lamports, StLamports},
     MINT_AUTHORITY, RESERVE_ACCOUNT,
 };
@@ -177,8 +184,10 @@ pub fn initialize_stake_account_undelegated<'a>(
 ///
 /// * The stSOL mint must be the one configured in the Solido instance.
 /// * The recipient account must be an stSOL SPL token account.
+#[allow(clippy::too_many_arguments)]
 pub fn mint_st_sol_to<'a>(
-    solido: &Lido,
+    solido_st_sol_mint: &Pubkey,
+    solido_mint_authority_bump_seed: u8,
     solido_address: &Pubkey,
     spl_token_program: &AccountInfo<'a>,
     st_sol_mint: &AccountInfo<'a>,
@@ -186,14 +195,14 @@ pub fn mint_st_sol_to<'a>(
     recipient: &AccountInfo<'a>,
     amount: StLamports,
 ) -> ProgramResult {
-    solido.check_mint_is_st_sol_mint(st_sol_mint)?;
-    solido.check_is_st_sol_account(recipient)?;
+    check_mint_is_st_sol_mint(solido_st_sol_mint, st_sol_mint)?;
+    check_is_st_sol_account(solido_st_sol_mint, recipient)?;
 
     let solido_address_bytes = solido_address.to_bytes();
     let authority_signature_seeds = [
         &solido_address_bytes[..],
         MINT_AUTHORITY,
-        &[solido.mint_authority_bump_seed],
+        &[solido_mint_authority_bump_seed],
     ];
     let signers = [&authority_signature_seeds[..]];
 
@@ -227,12 +236,12 @@ pub fn mint_st_sol_to<'a>(
 /// * The stSOL mint must be the one configured in the Solido instance.
 /// * The account account must be an stSOL SPL token account.
 pub fn burn_st_sol<'a, 'b>(
-    solido: &Lido,
+    st_sol_mint: &Pubkey,
     accounts: &WithdrawAccountsInfo<'a, 'b>,
     amount: StLamports,
 ) -> ProgramResult {
-    solido.check_mint_is_st_sol_mint(accounts.st_sol_mint)?;
-    solido.check_is_st_sol_account(accounts.st_sol_account)?;
+    check_mint_is_st_sol_mint(st_sol_mint, accounts.st_sol_mint)?;
+    check_is_st_sol_account(st_sol_mint, accounts.st_sol_account)?;
 
     let st_sol_account: spl_token::state::Account =
         spl_token::state::Account::unpack_from_slice(&accounts.st_sol_account.data.borrow())?;
@@ -318,26 +327,32 @@ pub fn transfer_stake_authority(
 }
 
 /// Mint stSOL for the given fees, and transfer them to the appropriate accounts.
-pub fn distribute_fees<'a, 'b>(
-    solido: &mut Lido,
+///
+/// The fees are recorded in `metrics`, the caller is responsible for storing
+/// those in the Solido state.
+pub fn distribute_fees<'a, 'b, D: Deref<Target = [u8]>>(
+    solido: &LidoView<D>,
+    metrics: &mut Metrics,
+    validators: &mut Validators,
     accounts: &CollectValidatorFeeInfo<'a, 'b>,
     fees: Fees,
 ) -> ProgramResult {
     // Convert all fees to stSOL according to the previously updated exchange rate.
     // In the case of fees, the SOL is already part of one of the stake accounts,
-    // but we do still need to mint stSOL to represent it.
+    // but we do still need to mint stSOL to represent it. The conversion rounds
+    // down, so we never mint more stSOL than the fees are worth.
+    let exchange_rate = solido.exchange_rate()?;
 
-    let treasury_amount = solido.exchange_rate.exchange_sol(fees.treasury_amount)?;
+    let treasury_amount = exchange_rate.exchange_sol(fees.treasury_amount)?;
 
-    let developer_amount = solido.exchange_rate.exchange_sol(fees.developer_amount)?;
+    let developer_amount = exchange_rate.exchange_sol(fees.developer_amount)?;
 
-    let per_validator_amount = solido
-        .exchange_rate
-        .exchange_sol(fees.reward_per_validator)?;
+    let per_validator_amount = exchange_rate.exchange_sol(fees.reward_per_validator)?;
 
     // The treasury and developer fee we can mint and pay immediately.
     mint_st_sol_to(
-        solido,
+        &solido.st_sol_mint(),
+        solido.mint_authority_bump_seed(),
         accounts.lido.key,
         accounts.spl_token_program,
         accounts.st_sol_mint,
@@ -346,7 +361,8 @@ pub fn distribute_fees<'a, 'b>(
         treasury_amount,
     )?;
     mint_st_sol_to(
-        solido,
+        &solido.st_sol_mint(),
+        solido.mint_authority_bump_seed(),
         accounts.lido.key,
         accounts.spl_token_program,
         accounts.st_sol_mint,
@@ -360,25 +376,17 @@ pub fn distribute_fees<'a, 'b>(
     // entitled to, and they can later claim it themselves with `ClaimValidatorFee`.
     let mut fee_validation_sol = Lamports(0);
     let mut fee_validation_st_sol = StLamports(0);
-    for validator in solido.validators.iter_entries_mut() {
+    for validator in validators.iter_entries_mut() {
         validator.fee_credit = (validator.fee_credit + per_validator_amount)?;
         fee_validation_sol = (fee_validation_sol + fees.reward_per_validator)?;
         fee_validation_st_sol = (fee_validation_st_sol + per_validator_amount)?;
     }
 
     // Also record our rewards in the metrics.
-    solido
-        .metrics
-        .observe_fee_treasury(fees.treasury_amount, treasury_amount)?;
-    solido
-        .metrics
-        .observe_fee_validation(fee_validation_sol, fee_validation_st_sol)?;
-    solido
-        .metrics
-        .observe_fee_developer(fees.developer_amount, developer_amount)?;
-    solido
-        .metrics
-        .observe_reward_st_sol_appreciation(fees.st_sol_appreciation_amount)?;
+    metrics.observe_fee_treasury(fees.treasury_amount, treasury_amount)?;
+    metrics.observe_fee_validation(fee_validation_sol, fee_validation_st_sol)?;
+    metrics.observe_fee_developer(fees.developer_amount, developer_amount)?;
+    metrics.observe_reward_st_sol_appreciation(fees.st_sol_appreciation_amount)?;
 
     Ok(())
 }
@@ -387,10 +395,10 @@ pub fn distribute_fees<'a, 'b>(
 /// by the validator's seeds. Returns the destination bump seed.
 pub fn check_unstake_accounts(
     program_id: &Pubkey,
-    lido: &Lido,
+    validators: &Validators,
     accounts: &UnstakeAccountsInfo,
 ) -> Result<u8, ProgramError> {
-    let validator = lido.validators.get(accounts.validator_vote_account.key)?;
+    let validator = validators.get(accounts.validator_vote_account.key)?;
 
     // If a validator doesn't have a stake account, it cannot be unstaked.
     if !validator.entry.has_stake_accounts() {
@@ -455,7 +463,7 @@ pub struct SplitStakeAccounts<'a, 'b> {
 ///   - Splits the stake.
 pub fn split_stake_account(
     lido_address: &Pubkey,
-    lido: &Lido,
+    stake_authority_bump_seed: u8,
     accounts: &SplitStakeAccounts,
     amount: Lamports,
     seeds: &[&[&[u8]]],
@@ -506,13 +514,14 @@ pub fn split_stake_account(
         &[&[
             &lido_address.to_bytes(),
             STAKE_AUTHORITY,
-            &[lido.stake_authority_bump_seed],
+            &[stake_authority_bump_seed],
         ]],
     )?;
     Ok(())
 }
 
-pub fn deserialize_lido(program_id: &Pubkey, lido: &AccountInfo) -> Result<Lido, ProgramError> {
+/// Check that the Lido state account is owned by the Lido program.
+pub fn check_lido_owner(program_id: &Pubkey, lido: &AccountInfo) -> ProgramResult {
     if lido.owner != program_id {
         msg!(
             "Lido state is owned by {}, but should be owned by the Lido program ({}).",
@@ -521,10 +530,103 @@ pub fn deserialize_lido(program_id: &Pubkey, lido: &AccountInfo) -> Result<Lido,
         );
         return Err(LidoError::InvalidOwner.into());
     }
+    Ok(())
+}
+
+/// Check that the Lido state is stored in the layout of the current version.
+pub fn check_lido_version(lido_data: &[u8]) -> ProgramResult {
+    match lido_data.first() {
+        Some(&LIDO_VERSION) => Ok(()),
+        version => {
+            msg!(
+                "Lido state has version {:?}, but the program expects version {}.",
+                version,
+                LIDO_VERSION
+            );
+            if version == Some(&0) {
+                msg!("Please migrate the state with MigrateStateToV1 first.");
+            }
+            Err(LidoError::InvalidLidoVersion.into())
+        }
+    }
+}
+
+pub fn deserialize_lido(program_id: &Pubkey, lido: &AccountInfo) -> Result<Lido, ProgramError> {
+    check_lido_owner(program_id, lido)?;
+    check_lido_version(&lido.data.borrow())?;
     let lido = try_from_slice_unchecked::<Lido>(&lido.data.borrow())?;
     Ok(lido)
 }
 
+/// Confirm that `validator_list` can hold a new validator list with room for
+/// `max_validators` validators.
+///
+/// The account must be owned by the program, rent-exempt, zeroed, and distinct
+/// from the Solido account.
+pub(crate) fn check_validator_list_uninitialized(
+    program_id: &Pubkey,
+    rent: &Rent,
+    lido: &AccountInfo,
+    validator_list: &AccountInfo,
+    max_validators: u32,
+) -> ProgramResult {
+    check_rent_exempt(rent, validator_list, "Validator list account")?;
+
+    if validator_list.key == lido.key {
+        msg!("The validator list must be a different account than the Solido account.");
+        return Err(LidoError::InvalidValidatorList.into());
+    }
+    if validator_list.owner != program_id {
+        msg!(
+            "Validator list is owned by {}, but should be owned by the Lido program ({}).",
+            validator_list.owner,
+            program_id
+        );
+        return Err(LidoError::InvalidOwner.into());
+    }
+    let is_uninitialized = validator_list.data.borrow().iter().all(|byte| *byte == 0);
+    if !is_uninitialized {
+        msg!(
+            "Account {} appears to be in use already, refusing to overwrite.",
+            validator_list.key
+        );
+        return Err(LidoError::AlreadyInUse.into());
+    }
+
+    let bytes_for_validators = Validators::required_bytes(max_validators as usize);
+    if bytes_for_validators != validator_list.data_len() {
+        msg!(
+            "Incorrect allocated bytes for the validator list: max_validators bytes: {}, should be {}",
+            bytes_for_validators,
+            validator_list.data_len()
+        );
+        return Err(LidoError::InvalidLidoSize.into());
+    }
+    Ok(())
+}
+
+/// Deserialize the validator list of a Solido instance.
+///
+/// Confirms that `validator_list` is the account at `validator_list_address`,
+/// the address stored in the Solido state.
+pub fn deserialize_validators(
+    program_id: &Pubkey,
+    validator_list_address: &Pubkey,
+    validator_list: &AccountInfo,
+) -> Result<Validators, ProgramError> {
+    check_validator_list(validator_list_address, validator_list)?;
+    if validator_list.owner != program_id {
+        msg!(
+            "Validator list is owned by {}, but should be owned by the Lido program ({}).",
+            validator_list.owner,
+            program_id
+        );
+        return Err(LidoError::InvalidOwner.into());
+    }
+    let validators = try_from_slice_unchecked::<Validators>(&validator_list.data.borrow())?;
+    Ok(validators)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -226,7 +226,11 @@ pub struct WithdrawMetric {
 }
 
 impl WithdrawMetric {
-    fn observe(&mut self, st_sol_amount: StLamports, sol_amount: Lamports) -> token::Result<()> {
+    pub fn observe(
+        &mut self,
+        st_sol_amount: StLamports,
+        sol_amount: Lamports,
+    ) -> token::Result<()> {
         self.total_st_sol_amount = (self.total_st_sol_amount + st_sol_amount)?;
         self.total_sol_amount = (self.total_sol_amount + sol_amount)?;
         self.count += 1;
@@ -1,22 +1,25 @@
 // SPDX-FileCopyrightText: 2021 Chorus One AG
 // SPDX-License-Identifier: GPL-3.0
 
+use solana_program::borsh::try_from_slice_unchecked;
 use solana_program::program::invoke_signed;
 use solana_program::rent::Rent;
 use solana_program::sysvar::Sysvar;
 use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg, pubkey::Pubkey};
 
-use crate::logic::check_rent_exempt;
+use crate::logic::{check_lido_owner, check_rent_exempt, check_validator_list_uninitialized};
 use crate::processor::StakeType;
 use crate::vote_state::PartialVoteState;
 use crate::{
     error::LidoError,
     instruction::{
         AddMaintainerInfo, AddValidatorInfo, ChangeRewardDistributionInfo, ClaimValidatorFeeInfo,
-        DeactivateValidatorInfo, MergeStakeInfo, RemoveMaintainerInfo, RemoveValidatorInfo,
+        DeactivateValidatorInfo, MergeStakeInfo, MigrateStateToV1Info, RemoveMaintainerInfo,
+        RemoveValidatorInfo,
     },
-    logic::{deserialize_lido, mint_st_sol_to},
-    state::{RewardDistribution, Validator},
+    lido_view::LidoView,
+    logic::{deserialize_lido, deserialize_validators, mint_st_sol_to},
+    state::{check_manager, FeeRecipients, Lido, LidoV0, RewardDistribution, Validator},
     token::StLamports,
     STAKE_AUTHORITY,
 };
@@ -27,22 +30,24 @@ pub fn process_change_reward_distribution(
     accounts_raw: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = ChangeRewardDistributionInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut lido = LidoView::from_account_mut(program_id, accounts.lido)?;
     lido.check_manager(accounts.manager)?;
 
     lido.check_is_st_sol_account(accounts.treasury_account)?;
     lido.check_is_st_sol_account(accounts.developer_account)?;
 
-    lido.reward_distribution = new_reward_distribution;
-    lido.fee_recipients.treasury_account = *accounts.treasury_account.key;
-    lido.fee_recipients.developer_account = *accounts.developer_account.key;
-
-    lido.save(accounts.lido)
+    lido.set_reward_distribution(&new_reward_distribution)?;
+    lido.set_fee_recipients(&FeeRecipients {
+        treasury_account: *accounts.treasury_account.key,
+        developer_account: *accounts.developer_account.key,
+    })
 }
 
 pub fn process_add_validator(program_id: &Pubkey, accounts_raw: &[AccountInfo]) -> ProgramResult {
     let accounts = AddValidatorInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
     let rent = &Rent::from_account_info(accounts.sysvar_rent)?;
     lido.check_manager(accounts.manager)?;
     lido.check_is_st_sol_account(accounts.validator_fee_st_sol_account)?;
@@ -62,12 +67,12 @@ pub fn process_add_validator(program_id: &Pubkey, accounts_raw: &[AccountInfo])
         accounts.validator_vote_account,
     )?;
 
-    lido.validators.add(
+    validators.add(
         *accounts.validator_vote_account.key,
         Validator::new(*accounts.validator_fee_st_sol_account.key),
     )?;
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 /// Remove a validator.
@@ -81,17 +86,17 @@ pub fn process_remove_validator(
     accounts_raw: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = RemoveValidatorInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
 
-    let removed_validator = lido
-        .validators
-        .remove(accounts.validator_vote_account_to_remove.key)?;
+    let removed_validator = validators.remove(accounts.validator_vote_account_to_remove.key)?;
 
     let result = removed_validator.check_can_be_removed();
     Validator::show_removed_error_msg(&result);
     result?;
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 /// Set the `active` flag to false for a given validator.
@@ -104,17 +109,17 @@ pub fn process_deactivate_validator(
     accounts_raw: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = DeactivateValidatorInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
     lido.check_manager(accounts.manager)?;
 
-    let validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account_to_deactivate.key)?;
+    let validator = validators.get_mut(accounts.validator_vote_account_to_deactivate.key)?;
 
     validator.entry.active = false;
     msg!("Validator {} deactivated.", validator.pubkey);
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 pub fn process_claim_validator_fee(
@@ -122,10 +127,11 @@ pub fn process_claim_validator_fee(
     accounts_raw: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = ClaimValidatorFeeInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
 
-    let pubkey_entry = lido
-        .validators
+    let pubkey_entry = validators
         .entries
         .iter_mut()
         .find(|pe| &pe.entry.fee_address == accounts.validator_fee_st_sol_account.key)
@@ -135,7 +141,8 @@ pub fn process_claim_validator_fee(
     pubkey_entry.entry.fee_credit = StLamports(0);
 
     mint_st_sol_to(
-        &lido,
+        &lido.st_sol_mint,
+        lido.mint_authority_bump_seed,
         accounts.lido.key,
         accounts.spl_token,
         accounts.st_sol_mint,
@@ -143,7 +150,7 @@ pub fn process_claim_validator_fee(
         accounts.validator_fee_st_sol_account,
         amount_claimed,
     )?;
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 /// Adds a maintainer to the list of maintainers
@@ -190,11 +197,11 @@ pub fn _process_change_validator_fee_account(
 /// All fully active stake accounts precede the activating stake accounts.
 pub fn process_merge_stake(program_id: &Pubkey, accounts_raw: &[AccountInfo]) -> ProgramResult {
     let accounts = MergeStakeInfo::try_from_slice(accounts_raw)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
 
-    let mut validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account.key)?;
+    let validator = validators.get_mut(accounts.validator_vote_account.key)?;
     let from_seed = validator.entry.stake_seeds.begin;
     let to_seed = validator.entry.stake_seeds.begin + 1;
 
@@ -267,5 +274,205 @@ pub fn process_merge_stake(program_id: &Pubkey, accounts_raw: &[AccountInfo]) ->
         ]],
     )?;
 
+    validators.save(accounts.validator_list)
+}
+
+/// Migrate a Solido instance from the version 0 layout, where the validators
+/// are stored in the Solido account, to version 1, where they live in a
+/// separate validator list account.
+///
+/// The runtime does not allow programs to resize accounts, so the Solido
+/// account keeps its size. The version 1 state is smaller than the version 0
+/// state that held the validators, so we write it at the start of the account,
+/// and zero the rest, where the validators used to be.
+pub fn process_migrate_state_to_v1(
+    program_id: &Pubkey,
+    accounts_raw: &[AccountInfo],
+) -> ProgramResult {
+    let accounts = MigrateStateToV1Info::try_from_slice(accounts_raw)?;
+    check_lido_owner(program_id, accounts.lido)?;
+
+    let lido_v0 = {
+        let data = accounts.lido.data.borrow();
+        if data.first() != Some(&0) {
+            msg!(
+                "Lido state has version {:?}, only version 0 can be migrated.",
+                data.first()
+            );
+            return Err(LidoError::InvalidLidoVersion.into());
+        }
+        try_from_slice_unchecked::<LidoV0>(&data)?
+    };
+    check_manager(&lido_v0.manager, accounts.manager)?;
+
+    let rent = Rent::from_account_info(accounts.sysvar_rent)?;
+    check_validator_list_uninitialized(
+        program_id,
+        &rent,
+        accounts.lido,
+        accounts.validator_list,
+        lido_v0.validators.maximum_entries,
+    )?;
+
+    let required_size = Lido::calculate_size(lido_v0.maintainers.maximum_entries);
+    if required_size > accounts.lido.data_len() {
+        msg!(
+            "The migrated state needs {} bytes, but the Solido account has only {}.",
+            required_size,
+            accounts.lido.data_len()
+        );
+        return Err(LidoError::InvalidLidoSize.into());
+    }
+
+    let (lido, validators) = lido_v0.into_v1(*accounts.validator_list.key);
+    msg!(
+        "Solido: Moving {} validators to {}.",
+        validators.len(),
+        accounts.validator_list.key
+    );
+    validators.save(accounts.validator_list)?;
+
+    accounts.lido.data.borrow_mut().fill(0);
     lido.save(accounts.lido)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{Maintainers, Validators, LIDO_V0_CONSTANT_SIZE, LIDO_VERSION};
+    use borsh::BorshSerialize;
+    use solana_program::program_error::ProgramError;
+    use solana_program::{system_program, sysvar};
+
+    /// Call `MigrateStateToV1` on the given account data.
+    fn migrate(
+        program_id: &Pubkey,
+        manager: &Pubkey,
+        validator_list: &Pubkey,
+        lido_data: &mut [u8],
+        validator_list_data: &mut [u8],
+    ) -> ProgramResult {
+        let lido_address = Pubkey::new_unique();
+        let mut rent_data = bincode::serialize(&Rent::default()).unwrap();
+        let (mut lido_lamports, mut manager_lamports, mut rent_lamports) = (0, 0, 0);
+        let mut validator_list_lamports = u64::MAX;
+        let accounts = [
+            AccountInfo::new(
+                &lido_address,
+                false,
+                true,
+                &mut lido_lamports,
+                lido_data,
+                program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                manager,
+                true,
+                false,
+                &mut manager_lamports,
+                &mut [],
+                &system_program::ID,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                validator_list,
+                false,
+                true,
+                &mut validator_list_lamports,
+                validator_list_data,
+                program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &sysvar::rent::ID,
+                false,
+                false,
+                &mut rent_lamports,
+                &mut rent_data,
+                &system_program::ID,
+                false,
+                0,
+            ),
+        ];
+        process_migrate_state_to_v1(program_id, &accounts)
+    }
+
+    #[test]
+    fn test_migrate_state_to_v1() {
+        let program_id = Pubkey::new_unique();
+        let validator_list = Pubkey::new_unique();
+
+        let mut validators = Validators::new(2);
+        validators
+            .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+            .unwrap();
+        let mut maintainers = Maintainers::new(1);
+        maintainers.add(Pubkey::new_unique(), ()).unwrap();
+        let lido_v0 = LidoV0 {
+            manager: Pubkey::new_unique(),
+            validators: validators.clone(),
+            maintainers,
+            ..LidoV0::default()
+        };
+
+        // The version 0 account was allocated for the maximum number of
+        // validators and maintainers.
+        let mut lido_data = lido_v0.try_to_vec().unwrap();
+        lido_data.resize(
+            LIDO_V0_CONSTANT_SIZE + Validators::required_bytes(2) + Maintainers::required_bytes(1),
+            0,
+        );
+        let mut validator_list_data = vec![0; Validators::required_bytes(2)];
+
+        // Only the manager can migrate.
+        assert_eq!(
+            migrate(
+                &program_id,
+                &Pubkey::new_unique(),
+                &validator_list,
+                &mut lido_data,
+                &mut validator_list_data,
+            ),
+            Err(ProgramError::from(LidoError::InvalidManager)),
+        );
+
+        migrate(
+            &program_id,
+            &lido_v0.manager,
+            &validator_list,
+            &mut lido_data,
+            &mut validator_list_data,
+        )
+        .unwrap();
+
+        let lido = try_from_slice_unchecked::<Lido>(&lido_data).unwrap();
+        assert_eq!(lido.lido_version, LIDO_VERSION);
+        assert_eq!(lido.manager, lido_v0.manager);
+        assert_eq!(lido.maintainers, lido_v0.maintainers);
+        assert_eq!(lido.validator_list, validator_list);
+        let migrated_validators =
+            try_from_slice_unchecked::<Validators>(&validator_list_data).unwrap();
+        assert_eq!(migrated_validators, validators);
+
+        // The space where the validators used to be is zeroed.
+        let migrated_size = Lido::calculate_size(1);
+        assert!(lido_data[migrated_size..].iter().all(|byte| *byte == 0));
+
+        // Migrating a second time fails, the state is no longer version 0.
+        let mut other_validator_list_data = vec![0; Validators::required_bytes(2)];
+        assert_eq!(
+            migrate(
+                &program_id,
+                &lido_v0.manager,
+                &Pubkey::new_unique(),
+                &mut lido_data,
+                &mut other_validator_list_data,
+            ),
+            Err(ProgramError::from(LidoError::InvalidLidoVersion)),
+        );
+    }
+}
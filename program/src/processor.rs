@@ -12,22 +12,24 @@ use crate::{
         StakeDepositAccountsInfo, UnstakeAccountsInfo, UpdateExchangeRateAccountsInfo,
         WithdrawAccountsInfo, WithdrawInactiveStakeInfo,
     },
+    lido_view::LidoView,
     logic::{
         burn_st_sol, check_mint, check_rent_exempt, check_unstake_accounts,
-        create_account_even_if_funded, deserialize_lido, distribute_fees,
-        initialize_stake_account_undelegated, mint_st_sol_to, split_stake_account,
-        transfer_stake_authority, CreateAccountOptions, SplitStakeAccounts,
+        check_validator_list_uninitialized, create_account_even_if_funded, deserialize_lido,
+        deserialize_validators, distribute_fees, initialize_stake_account_undelegated,
+        mint_st_sol_to, split_stake_account, transfer_stake_authority, CreateAccountOptions,
+        SplitStakeAccounts,
     },
     metrics::Metrics,
     process_management::{
         process_add_maintainer, process_add_validator, process_change_reward_distribution,
         process_claim_validator_fee, process_deactivate_validator, process_merge_stake,
-        process_remove_maintainer, process_remove_validator,
+        process_migrate_state_to_v1, process_remove_maintainer, process_remove_validator,
     },
     stake_account::{deserialize_stake_account, StakeAccount},
     state::{
-        ExchangeRate, FeeRecipients, Lido, Maintainers, RewardDistribution, Validators,
-        LIDO_CONSTANT_SIZE, LIDO_VERSION,
+        get_sol_balance, get_st_sol_supply, ExchangeRate, FeeRecipients, Lido, Maintainers,
+        RewardDistribution, Validators, LIDO_CONSTANT_SIZE, LIDO_VERSION,
     },
     token::{Lamports, Rational, StLamports},
     vote_instruction, MAXIMUM_UNSTAKE_ACCOUNTS, MINIMUM_STAKE_ACCOUNT_BALANCE, MINT_AUTHORITY,
@@ -79,14 +81,25 @@ pub fn process_initialize(
         return Err(LidoError::AlreadyInUse.into());
     }
 
+    check_validator_list_uninitialized(
+        program_id,
+        rent,
+        accounts.lido,
+        accounts.validator_list,
+        max_validators,
+    )?;
+
     // Bytes required for maintainers
     let bytes_for_maintainers = Maintainers::required_bytes(max_maintainers as usize);
-    // Bytes required for validators
-    let bytes_for_validators = Validators::required_bytes(max_validators as usize);
     // Calculate the expected lido's size
-    let bytes_sum = LIDO_CONSTANT_SIZE + bytes_for_validators + bytes_for_maintainers;
+    let bytes_sum = LIDO_CONSTANT_SIZE + bytes_for_maintainers;
     if bytes_sum != accounts.lido.data_len() {
-        msg!("Incorrect allocated bytes for the provided constrains: max_validator bytes: {}, max_maintainers bytes: {}, constant_size: {}, sum is {}, should be {}", bytes_for_validators, bytes_for_maintainers, LIDO_CONSTANT_SIZE, bytes_sum, accounts.lido.data_len());
+        msg!(
+            "Incorrect allocated bytes for the provided constrains: max_maintainers bytes: {}, constant_size: {}, should be {}",
+            bytes_for_maintainers,
+            LIDO_CONSTANT_SIZE,
+            accounts.lido.data_len()
+        );
         return Err(LidoError::InvalidLidoSize.into());
     }
 
@@ -126,14 +139,15 @@ pub fn process_initialize(
             developer_account: *accounts.developer_account.key,
         },
         metrics: Metrics::new(),
+        validator_list: *accounts.validator_list.key,
         maintainers: Maintainers::new(max_maintainers),
-        validators: Validators::new(max_validators),
     };
 
     // Confirm that the fee recipients are actually stSOL accounts.
     lido.check_is_st_sol_account(accounts.treasury_account)?;
     lido.check_is_st_sol_account(accounts.developer_account)?;
 
+    Validators::new(max_validators).save(accounts.validator_list)?;
     lido.save(accounts.lido)
 }
 
@@ -149,8 +163,15 @@ pub fn process_deposit(
         return Err(ProgramError::InvalidArgument);
     }
 
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    // Deposit is the most common instruction, and it touches only a few fields
+    // of the fixed-size part of the Lido state, so avoid deserializing and
+    // serializing the maintainers.
+    let lido = LidoView::from_account(program_id, accounts.lido)?;
     lido.check_reserve_account(program_id, accounts.lido.key, accounts.reserve_account)?;
+    let st_sol_mint = lido.st_sol_mint();
+    let mint_authority_bump_seed = lido.mint_authority_bump_seed();
+    let exchange_rate = lido.exchange_rate()?;
+    drop(lido);
 
     invoke(
         &system_instruction::transfer(accounts.user.key, accounts.reserve_account.key, amount.0),
@@ -161,10 +182,13 @@ pub fn process_deposit(
         ],
     )?;
 
-    let st_sol_amount = lido.exchange_rate.exchange_sol(amount)?;
+    // This rounds down, so the depositor never receives more stSOL than their
+    // SOL is worth, and the remainder benefits the pool.
+    let st_sol_amount = exchange_rate.exchange_sol(amount)?;
 
     mint_st_sol_to(
-        &lido,
+        &st_sol_mint,
+        mint_authority_bump_seed,
         accounts.lido.key,
         accounts.spl_token,
         accounts.st_sol_mint,
@@ -182,8 +206,8 @@ pub fn process_deposit(
         st_sol_amount
     );
 
-    lido.metrics.deposit_amount.observe(amount)?;
-    lido.save(accounts.lido)
+    let mut lido = LidoView::from_account_mut(program_id, accounts.lido)?;
+    lido.observe_deposit(amount)
 }
 
 pub fn process_stake_deposit(
@@ -193,14 +217,16 @@ pub fn process_stake_deposit(
 ) -> ProgramResult {
     let accounts = StakeDepositAccountsInfo::try_from_slice(raw_accounts)?;
 
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
 
     lido.check_maintainer(accounts.maintainer)?;
     lido.check_reserve_account(program_id, accounts.lido.key, accounts.reserve)?;
     lido.check_stake_authority(program_id, accounts.lido.key, accounts.stake_authority)?;
     lido.check_can_stake_amount(accounts.reserve, accounts.sysvar_rent, amount)?;
 
-    let validator = lido.validators.get(accounts.validator_vote_account.key)?;
+    let validator = validators.get(accounts.validator_vote_account.key)?;
 
     if !validator.entry.active {
         msg!(
@@ -215,8 +241,7 @@ pub fn process_stake_deposit(
     // stake balance, but it limits the power that maintainers have to disturb
     // the balance. More importantly, it ensures that when two maintainers create
     // the same StakeDeposit transaction, only one of them succeeds.
-    let minimum_stake_validator = lido
-        .validators
+    let minimum_stake_validator = validators
         .iter_active_entries()
         .min_by_key(|pair| pair.entry.effective_stake_balance())
         .ok_or(LidoError::NoActiveValidators)?;
@@ -239,9 +264,7 @@ pub fn process_stake_deposit(
     // From now on we will not reference other Lido fields, so we can get the
     // validator as mutable. This is a bit wasteful, but we can optimize when we
     // need dozens of validators, for now we are under the compute limit.
-    let validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account.key)?;
+    let validator = validators.get_mut(accounts.validator_vote_account.key)?;
 
     let stake_account_bump_seed = Lido::check_stake_account(
         program_id,
@@ -396,7 +419,7 @@ pub fn process_stake_deposit(
         )?;
     }
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 /// Unstakes from a validator, the funds are moved to the stake defined by the
@@ -407,12 +430,14 @@ pub fn process_unstake(
     raw_accounts: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = UnstakeAccountsInfo::try_from_slice(raw_accounts)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
     lido.check_maintainer(accounts.maintainer)?;
     lido.check_stake_authority(program_id, accounts.lido.key, accounts.stake_authority)?;
-    let destination_bump_seed = check_unstake_accounts(program_id, &lido, &accounts)?;
+    let destination_bump_seed = check_unstake_accounts(program_id, &validators, &accounts)?;
 
-    let validator = lido.validators.get(accounts.validator_vote_account.key)?;
+    let validator = validators.get(accounts.validator_vote_account.key)?;
 
     // Because `WithdrawInactiveStake` needs to reference all stake and unstake
     // accounts in a single transaction, we shouldn't have too many of them.
@@ -439,7 +464,7 @@ pub fn process_unstake(
 
     split_stake_account(
         accounts.lido.key,
-        &lido,
+        lido.stake_authority_bump_seed,
         &SplitStakeAccounts {
             source_stake_account: accounts.source_stake_account,
             destination_stake_account: accounts.destination_unstake_account,
@@ -474,9 +499,7 @@ pub fn process_unstake(
         ]],
     )?;
 
-    let validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account.key)?;
+    let validator = validators.get_mut(accounts.validator_vote_account.key)?;
 
     if validator.entry.active {
         // For active validators, we don't allow their stake accounts to contain
@@ -511,7 +534,7 @@ pub fn process_unstake(
     validator.entry.unstake_accounts_balance = (validator.entry.unstake_accounts_balance + amount)?;
     validator.entry.unstake_seeds.end += 1;
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 pub fn process_update_exchange_rate(
@@ -519,26 +542,29 @@ pub fn process_update_exchange_rate(
     raw_accounts: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = UpdateExchangeRateAccountsInfo::try_from_slice(raw_accounts)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut lido = LidoView::from_account_mut(program_id, accounts.lido)?;
+    let validators =
+        deserialize_validators(program_id, &lido.validator_list(), accounts.validator_list)?;
     lido.check_reserve_account(program_id, accounts.lido.key, accounts.reserve)?;
 
     let clock = Clock::from_account_info(accounts.sysvar_clock)?;
     let rent = Rent::from_account_info(accounts.sysvar_rent)?;
 
-    if lido.exchange_rate.computed_in_epoch >= clock.epoch {
+    let exchange_rate = lido.exchange_rate()?;
+    if exchange_rate.computed_in_epoch >= clock.epoch {
         msg!(
             "The exchange rate was already updated in epoch {}.",
-            lido.exchange_rate.computed_in_epoch
+            exchange_rate.computed_in_epoch
         );
         msg!("It can only be done once per epoch, so we are going to abort this transaction.");
         return Err(LidoError::ExchangeRateAlreadyUpToDate.into());
     }
 
-    lido.exchange_rate.computed_in_epoch = clock.epoch;
-    lido.exchange_rate.sol_balance = lido.get_sol_balance(&rent, accounts.reserve)?;
-    lido.exchange_rate.st_sol_supply = lido.get_st_sol_supply(accounts.st_sol_mint)?;
-
-    lido.save(accounts.lido)
+    lido.set_exchange_rate(&ExchangeRate {
+        computed_in_epoch: clock.epoch,
+        sol_balance: get_sol_balance(&validators, &rent, accounts.reserve)?,
+        st_sol_supply: get_st_sol_supply(&lido.st_sol_mint(), &validators, accounts.st_sol_mint)?,
+    })
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -656,7 +682,9 @@ pub fn process_withdraw_inactive_stake(
     raw_accounts: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = WithdrawInactiveStakeInfo::try_from_slice(raw_accounts)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = deserialize_lido(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list, accounts.validator_list)?;
     let stake_history = StakeHistory::from_account_info(accounts.sysvar_stake_history)?;
     let clock = Clock::from_account_info(accounts.sysvar_clock)?;
     let rent = Rent::from_account_info(accounts.sysvar_rent)?;
@@ -665,9 +693,7 @@ pub fn process_withdraw_inactive_stake(
     // and confirm that they can receive stSOL.
     lido.check_reserve_account(program_id, accounts.lido.key, accounts.reserve)?;
 
-    let validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account.key)?;
+    let validator = validators.get_mut(accounts.validator_vote_account.key)?;
 
     let mut stake_observed_total = Lamports(0);
     let mut excess_removed = Lamports(0);
@@ -818,7 +844,7 @@ pub fn process_withdraw_inactive_stake(
         .add(validator.entry.unstake_accounts_balance)
         .expect("If Solido has enough SOL to make this overflow, something has gone very wrong.");
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)
 }
 
 /// Collects the validator fee from the validator vote account and distributes
@@ -832,7 +858,9 @@ pub fn process_collect_validator_fee(
     raw_accounts: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = CollectValidatorFeeInfo::try_from_slice(raw_accounts)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = LidoView::from_account(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list(), accounts.validator_list)?;
     let rent = Rent::from_account_info(accounts.sysvar_rent)?;
 
     // Confirm that the passed accounts are the ones configured in the state,
@@ -846,7 +874,7 @@ pub fn process_collect_validator_fee(
     lido.check_exchange_rate_last_epoch(&clock, "CollectValidatorFee")?;
 
     // Confirm that the vote account passed in is actually part of the validator set.
-    lido.validators.get(accounts.validator_vote_account.key)?;
+    validators.get(accounts.validator_vote_account.key)?;
 
     let rewards_withdraw_authority = lido.check_rewards_withdraw_authority(
         program_id,
@@ -864,9 +892,10 @@ pub fn process_collect_validator_fee(
         .expect("Vote account should be rent exempt");
 
     let fees = lido
-        .reward_distribution
-        .split_reward(Lamports(rewards), lido.validators.len() as u64)?;
-    distribute_fees(&mut lido, &accounts, fees)?;
+        .reward_distribution()?
+        .split_reward(Lamports(rewards), validators.len() as u64)?;
+    let mut metrics = lido.metrics()?;
+    distribute_fees(&lido, &mut metrics, &mut validators, &accounts, fees)?;
 
     invoke_signed(
         &vote_instruction::withdraw(
@@ -884,10 +913,14 @@ pub fn process_collect_validator_fee(
         &[&[
             accounts.lido.key.as_ref(),
             REWARDS_WITHDRAW_AUTHORITY,
-            &[lido.rewards_withdraw_authority_bump_seed],
+            &[lido.rewards_withdraw_authority_bump_seed()],
         ]],
     )?;
-    lido.save(accounts.lido)
+    drop(lido);
+
+    validators.save(accounts.validator_list)?;
+    let mut lido = LidoView::from_account_mut(program_id, accounts.lido)?;
+    lido.set_metrics(&metrics)
 }
 
 /// Splits a stake account from a validator's stake account.
@@ -899,21 +932,26 @@ pub fn process_withdraw(
     raw_accounts: &[AccountInfo],
 ) -> ProgramResult {
     let accounts = WithdrawAccountsInfo::try_from_slice(raw_accounts)?;
-    let mut lido = deserialize_lido(program_id, accounts.lido)?;
+    let lido = LidoView::from_account(program_id, accounts.lido)?;
+    let mut validators =
+        deserialize_validators(program_id, &lido.validator_list(), accounts.validator_list)?;
     let clock = Clock::from_account_info(accounts.sysvar_clock)?;
     lido.check_exchange_rate_last_epoch(&clock, "Withdraw")?;
+    let st_sol_mint = lido.st_sol_mint();
+    let stake_authority_bump_seed = lido.stake_authority_bump_seed();
+    let exchange_rate = lido.exchange_rate()?;
+    drop(lido);
 
     // We should withdraw from the validator that has the most effective stake.
     // With effective here we mean "total in stake accounts" - "total in unstake
     // accounts", regardless of whether the stake in those accounts is active or not.
-    let validator = lido.validators.get(accounts.validator_vote_account.key)?;
+    let validator = validators.get(accounts.validator_vote_account.key)?;
 
     // Confirm that there is no other validator with a higher balance that
     // we could withdraw from. This alone is not sufficient to guarantee a uniform
     // stake balance, but prevents things from becoming more unbalanced than
     // necessary.
-    let maximum_stake_validator = lido
-        .validators
+    let maximum_stake_validator = validators
         .entries
         .iter()
         .max_by_key(|pair| pair.entry.effective_stake_balance())
@@ -946,11 +984,10 @@ pub fn process_withdraw(
         return Err(LidoError::InvalidStakeAccount.into());
     }
 
-    // Reduce validator's balance
-    let sol_to_withdraw = lido.exchange_rate.exchange_st_sol(amount)?;
-    let provided_validator = lido
-        .validators
-        .get_mut(accounts.validator_vote_account.key)?;
+    // Reduce validator's balance. The conversion rounds down, so we never pay
+    // out more SOL than the burned stSOL is worth.
+    let sol_to_withdraw = exchange_rate.exchange_st_sol(amount)?;
+    let provided_validator = validators.get_mut(accounts.validator_vote_account.key)?;
 
     let source_balance = Lamports(accounts.source_stake_account.lamports());
 
@@ -991,14 +1028,11 @@ pub fn process_withdraw(
         (provided_validator.entry.stake_accounts_balance - sol_to_withdraw)?;
 
     // Burn stSol tokens
-    burn_st_sol(&lido, &accounts, amount)?;
-
-    // Update withdrawal metrics.
-    lido.metrics.observe_withdrawal(amount, sol_to_withdraw)?;
+    burn_st_sol(&st_sol_mint, &accounts, amount)?;
 
     split_stake_account(
         accounts.lido.key,
-        &lido,
+        stake_authority_bump_seed,
         &SplitStakeAccounts {
             source_stake_account: accounts.source_stake_account,
             destination_stake_account: accounts.destination_stake_account,
@@ -1011,14 +1045,19 @@ pub fn process_withdraw(
     )?;
 
     // Give control of the stake to the user.
-    transfer_stake_authority(&accounts, lido.stake_authority_bump_seed)?;
+    transfer_stake_authority(&accounts, stake_authority_bump_seed)?;
 
     // Explain what we did in the logs, because block explorers can be an
     // inscrutable mess of accounts, especially without special parsers for
     // Solido transactions. With the logs, we can still identify what happened.
     msg!("Solido: Withdrew {} for {}.", amount, sol_to_withdraw);
 
-    lido.save(accounts.lido)
+    validators.save(accounts.validator_list)?;
+
+    // Withdraw only touches the fixed-size part of the Lido state, so avoid
+    // deserializing and serializing the maintainers.
+    let mut lido = LidoView::from_account_mut(program_id, accounts.lido)?;
+    lido.observe_withdrawal(amount, sol_to_withdraw)
 }
 
 /// Processes [Instruction](enum.Instruction.html).
@@ -1058,5 +1097,6 @@ pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> P
         LidoInstruction::AddMaintainer => process_add_maintainer(program_id, accounts),
         LidoInstruction::RemoveMaintainer => process_remove_maintainer(program_id, accounts),
         LidoInstruction::MergeStake => process_merge_stake(program_id, accounts),
+        LidoInstruction::MigrateStateToV1 => process_migrate_state_to_v1(program_id, accounts),
     }
 }
@@ -37,6 +37,8 @@ pub struct StakeAccount {
     pub balance: StakeBalance,
     pub credits_observed: u64,
     pub activation_epoch: Epoch,
+    /// The epoch in which the stake started deactivating, `Epoch::MAX` if it did not.
+    pub deactivation_epoch: Epoch,
     pub seed: u64,
 }
 
@@ -226,6 +228,7 @@ impl StakeAccount {
             },
             credits_observed: stake.credits_observed,
             activation_epoch: stake.delegation.activation_epoch,
+            deactivation_epoch: stake.delegation.deactivation_epoch,
             seed,
         }
     }
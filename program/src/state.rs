@@ -28,17 +28,32 @@ use crate::{
 };
 use crate::{REWARDS_WITHDRAW_AUTHORITY, VALIDATOR_STAKE_ACCOUNT, VALIDATOR_UNSTAKE_ACCOUNT};
 
-pub const LIDO_VERSION: u8 = 0;
+/// Version of the layout of the `Lido` struct.
+///
+/// * Version 0 stored the validators in the Solido account itself.
+/// * Version 1 stores them in a separate validator list account.
+///
+/// Version 0 instances can be upgraded with `MigrateStateToV1`.
+pub const LIDO_VERSION: u8 = 1;
 
-/// Size of a serialized `Lido` struct excluding validators and maintainers.
+/// Size of a serialized `Lido` struct excluding maintainers.
 ///
 /// To update this, run the tests and replace the value here with the test output.
-pub const LIDO_CONSTANT_SIZE: usize = 357;
+pub const LIDO_CONSTANT_SIZE: usize = 389;
+
+/// Size of a serialized [`LidoV0`] struct excluding validators and maintainers.
+pub const LIDO_V0_CONSTANT_SIZE: usize = 357;
 pub const VALIDATOR_CONSTANT_SIZE: usize = 89;
 
 pub type Validators = AccountMap<Validator>;
 
 impl Validators {
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        // Write through a temporary slice, see [`Lido::save`].
+        BorshSerialize::serialize(self, &mut &mut account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
     pub fn iter_active(&self) -> impl Iterator<Item = &Validator> {
         self.iter_entries().filter(|&v| v.active)
     }
@@ -143,6 +158,10 @@ pub struct ExchangeRate {
 
 impl ExchangeRate {
     /// Convert SOL to stSOL.
+    ///
+    /// This rounds down, in favour of the pool: when we mint stSOL in return
+    /// for SOL (on deposit, or when paying fees), we never mint more stSOL than
+    /// the SOL is worth.
     pub fn exchange_sol(&self, amount: Lamports) -> token::Result<StLamports> {
         // The exchange rate starts out at 1:1, if there are no deposits yet.
         // If we minted stSOL but there is no SOL, then also assume a 1:1 rate.
@@ -155,13 +174,33 @@ impl ExchangeRate {
             denominator: self.sol_balance.0,
         };
 
-        // The result is in Lamports, because the type system considers Rational
+        // The result is a u64, because the type system considers Rational
         // dimensionless, but in this case `rate` has dimensions stSOL/SOL, so
-        // we need to re-wrap the result in the right type.
-        (amount * rate).map(|x| StLamports(x.0))
+        // we need to wrap the result in the right type.
+        amount.mul_unrounded(rate).round_down().map(StLamports)
+    }
+
+    /// Confirm that the exchange rate was computed in the current epoch.
+    ///
+    /// `method` is the name of the instruction that needs an up-to-date rate.
+    pub fn check_computed_in_epoch(&self, clock: &Clock, method: &str) -> Result<(), LidoError> {
+        if self.computed_in_epoch < clock.epoch {
+            msg!(
+                "The exchange rate is outdated, it was last computed in epoch {}, \
+                but now it is epoch {}.",
+                self.computed_in_epoch,
+                clock.epoch,
+            );
+            msg!("Please call UpdateExchangeRate before calling {}.", method);
+            return Err(LidoError::ExchangeRateNotUpdatedInThisEpoch);
+        }
+        Ok(())
     }
 
     /// Convert stSOL to SOL.
+    ///
+    /// This rounds down, in favour of the pool: when we burn stSOL on withdrawal,
+    /// we never pay out more SOL than the stSOL is worth.
     pub fn exchange_st_sol(&self, amount: StLamports) -> Result<Lamports, LidoError> {
         // If there is no stSOL in existence, it cannot be exchanged.
         if self.st_sol_supply == StLamports(0) {
@@ -174,11 +213,145 @@ impl ExchangeRate {
             denominator: self.st_sol_supply.0,
         };
 
-        // The result is in StLamports, because the type system considers Rational
+        // The result is a u64, because the type system considers Rational
         // dimensionless, but in this case `rate` has dimensions SOL/stSOL, so
-        // we need to re-wrap the result in the right type.
-        Ok((amount * rate).map(|x| Lamports(x.0))?)
+        // we need to wrap the result in the right type.
+        Ok(amount.mul_unrounded(rate).round_down().map(Lamports)?)
+    }
+}
+
+/// Confirm that the given account is the stSOL mint `st_sol_mint`.
+pub fn check_mint_is_st_sol_mint(
+    st_sol_mint: &Pubkey,
+    mint_account_info: &AccountInfo,
+) -> ProgramResult {
+    if st_sol_mint != mint_account_info.key {
+        msg!(
+            "Expected to find our stSOL mint ({}), but got {} instead.",
+            st_sol_mint,
+            mint_account_info.key
+        );
+        return Err(LidoError::InvalidStSolAccount.into());
+    }
+    Ok(())
+}
+
+/// Confirm that the given account is an SPL token account with `st_sol_mint` as mint.
+pub fn check_is_st_sol_account(
+    st_sol_mint: &Pubkey,
+    token_account_info: &AccountInfo,
+) -> ProgramResult {
+    if token_account_info.owner != &spl_token::id() {
+        msg!(
+            "Expected SPL token account to be owned by {}, but it's owned by {} instead.",
+            spl_token::id(),
+            token_account_info.owner
+        );
+        return Err(LidoError::InvalidStSolAccountOwner.into());
+    }
+    let token_account =
+        match spl_token::state::Account::unpack_from_slice(&token_account_info.data.borrow()) {
+            Ok(account) => account,
+            Err(..) => {
+                msg!(
+                    "Expected an SPL token account at {}.",
+                    token_account_info.key
+                );
+                return Err(LidoError::InvalidStSolAccount.into());
+            }
+        };
+
+    if token_account.mint != *st_sol_mint {
+        msg!(
+            "Expected mint of {} to be our stSOL mint ({}), but found {}.",
+            token_account_info.key,
+            st_sol_mint,
+            token_account.mint,
+        );
+        return Err(LidoError::InvalidFeeRecipient.into());
+    }
+    Ok(())
+}
+
+/// Confirm that the given account is the validator list at `validator_list_address`.
+pub fn check_validator_list(
+    validator_list_address: &Pubkey,
+    validator_list: &AccountInfo,
+) -> ProgramResult {
+    if validator_list_address != validator_list.key {
+        msg!(
+            "Expected validator list {}, but got {} instead.",
+            validator_list_address,
+            validator_list.key
+        );
+        return Err(LidoError::InvalidValidatorList.into());
     }
+    Ok(())
+}
+
+/// Confirm that the given account is `manager`, the manager stored in the state.
+pub fn check_manager(manager: &Pubkey, manager_account_info: &AccountInfo) -> ProgramResult {
+    if manager != manager_account_info.key {
+        msg!("Invalid manager, not the same as the one stored in state");
+        return Err(LidoError::InvalidManager.into());
+    }
+    Ok(())
+}
+
+/// Return the address of the program-derived account for `seed`, derived from
+/// the Solido address with the given bump seed.
+pub fn get_program_derived_address(
+    program_id: &Pubkey,
+    solido_address: &Pubkey,
+    seed: &[u8],
+    bump_seed: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[&solido_address.to_bytes()[..], seed, &[bump_seed]],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+/// Compute the total amount of SOL managed by a Solido instance.
+///
+/// See [`Lido::get_sol_balance`].
+pub fn get_sol_balance(
+    validators: &Validators,
+    rent: &Rent,
+    reserve: &AccountInfo,
+) -> Result<Lamports, LidoError> {
+    let effective_reserve_balance = get_reserve_available_balance(rent, reserve)?;
+
+    // The remaining SOL managed is all in stake accounts.
+    let validator_balance: token::Result<Lamports> = validators
+        .iter_entries()
+        .map(|v| v.stake_accounts_balance)
+        .sum();
+
+    let result = validator_balance.and_then(|s| s + effective_reserve_balance)?;
+
+    Ok(result)
+}
+
+/// Return the total amount of stSOL in existence, for the mint `st_sol_mint`.
+///
+/// See [`Lido::get_st_sol_supply`].
+pub fn get_st_sol_supply(
+    st_sol_mint: &Pubkey,
+    validators: &Validators,
+    st_sol_mint_account_info: &AccountInfo,
+) -> Result<StLamports, ProgramError> {
+    check_mint_is_st_sol_mint(st_sol_mint, st_sol_mint_account_info)?;
+
+    let st_sol_mint = Mint::unpack_from_slice(&st_sol_mint_account_info.data.borrow())?;
+    let minted_supply = StLamports(st_sol_mint.supply);
+
+    let credit: token::Result<StLamports> = validators.iter_entries().map(|v| v.fee_credit).sum();
+
+    let result = credit.and_then(|s| s + minted_supply)?;
+
+    Ok(result)
 }
 
 #[repr(C)]
@@ -219,8 +392,14 @@ pub struct Lido {
     /// these metrics.
     pub metrics: Metrics,
 
-    /// Map of enrolled validators, maps their vote account to `Validator` details.
-    pub validators: Validators,
+    /// Address of the account that holds the validator list.
+    ///
+    /// The map of enrolled validators, from their vote account to `Validator`
+    /// details, lives in a separate account, so that the number of validators
+    /// is not limited by the size of the Solido account. Instructions that need
+    /// the validators take this account as well.
+    #[serde(serialize_with = "serialize_b58")]
+    pub validator_list: Pubkey,
 
     /// The set of maintainers.
     ///
@@ -232,73 +411,38 @@ pub struct Lido {
 }
 
 impl Lido {
-    /// Calculates the total size of Lido given two variables: `max_validators`
-    /// and `max_maintainers`, the maximum number of maintainers and validators,
-    /// respectively. It creates default structures for both and sum its sizes
-    /// with Lido's constant size.
-    pub fn calculate_size(max_validators: u32, max_maintainers: u32) -> usize {
+    /// Calculates the total size of Lido given `max_maintainers`, the maximum
+    /// number of maintainers. It creates a default structure for the
+    /// maintainers and sums its size with Lido's constant size.
+    ///
+    /// The validators live in a separate account, which needs
+    /// `Validators::required_bytes(max_validators)` bytes.
+    pub fn calculate_size(max_maintainers: u32) -> usize {
         let lido_instance = Lido {
-            validators: Validators::new_fill_default(max_validators),
             maintainers: Maintainers::new_fill_default(max_maintainers),
             ..Default::default()
         };
         get_instance_packed_len(&lido_instance).unwrap()
     }
 
+    /// Confirm that the given account is the validator list of this instance.
+    pub fn check_validator_list(&self, validator_list: &AccountInfo) -> ProgramResult {
+        check_validator_list(&self.validator_list, validator_list)
+    }
+
     /// Confirm that the given account is Solido's stSOL mint.
     pub fn check_mint_is_st_sol_mint(&self, mint_account_info: &AccountInfo) -> ProgramResult {
-        if &self.st_sol_mint != mint_account_info.key {
-            msg!(
-                "Expected to find our stSOL mint ({}), but got {} instead.",
-                self.st_sol_mint,
-                mint_account_info.key
-            );
-            return Err(LidoError::InvalidStSolAccount.into());
-        }
-        Ok(())
+        check_mint_is_st_sol_mint(&self.st_sol_mint, mint_account_info)
     }
 
     /// Confirm that the given account is an SPL token account with our stSOL mint as mint.
     pub fn check_is_st_sol_account(&self, token_account_info: &AccountInfo) -> ProgramResult {
-        if token_account_info.owner != &spl_token::id() {
-            msg!(
-                "Expected SPL token account to be owned by {}, but it's owned by {} instead.",
-                spl_token::id(),
-                token_account_info.owner
-            );
-            return Err(LidoError::InvalidStSolAccountOwner.into());
-        }
-        let token_account =
-            match spl_token::state::Account::unpack_from_slice(&token_account_info.data.borrow()) {
-                Ok(account) => account,
-                Err(..) => {
-                    msg!(
-                        "Expected an SPL token account at {}.",
-                        token_account_info.key
-                    );
-                    return Err(LidoError::InvalidStSolAccount.into());
-                }
-            };
-
-        if token_account.mint != self.st_sol_mint {
-            msg!(
-                "Expected mint of {} to be our stSOL mint ({}), but found {}.",
-                token_account_info.key,
-                self.st_sol_mint,
-                token_account.mint,
-            );
-            return Err(LidoError::InvalidFeeRecipient.into());
-        }
-        Ok(())
+        check_is_st_sol_account(&self.st_sol_mint, token_account_info)
     }
 
     /// Checks if the passed manager is the same as the one stored in the state
     pub fn check_manager(&self, manager: &AccountInfo) -> ProgramResult {
-        if &self.manager != manager.key {
-            msg!("Invalid manager, not the same as the one stored in state");
-            return Err(LidoError::InvalidManager.into());
-        }
-        Ok(())
+        check_manager(&self.manager, manager)
     }
 
     /// Checks if the passed maintainer belong to the list of maintainers
@@ -321,11 +465,8 @@ impl Lido {
     ///
     /// Also confirm that the recipient is still an stSOL account.
     pub fn check_treasury_fee_st_sol_account(&self, st_sol_account: &AccountInfo) -> ProgramResult {
-        if &self.fee_recipients.treasury_account != st_sol_account.key {
-            msg!("Invalid treasury fee stSOL account, not the same as the one stored in state.");
-            return Err(LidoError::InvalidFeeRecipient.into());
-        }
-        self.check_is_st_sol_account(st_sol_account)
+        self.fee_recipients
+            .check_treasury_fee_st_sol_account(&self.st_sol_mint, st_sol_account)
     }
 
     /// Check if the passed developer fee account is the one configured.
@@ -335,11 +476,8 @@ impl Lido {
         &self,
         st_sol_account: &AccountInfo,
     ) -> ProgramResult {
-        if &self.fee_recipients.developer_account != st_sol_account.key {
-            msg!("Invalid developer fee stSOL account, not the same as the one stored in state.");
-            return Err(LidoError::InvalidFeeRecipient.into());
-        }
-        self.check_is_st_sol_account(st_sol_account)
+        self.fee_recipients
+            .check_developer_fee_st_sol_account(&self.st_sol_mint, st_sol_account)
     }
 
     /// Return the address of the reserve account, the account where SOL gets
@@ -421,15 +559,12 @@ impl Lido {
         program_id: &Pubkey,
         solido_address: &Pubkey,
     ) -> Result<Pubkey, ProgramError> {
-        Pubkey::create_program_address(
-            &[
-                &solido_address.to_bytes()[..],
-                REWARDS_WITHDRAW_AUTHORITY,
-                &[self.rewards_withdraw_authority_bump_seed],
-            ],
+        get_program_derived_address(
             program_id,
+            solido_address,
+            REWARDS_WITHDRAW_AUTHORITY,
+            self.rewards_withdraw_authority_bump_seed,
         )
-        .map_err(|_| ProgramError::InvalidSeeds)
     }
 
     /// Confirm that the rewards withdraw authority belongs to this Lido
@@ -542,7 +677,12 @@ impl Lido {
         // runtime complained that an account's size was modified by a program
         // that wasn't its owner, double check that the name passed to
         // ProgramTest matches the name of the crate.
-        BorshSerialize::serialize(self, &mut *account.data.borrow_mut())?;
+        //
+        // Writing to a `&mut [u8]` advances the slice past the written bytes,
+        // so we write through a temporary slice. If we wrote to the slice in
+        // the `AccountInfo` itself, the account would appear to shrink when
+        // the program runs natively rather than in BPF.
+        BorshSerialize::serialize(self, &mut &mut account.data.borrow_mut()[..])?;
         Ok(())
     }
 
@@ -557,39 +697,23 @@ impl Lido {
     /// these will not be included.
     pub fn get_sol_balance(
         &self,
+        validators: &Validators,
         rent: &Rent,
         reserve: &AccountInfo,
     ) -> Result<Lamports, LidoError> {
-        let effective_reserve_balance = get_reserve_available_balance(rent, reserve)?;
-
-        // The remaining SOL managed is all in stake accounts.
-        let validator_balance: token::Result<Lamports> = self
-            .validators
-            .iter_entries()
-            .map(|v| v.stake_accounts_balance)
-            .sum();
-
-        let result = validator_balance.and_then(|s| s + effective_reserve_balance)?;
-
-        Ok(result)
+        get_sol_balance(validators, rent, reserve)
     }
 
     /// Return the total amount of stSOL in existence.
     ///
     /// The total is the amount minted so far, plus any unminted rewards that validators
     /// are entitled to, but haven’t claimed yet.
-    pub fn get_st_sol_supply(&self, st_sol_mint: &AccountInfo) -> Result<StLamports, ProgramError> {
-        self.check_mint_is_st_sol_mint(st_sol_mint)?;
-
-        let st_sol_mint = Mint::unpack_from_slice(&st_sol_mint.data.borrow())?;
-        let minted_supply = StLamports(st_sol_mint.supply);
-
-        let credit: token::Result<StLamports> =
-            self.validators.iter_entries().map(|v| v.fee_credit).sum();
-
-        let result = credit.and_then(|s| s + minted_supply)?;
-
-        Ok(result)
+    pub fn get_st_sol_supply(
+        &self,
+        validators: &Validators,
+        st_sol_mint: &AccountInfo,
+    ) -> Result<StLamports, ProgramError> {
+        get_st_sol_supply(&self.st_sol_mint, validators, st_sol_mint)
     }
 
     pub fn check_exchange_rate_last_epoch(
@@ -597,17 +721,53 @@ impl Lido {
         clock: &Clock,
         method: &str,
     ) -> Result<(), LidoError> {
-        if self.exchange_rate.computed_in_epoch < clock.epoch {
-            msg!(
-                "The exchange rate is outdated, it was last computed in epoch {}, \
-                but now it is epoch {}.",
-                self.exchange_rate.computed_in_epoch,
-                clock.epoch,
-            );
-            msg!("Please call UpdateExchangeRate before calling {}.", method);
-            return Err(LidoError::ExchangeRateNotUpdatedInThisEpoch);
-        }
-        Ok(())
+        self.exchange_rate.check_computed_in_epoch(clock, method)
+    }
+}
+
+/// The `Lido` struct in the layout of version 0.
+///
+/// In this version, the validators were stored in the Solido account itself,
+/// between the metrics and the maintainers. This is only used to migrate
+/// existing instances to the current layout.
+#[repr(C)]
+#[derive(Clone, Debug, Default, BorshDeserialize, BorshSerialize, Eq, PartialEq)]
+pub struct LidoV0 {
+    pub lido_version: u8,
+    pub manager: Pubkey,
+    pub st_sol_mint: Pubkey,
+    pub exchange_rate: ExchangeRate,
+    pub sol_reserve_account_bump_seed: u8,
+    pub stake_authority_bump_seed: u8,
+    pub mint_authority_bump_seed: u8,
+    pub rewards_withdraw_authority_bump_seed: u8,
+    pub reward_distribution: RewardDistribution,
+    pub fee_recipients: FeeRecipients,
+    pub metrics: Metrics,
+    pub validators: Validators,
+    pub maintainers: Maintainers,
+}
+
+impl LidoV0 {
+    /// Convert to the version 1 layout, with the validators stored in the
+    /// account at `validator_list`.
+    pub fn into_v1(self, validator_list: Pubkey) -> (Lido, Validators) {
+        let lido = Lido {
+            lido_version: 1,
+            manager: self.manager,
+            st_sol_mint: self.st_sol_mint,
+            exchange_rate: self.exchange_rate,
+            sol_reserve_account_bump_seed: self.sol_reserve_account_bump_seed,
+            stake_authority_bump_seed: self.stake_authority_bump_seed,
+            mint_authority_bump_seed: self.mint_authority_bump_seed,
+            rewards_withdraw_authority_bump_seed: self.rewards_withdraw_authority_bump_seed,
+            reward_distribution: self.reward_distribution,
+            fee_recipients: self.fee_recipients,
+            metrics: self.metrics,
+            validator_list,
+            maintainers: self.maintainers,
+        };
+        (lido, self.validators)
     }
 }
 
@@ -819,6 +979,38 @@ pub struct FeeRecipients {
     pub developer_account: Pubkey,
 }
 
+impl FeeRecipients {
+    /// Check if the passed treasury fee account is the one configured.
+    ///
+    /// Also confirm that the recipient is still an account for `st_sol_mint`.
+    pub fn check_treasury_fee_st_sol_account(
+        &self,
+        st_sol_mint: &Pubkey,
+        st_sol_account: &AccountInfo,
+    ) -> ProgramResult {
+        if &self.treasury_account != st_sol_account.key {
+            msg!("Invalid treasury fee stSOL account, not the same as the one stored in state.");
+            return Err(LidoError::InvalidFeeRecipient.into());
+        }
+        check_is_st_sol_account(st_sol_mint, st_sol_account)
+    }
+
+    /// Check if the passed developer fee account is the one configured.
+    ///
+    /// Also confirm that the recipient is still an account for `st_sol_mint`.
+    pub fn check_developer_fee_st_sol_account(
+        &self,
+        st_sol_mint: &Pubkey,
+        st_sol_account: &AccountInfo,
+    ) -> ProgramResult {
+        if &self.developer_account != st_sol_account.key {
+            msg!("Invalid developer fee stSOL account, not the same as the one stored in state.");
+            return Err(LidoError::InvalidFeeRecipient.into());
+        }
+        check_is_st_sol_account(st_sol_mint, st_sol_account)
+    }
+}
+
 impl RewardDistribution {
     pub fn sum(&self) -> u64 {
         // These adds don't overflow because we widen from u32 to u64 first.
@@ -864,13 +1056,27 @@ impl RewardDistribution {
     pub fn split_reward(&self, amount: Lamports, num_validators: u64) -> token::Result<Fees> {
         use std::ops::Add;
 
-        let treasury_amount = (amount * self.treasury_fraction())?;
-        let developer_amount = (amount * self.developer_fraction())?;
+        // Fees are rounded down, in favour of the pool: any fraction of a
+        // Lamport that we do not pay as a fee, goes to stSOL appreciation.
+        let treasury_amount = Lamports(
+            amount
+                .mul_unrounded(self.treasury_fraction())
+                .round_down()?,
+        );
+        let developer_amount = Lamports(
+            amount
+                .mul_unrounded(self.developer_fraction())
+                .round_down()?,
+        );
 
         // The actual amount that goes to validation can be a tiny bit lower
         // than the target amount, when the number of validators does not divide
         // the target amount. The loss is at most `num_validators` Lamports.
-        let validation_amount = (amount * self.validation_fraction())?;
+        let validation_amount = Lamports(
+            amount
+                .mul_unrounded(self.validation_fraction())
+                .round_down()?,
+        );
         let reward_per_validator = (validation_amount / num_validators)?;
 
         // Sanity check: We should not produce more fees than we had to split in
@@ -914,6 +1120,9 @@ pub struct Fees {
 #[cfg(test)]
 mod test_lido {
     use super::*;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use solana_program::native_token::LAMPORTS_PER_SOL;
     use solana_program::program_error::ProgramError;
 
     #[test]
@@ -957,33 +1166,97 @@ mod test_lido {
 
     #[test]
     fn test_lido_constant_size() {
-        // The minimal size of the struct is its size without any validators and
-        // maintainers.
+        // The minimal size of the struct is its size without any maintainers.
         let minimal = Lido::default();
         let mut data = Vec::new();
         BorshSerialize::serialize(&minimal, &mut data).unwrap();
 
         let num_entries = 0;
-        let size_validators = Validators::required_bytes(num_entries);
         let size_maintainers = Maintainers::required_bytes(num_entries);
 
+        assert_eq!(data.len() - size_maintainers, LIDO_CONSTANT_SIZE);
+
+        let minimal_v0 = LidoV0::default();
+        let data = minimal_v0.try_to_vec().unwrap();
+        let size_validators = Validators::required_bytes(num_entries);
         assert_eq!(
             data.len() - size_validators - size_maintainers,
-            LIDO_CONSTANT_SIZE
+            LIDO_V0_CONSTANT_SIZE
         );
     }
 
     #[test]
-    fn test_lido_serialization_roundtrips() {
+    fn test_lido_v0_decodes_and_migrates() {
         use solana_sdk::borsh::try_from_slice_unchecked;
 
-        let mut validators = Validators::new(10_000);
+        let mut validators = Validators::new(3);
         validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
+        let mut maintainers = Maintainers::new(2);
+        maintainers.add(Pubkey::new_unique(), ()).unwrap();
+        let mut metrics = Metrics::new();
+        metrics.observe_deposit(Lamports(19)).unwrap();
+
+        let expected = Lido {
+            lido_version: 1,
+            manager: Pubkey::new_unique(),
+            st_sol_mint: Pubkey::new_unique(),
+            exchange_rate: ExchangeRate {
+                computed_in_epoch: 11,
+                sol_balance: Lamports(13),
+                st_sol_supply: StLamports(17),
+            },
+            sol_reserve_account_bump_seed: 1,
+            stake_authority_bump_seed: 2,
+            mint_authority_bump_seed: 3,
+            rewards_withdraw_authority_bump_seed: 4,
+            reward_distribution: RewardDistribution {
+                treasury_fee: 2,
+                validation_fee: 3,
+                developer_fee: 4,
+                st_sol_appreciation: 7,
+            },
+            fee_recipients: FeeRecipients {
+                treasury_account: Pubkey::new_unique(),
+                developer_account: Pubkey::new_unique(),
+            },
+            metrics,
+            validator_list: Pubkey::new_unique(),
+            maintainers: maintainers.clone(),
+        };
+
+        // A version 0 account starts with the same fixed-size fields as the
+        // current version, followed by the validators and the maintainers. The
+        // account is allocated for the maximum number of both.
+        let mut data = expected.try_to_vec().unwrap()[..LIDO_V0_CONSTANT_SIZE].to_vec();
+        data[0] = 0;
+        data.extend(validators.try_to_vec().unwrap());
+        data.extend(maintainers.try_to_vec().unwrap());
+        let account_size =
+            LIDO_V0_CONSTANT_SIZE + Validators::required_bytes(3) + Maintainers::required_bytes(2);
+        data.resize(account_size, 0);
+
+        let lido_v0 = try_from_slice_unchecked::<LidoV0>(&data).unwrap();
+        assert_eq!(lido_v0.lido_version, 0);
+        assert_eq!(lido_v0.validators, validators);
+        assert_eq!(lido_v0.maintainers, maintainers);
+
+        let (lido, migrated_validators) = lido_v0.into_v1(expected.validator_list);
+        assert_eq!(lido, expected);
+        assert_eq!(migrated_validators, validators);
+
+        // The migrated state fits in the account that held the version 0 state.
+        assert!(Lido::calculate_size(2) <= account_size);
+    }
+
+    #[test]
+    fn test_lido_serialization_roundtrips() {
+        use solana_sdk::borsh::try_from_slice_unchecked;
+
         let maintainers = Maintainers::new(1);
         let lido = Lido {
-            lido_version: 0,
+            lido_version: LIDO_VERSION,
             manager: Pubkey::new_unique(),
             st_sol_mint: Pubkey::new_unique(),
             exchange_rate: ExchangeRate {
@@ -1006,7 +1279,7 @@ mod test_lido {
                 developer_account: Pubkey::new_unique(),
             },
             metrics: Metrics::new(),
-            validators: validators,
+            validator_list: Pubkey::new_unique(),
             maintainers: maintainers,
         };
         let mut data = Vec::new();
@@ -1016,6 +1289,21 @@ mod test_lido {
         assert_eq!(lido, lido_restored);
     }
 
+    #[test]
+    fn test_validators_serialization_roundtrips() {
+        use solana_sdk::borsh::try_from_slice_unchecked;
+
+        let mut validators = Validators::new(10_000);
+        validators
+            .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
+            .unwrap();
+        let mut data = Vec::new();
+        BorshSerialize::serialize(&validators, &mut data).unwrap();
+
+        let validators_restored = try_from_slice_unchecked(&data[..]).unwrap();
+        assert_eq!(validators, validators_restored);
+    }
+
     #[test]
     fn test_exchange_when_balance_and_supply_are_zero() {
         let rate = ExchangeRate {
@@ -1095,6 +1383,82 @@ mod test_lido {
         assert_eq!(sol_2, Lamports(999));
     }
 
+    /// Return a random exchange rate where one stSOL is worth between 1 and 2 SOL.
+    fn random_exchange_rate(rng: &mut StdRng) -> ExchangeRate {
+        let st_sol_supply = rng.gen_range(1, 1_000_000_000 * LAMPORTS_PER_SOL);
+        ExchangeRate {
+            computed_in_epoch: 0,
+            st_sol_supply: StLamports(st_sol_supply),
+            sol_balance: Lamports(st_sol_supply + rng.gen_range(0, st_sol_supply)),
+        }
+    }
+
+    #[test]
+    fn test_deposit_withdraw_cycles_never_lose_pool_lamports_within_epoch() {
+        // Within an epoch the exchange rate is fixed, while users deposit and
+        // withdraw. No matter how they do that, they should not be able to get
+        // out more SOL than they put in.
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1_000 {
+            let rate = random_exchange_rate(&mut rng);
+            let mut sol_in = Lamports(0);
+            let mut sol_out = Lamports(0);
+            let mut st_sol_held = StLamports(0);
+            for _ in 0..100 {
+                if rng.gen::<bool>() {
+                    let amount = Lamports(rng.gen_range(1, 1_000 * LAMPORTS_PER_SOL));
+                    st_sol_held = (st_sol_held + rate.exchange_sol(amount).unwrap()).unwrap();
+                    sol_in = (sol_in + amount).unwrap();
+                } else {
+                    let amount = StLamports(rng.gen_range(0, st_sol_held.0 + 1));
+                    sol_out = (sol_out + rate.exchange_st_sol(amount).unwrap()).unwrap();
+                    st_sol_held = (st_sol_held - amount).unwrap();
+                }
+            }
+            // Finally the user withdraws everything they have left.
+            sol_out = (sol_out + rate.exchange_st_sol(st_sol_held).unwrap()).unwrap();
+            assert!(
+                sol_out <= sol_in,
+                "Put in {}, got out {} at rate {:?}.",
+                sol_in,
+                sol_out,
+                rate
+            );
+        }
+    }
+
+    #[test]
+    fn test_deposit_withdraw_cycles_never_decrease_st_sol_value() {
+        // If the exchange rate were updated after every deposit and withdrawal,
+        // the SOL value of one stSOL should never go down.
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1_000 {
+            let mut rate = random_exchange_rate(&mut rng);
+            for _ in 0..100 {
+                let before = rate.clone();
+                let deposit = Lamports(rng.gen_range(1, 1_000 * LAMPORTS_PER_SOL));
+                let minted = rate.exchange_sol(deposit).unwrap();
+                rate.sol_balance = (rate.sol_balance + deposit).unwrap();
+                rate.st_sol_supply = (rate.st_sol_supply + minted).unwrap();
+
+                let burned = StLamports(rng.gen_range(0, minted.0 + 1));
+                let withdrawn = rate.exchange_st_sol(burned).unwrap();
+                rate.sol_balance = (rate.sol_balance - withdrawn).unwrap();
+                rate.st_sol_supply = (rate.st_sol_supply - burned).unwrap();
+
+                // sol_balance / st_sol_supply >= before.sol_balance / before.st_sol_supply.
+                let value_after = rate.sol_balance.0 as u128 * before.st_sol_supply.0 as u128;
+                let value_before = before.sol_balance.0 as u128 * rate.st_sol_supply.0 as u128;
+                assert!(
+                    value_after >= value_before,
+                    "Value of stSOL decreased from {:?} to {:?}.",
+                    before,
+                    rate
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_lido_for_deposit_wrong_mint() {
         let mut lido = Lido::default();
@@ -1130,14 +1494,15 @@ mod test_lido {
         use std::rc::Rc;
 
         let rent = &Rent::default();
-        let mut lido = Lido::default();
+        let lido = Lido::default();
+        let mut validators = Validators::default();
         let key = Pubkey::default();
         let mut amount = rent.minimum_balance(0);
         let mut reserve_account =
             AccountInfo::new(&key, true, true, &mut amount, &mut [], &key, false, 0);
 
         assert_eq!(
-            lido.get_sol_balance(&rent, &reserve_account),
+            lido.get_sol_balance(&validators, &rent, &reserve_account),
             Ok(Lamports(0))
         );
 
@@ -1145,24 +1510,24 @@ mod test_lido {
         reserve_account.lamports = Rc::new(RefCell::new(&mut new_amount));
 
         assert_eq!(
-            lido.get_sol_balance(&rent, &reserve_account),
+            lido.get_sol_balance(&validators, &rent, &reserve_account),
             Ok(Lamports(10))
         );
 
-        lido.validators.maximum_entries = 1;
-        lido.validators
+        validators.maximum_entries = 1;
+        validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
-        lido.validators.entries[0].entry.stake_accounts_balance = Lamports(37);
+        validators.entries[0].entry.stake_accounts_balance = Lamports(37);
         assert_eq!(
-            lido.get_sol_balance(&rent, &reserve_account),
+            lido.get_sol_balance(&validators, &rent, &reserve_account),
             Ok(Lamports(10 + 37))
         );
 
-        lido.validators.entries[0].entry.stake_accounts_balance = Lamports(u64::MAX);
+        validators.entries[0].entry.stake_accounts_balance = Lamports(u64::MAX);
 
         assert_eq!(
-            lido.get_sol_balance(&rent, &reserve_account),
+            lido.get_sol_balance(&validators, &rent, &reserve_account),
             Err(LidoError::CalculationFailure)
         );
 
@@ -1170,10 +1535,10 @@ mod test_lido {
         reserve_account.lamports = Rc::new(RefCell::new(&mut new_amount));
         // The amount here is more than the rent exemption that gets discounted
         // from the reserve, causing an overflow.
-        lido.validators.entries[0].entry.stake_accounts_balance = Lamports(5_000_000);
+        validators.entries[0].entry.stake_accounts_balance = Lamports(5_000_000);
 
         assert_eq!(
-            lido.get_sol_balance(&rent, &reserve_account),
+            lido.get_sol_balance(&validators, &rent, &reserve_account),
             Err(LidoError::CalculationFailure)
         );
     }
@@ -1193,6 +1558,7 @@ mod test_lido {
         mint.pack_into_slice(&mut data);
 
         let mut lido = Lido::default();
+        let mut validators = Validators::default();
         let mint_address = Pubkey::default();
         let mut amount = 0;
         let is_signer = false;
@@ -1213,24 +1579,24 @@ mod test_lido {
         lido.st_sol_mint = mint_address;
 
         assert_eq!(
-            lido.get_st_sol_supply(&st_sol_mint),
+            lido.get_st_sol_supply(&validators, &st_sol_mint),
             Ok(StLamports(200_000)),
         );
 
-        lido.validators.maximum_entries = 1;
-        lido.validators
+        validators.maximum_entries = 1;
+        validators
             .add(Pubkey::new_unique(), Validator::new(Pubkey::new_unique()))
             .unwrap();
-        lido.validators.entries[0].entry.fee_credit = StLamports(37);
+        validators.entries[0].entry.fee_credit = StLamports(37);
         assert_eq!(
-            lido.get_st_sol_supply(&st_sol_mint),
+            lido.get_st_sol_supply(&validators, &st_sol_mint),
             Ok(StLamports(200_000 + 37))
         );
 
         lido.st_sol_mint = Pubkey::new_unique();
 
         assert_eq!(
-            lido.get_st_sol_supply(&st_sol_mint),
+            lido.get_st_sol_supply(&validators, &st_sol_mint),
             Err(LidoError::InvalidStSolAccount.into())
         );
     }
@@ -40,6 +40,57 @@ impl PartialOrd for Rational {
     }
 }
 
+/// The exact result of multiplying an amount with a [`Rational`], before rounding.
+///
+/// The product of two `u64`s always fits in a `u128`, so we can hold it exactly
+/// and postpone the division until the caller says in which direction to round.
+/// When converting between SOL and stSOL, the rounding direction determines who
+/// receives the fraction of a Lamport, the user or the pool. Solido always
+/// rounds in favour of the pool, so no sequence of operations can extract more
+/// value from the pool than was put in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Unrounded {
+    numerator: u128,
+    denominator: u128,
+}
+
+impl Unrounded {
+    /// Return `amount * rate`, without rounding.
+    pub fn new(amount: u64, rate: Rational) -> Unrounded {
+        Unrounded {
+            // This multiplication cannot overflow, because we expand the
+            // u64s into u128, and u64::MAX * u64::MAX < u128::MAX.
+            numerator: amount as u128 * rate.numerator as u128,
+            denominator: rate.denominator as u128,
+        }
+    }
+
+    /// Round towards zero, to the largest integer not greater than the exact value.
+    pub fn round_down(&self) -> Result<u64> {
+        let result = self
+            .numerator
+            .checked_div(self.denominator)
+            .ok_or(ArithmeticError)?;
+        u64::try_from(result).map_err(|_| ArithmeticError)
+    }
+
+    /// Round away from zero, to the smallest integer not less than the exact value.
+    pub fn round_up(&self) -> Result<u64> {
+        let quotient = self
+            .numerator
+            .checked_div(self.denominator)
+            .ok_or(ArithmeticError)?;
+        let result = if self.numerator % self.denominator == 0 {
+            quotient
+        } else {
+            // Cannot overflow: if there is a remainder, the denominator is at
+            // least 2, so the quotient is at most u128::MAX / 2.
+            quotient + 1
+        };
+        u64::try_from(result).map_err(|_| ArithmeticError)
+    }
+}
+
 /// Error returned when a calculation in a token type overflows, underflows, or divides by zero.
 #[derive(Debug, Eq, PartialEq)]
 pub struct ArithmeticError;
@@ -84,17 +135,19 @@ macro_rules! impl_token {
             }
         }
 
+        impl $TokenLamports {
+            /// Multiply with `rate`, and leave it to the caller to pick a rounding direction.
+            pub fn mul_unrounded(self, rate: Rational) -> Unrounded {
+                Unrounded::new(self.0, rate)
+            }
+        }
+
+        /// Multiplication with a `Rational` rounds down. Use `mul_unrounded`
+        /// to round up instead.
         impl Mul<Rational> for $TokenLamports {
             type Output = Result<$TokenLamports>;
             fn mul(self, other: Rational) -> Result<$TokenLamports> {
-                // This multiplication cannot overflow, because we expand the
-                // u64s into u128, and u64::MAX * u64::MAX < u128::MAX.
-                let result_u128 = ((self.0 as u128) * (other.numerator as u128))
-                    .checked_div(other.denominator as u128)
-                    .ok_or(ArithmeticError)?;
-                u64::try_from(result_u128)
-                    .map($TokenLamports)
-                    .map_err(|_| ArithmeticError)
+                self.mul_unrounded(other).round_down().map($TokenLamports)
             }
         }
 
@@ -209,6 +262,8 @@ impl_token!(StLamports, "stSOL");
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::str::FromStr;
 
     #[test]
@@ -319,4 +374,77 @@ pub mod test {
         assert_eq!(x.partial_cmp(&y), None);
         assert_eq!(y.partial_cmp(&x), None);
     }
+
+    #[test]
+    fn test_unrounded_rounds_in_the_requested_direction() {
+        let one_third = Rational {
+            numerator: 1,
+            denominator: 3,
+        };
+        assert_eq!(Lamports(10).mul_unrounded(one_third).round_down(), Ok(3));
+        assert_eq!(Lamports(10).mul_unrounded(one_third).round_up(), Ok(4));
+        assert_eq!(Lamports(10) * one_third, Ok(Lamports(3)));
+
+        // When the result is an integer, there is nothing to round.
+        assert_eq!(Lamports(9).mul_unrounded(one_third).round_down(), Ok(3));
+        assert_eq!(Lamports(9).mul_unrounded(one_third).round_up(), Ok(3));
+
+        // The intermediate result does not overflow, only the final result may.
+        let almost_one = Rational {
+            numerator: u64::MAX - 1,
+            denominator: u64::MAX,
+        };
+        assert_eq!(
+            Lamports(u64::MAX).mul_unrounded(almost_one).round_up(),
+            Ok(u64::MAX - 1)
+        );
+        let two = Rational {
+            numerator: 2,
+            denominator: 1,
+        };
+        assert_eq!(
+            Lamports(u64::MAX).mul_unrounded(two).round_down(),
+            Err(ArithmeticError)
+        );
+
+        let division_by_zero = Rational {
+            numerator: 1,
+            denominator: 0,
+        };
+        assert_eq!(
+            Lamports(1).mul_unrounded(division_by_zero).round_down(),
+            Err(ArithmeticError)
+        );
+        assert_eq!(
+            Lamports(1).mul_unrounded(division_by_zero).round_up(),
+            Err(ArithmeticError)
+        );
+    }
+
+    #[test]
+    fn test_unrounded_round_down_and_round_up_enclose_exact_value() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100_000 {
+            // Shift by a random amount, so we cover values of all magnitudes.
+            let amount = rng.gen::<u64>() >> rng.gen_range(0, 64);
+            let denominator = (rng.gen::<u64>() >> rng.gen_range(0, 64)).max(1);
+            let numerator = rng.gen_range(0, denominator as u128 + 1) as u64;
+            let rate = Rational {
+                numerator,
+                denominator,
+            };
+            let exact = amount as u128 * numerator as u128;
+
+            let down = Lamports(amount).mul_unrounded(rate).round_down().unwrap();
+            let up = Lamports(amount).mul_unrounded(rate).round_up().unwrap();
+
+            assert!(down as u128 * denominator as u128 <= exact);
+            assert!(up as u128 * denominator as u128 >= exact);
+            if exact % denominator as u128 == 0 {
+                assert_eq!(down, up);
+            } else {
+                assert_eq!(down + 1, up);
+            }
+        }
+    }
 }
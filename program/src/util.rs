@@ -1,15 +1,111 @@
 // SPDX-FileCopyrightText: 2021 Chorus One AG
 // SPDX-License-Identifier: GPL-3.0
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
 use serde::ser::SerializeSeq;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use solana_program::pubkey::Pubkey;
 
 /// Function to use when serializing a public key, to print it using base58.
-pub fn serialize_b58<S: Serializer>(x: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+///
+/// This works for any type whose `Display` impl formats as base58, such as
+/// hashes and signatures too.
+pub fn serialize_b58<T: fmt::Display, S: Serializer>(
+    x: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
     serializer.serialize_str(&x.to_string())
 }
 
+/// Deserializer for values serialized with [`serialize_b58`].
+pub fn deserialize_b58<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    let str_value = String::deserialize(deserializer)?;
+    T::from_str(&str_value).map_err(serde::de::Error::custom)
+}
+
+/// Serialize an optional value as a base58 string, or `null` when absent.
+pub fn serialize_b58_option<T: fmt::Display, S: Serializer>(
+    x: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match x {
+        Some(x) => serializer.serialize_some(&x.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserializer for values serialized with [`serialize_b58_option`].
+pub fn deserialize_b58_option<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(str_value) => T::from_str(&str_value)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Serialize a map with its keys as base58 strings, and its values as they are.
+pub fn serialize_b58_keys<K: fmt::Display, V: Serialize, S: Serializer>(
+    map: &BTreeMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(k, v)| (k.to_string(), v)))
+}
+
+/// Deserializer for maps serialized with [`serialize_b58_keys`].
+pub fn deserialize_b58_keys<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+where
+    K: FromStr + Ord,
+    K::Err: fmt::Display,
+    V: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, V>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(k, v)| Ok((K::from_str(&k).map_err(serde::de::Error::custom)?, v)))
+        .collect()
+}
+
+/// Serialize a map with both its keys and values as base58 strings.
+pub fn serialize_b58_map<K: fmt::Display, V: fmt::Display, S: Serializer>(
+    map: &BTreeMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+}
+
+/// Deserializer for maps serialized with [`serialize_b58_map`].
+pub fn deserialize_b58_map<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+where
+    K: FromStr + Ord,
+    K::Err: fmt::Display,
+    V: FromStr,
+    V::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(k, v)| {
+            let k = K::from_str(&k).map_err(serde::de::Error::custom)?;
+            let v = V::from_str(&v).map_err(serde::de::Error::custom)?;
+            Ok((k, v))
+        })
+        .collect()
+}
+
 /// Serializer that serializes a list of pubkeys as an array of base58 strings.
 pub fn serialize_b58_slice<T: AsRef<[Pubkey]>, S: Serializer>(
     pubkeys: T,
@@ -39,9 +135,8 @@ impl Serialize for PubkeyBase58 {
 #[cfg(test)]
 mod test {
     use super::*;
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
     use serde_json;
-    use std::str::FromStr;
 
     #[test]
     fn test_serialize_b58() {
@@ -61,6 +156,84 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_deserialize_b58_roundtrip() {
+        #[derive(Deserialize, Serialize)]
+        struct Test {
+            #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+            pubkey: Pubkey,
+        }
+
+        let json = r#"{"pubkey":"3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc"}"#;
+        let x: Test = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            x.pubkey,
+            Pubkey::from_str("3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc").unwrap()
+        );
+        assert_eq!(serde_json::to_string(&x).unwrap(), json);
+
+        let invalid = r#"{"pubkey":"not base58"}"#;
+        assert!(serde_json::from_str::<Test>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_b58_option_roundtrip() {
+        #[derive(Deserialize, Serialize)]
+        struct Test {
+            #[serde(
+                serialize_with = "serialize_b58_option",
+                deserialize_with = "deserialize_b58_option"
+            )]
+            pubkey: Option<Pubkey>,
+        }
+
+        let json = r#"{"pubkey":"3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc"}"#;
+        let x: Test = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            x.pubkey,
+            Some(Pubkey::from_str("3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc").unwrap())
+        );
+        assert_eq!(serde_json::to_string(&x).unwrap(), json);
+
+        let json = r#"{"pubkey":null}"#;
+        let x: Test = serde_json::from_str(json).unwrap();
+        assert_eq!(x.pubkey, None);
+        assert_eq!(serde_json::to_string(&x).unwrap(), json);
+
+        let invalid = r#"{"pubkey":"not base58"}"#;
+        assert!(serde_json::from_str::<Test>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_b58_map_roundtrip() {
+        #[derive(Deserialize, Serialize)]
+        struct Test {
+            #[serde(
+                serialize_with = "serialize_b58_keys",
+                deserialize_with = "deserialize_b58_keys"
+            )]
+            balances: BTreeMap<Pubkey, u64>,
+            #[serde(
+                serialize_with = "serialize_b58_map",
+                deserialize_with = "deserialize_b58_map"
+            )]
+            owners: BTreeMap<Pubkey, Pubkey>,
+        }
+
+        let a = Pubkey::from_str("3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc").unwrap();
+        let b = Pubkey::from_str("7dwKwaz6gmNCQVjTGSrvZEuouJBuC7tzGw4voDa3iTrk").unwrap();
+        let json = r#"{"balances":{"3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc":1},"owners":{"3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc":"7dwKwaz6gmNCQVjTGSrvZEuouJBuC7tzGw4voDa3iTrk"}}"#;
+        let x: Test = serde_json::from_str(json).unwrap();
+        assert_eq!(x.balances.get(&a), Some(&1));
+        assert_eq!(x.owners.get(&a), Some(&b));
+        assert_eq!(serde_json::to_string(&x).unwrap(), json);
+
+        let invalid = r#"{"balances":{"not base58":1},"owners":{}}"#;
+        assert!(serde_json::from_str::<Test>(invalid).is_err());
+        let invalid = r#"{"balances":{},"owners":{"3kEkdGe68DuTKg6FhVrLPZ3Wm8EcUPCPjhCeu8WrGDoc":"not base58"}}"#;
+        assert!(serde_json::from_str::<Test>(invalid).is_err());
+    }
+
     #[test]
     fn test_serialize_b58_slice() {
         #[derive(Serialize)]
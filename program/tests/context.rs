@@ -35,7 +35,7 @@ use lido::{
     error::LidoError, instruction, RESERVE_ACCOUNT, REWARDS_WITHDRAW_AUTHORITY, STAKE_AUTHORITY,
 };
 use lido::{
-    state::{FeeRecipients, Lido, RewardDistribution, Validator},
+    state::{FeeRecipients, Lido, RewardDistribution, Validator, Validators},
     MINT_AUTHORITY,
 };
 
@@ -80,6 +80,7 @@ pub struct Context {
 
     // Key pairs for the accounts in the Solido instance.
     pub solido: Keypair,
+    pub validator_list: Keypair,
     pub manager: Keypair,
     pub st_sol_mint: Pubkey,
     pub maintainer: Option<Keypair>,
@@ -216,6 +217,7 @@ impl Context {
         let mut deterministic_keypair = DeterministicKeypairGen::new();
         let manager = deterministic_keypair.new_keypair();
         let solido = deterministic_keypair.new_keypair();
+        let validator_list = deterministic_keypair.new_keypair();
 
         let reward_distribution = RewardDistribution {
             validation_fee: 5,
@@ -256,6 +258,7 @@ impl Context {
             nonce: 0,
             manager,
             solido,
+            validator_list,
             st_sol_mint: Pubkey::default(),
             maintainer: None,
             validator: None,
@@ -281,9 +284,11 @@ impl Context {
 
         let max_validators = 10_000;
         let max_maintainers = 1000;
-        let solido_size = Lido::calculate_size(max_validators, max_maintainers);
+        let solido_size = Lido::calculate_size(max_maintainers);
+        let validator_list_size = Validators::required_bytes(max_validators as usize);
         let rent = result.context.banks_client.get_rent().await.unwrap();
         let rent_solido = rent.minimum_balance(solido_size);
+        let rent_validator_list = rent.minimum_balance(validator_list_size);
 
         let rent_reserve = rent.minimum_balance(0);
         result
@@ -302,6 +307,13 @@ impl Context {
                     solido_size as u64,
                     &id(),
                 ),
+                system_instruction::create_account(
+                    &payer,
+                    &result.validator_list.pubkey(),
+                    rent_validator_list,
+                    validator_list_size as u64,
+                    &id(),
+                ),
                 instruction::initialize(
                     &id(),
                     result.reward_distribution.clone(),
@@ -309,6 +321,7 @@ impl Context {
                     max_maintainers,
                     &instruction::InitializeAccountsMeta {
                         lido: result.solido.pubkey(),
+                        validator_list: result.validator_list.pubkey(),
                         manager: result.manager.pubkey(),
                         st_sol_mint: result.st_sol_mint,
                         treasury_account: result.treasury_st_sol_account,
@@ -317,7 +330,7 @@ impl Context {
                     },
                 ),
             ],
-            vec![&result.solido],
+            vec![&result.solido, &result.validator_list],
         )
         .await
         .expect("Failed to initialize Solido instance.");
@@ -720,6 +733,7 @@ impl Context {
                 &id(),
                 &lido::instruction::AddValidatorMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     manager: self.manager.pubkey(),
                     validator_vote_account: accounts.vote_account,
                     validator_fee_st_sol_account: accounts.fee_account,
@@ -759,6 +773,7 @@ impl Context {
                 &id(),
                 &lido::instruction::DeactivateValidatorMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     manager: self.manager.pubkey(),
                     validator_vote_account_to_deactivate: vote_account,
                 },
@@ -777,6 +792,7 @@ impl Context {
                 &id(),
                 &lido::instruction::RemoveValidatorMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_vote_account_to_remove: vote_account,
                 },
             )],
@@ -843,6 +859,7 @@ impl Context {
                 &id(),
                 &instruction::WithdrawAccountsMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     st_sol_mint: self.st_sol_mint,
                     st_sol_account_owner: user.pubkey(),
                     st_sol_account,
@@ -886,10 +903,9 @@ impl Context {
         approach: StakeDeposit,
         amount: Lamports,
     ) -> transport::Result<Pubkey> {
-        let solido = self.get_solido().await;
+        let validators = self.get_validators().await;
 
-        let validator_entry = solido
-            .validators
+        let validator_entry = validators
             .get(&validator_vote_account)
             .expect("Trying to stake with a non-member validator.");
 
@@ -925,6 +941,7 @@ impl Context {
                 &id(),
                 &instruction::StakeDepositAccountsMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     maintainer: maintainer.pubkey(),
                     validator_vote_account,
                     reserve: self.reserve_address,
@@ -960,8 +977,8 @@ impl Context {
         amount: Lamports,
     ) -> transport::Result<()> {
         // Where the new stake will live.
-        let solido = self.get_solido().await;
-        let validator = solido.validators.get(&validator_vote_account).unwrap();
+        let validators = self.get_validators().await;
+        let validator = validators.get(&validator_vote_account).unwrap();
 
         let (source_stake_account, _) = validator.find_stake_account_address(
             &id(),
@@ -983,6 +1000,7 @@ impl Context {
                 &id(),
                 &instruction::UnstakeAccountsMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_vote_account,
                     source_stake_account,
                     destination_unstake_account,
@@ -1036,6 +1054,7 @@ impl Context {
                 &id(),
                 &instruction::UpdateExchangeRateAccountsMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     reserve: self.reserve_address,
                     st_sol_mint: self.st_sol_mint,
                 },
@@ -1081,6 +1100,7 @@ impl Context {
                 &id(),
                 &instruction::MergeStakeMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_vote_account: validator.pubkey,
                     stake_authority: self.stake_authority,
                     from_stake: from_stake_account,
@@ -1112,8 +1132,8 @@ impl Context {
         &mut self,
         validator_vote_account: Pubkey,
     ) -> transport::Result<()> {
-        let solido = self.get_solido().await;
-        let validator = solido.validators.get(&validator_vote_account).unwrap();
+        let validators = self.get_validators().await;
+        let validator = validators.get(&validator_vote_account).unwrap();
 
         let mut stake_account_addrs: Vec<Pubkey> = Vec::new();
 
@@ -1135,6 +1155,7 @@ impl Context {
                 &id(),
                 &instruction::WithdrawInactiveStakeMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_vote_account,
                     stake_accounts: stake_account_addrs,
                     reserve: self.reserve_address,
@@ -1175,6 +1196,7 @@ impl Context {
                 &id(),
                 &instruction::CollectValidatorFeeMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_vote_account: validator_vote_account,
                     st_sol_mint: self.st_sol_mint,
                     mint_authority: self.mint_authority,
@@ -1206,11 +1228,8 @@ impl Context {
         &mut self,
         validator_vote_account: Pubkey,
     ) -> transport::Result<StLamports> {
-        let solido_before = self.get_solido().await;
-        let validator_before = solido_before
-            .validators
-            .get(&validator_vote_account)
-            .unwrap();
+        let validators_before = self.get_validators().await;
+        let validator_before = validators_before.get(&validator_vote_account).unwrap();
 
         let validator_balance_before = self
             .get_st_sol_balance(validator_before.entry.fee_address)
@@ -1223,6 +1242,7 @@ impl Context {
                 &id(),
                 &instruction::ClaimValidatorFeeMeta {
                     lido: self.solido.pubkey(),
+                    validator_list: self.validator_list.pubkey(),
                     validator_fee_st_sol_account: validator_before.entry.fee_address,
                     st_sol_mint: self.st_sol_mint,
                     mint_authority: self.mint_authority,
@@ -1231,11 +1251,8 @@ impl Context {
             vec![],
         )
         .await?;
-        let solido_after = self.get_solido().await;
-        let validator_after = solido_after
-            .validators
-            .get(&validator_vote_account)
-            .unwrap();
+        let validators_after = self.get_validators().await;
+        let validator_after = validators_after.get(&validator_vote_account).unwrap();
         // Assert all the credits are taken from.
         assert_eq!(validator_after.entry.fee_credit, StLamports(0));
 
@@ -1305,6 +1322,11 @@ impl Context {
         try_from_slice_unchecked::<Lido>(lido_account.data.as_slice()).unwrap()
     }
 
+    pub async fn get_validators(&mut self) -> Validators {
+        let validator_list_account = self.get_account(self.validator_list.pubkey()).await;
+        try_from_slice_unchecked::<Validators>(validator_list_account.data.as_slice()).unwrap()
+    }
+
     pub async fn get_rent(&mut self) -> Rent {
         self.context
             .banks_client
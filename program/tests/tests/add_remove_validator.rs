@@ -19,16 +19,16 @@ pub const TEST_STAKE_DEPOSIT_AMOUNT: Lamports = Lamports(10_000_000_000);
 async fn test_successful_add_validator() {
     let mut context = Context::new_with_maintainer().await;
 
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 0);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 0);
 
     let validator = context.add_validator().await;
 
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 1);
-    assert_eq!(solido.validators.entries[0].pubkey, validator.vote_account);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 1);
+    assert_eq!(validators.entries[0].pubkey, validator.vote_account);
     assert_eq!(
-        solido.validators.entries[0].entry.fee_address,
+        validators.entries[0].entry.fee_address,
         validator.fee_account
     );
 
@@ -41,8 +41,8 @@ async fn test_successful_add_validator() {
 async fn test_add_validator_with_invalid_owner() {
     let mut context = Context::new_with_maintainer().await;
 
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 0);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 0);
     let node_key = context.deterministic_keypair.new_keypair();
     let real_vote_account = context
         .create_vote_account(&node_key, context.withdraw_authority, 100)
@@ -70,15 +70,15 @@ async fn test_add_validator_with_invalid_owner() {
 #[tokio::test]
 async fn test_successful_remove_validator() {
     let mut context = Context::new_with_maintainer_and_validator().await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     context.deactivate_validator(validator.pubkey).await;
     context
         .try_remove_validator(validator.pubkey)
         .await
         .unwrap();
 
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 0);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 0);
 }
 
 #[tokio::test]
@@ -106,16 +106,16 @@ async fn test_remove_validator_with_unclaimed_credits() {
     context.update_exchange_rate().await;
     context.collect_validator_fee(validator.vote_account).await;
 
-    let solido = context.get_solido().await;
-    let vote_account = solido.validators.entries[0].pubkey;
+    let validators = context.get_validators().await;
+    let vote_account = validators.entries[0].pubkey;
     assert_eq!(
-        solido.validators.entries[0].entry.fee_credit,
+        validators.entries[0].entry.fee_credit,
         StLamports(62_301_530_769)
     );
 
     context.deactivate_validator(vote_account).await;
-    // let solido = context.get_solido().await;
-    // let vote_account = solido.validators.entries[0].pubkey;
+    // let validators = context.get_validators().await;
+    // let vote_account = validators.entries[0].pubkey;
     let result = context.try_remove_validator(vote_account).await;
     assert_solido_error!(result, LidoError::ValidatorHasUnclaimedCredit);
 }
@@ -123,7 +123,7 @@ async fn test_remove_validator_with_unclaimed_credits() {
 #[tokio::test]
 async fn test_removing_validator_with_stake_accounts_should_fail() {
     let (mut context, _) = Context::new_with_two_stake_accounts().await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     let result = context.try_remove_validator(validator.pubkey).await;
 
     // The validator should not be able to be removed if it is still active
@@ -138,19 +138,19 @@ async fn test_deactivate_validator() {
     let validator = context.add_validator().await;
 
     // Initially, the validator should be active.
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 1);
-    assert!(solido.validators.entries[0].entry.active);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 1);
+    assert!(validators.entries[0].entry.active);
 
     context.deactivate_validator(validator.vote_account).await;
 
     // After deactivation, it should be inactive.
-    let solido = context.get_solido().await;
-    assert_eq!(solido.validators.len(), 1);
-    assert!(!solido.validators.entries[0].entry.active);
+    let validators = context.get_validators().await;
+    assert_eq!(validators.len(), 1);
+    assert!(!validators.entries[0].entry.active);
 
     // Deactivation is idempotent.
     context.deactivate_validator(validator.vote_account).await;
-    let solido_after_second_deactivation = context.get_solido().await;
-    assert_eq!(solido, solido_after_second_deactivation);
+    let validators_after_second_deactivation = context.get_validators().await;
+    assert_eq!(validators, validators_after_second_deactivation);
 }
@@ -18,9 +18,12 @@ async fn test_collect_validator_fee() {
 
     // If we try to collect fee initially, that should work, but there is nothing to update.
     let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
     context.collect_validator_fee(validator.vote_account).await;
     let solido_after = context.get_solido().await;
+    let validators_after = context.get_validators().await;
     assert_eq!(solido_before, solido_after);
+    assert_eq!(validators_before, validators_after);
 
     // Deposit and stake the deposit with the validator. This creates one stake account.
     let initial_amount = Lamports(1_000_000_000);
@@ -67,8 +70,8 @@ async fn test_collect_validator_fee() {
     let developer_before = context
         .get_st_sol_balance(context.developer_st_sol_account)
         .await;
-    let solido_before = context.get_solido().await;
-    let validator_before = solido_before.validators.entries[0].entry.fee_credit;
+    let validators_before = context.get_validators().await;
+    let validator_before = validators_before.entries[0].entry.fee_credit;
 
     context.advance_to_normal_epoch(1);
     let account = context.get_account(validator.vote_account).await;
@@ -88,7 +91,8 @@ async fn test_collect_validator_fee() {
         .get_st_sol_balance(context.developer_st_sol_account)
         .await;
     let solido_after = context.get_solido().await;
-    let validator_after = solido_after.validators.entries[0].entry.fee_credit;
+    let validators_after = context.get_validators().await;
+    let validator_after = validators_after.entries[0].entry.fee_credit;
 
     // The treasury balance increase, when converted back to SOL, should be equal
     // to 3% of the rewards. Two lamports differ due to rounding errors.
@@ -149,7 +153,7 @@ async fn test_collect_validator_fee_withdraws_donations_to_the_reserve() {
     let developer_before = context
         .get_st_sol_balance(context.developer_st_sol_account)
         .await;
-    let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
 
     let vote_donation = context.collect_validator_fee(validator.vote_account).await;
     let reserve_after = context.get_sol_balance(context.reserve_address).await;
@@ -158,7 +162,7 @@ async fn test_collect_validator_fee_withdraws_donations_to_the_reserve() {
     assert_eq!(reserve_after, (reserve_before + vote_donation).unwrap());
     assert_eq!(vote_donation, donation);
 
-    let solido_after = context.get_solido().await;
+    let validators_after = context.get_validators().await;
     let treasury_after = context
         .get_st_sol_balance(context.treasury_st_sol_account)
         .await;
@@ -173,13 +177,13 @@ async fn test_collect_validator_fee_withdraws_donations_to_the_reserve() {
     assert_eq!(treasury_before, StLamports(0));
     assert_eq!(developer_before, StLamports(0));
     assert_eq!(
-        solido_before.validators.entries[0].entry.fee_credit,
+        validators_before.entries[0].entry.fee_credit,
         StLamports(0)
     );
     assert_eq!(treasury_after, StLamports(3_000));
     assert_eq!(developer_after, StLamports(2_000));
     assert_eq!(
-        solido_after.validators.entries[0].entry.fee_credit,
+        validators_after.entries[0].entry.fee_credit,
         StLamports(5_000)
     );
 }
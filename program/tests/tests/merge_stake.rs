@@ -16,7 +16,8 @@ async fn test_successful_merge_activating_stake() {
 
     let rent = context.get_rent().await;
     let solido_before = context.get_solido().await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validators_before = context.get_validators().await;
+    let validator = &validators_before.entries[0];
     let mut reserve_before = context.get_account(context.reserve_address).await;
 
     context.merge_stake(&validator, 0, 1).await;
@@ -32,26 +33,29 @@ async fn test_successful_merge_activating_stake() {
     assert_eq!(stake.delegation.stake, sum, "Unexpected delegated stake.");
 
     let solido_after = context.get_solido().await;
+    let validators_after = context.get_validators().await;
     let mut reserve_after = context.get_account(context.reserve_address).await;
     assert_eq!(
-        solido_after.validators.entries[0]
+        validators_after.entries[0]
             .entry
             .stake_accounts_balance,
         Lamports(20_000_000_000)
     );
 
-    let validator_before = &solido_before.validators.entries[0].entry;
-    let validator_after = &solido_after.validators.entries[0].entry;
+    let validator_before = &validators_before.entries[0].entry;
+    let validator_after = &validators_after.entries[0].entry;
     assert_eq!(
         validator_after.stake_seeds.begin,
         validator_before.stake_seeds.begin + 1,
     );
 
     let sol_before = solido_before.get_sol_balance(
+        &validators_before,
         &rent,
         &get_account_info(&context.reserve_address, &mut reserve_before),
     );
     let sol_after = solido_after.get_sol_balance(
+        &validators_after,
         &rent,
         &get_account_info(&context.reserve_address, &mut reserve_after),
     );
@@ -70,7 +74,7 @@ async fn test_merge_stake_combinations() {
 
     context.advance_to_normal_epoch(0);
 
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     context.deposit(Lamports(100_000_000_000)).await;
     context
         .stake_deposit(validator.pubkey, StakeDeposit::Append, stake_deposit_amount)
@@ -101,19 +105,23 @@ async fn test_merge_stake_combinations() {
 
     let rent = context.get_rent().await;
     let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
     let mut reserve_before = context.get_account(context.reserve_address).await;
 
     // Merging two activated stake accounts should succeed.
     context.merge_stake(&validator, 0, 1).await;
 
     let solido_after = context.get_solido().await;
+    let validators_after = context.get_validators().await;
     let mut reserve_after = context.get_account(context.reserve_address).await;
 
     let sol_before = solido_before.get_sol_balance(
+        &validators_before,
         &rent,
         &get_account_info(&context.reserve_address, &mut reserve_before),
     );
     let sol_after = solido_after.get_sol_balance(
+        &validators_after,
         &rent,
         &get_account_info(&context.reserve_address, &mut reserve_after),
     );
@@ -127,7 +135,7 @@ async fn test_merge_stake_combinations() {
 async fn test_merge_validator_with_zero_and_one_stake_account() {
     let mut context = Context::new_with_maintainer().await;
     context.add_validator().await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     context.deposit(Lamports(10_000_000_000)).await;
 
     // Try to merge stake on a validator that has no stake accounts.
@@ -150,7 +158,7 @@ async fn test_merge_validator_with_zero_and_one_stake_account() {
 #[tokio::test]
 async fn test_merge_with_donated_stake() {
     let (mut context, _stake_account_pubkeys) = Context::new_with_two_stake_accounts().await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     let (from_stake_account, _) = validator.find_stake_account_address(
         &crate::context::id(),
         &context.solido.pubkey(),
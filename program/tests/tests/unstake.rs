@@ -55,8 +55,8 @@ async fn test_successful_unstake() {
     let mut context = new_unstake_context(&[STAKE_AMOUNT]).await;
     let unstake_lamports = Lamports(1_000_000_000);
 
-    let solido = context.get_solido().await;
-    let validator = &solido.validators.entries[0];
+    let validators = context.get_validators().await;
+    let validator = &validators.entries[0];
 
     let stake_account_before = context.get_stake_account_from_seed(&validator, 0).await;
     context.unstake(validator.pubkey, unstake_lamports).await;
@@ -163,20 +163,20 @@ async fn test_unstake_from_inactive_validator() {
     assert!(is_insufficient_funds_error(result));
 
     // But unstaking exactly the stake account balance should work.
-    let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
     context.unstake(vote_account, STAKE_AMOUNT).await;
-    let solido_after = context.get_solido().await;
+    let validators_after = context.get_validators().await;
 
     assert_eq!(
-        solido_before.validators.entries[0].entry.stake_seeds.begin + 1,
-        solido_after.validators.entries[0].entry.stake_seeds.begin,
+        validators_before.entries[0].entry.stake_seeds.begin + 1,
+        validators_after.entries[0].entry.stake_seeds.begin,
         "Unstaking the full stake account amount should have bumped the steed.",
     );
 
     // We should be able to do it a second time and unstake the second stake account.
     context.unstake(vote_account, STAKE_AMOUNT).await;
 
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     assert_eq!(
         validator.entry.stake_seeds.begin, validator.entry.stake_seeds.end,
         "No stake accounts should be left after unstaking both."
@@ -201,7 +201,7 @@ async fn test_unstake_from_inactive_validator() {
 #[tokio::test]
 async fn test_unstake_with_funded_destination_stake() {
     let mut context = new_unstake_context(&[STAKE_AMOUNT]).await;
-    let validator = &context.get_solido().await.validators.entries[0];
+    let validator = &context.get_validators().await.entries[0];
     let (unstake_address, _) = validator.find_stake_account_address(
         &crate::context::id(),
         &context.solido.pubkey(),
@@ -236,8 +236,8 @@ async fn test_unstake_allows_at_most_three_unstake_accounts() {
     // Wait for the unstake accounts to deactivate.
     context.advance_to_normal_epoch(1);
 
-    let solido_before = context.get_solido().await;
-    let validator_before = &solido_before.validators.entries[0].entry;
+    let validators_before = context.get_validators().await;
+    let validator_before = &validators_before.entries[0].entry;
     assert_eq!(validator_before.unstake_seeds.begin, 0);
     assert_eq!(validator_before.unstake_seeds.end, 3);
 
@@ -245,8 +245,8 @@ async fn test_unstake_allows_at_most_three_unstake_accounts() {
     // unstake accounts again.
     context.withdraw_inactive_stake(vote_account).await;
 
-    let solido_after = context.get_solido().await;
-    let validator_after = &solido_after.validators.entries[0].entry;
+    let validators_after = context.get_validators().await;
+    let validator_after = &validators_after.entries[0].entry;
     assert_eq!(validator_after.unstake_seeds.begin, 3);
     assert_eq!(validator_after.unstake_seeds.end, 3);
 
@@ -259,8 +259,8 @@ async fn test_unstake_activating() {
     let mut context = Context::new_with_maintainer_and_validator().await;
     let unstake_lamports = Lamports(1_000_000_000);
 
-    let solido = context.get_solido().await;
-    let validator = &solido.validators.entries[0];
+    let validators = context.get_validators().await;
+    let validator = &validators.entries[0];
 
     context.deposit(Lamports(10_000_000_000)).await;
     context
@@ -14,14 +14,14 @@ async fn test_withdraw_inactive_stake() {
 
     // If we try to withdraw initially, that should work, but there is nothing to withdraw.
     // The 2nd time it runs, should succeed, but nothing should change
-    let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
     for _ in 0..2 {
         context
             .withdraw_inactive_stake(validator.vote_account)
             .await;
     }
-    let solido_after = context.get_solido().await;
-    assert_eq!(solido_before, solido_after);
+    let validators_after = context.get_validators().await;
+    assert_eq!(validators_before, validators_after);
 
     // Deposit and stake the deposit with the validator. This creates one stake account.
     let initial_amount = Lamports(1_000_000_000);
@@ -32,12 +32,12 @@ async fn test_withdraw_inactive_stake() {
 
     // We should be able to withdraw the inactive stake. It should be a no-op,
     // because we already knew the current validator's balance.
-    let solido_before = context.get_solido().await;
+    let validators_before = context.get_validators().await;
     context
         .withdraw_inactive_stake(validator.vote_account)
         .await;
-    let solido_after = context.get_solido().await;
-    assert_eq!(solido_before, solido_after);
+    let validators_after = context.get_validators().await;
+    assert_eq!(validators_before, validators_after);
 
     // Skip ahead a number of epochs.
     context.advance_to_normal_epoch(0);
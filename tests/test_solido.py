@@ -321,7 +321,7 @@ solido_instance = solido(
     solido_address,
 )
 
-assert solido_instance['solido']['validators']['entries'][0] == {
+assert solido_instance['validators']['entries'][0] == {
     'pubkey': validator.vote_account.pubkey,
     'entry': {
         'fee_credit': 0,
@@ -419,11 +419,57 @@ transaction_result = solido(
 transaction_address = transaction_result['transaction_address']
 approve_and_execute(transaction_address, test_addrs[0])
 
+print(f'\nChanging reward distribution ...')
+transaction_result = solido(
+    'change-reward-distribution',
+    '--multisig-program-id',
+    multisig_program_id,
+    '--solido-program-id',
+    solido_program_id,
+    '--solido-address',
+    solido_address,
+    '--treasury-fee-share',
+    '4',
+    '--validation-fee-share',
+    '4',
+    '--developer-fee-share',
+    '2',
+    '--st-sol-appreciation-share',
+    '90',
+    '--treasury-account',
+    treasury_account,
+    '--developer-account',
+    developer_account,
+    '--multisig-address',
+    multisig_instance,
+    keypair_path=test_addrs[0].keypair_path,
+)
+transaction_address = transaction_result['transaction_address']
+approve_and_execute(transaction_address, test_addrs[1])
+solido_instance = solido(
+    'show-solido',
+    '--solido-program-id',
+    solido_program_id,
+    '--solido-address',
+    solido_address,
+)
+assert solido_instance['solido']['reward_distribution'] == {
+    'treasury_fee': 4,
+    'validation_fee': 4,
+    'developer_fee': 2,
+    'st_sol_appreciation': 90,
+}
+
 current_epoch = int(solana('epoch'))
 
 
 def perform_maintenance() -> Any:
-    return solido(
+    """
+    Perform one step of maintenance, and return the single operation that it
+    performed, or None if there was nothing to do. With the few validators in
+    this test, no step batches multiple operations.
+    """
+    result = solido(
         'perform-maintenance',
         '--solido-address',
         solido_address,
@@ -433,6 +479,11 @@ def perform_maintenance() -> Any:
         'anytime',
         keypair_path=maintainer.keypair_path,
     )
+    if result is None:
+        return None
+    operations = [op for tx in result['transactions'] for op in tx['operations']]
+    assert len(operations) == 1, f'Expected a single operation, got {result}'
+    return operations[0]
 
 
 print('\nRunning maintenance (should be no-op if epoch is unchanged) ...')
@@ -632,7 +683,7 @@ solido_instance = solido(
     '--solido-address',
     solido_address,
 )
-assert not solido_instance['solido']['validators']['entries'][0]['entry'][
+assert not solido_instance['validators']['entries'][0]['entry'][
     'active'
 ], 'Validator should be inactive after deactivation.'
 print('> Validator is inactive as expected.')
@@ -660,7 +711,7 @@ solido_instance = solido(
     solido_address,
 )
 # Should have bumped the validator's `stake_seeds` and `unstake_seeds`.
-val = solido_instance['solido']['validators']['entries'][0]['entry']
+val = solido_instance['validators']['entries'][0]['entry']
 assert val['stake_seeds'] == {'begin': 1, 'end': 1}
 assert val['unstake_seeds'] == {'begin': 1, 'end': 2}
 
@@ -702,7 +753,7 @@ solido_instance = solido(
     '--solido-address',
     solido_address,
 )
-number_validators = len(solido_instance['solido']['validators']['entries'])
+number_validators = len(solido_instance['validators']['entries'])
 assert (
     number_validators == 1
 ), f'\nExpected no validators\nGot: {number_validators} validators'